@@ -0,0 +1,762 @@
+//! Observation of a process that is already running.
+//!
+//! This module provides functionality for attaching to a running process with `ptrace`, and for
+//! logging the data that its `read` and `write` system calls, and their vectored and positional
+//! variants, transfer on the observed file descriptors, without relaying anything: the process
+//! keeps its own standard streams. Once the process exits, or fdintercept is told to stop,
+//! fdintercept detaches from it, and the process goes on as if it had never been observed.
+
+use crate::fd;
+use crate::log::{ChunkLog, Clock, StreamLog};
+use crate::redact::RedactingLog;
+use crate::rotate::LogReopener;
+use crate::session::Session;
+use crate::session_logs::{self, LogTemplates, SessionLogs};
+use crate::settings::{FdDirection, ReopenSignal, ResolvedSettings, StreamMode, StreamModes};
+use crate::stats::{StreamStats, StreamsSummary};
+use anyhow::{Context, Result};
+use nix::errno::Errno;
+use nix::fcntl::OFlag;
+use nix::libc;
+use nix::poll::{self, PollFd, PollFlags, PollTimeout};
+use nix::sys::ptrace::{self, Options};
+use nix::sys::signal::Signal;
+use nix::sys::termios;
+use nix::sys::uio::{self, RemoteIoVec};
+use nix::sys::wait::{self, WaitPidFlag, WaitStatus};
+use nix::unistd::Pid;
+use signal_hook::consts::{SIGCHLD, SIGHUP, SIGINT, SIGTERM};
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, IoSliceMut, Read};
+use std::mem::MaybeUninit;
+use std::os::fd::{AsFd, OwnedFd, RawFd};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::net::UnixStream;
+use std::os::unix::process::ExitStatusExt;
+use std::process::ExitStatus;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// A file descriptor of the observed process whose data is logged.
+struct Watched {
+    /// Number of the file descriptor in the observed process.
+    fd: RawFd,
+    /// Whether the process reads the data from the file descriptor, or writes it into it.
+    direction: FdDirection,
+    /// Name of the stream, as it appears in error messages.
+    name: &'static str,
+    /// The log of the stream, if it is still being logged.
+    maybe_log: Option<RedactingLog<StreamLog>>,
+    /// Counters of the stream.
+    stats: StreamStats,
+}
+
+/// Where the data of a system call is in the memory of the observed process.
+#[derive(Clone, Copy)]
+enum Memory {
+    /// A single buffer, as `read` and `write` take.
+    Buffer {
+        /// Address of the buffer.
+        base: u64,
+    },
+    /// An array of buffers, as `readv` and `writev` take.
+    Vector {
+        /// Address of the array of `iovec` structs.
+        iov: u64,
+        /// Number of buffers in the array.
+        count: u64,
+    },
+}
+
+/// A system call that transfers data on a watched file descriptor, between its entry and its exit.
+#[derive(Clone, Copy)]
+struct Transfer {
+    /// Index of the watched file descriptor.
+    watched: usize,
+    /// Where the data is.
+    memory: Memory,
+}
+
+/// The threads of an observed process, and what they transfer on the watched file descriptors.
+struct Tracer {
+    /// PID of the observed process.
+    pid: Pid,
+    /// The attached threads, each with the transfer it is in the middle of, if any.
+    threads: HashMap<Pid, Option<Transfer>>,
+    /// The watched file descriptors.
+    watched: Vec<Watched>,
+    /// How the observed process ended, once it did.
+    maybe_status: Option<ExitStatus>,
+}
+
+impl Tracer {
+    /// Attaches to every thread of a process, and stops them, to start tracing their system calls.
+    ///
+    /// # Arguments
+    ///
+    /// * `pid` - PID of the process.
+    ///
+    /// # Returns
+    ///
+    /// Returns the tracer, with no watched file descriptors yet.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - Failed to attach to the process, e.g., because it doesn't exist, or fdintercept isn't
+    ///   allowed to trace it, or
+    /// - Failed to list the threads of the process.
+    fn attach(pid: Pid) -> Result<Self> {
+        let options = Options::PTRACE_O_TRACESYSGOOD | Options::PTRACE_O_TRACECLONE;
+        ptrace::seize(pid, options).with_context(|| {
+            format!(
+                "Error attaching to process {pid}. Tracing a process that isn't a child of \
+                 fdintercept may need the CAP_SYS_PTRACE capability, depending on \
+                 /proc/sys/kernel/yama/ptrace_scope"
+            )
+        })?;
+        let mut threads = HashMap::from([(pid, None)]);
+
+        // Threads created from now on are attached along with the thread that creates them, but
+        // one created while the threads are being listed may still be missed, so they are listed
+        // until no new ones show up.
+        loop {
+            let mut found_new = false;
+            let tasks = fs::read_dir(format!("/proc/{pid}/task"))
+                .with_context(|| format!("Error listing threads of process {pid}"))?;
+            for task in tasks {
+                let task =
+                    task.with_context(|| format!("Error listing threads of process {pid}"))?;
+                let Some(tid) = task.file_name().to_str().and_then(|tid| tid.parse().ok()) else {
+                    continue;
+                };
+                let tid = Pid::from_raw(tid);
+                if threads.contains_key(&tid) {
+                    continue;
+                }
+                found_new = true;
+                match ptrace::seize(tid, options) {
+                    // `EPERM` means the thread is already attached, because it was created by an
+                    // attached thread.
+                    Ok(()) | Err(Errno::EPERM) => {
+                        threads.insert(tid, None);
+                    }
+                    // The thread is already gone.
+                    Err(Errno::ESRCH) => (),
+                    Err(e) => {
+                        return Err(e).with_context(|| format!("Error attaching to thread {tid}"));
+                    }
+                }
+            }
+            if !found_new {
+                break;
+            }
+        }
+
+        // The threads only stop at system calls once they are resumed from a stop.
+        for &tid in threads.keys() {
+            match ptrace::interrupt(tid) {
+                Ok(()) | Err(Errno::ESRCH) => (),
+                Err(e) => return Err(e).with_context(|| format!("Error stopping thread {tid}")),
+            }
+        }
+
+        Ok(Self {
+            pid,
+            threads,
+            watched: Vec::new(),
+            maybe_status: None,
+        })
+    }
+
+    /// Handles a stop of a thread, and resumes it, or detaches from it.
+    ///
+    /// # Arguments
+    ///
+    /// * `status` - What happened to the thread.
+    /// * `detaching` - Whether to detach from the thread, instead of resuming it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the thread couldn't be resumed or detached from.
+    fn handle(&mut self, status: WaitStatus, detaching: bool) -> Result<()> {
+        match status {
+            WaitStatus::PtraceSyscall(tid) => {
+                self.threads.entry(tid).or_default();
+                self.syscall_stop(tid);
+                self.resume(tid, None, detaching)
+            }
+            WaitStatus::PtraceEvent(tid, signal, event) => {
+                self.threads.entry(tid).or_default();
+                if event == libc::PTRACE_EVENT_CLONE {
+                    if let Ok(new_tid) = ptrace::getevent(tid) {
+                        // unwrap: Safe because the kernel reports the ID of the new thread, which
+                        // always fits a PID.
+                        self.threads
+                            .entry(Pid::from_raw(new_tid.try_into().unwrap()))
+                            .or_default();
+                    }
+                }
+                // Without one of our own stops pending, a stop reporting a stop signal is the
+                // whole process stopping, e.g., because of Ctrl+Z, and the thread must stay stopped
+                // until the process is continued.
+                if event == libc::PTRACE_EVENT_STOP && signal != Signal::SIGTRAP && !detaching {
+                    return listen(tid);
+                }
+                self.resume(tid, None, detaching)
+            }
+            // A signal is about to be delivered to the thread, which must still get it.
+            WaitStatus::Stopped(tid, signal) => {
+                self.threads.entry(tid).or_default();
+                self.resume(tid, Some(signal), detaching)
+            }
+            WaitStatus::Exited(tid, code) => {
+                self.threads.remove(&tid);
+                if tid == self.pid {
+                    self.maybe_status = Some(ExitStatus::from_raw((code & 0xff) << 8));
+                }
+                Ok(())
+            }
+            WaitStatus::Signaled(tid, signal, core_dumped) => {
+                self.threads.remove(&tid);
+                if tid == self.pid {
+                    self.maybe_status = Some(ExitStatus::from_raw(
+                        signal as i32 | if core_dumped { 0x80 } else { 0 },
+                    ));
+                }
+                Ok(())
+            }
+            WaitStatus::Continued(_) | WaitStatus::StillAlive => Ok(()),
+        }
+    }
+
+    /// Resumes a stopped thread until its next system call, or detaches from it.
+    ///
+    /// # Arguments
+    ///
+    /// * `tid` - ID of the thread.
+    /// * `maybe_signal` - The signal to deliver to the thread, if any.
+    /// * `detaching` - Whether to detach from the thread, instead of resuming it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the thread couldn't be resumed or detached from, unless it is gone.
+    fn resume(&mut self, tid: Pid, maybe_signal: Option<Signal>, detaching: bool) -> Result<()> {
+        let result = if detaching {
+            self.threads.remove(&tid);
+            ptrace::detach(tid, maybe_signal)
+        } else {
+            ptrace::syscall(tid, maybe_signal)
+        };
+        match result {
+            // The thread was killed while it was stopped, and its exit is reported next.
+            Ok(()) | Err(Errno::ESRCH) => Ok(()),
+            Err(e) => Err(e).with_context(|| format!("Error resuming thread {tid}")),
+        }
+    }
+
+    /// Handles the entry or the exit of a system call of a thread.
+    ///
+    /// On the entry of a system call that transfers data on a watched file descriptor, where the
+    /// data is is remembered. On its exit, the data that was actually transferred is logged.
+    ///
+    /// # Arguments
+    ///
+    /// * `tid` - ID of the thread.
+    fn syscall_stop(&mut self, tid: Pid) {
+        let Ok(info) = syscall_info(tid) else {
+            // The thread was killed while it was stopped.
+            return;
+        };
+        match info.op {
+            libc::PTRACE_SYSCALL_INFO_ENTRY => {
+                // SAFETY: `op` tells that the kernel filled in the entry of the system call.
+                let entry = unsafe { info.u.entry };
+                let maybe_transfer = self.transfer(entry.nr, &entry.args);
+                self.threads.insert(tid, maybe_transfer);
+            }
+            libc::PTRACE_SYSCALL_INFO_EXIT => {
+                // SAFETY: `op` tells that the kernel filled in the exit of the system call.
+                let exit = unsafe { info.u.exit };
+                if let Some(Some(transfer)) = self.threads.insert(tid, None) {
+                    self.complete(tid, transfer, exit.sval, exit.is_error != 0);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Finds out whether a system call transfers data on a watched file descriptor.
+    ///
+    /// # Arguments
+    ///
+    /// * `nr` - Number of the system call.
+    /// * `args` - Arguments of the system call.
+    ///
+    /// # Returns
+    ///
+    /// Returns the transfer, or `None` if the system call isn't one.
+    fn transfer(&self, nr: u64, args: &[u64; 6]) -> Option<Transfer> {
+        let (direction, vectored) = match libc::c_long::try_from(nr).ok()? {
+            libc::SYS_read | libc::SYS_pread64 => (FdDirection::In, false),
+            libc::SYS_readv | libc::SYS_preadv | libc::SYS_preadv2 => (FdDirection::In, true),
+            libc::SYS_write | libc::SYS_pwrite64 => (FdDirection::Out, false),
+            libc::SYS_writev | libc::SYS_pwritev | libc::SYS_pwritev2 => (FdDirection::Out, true),
+            _ => return None,
+        };
+        let fd = RawFd::try_from(args[0]).ok()?;
+        let watched = self
+            .watched
+            .iter()
+            .position(|watched| watched.fd == fd && watched.direction == direction)?;
+        let memory = if vectored {
+            Memory::Vector {
+                iov: args[1],
+                count: args[2],
+            }
+        } else {
+            Memory::Buffer { base: args[1] }
+        };
+        Some(Transfer { watched, memory })
+    }
+
+    /// Logs the data of a system call that transferred data on a watched file descriptor, and
+    /// records it in the counters of its stream.
+    ///
+    /// A failure to log only stops the logging of the stream, since the observed process doesn't
+    /// depend on fdintercept.
+    ///
+    /// # Arguments
+    ///
+    /// * `tid` - ID of the thread that made the system call.
+    /// * `transfer` - The transfer.
+    /// * `sval` - What the system call returned: the number of bytes transferred, or the negated
+    ///   error number.
+    /// * `is_error` - Whether the system call failed.
+    fn complete(&mut self, tid: Pid, transfer: Transfer, sval: i64, is_error: bool) {
+        let watched = &mut self.watched[transfer.watched];
+        let result = if is_error {
+            // unwrap: Safe because error numbers are small.
+            Err(io::Error::from_raw_os_error((-sval).try_into().unwrap()))
+        } else {
+            // unwrap: Safe because a successful transfer returns the non-negative number of bytes
+            // transferred, which fits in memory.
+            Ok(usize::try_from(sval).unwrap())
+        };
+        watched.stats.record_read(&result);
+
+        let (Ok(len @ 1..), Some(log)) = (result, &mut watched.maybe_log) else {
+            return;
+        };
+        if let Err(e) = read_memory(tid, transfer.memory, len).and_then(|data| log.log_chunk(&data))
+        {
+            eprintln!(
+                "Error logging {}, which is no longer logged: {e}",
+                watched.name
+            );
+            if let Some(log) = watched.maybe_log.take() {
+                if let Err(e) = fd::finish_log(Some(log), watched.name) {
+                    eprintln!("{e}");
+                }
+            }
+        }
+    }
+
+    /// Detaches from every thread, logging the transfers that complete in the meantime.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - Failed to stop a thread,
+    /// - Failed to wait for a thread to stop, or
+    /// - Failed to detach from a thread.
+    fn detach(&mut self) -> Result<()> {
+        // A thread can only be detached from while it is stopped.
+        for &tid in self.threads.keys() {
+            match ptrace::interrupt(tid) {
+                Ok(()) | Err(Errno::ESRCH) => (),
+                Err(e) => return Err(e).with_context(|| format!("Error stopping thread {tid}")),
+            }
+        }
+        // The process may be a child of fdintercept, which can still be waited for once it was
+        // detached from, so the threads are only waited for until none is left attached.
+        while !self.threads.is_empty() {
+            match wait::waitpid(None, Some(WaitPidFlag::__WALL)) {
+                Ok(status) => self.handle(status, true)?,
+                Err(Errno::ECHILD) => break,
+                Err(Errno::EINTR) => (),
+                Err(e) => return Err(e).context("Error waiting for process"),
+            }
+        }
+        Ok(())
+    }
+
+    /// Summarizes the traffic of the standard streams that were watched.
+    ///
+    /// # Returns
+    ///
+    /// Returns the summary, with empty counters for the standard streams that weren't watched.
+    /// The additional file descriptors are always watched.
+    fn summary(&self) -> StreamsSummary {
+        let summary = |name| {
+            self.watched
+                .iter()
+                .find(|watched| watched.name == name)
+                .map(|watched| watched.stats.summary())
+                .unwrap_or_default()
+        };
+        StreamsSummary {
+            stdin: summary("stdin"),
+            stdout: summary("stdout"),
+            stderr: summary("stderr"),
+            extra_fds: self
+                .watched
+                .iter()
+                .filter(|watched| watched.fd > 2)
+                .map(|watched| (watched.name, watched.stats.summary()))
+                .collect(),
+        }
+    }
+}
+
+/// Gets the entry or the exit of the system call a thread is stopped at.
+///
+/// Unlike `ptrace::syscall_info`, this tells the kernel how large the buffer is, without which it
+/// fills in nothing.
+///
+/// # Arguments
+///
+/// * `tid` - ID of the thread.
+///
+/// # Returns
+///
+/// Returns what the kernel knows of the system call.
+///
+/// # Errors
+///
+/// Returns an error if the thread isn't stopped, e.g., because it is gone.
+fn syscall_info(tid: Pid) -> nix::Result<libc::ptrace_syscall_info> {
+    let mut info = MaybeUninit::<libc::ptrace_syscall_info>::zeroed();
+    // SAFETY: The kernel writes at most as many bytes as the size passed as the address, which is
+    // the size of `info`.
+    let result = unsafe {
+        libc::ptrace(
+            libc::PTRACE_GET_SYSCALL_INFO,
+            tid.as_raw(),
+            size_of::<libc::ptrace_syscall_info>(),
+            info.as_mut_ptr(),
+        )
+    };
+    Errno::result(result)?;
+    // SAFETY: `info` was zeroed, which is a valid value for it, before the kernel filled it in.
+    Ok(unsafe { info.assume_init() })
+}
+
+/// Lets a thread of a stopped process stay stopped, while being told once it is continued.
+///
+/// # Arguments
+///
+/// * `tid` - ID of the thread.
+///
+/// # Errors
+///
+/// Returns an error if the thread couldn't be told so, unless it is gone.
+fn listen(tid: Pid) -> Result<()> {
+    // SAFETY: `PTRACE_LISTEN` doesn't access the address nor the data, which are both null.
+    let result = unsafe {
+        libc::ptrace(
+            libc::PTRACE_LISTEN,
+            tid.as_raw(),
+            std::ptr::null_mut::<libc::c_void>(),
+            std::ptr::null_mut::<libc::c_void>(),
+        )
+    };
+    match Errno::result(result) {
+        Ok(_) | Err(Errno::ESRCH) => Ok(()),
+        Err(e) => Err(e).with_context(|| format!("Error resuming thread {tid}")),
+    }
+}
+
+/// Copies data out of the memory of a thread.
+///
+/// # Arguments
+///
+/// * `tid` - ID of the thread.
+/// * `memory` - Where the data is.
+/// * `len` - How many bytes of data to copy, from the start of the data.
+///
+/// # Returns
+///
+/// Returns the data, which may be shorter than `len` if part of the memory can't be read.
+///
+/// # Errors
+///
+/// Returns an error if the memory can't be read at all.
+fn read_memory(tid: Pid, memory: Memory, len: usize) -> io::Result<Vec<u8>> {
+    let address = |value: u64| {
+        usize::try_from(value).map_err(|_| io::Error::from(io::ErrorKind::InvalidData))
+    };
+    let regions = match memory {
+        Memory::Buffer { base } => vec![RemoteIoVec {
+            base: address(base)?,
+            len,
+        }],
+        Memory::Vector { iov, count } => {
+            const IOVEC_SIZE: usize = size_of::<libc::iovec>();
+            const WORD_SIZE: usize = size_of::<usize>();
+            // unwrap: Safe because the limit is a small positive number.
+            let count = address(count)?.min(usize::try_from(libc::UIO_MAXIOV).unwrap());
+            let iovecs = read_regions(
+                tid,
+                &[RemoteIoVec {
+                    base: address(iov)?,
+                    len: count * IOVEC_SIZE,
+                }],
+                count * IOVEC_SIZE,
+            )?;
+            // The buffers are filled, or emptied, in order, so only the first ones hold the data.
+            let mut left = len;
+            let mut regions = Vec::new();
+            for iovec in iovecs.chunks_exact(IOVEC_SIZE) {
+                if left == 0 {
+                    break;
+                }
+                // unwrap: Safe because an `iovec` is a pointer followed by a length, each one
+                // word long.
+                let base = usize::from_ne_bytes(iovec[..WORD_SIZE].try_into().unwrap());
+                let buffer_len =
+                    usize::from_ne_bytes(iovec[WORD_SIZE..2 * WORD_SIZE].try_into().unwrap());
+                let len = buffer_len.min(left);
+                regions.push(RemoteIoVec { base, len });
+                left -= len;
+            }
+            regions
+        }
+    };
+    read_regions(tid, &regions, len)
+}
+
+/// Copies regions of the memory of a thread into a single buffer.
+///
+/// # Arguments
+///
+/// * `tid` - ID of the thread.
+/// * `regions` - The regions to copy, in order.
+/// * `len` - Total length in bytes of the regions.
+///
+/// # Returns
+///
+/// Returns the data, which may be shorter than `len` if part of the memory can't be read.
+///
+/// # Errors
+///
+/// Returns an error if the memory can't be read at all.
+fn read_regions(tid: Pid, regions: &[RemoteIoVec], len: usize) -> io::Result<Vec<u8>> {
+    let mut data = vec![0; len];
+    let read = uio::process_vm_readv(tid, &mut [IoSliceMut::new(&mut data)], regions)?;
+    data.truncate(read);
+    Ok(data)
+}
+
+/// Opens the terminal the input of a process is typed into, if its stdin is attached to one.
+///
+/// # Arguments
+///
+/// * `pid` - PID of the process.
+///
+/// # Returns
+///
+/// Returns the terminal, or `None` if the stdin of the process isn't a terminal, or can't be
+/// opened.
+fn input_terminal(pid: Pid) -> Option<OwnedFd> {
+    // The terminal is only opened to read its mode, so it must not become the controlling terminal
+    // of fdintercept, nor block on being opened.
+    let terminal: OwnedFd = OpenOptions::new()
+        .read(true)
+        .custom_flags((OFlag::O_NOCTTY | OFlag::O_NONBLOCK).bits())
+        .open(format!("/proc/{pid}/fd/0"))
+        .ok()?
+        .into();
+    termios::tcgetattr(&terminal).ok()?;
+    Some(terminal)
+}
+
+/// Attaches to a running process, and logs what it transfers on the observed file descriptors
+/// until it exits, or fdintercept is told to stop, and then detaches from it.
+///
+/// # Arguments
+///
+/// * `settings` - The settings of the session.
+/// * `pid` - PID of the process to attach to.
+///
+/// # Returns
+///
+/// Doesn't return if it succeeds, but exits with the exit code of the process, or 128 + the
+/// number of the signal that terminated it, or with 0, if fdintercept detached from it before it
+/// ended.
+///
+/// # Errors
+///
+/// Returns an error if:
+/// - Failed to register the signal handlers,
+/// - Failed to attach to the process,
+/// - Failed to create the session directory, the manifest, or the log files, or
+/// - Failed to trace the process.
+///
+/// # Signal Handling
+///
+/// - SIGHUP, SIGINT, and SIGTERM: Detach from the process, which goes on running.
+/// - The configured reopen signal, if any: Reopen the log files. If it is SIGHUP, SIGHUP no longer
+///   detaches.
+pub fn run(settings: &ResolvedSettings, pid: i32) -> Result<()> {
+    // The handlers only set flags, and wake up the loop below, which waits for the process and
+    // for signals at once.
+    let terminate = Arc::new(AtomicBool::new(false));
+    let reopen = Arc::new(AtomicBool::new(false));
+    let (wake_rx, wake_tx) = UnixStream::pair().context("Error creating socket pair")?;
+    wake_rx
+        .set_nonblocking(true)
+        .context("Error creating socket pair")?;
+    let maybe_reopen_signum = settings.reopen_signal.map(ReopenSignal::signum);
+    for signum in [SIGHUP, SIGINT, SIGTERM] {
+        if Some(signum) != maybe_reopen_signum {
+            signal_hook::flag::register(signum, terminate.clone())
+                .context("Failed to register signal handlers")?;
+        }
+    }
+    if let Some(signum) = maybe_reopen_signum {
+        signal_hook::flag::register(signum, reopen.clone())
+            .context("Failed to register reopen signal handler")?;
+    }
+    for signum in [SIGHUP, SIGINT, SIGTERM, SIGCHLD]
+        .into_iter()
+        .chain(maybe_reopen_signum)
+    {
+        signal_hook::low_level::pipe::register(
+            signum,
+            wake_tx.try_clone().context("Error duplicating socket")?,
+        )
+        .context("Failed to register signal handlers")?;
+    }
+    let maybe_reopener = maybe_reopen_signum.map(|_| LogReopener::default());
+
+    let clock = Clock::new();
+    let session = Session::new(clock.started_at());
+    let log_templates = LogTemplates::parse(settings)?;
+    let maybe_session_dir = session_logs::create_session_dir(&session, settings)?;
+
+    let pid = Pid::from_raw(pid);
+    let mut tracer = Tracer::attach(pid)?;
+
+    // The threads of the process stay stopped until the logs are created, so nothing is missed.
+    // The process keeps the terminal it was attached to, so its window size changes aren't
+    // forwarded nor recorded.
+    let SessionLogs {
+        mut maybe_manifest,
+        shared_logs,
+        stdin: stdin_log,
+        stdout: stdout_log,
+        stderr: stderr_log,
+        extra_fds: extra_fd_logs,
+        resize_logs: _,
+    } = SessionLogs::create(
+        log_templates,
+        settings,
+        &session,
+        clock,
+        // unwrap: Safe because PIDs are positive.
+        pid.as_raw().try_into().unwrap(),
+        maybe_session_dir.as_deref(),
+        maybe_reopener.as_ref(),
+    )?;
+    let StreamModes {
+        stdin: stdin_mode,
+        stdout: stdout_mode,
+        stderr: stderr_mode,
+    } = settings.stream_modes;
+    let streams = [
+        (0, FdDirection::In, "stdin", stdin_mode, stdin_log),
+        (1, FdDirection::Out, "stdout", stdout_mode, stdout_log),
+        (2, FdDirection::Out, "stderr", stderr_mode, stderr_log),
+    ];
+    for (fd, direction, name, stream_mode, maybe_log) in streams {
+        if stream_mode == StreamMode::Intercept {
+            // What is typed while the process turned echo off, e.g., a password, is never logged.
+            let maybe_terminal = (fd == 0).then(|| input_terminal(pid)).flatten();
+            tracer.watched.push(Watched {
+                fd,
+                direction,
+                name,
+                maybe_log: maybe_log.map(|log| RedactingLog::new(log, maybe_terminal)),
+                stats: StreamStats::default(),
+            });
+        }
+    }
+    for (extra_fd, maybe_log) in settings.extra_fds.iter().zip(extra_fd_logs) {
+        tracer.watched.push(Watched {
+            fd: extra_fd.fd,
+            direction: extra_fd.direction,
+            name: extra_fd.name(),
+            maybe_log: maybe_log.map(|log| RedactingLog::new(log, None)),
+            stats: StreamStats::default(),
+        });
+    }
+
+    let mut wake_buffer = [0; 64];
+    loop {
+        match wait::waitpid(None, Some(WaitPidFlag::__WALL | WaitPidFlag::WNOHANG)) {
+            Ok(WaitStatus::StillAlive) => (),
+            Ok(status) => {
+                tracer.handle(status, false)?;
+                continue;
+            }
+            // Every thread of the process is gone.
+            Err(Errno::ECHILD) => break,
+            Err(Errno::EINTR) => continue,
+            Err(e) => return Err(e).context("Error waiting for process"),
+        }
+
+        if reopen.swap(false, Ordering::SeqCst) {
+            if let Some(reopener) = &maybe_reopener {
+                reopener.reopen_all_reporting();
+            }
+        }
+        if terminate.load(Ordering::SeqCst) {
+            tracer.detach().context("Error detaching from process")?;
+            break;
+        }
+
+        // Every stop of a thread sends fdintercept a SIGCHLD, so the signals are all there is to
+        // wait for.
+        match poll::poll(
+            &mut [PollFd::new(wake_rx.as_fd(), PollFlags::POLLIN)],
+            PollTimeout::NONE,
+        ) {
+            Ok(_) | Err(Errno::EINTR) => (),
+            Err(e) => return Err(e).context("Error waiting for signals"),
+        }
+        while (&wake_rx).read(&mut wake_buffer).is_ok_and(|read| read > 0) {}
+    }
+
+    for watched in &mut tracer.watched {
+        if let Err(e) = fd::finish_log(watched.maybe_log.take(), watched.name) {
+            eprintln!("Error in {}: {e}", watched.name);
+        }
+    }
+    session_logs::finish(
+        &shared_logs,
+        maybe_manifest.as_mut(),
+        clock,
+        tracer.maybe_status,
+        tracer.summary(),
+        settings.summary,
+    );
+
+    std::process::exit(tracer.maybe_status.map_or(0, |status| {
+        status
+            .code()
+            .or_else(|| status.signal().map(|signum| 128 + signum))
+            .unwrap_or(1)
+    }));
+}
@@ -0,0 +1,318 @@
+//! Compression of log files.
+//!
+//! This module provides streaming compression of log files, in the gzip or zstd formats, so that
+//! long sessions don't fill up the disk. The compression of each log file is either chosen
+//! explicitly, or inferred from the extension of its name: `.gz` for gzip and `.zst` for zstd.
+//!
+//! A compressed log file is only readable to its end once its trailer was written, so it must be
+//! finished when the stream it records ends. If it is dropped before that, e.g., because
+//! fdintercept is bailing out with an error, it is finished on drop instead.
+//!
+//! Compressed recordings can also be read back, e.g., to replay a compressed stdin log.
+
+use crate::log::LogSink;
+use crate::settings::Compression;
+use flate2::Compression as GzipLevel;
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Write};
+use std::path::Path;
+
+/// Compression level used for zstd. Level 3 is zstd's own default, which compresses about as well
+/// as gzip's default level while being much faster.
+const ZSTD_LEVEL: i32 = 3;
+
+/// A compression format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+    /// The gzip format.
+    Gzip,
+    /// The zstd format.
+    Zstd,
+}
+
+impl Codec {
+    /// Infers the compression format of a file from the extension of its name.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to the file.
+    ///
+    /// # Returns
+    ///
+    /// Returns the compression format, or `None` if the extension doesn't denote one.
+    pub fn from_extension(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "gz" => Some(Self::Gzip),
+            "zst" => Some(Self::Zstd),
+            _ => None,
+        }
+    }
+
+    /// Determines the compression format of a log file.
+    ///
+    /// # Arguments
+    ///
+    /// * `compression` - The compression chosen for the log files.
+    /// * `path` - Path to the log file.
+    ///
+    /// # Returns
+    ///
+    /// Returns the compression format, or `None` if the log file should not be compressed.
+    pub fn for_log_file(compression: Compression, path: &Path) -> Option<Self> {
+        match compression {
+            Compression::Auto => Self::from_extension(path),
+            Compression::None => None,
+            Compression::Gzip => Some(Self::Gzip),
+            Compression::Zstd => Some(Self::Zstd),
+        }
+    }
+}
+
+/// A log file, compressed as it is written if so requested.
+pub enum LogFile {
+    /// A log file that is written as is.
+    Plain(File),
+    /// A log file compressed with gzip.
+    Gzip(GzEncoder<File>),
+    /// A log file compressed with zstd.
+    Zstd(zstd::Encoder<'static, File>),
+}
+
+impl LogFile {
+    /// Wraps an open file into a log file.
+    ///
+    /// # Arguments
+    ///
+    /// * `file` - The file the log is written to.
+    /// * `maybe_codec` - Optional format to compress the log in.
+    ///
+    /// # Returns
+    ///
+    /// Returns the log file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the compressor could not be set up.
+    pub fn new(file: File, maybe_codec: Option<Codec>) -> io::Result<Self> {
+        Ok(match maybe_codec {
+            None => Self::Plain(file),
+            Some(Codec::Gzip) => Self::Gzip(GzEncoder::new(file, GzipLevel::default())),
+            Some(Codec::Zstd) => Self::Zstd(zstd::Encoder::new(file, ZSTD_LEVEL)?),
+        })
+    }
+}
+
+impl Write for LogFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Plain(file) => file.write(buf),
+            Self::Gzip(encoder) => encoder.write(buf),
+            Self::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Plain(file) => file.flush(),
+            Self::Gzip(encoder) => encoder.flush(),
+            Self::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+impl LogSink for LogFile {
+    fn finish(&mut self) -> io::Result<()> {
+        // Finishing is idempotent in both compressors, so a log file that was already finished can
+        // safely be finished again when dropped.
+        match self {
+            Self::Plain(file) => file.flush(),
+            Self::Gzip(encoder) => encoder.try_finish(),
+            Self::Zstd(encoder) => encoder.do_finish(),
+        }
+    }
+}
+
+impl Drop for LogFile {
+    fn drop(&mut self) {
+        if let Err(e) = self.finish() {
+            eprintln!("Error finishing log file: {e}");
+        }
+    }
+}
+
+/// Opens a recording for reading, decompressing it if the extension of its name calls for it.
+///
+/// Both formats allow several compressed streams to be concatenated, as happens when a compressed
+/// log file is appended to across sessions, and all of them are read in sequence.
+///
+/// # Arguments
+///
+/// * `path` - Path to the recording.
+///
+/// # Returns
+///
+/// Returns a reader of the decompressed contents of the recording.
+///
+/// # Errors
+///
+/// Returns an error if the recording can't be opened, or if the decompressor could not be set up.
+pub fn open_recording(path: &Path) -> io::Result<Box<dyn Read + Send>> {
+    let file = File::open(path)?;
+    Ok(match Codec::from_extension(path) {
+        None => Box::new(file),
+        Some(Codec::Gzip) => Box::new(MultiGzDecoder::new(BufReader::new(file))),
+        Some(Codec::Zstd) => Box::new(zstd::Decoder::new(file)?),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    mod codec_from_extension {
+        use super::*;
+
+        #[test]
+        fn gzip() {
+            assert_eq!(
+                Codec::from_extension(Path::new("stdout.log.gz")),
+                Some(Codec::Gzip)
+            );
+        }
+
+        #[test]
+        fn zstd() {
+            assert_eq!(
+                Codec::from_extension(Path::new("stdout.log.zst")),
+                Some(Codec::Zstd)
+            );
+        }
+
+        #[test]
+        fn uncompressed() {
+            assert_eq!(Codec::from_extension(Path::new("stdout.log")), None);
+            assert_eq!(Codec::from_extension(Path::new("gz")), None);
+        }
+    }
+
+    mod codec_for_log_file {
+        use super::*;
+
+        #[test]
+        fn auto() {
+            assert_eq!(
+                Codec::for_log_file(Compression::Auto, Path::new("stdout.log.zst")),
+                Some(Codec::Zstd)
+            );
+            assert_eq!(
+                Codec::for_log_file(Compression::Auto, Path::new("stdout.log")),
+                None
+            );
+        }
+
+        #[test]
+        fn explicit() {
+            assert_eq!(
+                Codec::for_log_file(Compression::Gzip, Path::new("stdout.log")),
+                Some(Codec::Gzip)
+            );
+            assert_eq!(
+                Codec::for_log_file(Compression::Zstd, Path::new("stdout.log.gz")),
+                Some(Codec::Zstd)
+            );
+            assert_eq!(
+                Codec::for_log_file(Compression::None, Path::new("stdout.log.gz")),
+                None
+            );
+        }
+    }
+
+    mod log_file {
+        use super::*;
+
+        /// Writes `data` to a new log file at `path` compressed with `codec`, finishing it either
+        /// explicitly or by dropping it, and reads it back.
+        fn round_trip(path: &PathBuf, codec: Option<Codec>, finish: bool) -> Vec<u8> {
+            let mut log_file = LogFile::new(File::create(path).unwrap(), codec).unwrap();
+            log_file.write_all(b"hello ").unwrap();
+            log_file.write_all(b"world\n").unwrap();
+            if finish {
+                log_file.finish().unwrap();
+            }
+            drop(log_file);
+
+            let mut contents = Vec::new();
+            open_recording(path)
+                .unwrap()
+                .read_to_end(&mut contents)
+                .unwrap();
+            contents
+        }
+
+        #[test]
+        fn plain() {
+            let tmp_dir = tempfile::TempDir::new().unwrap();
+            let path = tmp_dir.path().join("stdout.log");
+
+            assert_eq!(round_trip(&path, None, true), b"hello world\n");
+        }
+
+        #[test]
+        fn gzip() {
+            let tmp_dir = tempfile::TempDir::new().unwrap();
+            let path = tmp_dir.path().join("stdout.log.gz");
+
+            assert_eq!(round_trip(&path, Some(Codec::Gzip), true), b"hello world\n");
+        }
+
+        #[test]
+        fn zstd() {
+            let tmp_dir = tempfile::TempDir::new().unwrap();
+            let path = tmp_dir.path().join("stdout.log.zst");
+
+            assert_eq!(round_trip(&path, Some(Codec::Zstd), true), b"hello world\n");
+        }
+
+        #[test]
+        fn finished_on_drop() {
+            let tmp_dir = tempfile::TempDir::new().unwrap();
+            let gzip_path = tmp_dir.path().join("stdout.log.gz");
+            let zstd_path = tmp_dir.path().join("stdout.log.zst");
+
+            assert_eq!(
+                round_trip(&gzip_path, Some(Codec::Gzip), false),
+                b"hello world\n"
+            );
+            assert_eq!(
+                round_trip(&zstd_path, Some(Codec::Zstd), false),
+                b"hello world\n"
+            );
+        }
+
+        #[test]
+        fn appended_across_sessions() {
+            let tmp_dir = tempfile::TempDir::new().unwrap();
+            let path = tmp_dir.path().join("stdout.log.zst");
+
+            for _ in 0..2 {
+                let file = std::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&path)
+                    .unwrap();
+                let mut log_file = LogFile::new(file, Some(Codec::Zstd)).unwrap();
+                log_file.write_all(b"hello\n").unwrap();
+            }
+
+            let mut contents = Vec::new();
+            open_recording(&path)
+                .unwrap()
+                .read_to_end(&mut contents)
+                .unwrap();
+            assert_eq!(contents, b"hello\nhello\n");
+        }
+    }
+}
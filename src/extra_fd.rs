@@ -0,0 +1,347 @@
+//! Interception of file descriptors beyond stdin, stdout, and stderr.
+//!
+//! This module provides functionality for giving the target command a pipe as each additional file
+//! descriptor it was asked to have, such as a status channel on fd 3, and for relaying each of them
+//! from or to the file descriptor of fdintercept with the same number, as stdin, stdout, and stderr
+//! are relayed, so that fdintercept stays transparent to whatever is on the other end.
+
+use crate::fd;
+use crate::log::{ChunkLog, StreamLog};
+use crate::settings::{ExtraFd, FdDirection};
+use crate::stats::StreamStats;
+use crate::threads;
+use anyhow::{Context, Result};
+use nix::fcntl::{self, FcntlArg, OFlag};
+use std::fs::File;
+use std::io::{self, Write};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::process::CommandExt;
+use std::process::Command;
+use std::sync::mpsc;
+use std::thread::{self, ScopedJoinHandle};
+
+/// The ends of the pipes of the additional file descriptors that the target command gets.
+pub struct ChildFds(Vec<(OwnedFd, RawFd)>);
+
+impl ChildFds {
+    /// Makes a command get each end of a pipe as the file descriptor it is meant to be.
+    ///
+    /// The ends themselves must be dropped once the command was spawned, so that the pipes are
+    /// closed once the target command closes them.
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - The command to attach the file descriptors to.
+    pub fn attach(&self, command: &mut Command) {
+        let mappings: Vec<_> = self
+            .0
+            .iter()
+            .map(|(end, fd)| (end.as_raw_fd(), *fd))
+            .collect();
+        // SAFETY: The closure runs in the child process, between `fork` and `exec`, where only
+        // async-signal-safe functions may be called, and `dup2` is. It doesn't allocate either,
+        // since the mappings were collected beforehand.
+        unsafe {
+            command.pre_exec(move || {
+                for &(end, fd) in &mappings {
+                    // The ends are all numbered above the file descriptors they become, so none of
+                    // them is replaced before it was duplicated. Unlike the end, the duplicate
+                    // isn't closed on exec.
+                    if nix::libc::dup2(end, fd) == -1 {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+                Ok(())
+            });
+        }
+    }
+}
+
+/// An additional file descriptor of the target command, as fdintercept relays it.
+pub struct Relay {
+    /// Name of the file descriptor, e.g., `fd3`, as it appears in the logs and error messages.
+    pub name: &'static str,
+    /// Direction in which the file descriptor is relayed.
+    direction: FdDirection,
+    /// The file descriptor of fdintercept with the same number, if it is open.
+    maybe_own: Option<File>,
+    /// The end of the pipe that fdintercept keeps.
+    pipe: File,
+}
+
+impl Relay {
+    /// Relays the file descriptor until it ends.
+    ///
+    /// With [`FdDirection::Out`], what the target command writes is relayed to the file descriptor
+    /// of fdintercept, or only logged, if that isn't open, until the target command closes it.
+    /// With [`FdDirection::In`], the file descriptor of fdintercept is relayed to the target
+    /// command until it ends or fdintercept is told to stop. If it isn't open, the target command
+    /// finds its file descriptor at its end right away.
+    ///
+    /// # Arguments
+    ///
+    /// * `buffer_size` - Size of the buffer in bytes used for data transfer.
+    /// * `maybe_log` - Optional log for recording the transferred data.
+    /// * `stats` - Counters of the file descriptor.
+    /// * `signal_rx` - File descriptor that becomes readable once fdintercept should stop.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` once the file descriptor ended.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file descriptor couldn't be relayed.
+    pub fn run(
+        self,
+        buffer_size: usize,
+        maybe_log: Option<impl ChunkLog>,
+        stats: &StreamStats,
+        signal_rx: OwnedFd,
+    ) -> Result<()> {
+        match (self.direction, self.maybe_own) {
+            (FdDirection::Out, maybe_own) => fd::process_fd(
+                self.pipe,
+                maybe_own.map_or_else(
+                    || Box::new(io::sink()) as Box<dyn Write + Send>,
+                    |own| Box::new(own),
+                ),
+                buffer_size,
+                maybe_log,
+                stats,
+                self.name,
+                None,
+            ),
+            (FdDirection::In, Some(own)) => fd::process_fd(
+                own,
+                self.pipe,
+                buffer_size,
+                maybe_log,
+                stats,
+                self.name,
+                Some(signal_rx),
+            ),
+            (FdDirection::In, None) => fd::finish_log(maybe_log, self.name),
+        }
+    }
+}
+
+/// Spawns a thread that runs each relay.
+///
+/// # Arguments
+///
+/// * `scope` - The thread scope in which the threads will be created.
+/// * `handle_tx` - Channel sender for reporting the thread handles and names back to the parent.
+/// * `relays` - The relays to run, each with its log, if any, and its counters.
+/// * `buffer_size` - Size of the buffer in bytes used for data transfer.
+/// * `signal_rx` - File descriptor that becomes readable once fdintercept should stop.
+///
+/// # Returns
+///
+/// Returns `Ok(())` if all the threads were spawned.
+///
+/// # Errors
+///
+/// Returns an error if:
+/// - Failed to duplicate the signal pipe, or
+/// - Failed to create a thread.
+pub fn spawn_relays<'scope>(
+    scope: &'scope thread::Scope<'scope, '_>,
+    handle_tx: &mpsc::Sender<(&'static str, ScopedJoinHandle<'scope, Result<()>>)>,
+    relays: impl IntoIterator<Item = (Relay, Option<StreamLog>, &'scope StreamStats)>,
+    buffer_size: usize,
+    signal_rx: &OwnedFd,
+) -> Result<()> {
+    for (relay, maybe_log, stats) in relays {
+        let name = relay.name;
+        let signal_rx = signal_rx
+            .try_clone()
+            .context("Error duplicating signal pipe")?;
+        threads::spawn_self_shipping_thread_in_scope(scope, handle_tx.clone(), name, move || {
+            relay.run(buffer_size, maybe_log, stats, signal_rx)
+        })
+        .with_context(|| format!("Failed to create thread to process {name}"))?;
+    }
+    Ok(())
+}
+
+/// Opens the pipes of the additional file descriptors of the target command.
+///
+/// This must be called before fdintercept opens any file descriptor of its own, since it checks
+/// whether each file descriptor of fdintercept with the same number is open, and any file
+/// descriptor opened in the meantime might have taken that number.
+///
+/// # Arguments
+///
+/// * `extra_fds` - The additional file descriptors to intercept.
+///
+/// # Returns
+///
+/// Returns the ends of the pipes that the target command gets, and the relays of the file
+/// descriptors, in the same order as `extra_fds`.
+///
+/// # Errors
+///
+/// Returns an error if:
+/// - Failed to duplicate a file descriptor of fdintercept, or
+/// - Failed to create or duplicate a pipe.
+pub fn open(extra_fds: &[ExtraFd]) -> Result<(ChildFds, Vec<Relay>)> {
+    let min_child_end = extra_fds.iter().map(|extra_fd| extra_fd.fd + 1).max();
+    let mut child_fds = Vec::new();
+    let mut relays = Vec::new();
+    for extra_fd in extra_fds {
+        let name = extra_fd.name();
+        let maybe_own =
+            claim_own_fd(extra_fd.fd).with_context(|| format!("Error duplicating {name}"))?;
+
+        let (rx, tx) = nix::unistd::pipe2(OFlag::O_CLOEXEC)
+            .with_context(|| format!("Error creating pipe for {name}"))?;
+        let (child_end, pipe) = match extra_fd.direction {
+            FdDirection::In => (rx, tx),
+            FdDirection::Out => (tx, rx),
+        };
+        // unwrap: Safe because `min_child_end` only is `None` if there are no file descriptors.
+        let child_end = move_above(&child_end, min_child_end.unwrap())
+            .with_context(|| format!("Error duplicating pipe for {name}"))?;
+
+        child_fds.push((child_end, extra_fd.fd));
+        relays.push(Relay {
+            name,
+            direction: extra_fd.direction,
+            maybe_own,
+            pipe: File::from(pipe),
+        });
+    }
+    Ok((ChildFds(child_fds), relays))
+}
+
+/// Duplicates a file descriptor of fdintercept, if it is open.
+///
+/// # Arguments
+///
+/// * `fd` - The file descriptor to duplicate.
+///
+/// # Returns
+///
+/// Returns the duplicate, which is closed on exec, or `None` if the file descriptor isn't open.
+///
+/// # Errors
+///
+/// Returns an error if the file descriptor is open, but couldn't be duplicated.
+fn claim_own_fd(fd: RawFd) -> io::Result<Option<File>> {
+    // SAFETY: `F_DUPFD_CLOEXEC` only duplicates the file descriptor, if it is open, and doesn't
+    // access any memory.
+    let duplicate = unsafe { nix::libc::fcntl(fd, nix::libc::F_DUPFD_CLOEXEC, 3) };
+    if duplicate == -1 {
+        let e = io::Error::last_os_error();
+        if e.raw_os_error() == Some(nix::libc::EBADF) {
+            return Ok(None);
+        }
+        return Err(e);
+    }
+    // SAFETY: `duplicate` was just created, so nothing else owns it.
+    Ok(Some(File::from(unsafe { OwnedFd::from_raw_fd(duplicate) })))
+}
+
+/// Duplicates a file descriptor into one that is numbered at least as high as a minimum.
+///
+/// # Arguments
+///
+/// * `fd` - The file descriptor to duplicate.
+/// * `min` - The lowest number the duplicate may have.
+///
+/// # Returns
+///
+/// Returns the duplicate, which is closed on exec.
+///
+/// # Errors
+///
+/// Returns an error if the file descriptor couldn't be duplicated.
+fn move_above(fd: &OwnedFd, min: RawFd) -> io::Result<OwnedFd> {
+    let duplicate = fcntl::fcntl(fd, FcntlArg::F_DUPFD_CLOEXEC(min))?;
+    // SAFETY: `duplicate` was just created, so nothing else owns it.
+    Ok(unsafe { OwnedFd::from_raw_fd(duplicate) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::path::PathBuf;
+    use std::process::Stdio;
+
+    /// A log that drops the chunks.
+    struct NoLog;
+
+    impl ChunkLog for NoLog {
+        fn log_chunk(&mut self, _chunk: &[u8]) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    mod open {
+        use super::*;
+
+        #[test]
+        fn child_writes_into_pipe() {
+            let (child_fds, mut relays) =
+                open(&[ExtraFd::new(7, FdDirection::Out, PathBuf::from("fd7.log"))]).unwrap();
+            assert!(child_fds.0[0].0.as_raw_fd() > 7);
+
+            let mut command = Command::new("sh");
+            command
+                .args(["-c", "echo status >&7"])
+                .stdout(Stdio::null());
+            child_fds.attach(&mut command);
+            let mut child = command.spawn().unwrap();
+            drop(child_fds);
+            assert!(child.wait().unwrap().success());
+
+            let mut contents = String::new();
+            relays.remove(0).pipe.read_to_string(&mut contents).unwrap();
+            assert_eq!(contents, "status\n");
+        }
+
+        #[test]
+        fn child_reads_from_pipe() {
+            let (child_fds, relays) =
+                open(&[ExtraFd::new(8, FdDirection::In, PathBuf::from("fd8.log"))]).unwrap();
+
+            let mut command = Command::new("sh");
+            command.args(["-c", "read line <&8; echo \"$line\""]);
+            child_fds.attach(&mut command);
+            let child = command.stdout(Stdio::piped()).spawn().unwrap();
+            drop(child_fds);
+
+            let Relay { mut pipe, .. } = relays.into_iter().next().unwrap();
+            pipe.write_all(b"input\n").unwrap();
+            drop(pipe);
+            assert_eq!(child.wait_with_output().unwrap().stdout, b"input\n");
+        }
+    }
+
+    mod relay_run {
+        use super::*;
+
+        #[test]
+        fn in_without_own_fd() {
+            let (rx, tx) = nix::unistd::pipe().unwrap();
+            let relay = Relay {
+                name: "fd9",
+                direction: FdDirection::In,
+                maybe_own: None,
+                pipe: File::from(tx),
+            };
+            let (signal_rx, _signal_tx) = nix::unistd::pipe().unwrap();
+
+            relay
+                .run(1024, None::<NoLog>, &StreamStats::default(), signal_rx)
+                .unwrap();
+
+            // The write end of the pipe was closed, so the target command finds its end.
+            let mut contents = Vec::new();
+            File::from(rx).read_to_end(&mut contents).unwrap();
+            assert!(contents.is_empty());
+        }
+    }
+}
@@ -5,13 +5,19 @@
 //! - Processing file descriptor events in a non-blocking manner, and
 //! - Handling signals and data transfer between file descriptors.
 
+use crate::compress::{Codec, LogFile};
+use crate::log::{ChunkLog, LogWriter};
+use crate::rotate::{LogReopener, RotatingLogFile, Rotation, existing_size};
+use crate::session;
+use crate::settings::Compression;
+use crate::stats::{CountingReader, StreamStats};
 use anyhow::{Context, Result};
 use nix::fcntl::{self, OFlag};
 use std::fs::OpenOptions;
 use std::io::{self, Read, Write};
 use std::os::fd::{AsFd, OwnedFd};
 use std::os::unix::io::AsRawFd;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 
 /// Mio token that represents that the source file descriptor is ready to be read.
@@ -86,26 +92,40 @@ impl std::error::Error for ProcessEventsForFdError {}
 /// # Arguments
 ///
 /// * `maybe_path` - Optional path where the log file should be created.
+/// * `maybe_log_dir` - Optional directory that a relative `maybe_path` is relative to, instead of
+///   the current working directory.
 /// * `recreate_logs` - If true, truncates existing log file; if false, appends to it.
+/// * `compression` - Compression of the log file. When appending to a compressed log file, a new
+///   compressed stream is started after the existing ones.
+/// * `maybe_rotation` - Optional size or period after which the log file is rotated, and how many
+///   rotated files are kept.
+/// * `maybe_reopener` - Optional reopener to register the log file with, so that it can be
+///   reopened on request.
 ///
 /// # Returns
 ///
-/// Returns `Ok(Some(impl Write))` if a path was provided and the file was successfully created, or
+/// Returns `Ok(Some(LogWriter))` if a path was provided and the file was successfully created, or
 /// `Ok(None)` if no path was provided.
 ///
 /// # Errors
 ///
 /// Returns an error if:
 /// - Failed to create parent directories,
-/// - Failed to create or open the log file, or
-/// - Insufficient permissions.
+/// - Failed to create or open the log file,
+/// - Insufficient permissions, or
+/// - Failed to set up the compressor.
 pub fn create_log_file(
     maybe_path: Option<&PathBuf>,
+    maybe_log_dir: Option<&Path>,
     recreate_logs: bool,
-) -> Result<Option<impl Write>> {
+    compression: Compression,
+    maybe_rotation: Option<Rotation>,
+    maybe_reopener: Option<&LogReopener>,
+) -> Result<Option<LogWriter>> {
     let Some(path) = maybe_path else {
         return Ok(None);
     };
+    let path = &session::resolve_path(maybe_log_dir, path);
 
     if let Some(parent) = path.parent() {
         std::fs::create_dir_all(parent).context(format!(
@@ -121,10 +141,34 @@ pub fn create_log_file(
     } else {
         options.append(true);
     }
-    Ok(Some(options.open(path).context(format!(
+    let file = options.open(path).context(format!(
         "Failed to create/open log file: {}",
         path.display()
-    ))?))
+    ))?;
+    let maybe_codec = Codec::for_log_file(compression, path);
+    let size = existing_size(&file, maybe_codec).context(format!(
+        "Failed to get the size of log file: {}",
+        path.display()
+    ))?;
+    let log_file = LogFile::new(file, maybe_codec).context(format!(
+        "Failed to set up compression of log file: {}",
+        path.display()
+    ))?;
+    if maybe_rotation.is_none() && maybe_reopener.is_none() {
+        return Ok(Some(Box::new(log_file)));
+    }
+
+    let rotating_log_file = RotatingLogFile::new(
+        path.clone(),
+        maybe_codec,
+        maybe_rotation.unwrap_or_default(),
+        log_file,
+        size,
+    );
+    Ok(Some(match maybe_reopener {
+        Some(reopener) => Box::new(reopener.register(rotating_log_file)),
+        None => Box::new(rotating_log_file),
+    }))
 }
 
 /// Processes a file descriptor, handling data transfer and optional logging.
@@ -134,7 +178,8 @@ pub fn create_log_file(
 /// * `src_fd` - Source file descriptor implementing `Read + AsRawFd`.
 /// * `dst_fd` - Destination file descriptor implementing `Write`.
 /// * `buffer_size` - Size of the buffer in bytes used for data transfer.
-/// * `maybe_log` - Optional writer for logging the transferred data.
+/// * `maybe_log` - Optional log for recording the transferred data.
+/// * `stats` - Counters of the stream, updated with every read from the source.
 /// * `log_descriptor` - Static string describing the log for error messages.
 /// * `maybe_signal_rx` - Optional owned file descriptor for signal handling.
 ///
@@ -149,13 +194,15 @@ pub fn create_log_file(
 /// - Error occurred during polling, or
 /// - Error processing events (except for log write errors, which disable logging).
 pub fn process_fd(
-    mut src_fd: impl Read + AsFd + AsRawFd,
+    src_fd: impl Read + AsFd + AsRawFd,
     mut dst_fd: impl Write,
     buffer_size: usize,
-    mut maybe_log: Option<impl Write>,
+    mut maybe_log: Option<impl ChunkLog>,
+    stats: &StreamStats,
     log_descriptor: &'static str,
     maybe_signal_rx: Option<OwnedFd>,
 ) -> Result<()> {
+    let mut src_fd = CountingReader::new(src_fd, stats);
     let mut poll = set_up_poll(&src_fd, maybe_signal_rx.as_ref(), log_descriptor)
         .context("Error setting up poll")?;
 
@@ -188,7 +235,9 @@ pub fn process_fd(
             // If we got an EOF, this means that the stream is not open anymore and there will be
             // no more data flowing. Just let the thread die. If we got a signal, this means we
             // want to end the process.
-            Ok(ProcessEventsForFdSuccess::Eof | ProcessEventsForFdSuccess::Signal) => return Ok(()),
+            Ok(ProcessEventsForFdSuccess::Eof | ProcessEventsForFdSuccess::Signal) => {
+                return finish_log(maybe_log, log_descriptor);
+            }
             Err(ProcessEventsForFdError::Log(e)) => {
                 eprintln!("Error writing to {log_descriptor} log, disabling logging: {e}");
                 maybe_log.take();
@@ -203,11 +252,32 @@ pub fn process_fd(
         if event_outcomes.len() == 1 {
             // There was a signal event, and we already processed the fd readable event that
             // happened simultaneously. We can just return.
-            return Ok(());
+            return finish_log(maybe_log, log_descriptor);
         }
     }
 }
 
+/// Finishes the log of a stream that has ended, so that nothing held back in it is lost.
+///
+/// # Arguments
+///
+/// * `maybe_log` - Optional log of the stream.
+/// * `log_descriptor` - Static string describing the log for error messages.
+///
+/// # Returns
+///
+/// Returns `Ok(())` if there is no log, or if it was finished successfully.
+///
+/// # Errors
+///
+/// Returns an error if the log could not be finished.
+pub fn finish_log(maybe_log: Option<impl ChunkLog>, log_descriptor: &str) -> Result<()> {
+    maybe_log.map_or(Ok(()), |mut log| {
+        log.finish()
+            .context(format!("Error finishing {log_descriptor} log"))
+    })
+}
+
 /// Sets up a poll instance for monitoring file descriptors.
 ///
 /// # Arguments
@@ -232,9 +302,20 @@ fn set_up_poll(
 ) -> Result<mio::Poll> {
     let poll = mio::Poll::new().context("Error creating poll of events")?;
 
-    register_fd_into_poll(&poll, src_fd, SRC_TOKEN).context(format!(
-        "Error registering {log_descriptor} source stream in poll of events"
-    ))?;
+    match register_fd_into_poll(&poll, src_fd, SRC_TOKEN) {
+        Ok(()) => (),
+        // Regular files, and devices such as `/dev/null`, can't be polled, since they are always
+        // ready. They are read whenever polling times out instead.
+        Err(e)
+            if e.downcast_ref::<io::Error>()
+                .and_then(io::Error::raw_os_error)
+                == Some(nix::libc::EPERM) => {}
+        Err(e) => {
+            return Err(e).context(format!(
+                "Error registering {log_descriptor} source stream in poll of events"
+            ));
+        }
+    }
 
     if let Some(signal_rx) = maybe_signal_rx {
         register_fd_into_poll(&poll, signal_rx, SIGNAL_TOKEN)
@@ -287,7 +368,7 @@ fn register_fd_into_poll(poll: &mio::Poll, fd: &(impl AsFd + AsRawFd), token: us
 /// * `src_fd` - Source to read from.
 /// * `dst_fd` - Destination to write to.
 /// * `buffer` - Buffer for data transfer.
-/// * `maybe_log` - Optional log writer.
+/// * `maybe_log` - Optional log.
 ///
 /// # Returns
 ///
@@ -297,7 +378,7 @@ fn process_events_for_fd(
     src_fd: &mut impl Read,
     dst_fd: &mut impl Write,
     buffer: &mut [u8],
-    maybe_log: &mut Option<impl Write>,
+    maybe_log: &mut Option<impl ChunkLog>,
 ) -> Vec<Result<ProcessEventsForFdSuccess, ProcessEventsForFdError>> {
     match events.len() {
         0 => vec![inner_fd_event_readable(src_fd, dst_fd, buffer, maybe_log)],
@@ -319,12 +400,15 @@ fn process_events_for_fd(
 
 /// Handles a readable event for a file descriptor.
 ///
+/// Each chunk is logged before it is written to the destination, so that anything the other end
+/// does in response to it is observed, and sequenced in a shared timeline, after it.
+///
 /// # Arguments
 ///
 /// * `src_fd` - Source to read from.
 /// * `dst_fd` - Destination to write to.
 /// * `buffer` - Buffer for data transfer.
-/// * `maybe_log` - Optional log writer.
+/// * `maybe_log` - Optional log.
 ///
 /// # Returns
 ///
@@ -333,7 +417,7 @@ fn inner_fd_event_readable(
     src_fd: &mut impl Read,
     dst_fd: &mut impl Write,
     buffer: &mut [u8],
-    maybe_log: &mut Option<impl Write>,
+    maybe_log: &mut Option<impl ChunkLog>,
 ) -> Result<ProcessEventsForFdSuccess, ProcessEventsForFdError> {
     // Keep reading from the source fd until we get a `WouldBlock`.
     loop {
@@ -345,12 +429,28 @@ fn inner_fd_event_readable(
             Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
                 return Ok(ProcessEventsForFdSuccess::DataLogged);
             }
+            // This is how a pseudo-terminal reports that everything attached to it closed it,
+            // instead of reaching its end.
+            Err(e) if e.raw_os_error() == Some(nix::libc::EIO) => {
+                return Ok(ProcessEventsForFdSuccess::Eof);
+            }
             Err(e) => {
                 return Err(ProcessEventsForFdError::Read(e));
             }
         };
 
-        match dst_fd.write_all(&buffer[..bytes_read]) {
+        // A failure to log must not prevent the data from reaching its destination, so we only
+        // report it after the data was written.
+        let log_result = maybe_log
+            .as_mut()
+            .map_or(Ok(()), |log| log.log_chunk(&buffer[..bytes_read]));
+
+        // Stdout is line-buffered, so without flushing, a prompt that doesn't end in a newline,
+        // such as a password prompt, would only be seen once it was answered.
+        match dst_fd
+            .write_all(&buffer[..bytes_read])
+            .and_then(|()| dst_fd.flush())
+        {
             Ok(()) => (),
             Err(e) if e.kind() == io::ErrorKind::BrokenPipe => {
                 return Ok(ProcessEventsForFdSuccess::Eof);
@@ -360,10 +460,8 @@ fn inner_fd_event_readable(
             }
         }
 
-        if let Some(log) = maybe_log {
-            if let Err(e) = log.write_all(&buffer[..bytes_read]) {
-                return Err(ProcessEventsForFdError::Log(e));
-            }
+        if let Err(e) = log_result {
+            return Err(ProcessEventsForFdError::Log(e));
         }
     }
 }
@@ -388,7 +486,10 @@ mod tests {
                 }
                 Some(Err(e)) => {
                     self.current += 1;
-                    Err(io::Error::new(e.kind(), e.to_string()))
+                    Err(e.raw_os_error().map_or_else(
+                        || io::Error::new(e.kind(), e.to_string()),
+                        io::Error::from_raw_os_error,
+                    ))
                 }
                 None => Ok(0), // EOF when no more responses.
             }
@@ -441,6 +542,12 @@ mod tests {
         }
     }
 
+    impl ChunkLog for MockWrite {
+        fn log_chunk(&mut self, chunk: &[u8]) -> io::Result<()> {
+            self.write_all(chunk)
+        }
+    }
+
     mod create_log_file {
         use super::*;
         use std::fs;
@@ -449,7 +556,11 @@ mod tests {
 
         #[test]
         fn none() {
-            assert!(create_log_file(None, false).unwrap().is_none());
+            assert!(
+                create_log_file(None, None, false, Compression::Auto, None, None)
+                    .unwrap()
+                    .is_none()
+            );
         }
 
         #[test]
@@ -457,12 +568,45 @@ mod tests {
             let temp_dir = TempDir::new().unwrap();
             let log_path = temp_dir.path().join("nested/dirs/test.log");
 
-            let result = create_log_file(Some(&log_path), false).unwrap();
+            let result =
+                create_log_file(Some(&log_path), None, false, Compression::Auto, None, None)
+                    .unwrap();
 
             assert!(result.is_some());
             assert!(log_path.exists());
         }
 
+        #[test]
+        fn in_log_dir() {
+            let temp_dir = TempDir::new().unwrap();
+            let log_dir = temp_dir.path().join("session");
+            let absolute_log_path = temp_dir.path().join("absolute.log");
+
+            create_log_file(
+                Some(&PathBuf::from("test.log")),
+                Some(&log_dir),
+                false,
+                Compression::Auto,
+                None,
+                None,
+            )
+            .unwrap()
+            .unwrap();
+            create_log_file(
+                Some(&absolute_log_path),
+                Some(&log_dir),
+                false,
+                Compression::Auto,
+                None,
+                None,
+            )
+            .unwrap()
+            .unwrap();
+
+            assert!(log_dir.join("test.log").exists());
+            assert!(absolute_log_path.exists());
+        }
+
         #[test]
         fn existing_file_appends() {
             let temp_dir = TempDir::new().unwrap();
@@ -470,7 +614,10 @@ mod tests {
 
             fs::write(&log_path, "initial content").unwrap();
 
-            let mut file = create_log_file(Some(&log_path), false).unwrap().unwrap();
+            let mut file =
+                create_log_file(Some(&log_path), None, false, Compression::Auto, None, None)
+                    .unwrap()
+                    .unwrap();
             file.write_all(b"appended content").unwrap();
             drop(file);
 
@@ -485,7 +632,10 @@ mod tests {
 
             fs::write(&log_path, "initial content").unwrap();
 
-            let mut file = create_log_file(Some(&log_path), true).unwrap().unwrap();
+            let mut file =
+                create_log_file(Some(&log_path), None, true, Compression::Auto, None, None)
+                    .unwrap()
+                    .unwrap();
             file.write_all(b"new content").unwrap();
             drop(file);
 
@@ -493,13 +643,80 @@ mod tests {
             assert_eq!(content, "new content");
         }
 
+        #[test]
+        fn compressed() {
+            let temp_dir = TempDir::new().unwrap();
+            let log_path = temp_dir.path().join("test.log.gz");
+
+            let mut file =
+                create_log_file(Some(&log_path), None, false, Compression::Auto, None, None)
+                    .unwrap()
+                    .unwrap();
+            file.write_all(b"compressed content").unwrap();
+            file.finish().unwrap();
+            drop(file);
+
+            let mut content = String::new();
+            flate2::read::GzDecoder::new(fs::File::open(&log_path).unwrap())
+                .read_to_string(&mut content)
+                .unwrap();
+            assert_eq!(content, "compressed content");
+        }
+
+        #[test]
+        fn compression_disabled() {
+            let temp_dir = TempDir::new().unwrap();
+            let log_path = temp_dir.path().join("test.log.gz");
+
+            let mut file =
+                create_log_file(Some(&log_path), None, false, Compression::None, None, None)
+                    .unwrap()
+                    .unwrap();
+            file.write_all(b"plain content").unwrap();
+            drop(file);
+
+            let content = fs::read_to_string(&log_path).unwrap();
+            assert_eq!(content, "plain content");
+        }
+
+        #[test]
+        fn rotated() {
+            let temp_dir = TempDir::new().unwrap();
+            let log_path = temp_dir.path().join("test.log");
+
+            fs::write(&log_path, "initial content").unwrap();
+
+            let mut file = create_log_file(
+                Some(&log_path),
+                None,
+                false,
+                Compression::Auto,
+                Some(Rotation {
+                    max_size: Some(20),
+                    interval: None,
+                    max_files: 1,
+                }),
+                None,
+            )
+            .unwrap()
+            .unwrap();
+            file.write_all(b"appended content").unwrap();
+            drop(file);
+
+            assert_eq!(fs::read_to_string(&log_path).unwrap(), "appended content");
+            assert_eq!(
+                fs::read_to_string(temp_dir.path().join("test.log.1")).unwrap(),
+                "initial content"
+            );
+        }
+
         #[test]
         fn permission_error() {
             let temp_dir = TempDir::new().unwrap();
             fs::set_permissions(temp_dir.path(), fs::Permissions::from_mode(0o444)).unwrap();
             let log_path = temp_dir.path().join("test.log");
 
-            match create_log_file(Some(&log_path), false) {
+            match create_log_file(Some(&log_path), None, false, Compression::Auto, None, None) {
                 Ok(_) => panic!("Expected an error"),
                 Err(e) => assert!(e.to_string().contains("Failed to create/open log file")),
             }
@@ -526,6 +743,12 @@ mod tests {
             }
         }
 
+        impl ChunkLog for RefCellWriter {
+            fn log_chunk(&mut self, chunk: &[u8]) -> io::Result<()> {
+                self.write_all(chunk)
+            }
+        }
+
         #[test]
         fn success() {
             let src = MockRead {
@@ -545,17 +768,24 @@ mod tests {
 
             let dst = Rc::new(RefCell::new(dst));
             let log_file = Rc::new(RefCell::new(log_file));
+            let stats = StreamStats::default();
 
             process_fd(
                 src,
                 RefCellWriter(dst.clone()),
                 1024,
                 Some(RefCellWriter(log_file.clone())),
+                &stats,
                 "test",
                 None,
             )
             .unwrap();
 
+            let summary = stats.summary();
+            assert_eq!(summary.bytes, 8);
+            assert_eq!(summary.chunks, 2);
+            assert_eq!(summary.max_chunk, 5);
+
             assert_eq!(dst.borrow().written_data.len(), 2);
             assert_eq!(dst.borrow().written_data[0].len(), 5);
             assert_eq!(dst.borrow().written_data[1].len(), 3);
@@ -585,6 +815,12 @@ mod tests {
             set_up_poll(&file, Some(&signal_rx), "test").unwrap();
         }
 
+        #[test]
+        fn regular_file() {
+            let file = tempfile::tempfile().unwrap();
+            set_up_poll(&file, None, "test").unwrap();
+        }
+
         fn create_file_from_pipe() -> File {
             let (_, tx) = pipe().unwrap();
             // Convert to `File`.
@@ -776,6 +1012,22 @@ mod tests {
             assert_eq!(log_file.as_ref().unwrap().written_data[0].len(), 5);
         }
 
+        #[test]
+        fn flushes_partial_lines() {
+            let mut src = MockRead {
+                responses: vec![Ok(5), Err(Error::new(ErrorKind::WouldBlock, "would block"))],
+                current: 0,
+            };
+            let mut dst = io::LineWriter::new(Vec::new());
+
+            let mut buffer = vec![0; 1024];
+            assert!(matches!(
+                inner_fd_event_readable(&mut src, &mut dst, &mut buffer, &mut None::<MockWrite>),
+                Ok(ProcessEventsForFdSuccess::DataLogged)
+            ));
+            assert_eq!(dst.get_ref().len(), 5);
+        }
+
         #[test]
         fn success_without_log() {
             let mut src = MockRead {
@@ -820,6 +1072,27 @@ mod tests {
             ));
         }
 
+        #[test]
+        fn closed_pseudo_terminal_on_read() {
+            let mut src = MockRead {
+                responses: vec![Err(Error::from_raw_os_error(nix::libc::EIO))],
+                current: 0,
+            };
+            let mut dst = MockWrite {
+                responses: vec![],
+                current: 0,
+                written_data: vec![],
+            };
+
+            let mut buffer = vec![0; 1024];
+            let mut log_file: Option<MockWrite> = None;
+
+            assert!(matches!(
+                inner_fd_event_readable(&mut src, &mut dst, &mut buffer, &mut log_file),
+                Ok(ProcessEventsForFdSuccess::Eof)
+            ));
+        }
+
         #[test]
         fn error_on_read() {
             let mut src = MockRead {
@@ -906,6 +1179,9 @@ mod tests {
                 inner_fd_event_readable(&mut src, &mut dst, &mut buffer, &mut log_file),
                 Err(ProcessEventsForFdError::Log(_))
             ));
+            // The data still reaches its destination.
+            assert_eq!(dst.written_data.len(), 1);
+            assert_eq!(dst.written_data[0].len(), 5);
         }
     }
 }
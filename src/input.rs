@@ -0,0 +1,261 @@
+//! Sources of the stdin of the target command.
+//!
+//! This module provides functionality for feeding the stdin of the target command from wherever
+//! it was configured to come from: the stdin of fdintercept, a file such as a FIFO or `/dev/null`,
+//! text given inline, or a recorded stdin log that is replayed. Whatever it comes from, it is
+//! relayed and logged the same way.
+
+use crate::fd;
+use crate::log::ChunkLog;
+use crate::replay::Recording;
+use crate::settings::{Mode, ResolvedSettings, StdinSource};
+use crate::stats::StreamStats;
+use anyhow::{Context, Result};
+use nix::errno::Errno;
+use nix::poll::{self, PollFd, PollFlags, PollTimeout};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::os::fd::{AsFd, OwnedFd};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+
+/// What the stdin of the target command is fed from.
+pub enum Input {
+    /// The stdin of fdintercept.
+    Stdin,
+    /// A file, such as a regular file, a FIFO, or `/dev/null`.
+    File(File),
+    /// Input that is fed as it is, or with its original timing, such as a recording or text given
+    /// inline.
+    Recording(Recording),
+}
+
+impl Input {
+    /// Opens whatever the stdin of the target command is fed from.
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - The settings of the session.
+    ///
+    /// # Returns
+    ///
+    /// Returns what the stdin of the target command is fed from.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the recording, or the file, can't be opened.
+    pub fn open(settings: &ResolvedSettings) -> Result<Self> {
+        if let Mode::Replay {
+            input,
+            timing,
+            speed,
+        } = &settings.mode
+        {
+            return Ok(Self::Recording(Recording::open(
+                input,
+                timing.as_deref(),
+                *speed,
+            )?));
+        }
+        Ok(match &settings.stdin_source {
+            None => Self::Stdin,
+            Some(StdinSource::Path(path)) => Self::File(
+                open_file(path)
+                    .with_context(|| format!("Failed to open stdin source: {}", path.display()))?,
+            ),
+            Some(StdinSource::Text { text }) => Self::Recording(Recording::text(text)),
+        })
+    }
+
+    /// Whether the input is typed in the terminal, rather than read from somewhere else.
+    ///
+    /// # Returns
+    ///
+    /// Returns `true` if the input is the stdin of fdintercept.
+    pub const fn is_stdin(&self) -> bool {
+        matches!(self, Self::Stdin)
+    }
+
+    /// Feeds the input into a destination until it ends, or fdintercept is told to stop.
+    ///
+    /// # Arguments
+    ///
+    /// * `dst` - Destination to feed the input into, usually the stdin of the target command.
+    /// * `buffer_size` - Size of the buffer in bytes used for data transfer.
+    /// * `maybe_log` - Optional log for recording the fed data.
+    /// * `stats` - Counters of stdin, updated with every read from the input.
+    /// * `signal_rx` - File descriptor that becomes readable once fdintercept should stop.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` when all the input was fed, when the destination was closed before that,
+    /// or when fdintercept was told to stop.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the input can't be read, or if the destination can't be written to.
+    pub fn feed(
+        self,
+        dst: impl Write,
+        buffer_size: usize,
+        maybe_log: Option<impl ChunkLog>,
+        stats: &StreamStats,
+        signal_rx: OwnedFd,
+    ) -> Result<()> {
+        match self {
+            Self::Stdin => fd::process_fd(
+                io::stdin(),
+                dst,
+                buffer_size,
+                maybe_log,
+                stats,
+                "stdin",
+                Some(signal_rx),
+            ),
+            Self::File(file) => {
+                if wait_until_readable(&file, &signal_rx)
+                    .context("Error waiting for stdin source")?
+                {
+                    return fd::finish_log(maybe_log, "stdin");
+                }
+                fd::process_fd(
+                    file,
+                    dst,
+                    buffer_size,
+                    maybe_log,
+                    stats,
+                    "stdin",
+                    Some(signal_rx),
+                )
+            }
+            Self::Recording(recording) => {
+                recording.feed(dst, buffer_size, maybe_log, stats, signal_rx)
+            }
+        }
+    }
+}
+
+/// Opens a file to read from, without blocking.
+///
+/// Opening a FIFO for reading would otherwise wait for a writer to open it, and fdintercept
+/// couldn't be told to stop in the meantime.
+///
+/// # Arguments
+///
+/// * `path` - Path to the file.
+///
+/// # Returns
+///
+/// Returns the opened file, which is non-blocking.
+///
+/// # Errors
+///
+/// Returns an error if the file can't be opened.
+fn open_file(path: &Path) -> io::Result<File> {
+    OpenOptions::new()
+        .read(true)
+        .custom_flags(nix::libc::O_NONBLOCK)
+        .open(path)
+}
+
+/// Waits until a file can be read from, or fdintercept is told to stop.
+///
+/// A FIFO without a writer reads as if it ended, so this waits for a writer to write to it, or
+/// to close it, before it is read from. Any other file can be read from right away.
+///
+/// # Arguments
+///
+/// * `file` - The file to wait for.
+/// * `signal_rx` - File descriptor that becomes readable once fdintercept should stop.
+///
+/// # Returns
+///
+/// Returns `true` if fdintercept was told to stop, or `false` if the file can be read from.
+///
+/// # Errors
+///
+/// Returns an error if polling failed.
+fn wait_until_readable(file: &File, signal_rx: &OwnedFd) -> io::Result<bool> {
+    let mut fds = [
+        PollFd::new(file.as_fd(), PollFlags::POLLIN),
+        PollFd::new(signal_rx.as_fd(), PollFlags::POLLIN),
+    ];
+    loop {
+        match poll::poll(&mut fds, PollTimeout::NONE) {
+            Ok(_) => return Ok(fds[1].any().unwrap_or_default()),
+            Err(Errno::EINTR) => (),
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nix::sys::stat::Mode as FileMode;
+    use std::io::Read;
+
+    /// A log that drops the chunks.
+    struct NoLog;
+
+    impl ChunkLog for NoLog {
+        fn log_chunk(&mut self, _chunk: &[u8]) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    mod feed {
+        use super::*;
+
+        #[test]
+        fn fifo_waits_for_writer() {
+            let tmp_dir = tempfile::TempDir::new().unwrap();
+            let path = tmp_dir.path().join("input.fifo");
+            nix::unistd::mkfifo(&path, FileMode::S_IRUSR | FileMode::S_IWUSR).unwrap();
+            let input = Input::File(open_file(&path).unwrap());
+            let (rx, tx) = nix::unistd::pipe().unwrap();
+            let (signal_rx, _signal_tx) = nix::unistd::pipe().unwrap();
+
+            let writer = std::thread::spawn(move || {
+                // The input would end right away if it didn't wait for the writer.
+                std::thread::sleep(std::time::Duration::from_millis(200));
+                let mut fifo = OpenOptions::new().write(true).open(path).unwrap();
+                fifo.write_all(b"hello\n").unwrap();
+            });
+            input
+                .feed(
+                    File::from(tx),
+                    1024,
+                    None::<NoLog>,
+                    &StreamStats::default(),
+                    signal_rx,
+                )
+                .unwrap();
+            writer.join().unwrap();
+
+            let mut contents = String::new();
+            File::from(rx).read_to_string(&mut contents).unwrap();
+            assert_eq!(contents, "hello\n");
+        }
+
+        #[test]
+        fn fifo_without_writer_stops() {
+            let tmp_dir = tempfile::TempDir::new().unwrap();
+            let path = tmp_dir.path().join("input.fifo");
+            nix::unistd::mkfifo(&path, FileMode::S_IRUSR | FileMode::S_IWUSR).unwrap();
+            let input = Input::File(open_file(&path).unwrap());
+            let (signal_rx, signal_tx) = nix::unistd::pipe().unwrap();
+            nix::unistd::write(&signal_tx, &[1]).unwrap();
+
+            input
+                .feed(
+                    io::sink(),
+                    1024,
+                    None::<NoLog>,
+                    &StreamStats::default(),
+                    signal_rx,
+                )
+                .unwrap();
+        }
+    }
+}
@@ -0,0 +1,2089 @@
+//! Log destinations for intercepted streams.
+//!
+//! This module provides the destinations that intercepted chunks are recorded into:
+//! - Per-stream logs, which record the chunks of a single stream, and
+//! - Shared logs, which record the chunks of several streams in the order they were observed,
+//!   along with the changes of the window size of the terminal:
+//!   - A timeline log, which tags every chunk with the name of the stream it came from, and
+//!   - An asciicast v2 recording, which can be played back with asciinema.
+//!
+//! How each chunk is rendered into the per-stream logs and the timeline log is determined by a
+//! [`RecordFormat`].
+//!
+//! The changes of the window size of the terminal are recorded into the shared logs, and into the
+//! per-stream logs whose format can tell them apart from the data of the stream.
+
+use crate::settings::{LinePrefix, LinePrefixPart, LogFormat, Target};
+use crate::terminal::WindowSize;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+/// A destination that log records are written to.
+pub trait LogSink: Write + Send {
+    /// Writes out anything buffered, and completes the destination, e.g., by writing the trailer
+    /// of a compressed file. Nothing is written to the destination afterwards.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the destination could not be completed.
+    fn finish(&mut self) -> io::Result<()> {
+        self.flush()
+    }
+}
+
+/// A writer that log records are written to.
+pub type LogWriter = Box<dyn LogSink>;
+
+/// A destination for chunks of data intercepted from a stream.
+pub trait ChunkLog {
+    /// Records a chunk of data, as it was read from the stream in a single read call.
+    ///
+    /// # Arguments
+    ///
+    /// * `chunk` - The data that was read from the stream.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the chunk could not be written to the underlying destination.
+    fn log_chunk(&mut self, chunk: &[u8]) -> io::Result<()>;
+
+    /// Records anything that was held back waiting for more data, once the stream has ended.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the held back data could not be written to the underlying destination.
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A log that the changes of the window size of the terminal are recorded into.
+pub trait ResizeLog: Send + Sync {
+    /// Records a change of the window size of the terminal.
+    ///
+    /// # Arguments
+    ///
+    /// * `wall` - Wall-clock time at which the window size changed.
+    /// * `mono` - Monotonic time at which the window size changed.
+    /// * `size` - The new window size.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the record could not be written to the underlying destination.
+    fn log_resize(&self, wall: SystemTime, mono: Duration, size: WindowSize) -> io::Result<()>;
+}
+
+/// Writes to each of several logs, going on even if writing to one of them fails, so that a
+/// failure in one of them doesn't leave a gap in the others.
+///
+/// # Arguments
+///
+/// * `logs` - The logs to write to.
+/// * `write` - Writes to a log.
+///
+/// # Returns
+///
+/// Returns `Ok(())` if writing to every log succeeded, or the first error otherwise.
+pub fn write_each<T>(
+    logs: impl IntoIterator<Item = T>,
+    mut write: impl FnMut(T) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut result = Ok(());
+    for log in logs {
+        result = result.and(write(log));
+    }
+    result
+}
+
+/// The clock that timestamps records, shared by all the logs of a session.
+///
+/// Monotonic timestamps are measured from the moment the clock was created, so they can be
+/// compared across streams to measure latencies without being affected by changes to the system
+/// clock.
+#[derive(Debug, Clone, Copy)]
+pub struct Clock {
+    /// The moment the session started.
+    start: Instant,
+    /// The wall-clock time at which the session started.
+    start_wall: SystemTime,
+}
+
+impl Clock {
+    /// Creates a clock whose monotonic timestamps start counting from now.
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            start_wall: SystemTime::now(),
+        }
+    }
+
+    /// Returns the wall-clock time at which the clock was created.
+    pub const fn started_at(&self) -> SystemTime {
+        self.start_wall
+    }
+
+    /// Reads the current time.
+    ///
+    /// # Returns
+    ///
+    /// Returns the current wall-clock time and the monotonic time elapsed since the clock was
+    /// created.
+    pub fn now(&self) -> (SystemTime, Duration) {
+        (SystemTime::now(), self.start.elapsed())
+    }
+}
+
+/// The format of the records in the per-stream logs and the timeline log, together with the
+/// options that fine-tune it.
+#[derive(Debug, Clone)]
+pub struct RecordFormat {
+    /// The format of the records.
+    pub kind: LogFormat,
+    /// Whether to mark the boundaries of the chunks in a hex dump.
+    pub hex_chunk_marks: bool,
+    /// Prefix of each line in the lines format.
+    pub line_prefix: LinePrefix,
+}
+
+/// A chunk of data together with everything known about when and where it was observed.
+struct Record<'a> {
+    /// Sequence number of the record within the log it is being written to.
+    seq: u64,
+    /// Name of the stream the chunk came from.
+    stream: &'a str,
+    /// Wall-clock time at which the chunk was observed.
+    wall: SystemTime,
+    /// Monotonic time, since the start of the session, at which the chunk was observed.
+    mono: Duration,
+    /// The data that was read from the stream.
+    data: &'a [u8],
+}
+
+/// Renders the records of a single stream into the bytes that are written to a log.
+trait Encoder {
+    /// Renders a record.
+    ///
+    /// # Arguments
+    ///
+    /// * `record` - The record to render.
+    /// * `out` - Buffer the rendered bytes are appended to.
+    fn encode(&mut self, record: &Record, out: &mut Vec<u8>);
+
+    /// Renders anything that was held back waiting for more data, once the stream has ended.
+    ///
+    /// # Arguments
+    ///
+    /// * `record` - A record without data, carrying the time at which the stream ended.
+    /// * `out` - Buffer the rendered bytes are appended to.
+    fn finish(&mut self, _record: &Record, _out: &mut Vec<u8>) {}
+
+    /// Creates an encoder that renders events that aren't chunks of the stream, such as the
+    /// changes of the window size, into the same log, as records of a stream of their own.
+    ///
+    /// Each event is rendered whole, by a fresh encoder, so that whatever this encoder holds back
+    /// waiting for more data is left as it is.
+    ///
+    /// # Returns
+    ///
+    /// Returns the encoder, or `None` if the format can't tell events apart from the data of the
+    /// stream.
+    fn event_encoder(&self) -> Option<Box<dyn Encoder + Send>> {
+        None
+    }
+}
+
+/// Renders only the data of each record, exactly as it was intercepted.
+struct RawEncoder;
+
+impl Encoder for RawEncoder {
+    fn encode(&mut self, record: &Record, out: &mut Vec<u8>) {
+        out.extend_from_slice(record.data);
+    }
+}
+
+/// Renders each record as a header line followed by the data and a newline.
+///
+/// The header carries the sequence number, the name of the stream, optionally the wall-clock and
+/// monotonic timestamps, and the length of the data in bytes, e.g.:
+///
+/// ```text
+/// [#0 stdin wall=2025-06-01T12:01:02.123456Z mono=0.001234 len=6]
+/// hello
+///
+/// ```
+struct FramedEncoder {
+    /// Whether to include timestamps in the header.
+    timestamps: bool,
+}
+
+impl Encoder for FramedEncoder {
+    fn encode(&mut self, record: &Record, out: &mut Vec<u8>) {
+        let header = if self.timestamps {
+            format!(
+                "[#{} {} wall={} mono={:.6} len={}]\n",
+                record.seq,
+                record.stream,
+                humantime::format_rfc3339_micros(record.wall),
+                record.mono.as_secs_f64(),
+                record.data.len()
+            )
+        } else {
+            format!(
+                "[#{} {} len={}]\n",
+                record.seq,
+                record.stream,
+                record.data.len()
+            )
+        };
+        out.extend_from_slice(header.as_bytes());
+        out.extend_from_slice(record.data);
+        out.push(b'\n');
+    }
+
+    fn event_encoder(&self) -> Option<Box<dyn Encoder + Send>> {
+        Some(Box::new(Self {
+            timestamps: self.timestamps,
+        }))
+    }
+}
+
+/// Renders the data of the records as an `xxd`-style dump, with the offset of each line within the
+/// stream, the bytes in hexadecimal, and the bytes as ASCII, e.g.:
+///
+/// ```text
+/// 00000000: 6865 6c6c 6f0a                           hello.
+/// ```
+///
+/// Without chunk marks, the dump is continuous, as if the whole stream had been dumped at once, so
+/// bytes that don't fill a line are held back until more data arrives or the stream ends. With
+/// chunk marks, each chunk starts on a new line, and is preceded by a header with its sequence
+/// number, the name of the stream, and its length in bytes:
+///
+/// ```text
+/// [#3 stdout len=6]
+/// 00000040: 6865 6c6c 6f0a                           hello.
+/// ```
+struct HexEncoder {
+    /// Whether to mark the boundaries of the chunks.
+    chunk_marks: bool,
+    /// Offset within the stream of the next byte that will be dumped.
+    offset: u64,
+    /// Bytes held back because they don't fill a line.
+    pending: Vec<u8>,
+}
+
+/// Number of bytes in each line of a hex dump.
+const HEX_LINE_WIDTH: usize = 16;
+
+impl HexEncoder {
+    /// Creates an encoder for a stream whose first byte is at offset 0.
+    ///
+    /// # Arguments
+    ///
+    /// * `chunk_marks` - Whether to mark the boundaries of the chunks.
+    const fn new(chunk_marks: bool) -> Self {
+        Self {
+            chunk_marks,
+            offset: 0,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Dumps bytes as lines of [`HEX_LINE_WIDTH`] bytes, the last of which may be shorter.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - The bytes to dump, starting at the current offset.
+    /// * `out` - Buffer the dump is appended to.
+    fn dump(&mut self, bytes: &[u8], out: &mut Vec<u8>) {
+        for line in bytes.chunks(HEX_LINE_WIDTH) {
+            let mut hex = String::with_capacity(HEX_LINE_WIDTH * 5 / 2);
+            for (i, byte) in line.iter().enumerate() {
+                if i > 0 && i % 2 == 0 {
+                    hex.push(' ');
+                }
+                // unwrap: Safe because writing to a `String` can't fail.
+                write!(hex, "{byte:02x}").unwrap();
+            }
+            let ascii: String = line
+                .iter()
+                .map(|&byte| {
+                    if byte.is_ascii_graphic() || byte == b' ' {
+                        char::from(byte)
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+            out.extend_from_slice(
+                format!(
+                    "{:08x}: {hex:<width$}  {ascii}\n",
+                    self.offset,
+                    width = HEX_LINE_WIDTH * 5 / 2 - 1
+                )
+                .as_bytes(),
+            );
+            self.offset += line.len() as u64;
+        }
+    }
+}
+
+impl Encoder for HexEncoder {
+    fn encode(&mut self, record: &Record, out: &mut Vec<u8>) {
+        if self.chunk_marks {
+            out.extend_from_slice(
+                format!(
+                    "[#{} {} len={}]\n",
+                    record.seq,
+                    record.stream,
+                    record.data.len()
+                )
+                .as_bytes(),
+            );
+            self.dump(record.data, out);
+            return;
+        }
+
+        self.pending.extend_from_slice(record.data);
+        let complete = self.pending.len() - self.pending.len() % HEX_LINE_WIDTH;
+        let pending = std::mem::take(&mut self.pending);
+        self.dump(&pending[..complete], out);
+        self.pending = pending[complete..].to_vec();
+    }
+
+    fn finish(&mut self, _record: &Record, out: &mut Vec<u8>) {
+        let pending = std::mem::take(&mut self.pending);
+        self.dump(&pending, out);
+    }
+}
+
+/// Renders the data of the records as complete lines, each preceded by a prefix, e.g.:
+///
+/// ```text
+/// [stdout 12:01:02.123] Echo: hello
+/// ```
+///
+/// Chunks may split lines arbitrarily, so the beginning of a line is held back until its newline
+/// arrives or the stream ends. The times in the prefix are the times at which the lines started.
+/// A line that is still incomplete when the stream ends is completed with a newline.
+struct LinesEncoder {
+    /// Prefix of each line.
+    prefix: LinePrefix,
+    /// The beginning of the current line, held back until its newline arrives.
+    pending: Vec<u8>,
+    /// The wall-clock and monotonic times at which the current line started, if it started.
+    maybe_started: Option<(SystemTime, Duration)>,
+}
+
+impl LinesEncoder {
+    /// Creates an encoder that precedes each line with `prefix`.
+    ///
+    /// # Arguments
+    ///
+    /// * `prefix` - Prefix of each line.
+    const fn new(prefix: LinePrefix) -> Self {
+        Self {
+            prefix,
+            pending: Vec::new(),
+            maybe_started: None,
+        }
+    }
+
+    /// Renders the current line, preceded by its prefix.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream` - Name of the stream the line came from.
+    /// * `end` - The end of the line, which completes the bytes held back.
+    /// * `out` - Buffer the line is appended to.
+    fn write_line(&mut self, stream: &str, end: &[u8], out: &mut Vec<u8>) {
+        // unwrap: Safe because a line is only ever written after it started.
+        let (wall, mono) = self.maybe_started.take().unwrap();
+        for part in &self.prefix.0 {
+            match part {
+                LinePrefixPart::Literal(text) => out.extend_from_slice(text.as_bytes()),
+                LinePrefixPart::Stream => out.extend_from_slice(stream.as_bytes()),
+                LinePrefixPart::Time => {
+                    // The RFC 3339 timestamp looks like `2025-06-01T12:01:02.123Z`.
+                    let timestamp = humantime::format_rfc3339_millis(wall).to_string();
+                    out.extend_from_slice(&timestamp.as_bytes()[11..23]);
+                }
+                LinePrefixPart::Timestamp => out.extend_from_slice(
+                    humantime::format_rfc3339_micros(wall)
+                        .to_string()
+                        .as_bytes(),
+                ),
+                LinePrefixPart::Mono => {
+                    out.extend_from_slice(format!("{:.6}", mono.as_secs_f64()).as_bytes());
+                }
+            }
+        }
+        out.append(&mut self.pending);
+        out.extend_from_slice(end);
+    }
+}
+
+impl Encoder for LinesEncoder {
+    fn encode(&mut self, record: &Record, out: &mut Vec<u8>) {
+        let mut data = record.data;
+        while !data.is_empty() {
+            if self.maybe_started.is_none() {
+                self.maybe_started = Some((record.wall, record.mono));
+            }
+            let Some(newline) = data.iter().position(|&byte| byte == b'\n') else {
+                self.pending.extend_from_slice(data);
+                break;
+            };
+            self.write_line(record.stream, &data[..=newline], out);
+            data = &data[newline + 1..];
+        }
+    }
+
+    fn finish(&mut self, record: &Record, out: &mut Vec<u8>) {
+        if self.maybe_started.is_some() {
+            self.write_line(record.stream, b"\n", out);
+        }
+    }
+
+    fn event_encoder(&self) -> Option<Box<dyn Encoder + Send>> {
+        Some(Box::new(Self::new(self.prefix.clone())))
+    }
+}
+
+/// Renders each record as a single-line JSON object, so that logs can be processed with JSON Lines
+/// tools, e.g.:
+///
+/// ```text
+/// {"seq":0,"stream":"stdin","timestamp":"2025-06-01T12:01:02.123456Z","monotonic":0.001234,"length":6,"encoding":"utf8","payload":"hello\n"}
+/// ```
+///
+/// The payload is the data as a string if it is valid UTF-8, or the data encoded in base64
+/// otherwise, as indicated by the `encoding` field.
+struct JsonlEncoder;
+
+/// The JSON object that [`JsonlEncoder`] renders for each record.
+#[derive(Serialize)]
+struct JsonlRecord<'a> {
+    /// Sequence number of the record.
+    seq: u64,
+    /// Name of the stream the chunk came from.
+    stream: &'a str,
+    /// Wall-clock time at which the chunk was observed, in RFC 3339 format.
+    timestamp: String,
+    /// Monotonic time at which the chunk was observed, in seconds since the start of the session.
+    monotonic: f64,
+    /// Length of the data in bytes.
+    length: usize,
+    /// How the payload is encoded, either `utf8` or `base64`.
+    encoding: &'static str,
+    /// The data, encoded as indicated by `encoding`.
+    payload: Cow<'a, str>,
+}
+
+impl Encoder for JsonlEncoder {
+    fn encode(&mut self, record: &Record, out: &mut Vec<u8>) {
+        let (encoding, payload) = match std::str::from_utf8(record.data) {
+            Ok(text) => ("utf8", Cow::Borrowed(text)),
+            Err(_) => ("base64", Cow::Owned(BASE64.encode(record.data))),
+        };
+        // unwrap: Safe because serializing a struct of strings and numbers into a `Vec` can't
+        // fail.
+        serde_json::to_writer(
+            &mut *out,
+            &JsonlRecord {
+                seq: record.seq,
+                stream: record.stream,
+                timestamp: humantime::format_rfc3339_micros(record.wall).to_string(),
+                monotonic: record.mono.as_secs_f64(),
+                length: record.data.len(),
+                encoding,
+                payload,
+            },
+        )
+        .unwrap();
+        out.push(b'\n');
+    }
+
+    fn event_encoder(&self) -> Option<Box<dyn Encoder + Send>> {
+        Some(Box::new(Self))
+    }
+}
+
+/// Creates the encoder that renders records in `format`.
+///
+/// # Arguments
+///
+/// * `format` - The format of the log.
+/// * `shared` - Whether the log is shared by several streams. A shared log always needs to tell
+///   the streams apart, so in that case raw data is framed with a header, and hex dumps always
+///   have chunk marks. In the lines format, it is up to the line prefix to tell them apart.
+///
+/// # Returns
+///
+/// Returns the encoder for the format.
+fn new_encoder(format: &RecordFormat, shared: bool) -> Box<dyn Encoder + Send> {
+    match format.kind {
+        LogFormat::Raw if !shared => Box::new(RawEncoder),
+        LogFormat::Raw => Box::new(FramedEncoder { timestamps: false }),
+        LogFormat::Timestamped => Box::new(FramedEncoder { timestamps: true }),
+        LogFormat::Jsonl => Box::new(JsonlEncoder),
+        LogFormat::Hex => Box::new(HexEncoder::new(format.hex_chunk_marks || shared)),
+        LogFormat::Lines => Box::new(LinesEncoder::new(format.line_prefix.clone())),
+    }
+}
+
+/// Renders each record as a timing line with the time elapsed since the previous record, in
+/// seconds, and the length of the data in bytes, e.g.:
+///
+/// ```text
+/// 0.512345 6
+/// ```
+///
+/// This is the same format that `script --timing` writes, so a log in the raw format and its
+/// timing file can be replayed with the original gaps between chunks. The first record is timed
+/// from the start of the session.
+struct TimingEncoder {
+    /// Monotonic time of the previous record.
+    last_mono: Duration,
+}
+
+impl Encoder for TimingEncoder {
+    fn encode(&mut self, record: &Record, out: &mut Vec<u8>) {
+        let delay = record.mono.saturating_sub(self.last_mono);
+        self.last_mono = record.mono;
+        out.extend_from_slice(
+            format!("{:.6} {}\n", delay.as_secs_f64(), record.data.len()).as_bytes(),
+        );
+    }
+}
+
+/// Renders records as the events of an asciicast v2 recording, e.g.:
+///
+/// ```text
+/// [0.001234, "o", "hello\r\n"]
+/// ```
+///
+/// The data of every event must be text, so a multi-byte UTF-8 character that was split across two
+/// chunks is held back until the rest of it arrives, and any bytes that are not valid UTF-8 are
+/// replaced with U+FFFD.
+struct AsciicastEncoder {
+    /// Event code of the stream, `o` for output or `i` for input.
+    code: &'static str,
+    /// Trailing bytes of the previous chunks that are the beginning of an incomplete character.
+    pending: Vec<u8>,
+}
+
+impl Encoder for AsciicastEncoder {
+    fn encode(&mut self, record: &Record, out: &mut Vec<u8>) {
+        self.pending.extend_from_slice(record.data);
+        let complete = self.pending.len() - incomplete_utf8_suffix_len(&self.pending);
+        if complete == 0 {
+            return;
+        }
+        let text = String::from_utf8_lossy(&self.pending[..complete]);
+        // unwrap: Safe because serializing a tuple of strings and numbers into a `Vec` can't
+        // fail.
+        serde_json::to_writer(&mut *out, &(record.mono.as_secs_f64(), self.code, text)).unwrap();
+        out.push(b'\n');
+        self.pending.drain(..complete);
+    }
+
+    fn finish(&mut self, record: &Record, out: &mut Vec<u8>) {
+        if self.pending.is_empty() {
+            return;
+        }
+        // The stream ended in the middle of a character, so it will never be completed.
+        let text = String::from_utf8_lossy(&self.pending);
+        // unwrap: Safe because serializing a tuple of strings and numbers into a `Vec` can't
+        // fail.
+        serde_json::to_writer(&mut *out, &(record.mono.as_secs_f64(), self.code, text)).unwrap();
+        out.push(b'\n');
+        self.pending.clear();
+    }
+}
+
+/// Measures the incomplete UTF-8 character at the end of some bytes.
+///
+/// # Arguments
+///
+/// * `bytes` - The bytes to inspect.
+///
+/// # Returns
+///
+/// Returns the number of trailing bytes that are the beginning of a multi-byte character whose
+/// remaining bytes are missing, or 0 if the bytes don't end in the middle of a character.
+fn incomplete_utf8_suffix_len(bytes: &[u8]) -> usize {
+    for back in 1..=bytes.len().min(3) {
+        let byte = bytes[bytes.len() - back];
+        // Skip continuation bytes until we find the byte that starts the last character.
+        if byte & 0b1100_0000 == 0b1000_0000 {
+            continue;
+        }
+        let needed = match byte {
+            0xc0..=0xdf => 2,
+            0xe0..=0xef => 3,
+            0xf0..=0xf7 => 4,
+            _ => 1,
+        };
+        return if needed > back { back } else { 0 };
+    }
+    0
+}
+
+/// The header line of an asciicast v2 recording.
+#[derive(Serialize)]
+struct AsciicastHeader<'a> {
+    /// Version of the asciicast format.
+    version: u8,
+    /// Width of the terminal, in columns.
+    width: u16,
+    /// Height of the terminal, in rows.
+    height: u16,
+    /// Time at which the recording started, in seconds since the Unix epoch.
+    timestamp: u64,
+    /// The command that was recorded.
+    command: String,
+    /// Environment variables that describe the terminal the command was run in.
+    env: BTreeMap<&'a str, String>,
+}
+
+/// A log shared by several streams that records chunks in the order they were observed.
+///
+/// Every chunk gets a sequence number that is unique across all the streams sharing the log. The
+/// sequence number is assigned under the same lock that serializes writes to the underlying
+/// writer, so the order of the records in the log always matches their sequence numbers. For the
+/// same reason, the monotonic timestamps in the log never go backwards: a chunk that was observed
+/// just before another one, but that lost the race for the lock, is recorded at the time of the
+/// chunk before it.
+pub struct SharedLog {
+    /// What kind of shared log this is.
+    kind: SharedLogKind,
+    /// The underlying writer and the sequencing state, guarded together.
+    state: Mutex<SharedLogState>,
+}
+
+/// The kinds of [`SharedLog`].
+enum SharedLogKind {
+    /// A timeline of every chunk of every stream, in the given format.
+    Timeline(RecordFormat),
+    /// An asciicast v2 recording of the output streams, and optionally of stdin.
+    Asciicast {
+        /// Whether to record stdin as input events.
+        include_stdin: bool,
+    },
+}
+
+/// The mutable state of a [`SharedLog`].
+struct SharedLogState {
+    /// Where the records are written to.
+    writer: LogWriter,
+    /// Sequence number that will be assigned to the next record.
+    next_seq: u64,
+    /// Monotonic timestamp of the last record.
+    last_mono: Duration,
+}
+
+impl SharedLog {
+    /// Creates a new timeline that writes its records to `writer`.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - Where the records of the timeline will be written to.
+    /// * `format` - Format of the records in the timeline.
+    pub fn timeline(writer: LogWriter, format: RecordFormat) -> Self {
+        Self::new(writer, SharedLogKind::Timeline(format))
+    }
+
+    /// Creates a new asciicast v2 recording that writes its events to `writer`, and writes the
+    /// header of the recording.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - Where the recording will be written to.
+    /// * `clock` - Clock that timestamps the chunks, whose start is the start of the recording.
+    /// * `target` - The command being recorded.
+    /// * `size` - Size of the terminal the command is being run in.
+    /// * `include_stdin` - Whether to record stdin as input events.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the target command can't be quoted, or if the header could not be
+    /// written to `writer`.
+    pub fn asciicast(
+        mut writer: LogWriter,
+        clock: Clock,
+        target: &Target,
+        size: WindowSize,
+        include_stdin: bool,
+    ) -> io::Result<Self> {
+        let command = shlex::try_join(
+            std::iter::once(target.executable.as_str())
+                .chain(target.args.iter().map(String::as_str)),
+        )
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let header = AsciicastHeader {
+            version: 2,
+            width: size.cols,
+            height: size.rows,
+            timestamp: clock
+                .started_at()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map_or(0, |since_epoch| since_epoch.as_secs()),
+            command,
+            env: ["SHELL", "TERM"]
+                .into_iter()
+                .filter_map(|name| std::env::var(name).ok().map(|value| (name, value)))
+                .collect(),
+        };
+        let mut line = serde_json::to_vec(&header)?;
+        line.push(b'\n');
+        writer.write_all(&line)?;
+        Ok(Self::new(
+            writer,
+            SharedLogKind::Asciicast { include_stdin },
+        ))
+    }
+
+    /// Creates a new shared log of any kind.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - Where the records of the log will be written to.
+    /// * `kind` - What kind of shared log to create.
+    fn new(writer: LogWriter, kind: SharedLogKind) -> Self {
+        Self {
+            kind,
+            state: Mutex::new(SharedLogState {
+                writer,
+                next_seq: 0,
+                last_mono: Duration::ZERO,
+            }),
+        }
+    }
+
+    /// Creates the encoder that renders the records of a stream into this log.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream` - Name of the stream.
+    ///
+    /// # Returns
+    ///
+    /// Returns the encoder for the stream, or `None` if this log doesn't record the stream.
+    fn new_encoder(&self, stream: &str) -> Option<Box<dyn Encoder + Send>> {
+        match &self.kind {
+            SharedLogKind::Timeline(format) => Some(new_encoder(format, true)),
+            &SharedLogKind::Asciicast { include_stdin } => {
+                // Additional file descriptors are never seen on screen, so they aren't recorded.
+                let code = match stream {
+                    "stdin" if include_stdin => "i",
+                    "stdout" | "stderr" => "o",
+                    _ => return None,
+                };
+                Some(Box::new(AsciicastEncoder {
+                    code,
+                    pending: Vec::new(),
+                }))
+            }
+        }
+    }
+
+    /// Completes the log, once all the streams it records have ended.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the underlying writer could not be completed.
+    pub fn finish(&self) -> io::Result<()> {
+        // unwrap: Safe because the lock is only ever held while writing a record, and writing
+        // doesn't panic.
+        self.state.lock().unwrap().writer.finish()
+    }
+
+    /// Writes a record for a chunk of data to the log.
+    ///
+    /// # Arguments
+    ///
+    /// * `encoder` - The encoder of the stream the chunk came from.
+    /// * `stream` - Name of the stream the chunk came from.
+    /// * `wall` - Wall-clock time at which the chunk was observed.
+    /// * `mono` - Monotonic time at which the chunk was observed.
+    /// * `data` - The data that was read from the stream.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the record could not be written to the underlying writer.
+    fn write_record(
+        &self,
+        encoder: &mut dyn Encoder,
+        stream: &str,
+        wall: SystemTime,
+        mono: Duration,
+        data: &[u8],
+    ) -> io::Result<()> {
+        self.write(stream, wall, mono, data, |record, out| {
+            encoder.encode(record, out);
+        })
+    }
+
+    /// Writes whatever the encoder of a stream held back, once the stream has ended.
+    ///
+    /// # Arguments
+    ///
+    /// * `encoder` - The encoder of the stream that ended.
+    /// * `stream` - Name of the stream that ended.
+    /// * `wall` - Wall-clock time at which the stream ended.
+    /// * `mono` - Monotonic time at which the stream ended.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the held back data could not be written to the underlying writer.
+    fn finish_stream(
+        &self,
+        encoder: &mut dyn Encoder,
+        stream: &str,
+        wall: SystemTime,
+        mono: Duration,
+    ) -> io::Result<()> {
+        self.write(stream, wall, mono, &[], |record, out| {
+            encoder.finish(record, out);
+        })
+    }
+
+    /// Renders a record under the lock, and writes it to the log.
+    ///
+    /// The record only takes up a sequence number if it was rendered into something, or if it
+    /// carries data.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream` - Name of the stream the record belongs to.
+    /// * `wall` - Wall-clock time of the record.
+    /// * `mono` - Monotonic time of the record.
+    /// * `data` - The data of the record.
+    /// * `render` - Renders the record.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the record could not be written to the underlying writer.
+    fn write(
+        &self,
+        stream: &str,
+        wall: SystemTime,
+        mono: Duration,
+        data: &[u8],
+        render: impl FnOnce(&Record, &mut Vec<u8>),
+    ) -> io::Result<()> {
+        // unwrap: Safe because the lock is only ever held while writing a record, and writing
+        // doesn't panic.
+        let mut state = self.state.lock().unwrap();
+
+        let mono = mono.max(state.last_mono);
+        let mut out = Vec::new();
+        render(
+            &Record {
+                seq: state.next_seq,
+                stream,
+                wall,
+                mono,
+                data,
+            },
+            &mut out,
+        );
+        if out.is_empty() && data.is_empty() {
+            return Ok(());
+        }
+        state.writer.write_all(&out)?;
+
+        state.next_seq += 1;
+        state.last_mono = mono;
+        drop(state);
+        Ok(())
+    }
+}
+
+impl ResizeLog for SharedLog {
+    /// In a timeline, the change is recorded like a chunk of a `resize` stream, whose data is the
+    /// new size, e.g., `100x30`. In an asciicast recording, it is recorded as a resize event.
+    fn log_resize(&self, wall: SystemTime, mono: Duration, size: WindowSize) -> io::Result<()> {
+        let data = format!("{}x{}", size.cols, size.rows);
+        match &self.kind {
+            SharedLogKind::Timeline(format) => {
+                // The change is a record of its own, that nothing comes after, so anything an
+                // encoder would hold back waiting for more data is written out right away.
+                let mut encoder = new_encoder(format, true);
+                self.write("resize", wall, mono, data.as_bytes(), |record, out| {
+                    encoder.encode(record, out);
+                    encoder.finish(record, out);
+                })
+            }
+            SharedLogKind::Asciicast { .. } => {
+                self.write("resize", wall, mono, data.as_bytes(), |record, out| {
+                    // unwrap: Safe because serializing a tuple of strings and numbers into a `Vec`
+                    // can't fail.
+                    serde_json::to_writer(&mut *out, &(record.mono.as_secs_f64(), "r", &data))
+                        .unwrap();
+                    out.push(b'\n');
+                })
+            }
+        }
+    }
+}
+
+/// All the logs that the chunks of a single stream are recorded into.
+pub struct StreamLog {
+    /// Name of the stream.
+    stream: &'static str,
+    /// Clock that timestamps the chunks.
+    clock: Clock,
+    /// Files that record only the chunks of this stream: the log file and the timing file, if
+    /// enabled. They are shared with the [`StreamResizeLog`] of the stream, if any.
+    files: Vec<Arc<Mutex<FileLog>>>,
+    /// The shared logs that record this stream, each with this stream's encoder for it.
+    shared: Vec<(Arc<SharedLog>, Box<dyn Encoder + Send>)>,
+}
+
+/// A file that records the chunks of a single stream.
+struct FileLog {
+    /// Where the records are written to.
+    writer: LogWriter,
+    /// Renders the records.
+    encoder: Box<dyn Encoder + Send>,
+    /// Sequence number that will be assigned to the next record.
+    next_seq: u64,
+}
+
+impl StreamLog {
+    /// Combines the logs of a stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream` - Name of the stream, which is attached to its records where the format calls
+    ///   for it.
+    /// * `clock` - Clock that timestamps the chunks.
+    /// * `maybe_file` - Optional log file that records only the chunks of this stream.
+    /// * `format` - Format of the records in the log file.
+    /// * `maybe_timing` - Optional timing file that records when each chunk of this stream was
+    ///   observed, and how long it was.
+    /// * `shared_logs` - Logs shared with other streams. Those that don't record this stream are
+    ///   ignored.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Some(StreamLog)` if at least one of the logs records the stream, or `None` if the
+    /// stream isn't being logged at all.
+    pub fn new(
+        stream: &'static str,
+        clock: Clock,
+        maybe_file: Option<LogWriter>,
+        format: &RecordFormat,
+        maybe_timing: Option<LogWriter>,
+        shared_logs: &[Arc<SharedLog>],
+    ) -> Option<Self> {
+        let shared: Vec<_> = shared_logs
+            .iter()
+            .filter_map(|log| {
+                log.new_encoder(stream)
+                    .map(|encoder| (log.clone(), encoder))
+            })
+            .collect();
+        let files: Vec<_> = maybe_file
+            .map(|writer| (writer, new_encoder(format, false)))
+            .into_iter()
+            .chain(maybe_timing.map(|writer| {
+                let encoder: Box<dyn Encoder + Send> = Box::new(TimingEncoder {
+                    last_mono: Duration::ZERO,
+                });
+                (writer, encoder)
+            }))
+            .map(|(writer, encoder)| {
+                Arc::new(Mutex::new(FileLog {
+                    writer,
+                    encoder,
+                    next_seq: 0,
+                }))
+            })
+            .collect();
+        if files.is_empty() && shared.is_empty() {
+            return None;
+        }
+        Some(Self {
+            stream,
+            clock,
+            files,
+            shared,
+        })
+    }
+
+    /// Returns the log that the changes of the window size are recorded into among the files of
+    /// this stream, i.e., its log file, if its format can tell them apart from the data of the
+    /// stream. The shared logs record the changes on their own.
+    ///
+    /// # Returns
+    ///
+    /// Returns the log, or `None` if no file of this stream can record the changes.
+    pub fn resize_log(&self) -> Option<StreamResizeLog> {
+        self.files
+            .iter()
+            // unwrap: Safe because the lock is only ever held while writing a record, and writing
+            // doesn't panic.
+            .find(|file| file.lock().unwrap().encoder.event_encoder().is_some())
+            .map(|file| StreamResizeLog(file.clone()))
+    }
+}
+
+/// The log file of a single stream, as the log that the changes of the window size are recorded
+/// into, like the chunks of a `resize` stream.
+pub struct StreamResizeLog(Arc<Mutex<FileLog>>);
+
+impl ResizeLog for StreamResizeLog {
+    fn log_resize(&self, wall: SystemTime, mono: Duration, size: WindowSize) -> io::Result<()> {
+        // unwrap: Safe because the lock is only ever held while writing a record, and writing
+        // doesn't panic.
+        let mut file = self.0.lock().unwrap();
+        // unwrap: Safe because a `StreamResizeLog` is only ever created for a file whose encoder
+        // has an event encoder.
+        let mut encoder = file.encoder.event_encoder().unwrap();
+        let data = format!("{}x{}", size.cols, size.rows);
+        let record = Record {
+            seq: file.next_seq,
+            stream: "resize",
+            wall,
+            mono,
+            data: data.as_bytes(),
+        };
+        let mut out = Vec::new();
+        encoder.encode(&record, &mut out);
+        encoder.finish(&record, &mut out);
+        file.next_seq += 1;
+        file.writer.write_all(&out)
+    }
+}
+
+impl ChunkLog for StreamLog {
+    fn log_chunk(&mut self, chunk: &[u8]) -> io::Result<()> {
+        let (wall, mono) = self.clock.now();
+
+        write_each(&self.files, |file| {
+            // unwrap: Safe because the lock is only ever held while writing a record, and writing
+            // doesn't panic.
+            let file = &mut *file.lock().unwrap();
+            let mut out = Vec::new();
+            file.encoder.encode(
+                &Record {
+                    seq: file.next_seq,
+                    stream: self.stream,
+                    wall,
+                    mono,
+                    data: chunk,
+                },
+                &mut out,
+            );
+            file.next_seq += 1;
+            file.writer.write_all(&out)
+        })
+        .and(write_each(&mut self.shared, |(log, encoder)| {
+            log.write_record(encoder.as_mut(), self.stream, wall, mono, chunk)
+        }))
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        let (wall, mono) = self.clock.now();
+
+        write_each(&self.files, |file| {
+            // unwrap: Safe because the lock is only ever held while writing a record, and writing
+            // doesn't panic.
+            let file = &mut *file.lock().unwrap();
+            let mut out = Vec::new();
+            file.encoder.finish(
+                &Record {
+                    seq: file.next_seq,
+                    stream: self.stream,
+                    wall,
+                    mono,
+                    data: &[],
+                },
+                &mut out,
+            );
+            file.writer
+                .write_all(&out)
+                .and_then(|()| file.writer.finish())
+        })
+        .and(write_each(&mut self.shared, |(log, encoder)| {
+            log.finish_stream(encoder.as_mut(), self.stream, wall, mono)
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A writer whose contents can be inspected after it was boxed into a [`LogWriter`].
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl SharedBuffer {
+        fn contents(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl LogSink for SharedBuffer {}
+
+    struct FailingWriter;
+
+    impl Write for FailingWriter {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::Error::other("write error"))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl LogSink for FailingWriter {}
+
+    fn format(kind: LogFormat) -> RecordFormat {
+        RecordFormat {
+            kind,
+            hex_chunk_marks: false,
+            line_prefix: LinePrefix(vec![
+                LinePrefixPart::Literal("[".to_string()),
+                LinePrefixPart::Stream,
+                LinePrefixPart::Literal("] ".to_string()),
+            ]),
+        }
+    }
+
+    fn record(data: &[u8]) -> Record {
+        Record {
+            seq: 3,
+            stream: "stdout",
+            wall: SystemTime::UNIX_EPOCH + Duration::from_micros(1_748_779_262_123_456),
+            mono: Duration::from_micros(1_234),
+            data,
+        }
+    }
+
+    mod raw_encoder {
+        use super::*;
+
+        #[test]
+        fn data_only() {
+            let mut out = Vec::new();
+            RawEncoder.encode(&record(b"hello\n"), &mut out);
+            assert_eq!(out, b"hello\n");
+        }
+    }
+
+    mod framed_encoder {
+        use super::*;
+
+        #[test]
+        fn without_timestamps() {
+            let mut out = Vec::new();
+            FramedEncoder { timestamps: false }.encode(&record(b"hello\n"), &mut out);
+            assert_eq!(out, b"[#3 stdout len=6]\nhello\n\n");
+        }
+
+        #[test]
+        fn with_timestamps() {
+            let mut out = Vec::new();
+            FramedEncoder { timestamps: true }.encode(&record(b"hello\n"), &mut out);
+            assert_eq!(
+                String::from_utf8(out).unwrap(),
+                "[#3 stdout wall=2025-06-01T12:01:02.123456Z mono=0.001234 len=6]\nhello\n\n"
+            );
+        }
+    }
+
+    mod hex_encoder {
+        use super::*;
+
+        #[test]
+        fn matches_xxd() {
+            let mut encoder = HexEncoder::new(false);
+            let mut out = Vec::new();
+            encoder.encode(&record(b"hello world, this is long\n"), &mut out);
+            encoder.finish(&record(b""), &mut out);
+            assert_eq!(
+                String::from_utf8(out).unwrap(),
+                "00000000: 6865 6c6c 6f20 776f 726c 642c 2074 6869  hello world, thi\n\
+                 00000010: 7320 6973 206c 6f6e 670a                 s is long.\n"
+            );
+        }
+
+        #[test]
+        fn continuous_across_chunks() {
+            let mut encoder = HexEncoder::new(false);
+            let mut out = Vec::new();
+            encoder.encode(&record(b"0123456789"), &mut out);
+            assert!(out.is_empty());
+            encoder.encode(&record(b"abcdefghij"), &mut out);
+            assert_eq!(
+                String::from_utf8(out.clone()).unwrap(),
+                "00000000: 3031 3233 3435 3637 3839 6162 6364 6566  0123456789abcdef\n"
+            );
+            out.clear();
+            encoder.finish(&record(b""), &mut out);
+            assert_eq!(
+                String::from_utf8(out).unwrap(),
+                "00000010: 6768 696a                                ghij\n"
+            );
+        }
+
+        #[test]
+        fn chunk_marks() {
+            let mut encoder = HexEncoder::new(true);
+            let mut out = Vec::new();
+            encoder.encode(&record(b"hi\n"), &mut out);
+            encoder.encode(&record(&[0x00, 0xff]), &mut out);
+            encoder.finish(&record(b""), &mut out);
+            assert_eq!(
+                String::from_utf8(out).unwrap(),
+                "[#3 stdout len=3]\n\
+                 00000000: 6869 0a                                  hi.\n\
+                 [#3 stdout len=2]\n\
+                 00000003: 00ff                                     ..\n"
+            );
+        }
+    }
+
+    mod lines_encoder {
+        use super::*;
+
+        fn encoder(prefix: Vec<LinePrefixPart>) -> LinesEncoder {
+            LinesEncoder::new(LinePrefix(prefix))
+        }
+
+        #[test]
+        fn complete_lines() {
+            let mut encoder = encoder(vec![
+                LinePrefixPart::Literal("[".to_string()),
+                LinePrefixPart::Stream,
+                LinePrefixPart::Literal(" ".to_string()),
+                LinePrefixPart::Time,
+                LinePrefixPart::Literal("] ".to_string()),
+            ]);
+            let mut out = Vec::new();
+            encoder.encode(&record(b"one\ntwo\n"), &mut out);
+            assert_eq!(
+                String::from_utf8(out).unwrap(),
+                "[stdout 12:01:02.123] one\n[stdout 12:01:02.123] two\n"
+            );
+        }
+
+        #[test]
+        fn partial_lines_are_held_back() {
+            let mut encoder = encoder(vec![
+                LinePrefixPart::Mono,
+                LinePrefixPart::Literal(" ".to_string()),
+            ]);
+            let mut out = Vec::new();
+            encoder.encode(&record(b"hel"), &mut out);
+            assert!(out.is_empty());
+            encoder.encode(
+                &Record {
+                    mono: Duration::from_secs(1),
+                    ..record(b"lo\nwor")
+                },
+                &mut out,
+            );
+            // The line is timed from when it started.
+            assert_eq!(String::from_utf8(out.clone()).unwrap(), "0.001234 hello\n");
+            out.clear();
+            encoder.finish(
+                &Record {
+                    mono: Duration::from_secs(2),
+                    ..record(b"")
+                },
+                &mut out,
+            );
+            assert_eq!(String::from_utf8(out).unwrap(), "1.000000 wor\n");
+        }
+
+        #[test]
+        fn nothing_to_finish() {
+            let mut encoder = encoder(vec![LinePrefixPart::Timestamp]);
+            let mut out = Vec::new();
+            encoder.encode(&record(b"done\n"), &mut out);
+            assert_eq!(
+                String::from_utf8(out.clone()).unwrap(),
+                "2025-06-01T12:01:02.123456Zdone\n"
+            );
+            out.clear();
+            encoder.finish(&record(b""), &mut out);
+            assert!(out.is_empty());
+        }
+    }
+
+    mod jsonl_encoder {
+        use super::*;
+
+        #[test]
+        fn utf8_payload() {
+            let mut out = Vec::new();
+            JsonlEncoder.encode(&record(b"hello \"world\"\n"), &mut out);
+            assert_eq!(
+                String::from_utf8(out).unwrap(),
+                "{\"seq\":3,\"stream\":\"stdout\",\"timestamp\":\"2025-06-01T12:01:02.123456Z\",\
+                 \"monotonic\":0.001234,\"length\":14,\"encoding\":\"utf8\",\
+                 \"payload\":\"hello \\\"world\\\"\\n\"}\n"
+            );
+        }
+
+        #[test]
+        fn binary_payload() {
+            let mut out = Vec::new();
+            JsonlEncoder.encode(&record(&[0xff, 0x00, 0x61]), &mut out);
+            let value: serde_json::Value = serde_json::from_slice(&out).unwrap();
+            assert_eq!(value["length"], 3);
+            assert_eq!(value["encoding"], "base64");
+            assert_eq!(value["payload"], "/wBh");
+        }
+
+        #[test]
+        fn one_line_per_record() {
+            let mut out = Vec::new();
+            JsonlEncoder.encode(&record(b"a\nb\n"), &mut out);
+            JsonlEncoder.encode(&record(b"c"), &mut out);
+            let out = String::from_utf8(out).unwrap();
+            assert_eq!(out.lines().count(), 2);
+            assert!(out.ends_with('\n'));
+        }
+    }
+
+    mod new_encoder {
+        use super::*;
+
+        #[test]
+        fn raw_is_framed_only_when_shared() {
+            let mut out = Vec::new();
+            new_encoder(&format(LogFormat::Raw), false).encode(&record(b"hi"), &mut out);
+            assert_eq!(out, b"hi");
+
+            let mut out = Vec::new();
+            new_encoder(&format(LogFormat::Raw), true).encode(&record(b"hi"), &mut out);
+            assert_eq!(out, b"[#3 stdout len=2]\nhi\n");
+        }
+
+        #[test]
+        fn timestamped() {
+            for shared in [false, true] {
+                let mut out = Vec::new();
+                new_encoder(&format(LogFormat::Timestamped), shared)
+                    .encode(&record(b"hi"), &mut out);
+                assert!(
+                    String::from_utf8(out)
+                        .unwrap()
+                        .starts_with("[#3 stdout wall=")
+                );
+            }
+        }
+    }
+
+    mod timing_encoder {
+        use super::*;
+
+        #[test]
+        fn delays_between_records() {
+            let mut encoder = TimingEncoder {
+                last_mono: Duration::ZERO,
+            };
+            let mut out = Vec::new();
+            encoder.encode(&record(b"hello\n"), &mut out);
+            encoder.encode(
+                &Record {
+                    mono: Duration::from_micros(501_234),
+                    ..record(b"exit\n")
+                },
+                &mut out,
+            );
+            assert_eq!(String::from_utf8(out).unwrap(), "0.001234 6\n0.500000 5\n");
+        }
+    }
+
+    mod asciicast_encoder {
+        use super::*;
+
+        fn encode(encoder: &mut AsciicastEncoder, data: &[u8]) -> String {
+            let mut out = Vec::new();
+            encoder.encode(&record(data), &mut out);
+            String::from_utf8(out).unwrap()
+        }
+
+        #[test]
+        fn output_event() {
+            let mut encoder = AsciicastEncoder {
+                code: "o",
+                pending: Vec::new(),
+            };
+            assert_eq!(
+                encode(&mut encoder, b"hello\r\n"),
+                "[0.001234,\"o\",\"hello\\r\\n\"]\n"
+            );
+        }
+
+        #[test]
+        fn split_character_is_held_back() {
+            let mut encoder = AsciicastEncoder {
+                code: "i",
+                pending: Vec::new(),
+            };
+            let euro = "€".as_bytes();
+            assert_eq!(encode(&mut encoder, &euro[..1]), "");
+            assert_eq!(
+                encode(&mut encoder, &[&euro[1..], b"!"].concat()),
+                "[0.001234,\"i\",\"€!\"]\n"
+            );
+        }
+
+        #[test]
+        fn invalid_bytes_are_replaced() {
+            let mut encoder = AsciicastEncoder {
+                code: "o",
+                pending: Vec::new(),
+            };
+            assert_eq!(
+                encode(&mut encoder, &[b'a', 0xff, b'b']),
+                "[0.001234,\"o\",\"a\u{fffd}b\"]\n"
+            );
+        }
+    }
+
+    mod incomplete_utf8_suffix_len {
+        use super::*;
+
+        #[test]
+        fn complete() {
+            assert_eq!(incomplete_utf8_suffix_len(b""), 0);
+            assert_eq!(incomplete_utf8_suffix_len(b"abc"), 0);
+            assert_eq!(incomplete_utf8_suffix_len("a\u{1f600}".as_bytes()), 0);
+        }
+
+        #[test]
+        fn incomplete() {
+            let emoji = "\u{1f600}".as_bytes();
+            assert_eq!(incomplete_utf8_suffix_len(&emoji[..1]), 1);
+            assert_eq!(incomplete_utf8_suffix_len(&emoji[..2]), 2);
+            assert_eq!(incomplete_utf8_suffix_len(&[b"a", &emoji[..3]].concat()), 3);
+        }
+
+        #[test]
+        fn stray_continuation_bytes() {
+            assert_eq!(incomplete_utf8_suffix_len(&[0x80, 0x80, 0x80, 0x80]), 0);
+        }
+    }
+
+    mod asciicast {
+        use super::*;
+        use non_empty_string::NonEmptyString;
+
+        fn target() -> Target {
+            Target {
+                executable: NonEmptyString::new("echo".to_string()).unwrap(),
+                args: vec!["hello world".to_string()],
+            }
+        }
+
+        #[test]
+        fn header_and_events() {
+            let buffer = SharedBuffer::default();
+            let clock = Clock::new();
+            let recording = Arc::new(
+                SharedLog::asciicast(
+                    Box::new(buffer.clone()),
+                    clock,
+                    &target(),
+                    WindowSize {
+                        cols: 100,
+                        rows: 30,
+                    },
+                    false,
+                )
+                .unwrap(),
+            );
+            assert!(
+                StreamLog::new(
+                    "stdin",
+                    clock,
+                    None,
+                    &format(LogFormat::Raw),
+                    None,
+                    &[recording.clone()]
+                )
+                .is_none()
+            );
+            let mut stdout_log = StreamLog::new(
+                "stdout",
+                clock,
+                None,
+                &format(LogFormat::Raw),
+                None,
+                &[recording.clone()],
+            )
+            .unwrap();
+            let mut stderr_log = StreamLog::new(
+                "stderr",
+                clock,
+                None,
+                &format(LogFormat::Raw),
+                None,
+                &[recording],
+            )
+            .unwrap();
+
+            stdout_log.log_chunk(b"out").unwrap();
+            stderr_log.log_chunk(b"err").unwrap();
+
+            let contents = buffer.contents();
+            let lines: Vec<_> = contents.lines().collect();
+            assert_eq!(lines.len(), 3);
+            let header: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+            assert_eq!(header["version"], 2);
+            assert_eq!(header["width"], 100);
+            assert_eq!(header["height"], 30);
+            assert_eq!(header["command"], "echo 'hello world'");
+            let stdout_event: serde_json::Value = serde_json::from_str(lines[1]).unwrap();
+            assert_eq!(stdout_event[1], "o");
+            assert_eq!(stdout_event[2], "out");
+            let stderr_event: serde_json::Value = serde_json::from_str(lines[2]).unwrap();
+            assert_eq!(stderr_event[1], "o");
+            assert_eq!(stderr_event[2], "err");
+            assert!(stderr_event[0].as_f64() >= stdout_event[0].as_f64());
+        }
+
+        #[test]
+        fn stdin_as_input_events() {
+            let buffer = SharedBuffer::default();
+            let clock = Clock::new();
+            let recording = Arc::new(
+                SharedLog::asciicast(
+                    Box::new(buffer.clone()),
+                    clock,
+                    &target(),
+                    WindowSize::default(),
+                    true,
+                )
+                .unwrap(),
+            );
+            let mut stdin_log = StreamLog::new(
+                "stdin",
+                clock,
+                None,
+                &format(LogFormat::Raw),
+                None,
+                &[recording],
+            )
+            .unwrap();
+
+            stdin_log.log_chunk(b"ls\r").unwrap();
+
+            let contents = buffer.contents();
+            let event: serde_json::Value =
+                serde_json::from_str(contents.lines().nth(1).unwrap()).unwrap();
+            assert_eq!(event[1], "i");
+            assert_eq!(event[2], "ls\r");
+        }
+
+        #[test]
+        fn extra_fds_not_recorded() {
+            let buffer = SharedBuffer::default();
+            let clock = Clock::new();
+            let recording = Arc::new(
+                SharedLog::asciicast(
+                    Box::new(buffer.clone()),
+                    clock,
+                    &target(),
+                    WindowSize::default(),
+                    true,
+                )
+                .unwrap(),
+            );
+            assert!(
+                StreamLog::new(
+                    "fd3",
+                    clock,
+                    None,
+                    &format(LogFormat::Raw),
+                    None,
+                    &[recording]
+                )
+                .is_none()
+            );
+
+            // Only the header.
+            assert_eq!(buffer.contents().lines().count(), 1);
+        }
+
+        #[test]
+        fn resize_events() {
+            let buffer = SharedBuffer::default();
+            let clock = Clock::new();
+            let recording = SharedLog::asciicast(
+                Box::new(buffer.clone()),
+                clock,
+                &target(),
+                WindowSize::default(),
+                false,
+            )
+            .unwrap();
+
+            let (wall, mono) = clock.now();
+            recording
+                .log_resize(
+                    wall,
+                    mono,
+                    WindowSize {
+                        cols: 100,
+                        rows: 30,
+                    },
+                )
+                .unwrap();
+
+            let contents = buffer.contents();
+            let event: serde_json::Value =
+                serde_json::from_str(contents.lines().nth(1).unwrap()).unwrap();
+            assert_eq!(event[1], "r");
+            assert_eq!(event[2], "100x30");
+        }
+    }
+
+    mod timeline {
+        use super::*;
+        use std::thread;
+
+        #[test]
+        fn records_are_tagged_and_sequenced() {
+            let buffer = SharedBuffer::default();
+            let timeline = Arc::new(SharedLog::timeline(
+                Box::new(buffer.clone()),
+                format(LogFormat::Raw),
+            ));
+            let clock = Clock::new();
+            let mut stdin_log = StreamLog::new(
+                "stdin",
+                clock,
+                None,
+                &format(LogFormat::Raw),
+                None,
+                &[timeline.clone()],
+            )
+            .unwrap();
+            let mut stdout_log = StreamLog::new(
+                "stdout",
+                clock,
+                None,
+                &format(LogFormat::Raw),
+                None,
+                &[timeline],
+            )
+            .unwrap();
+
+            stdin_log.log_chunk(b"hello\n").unwrap();
+            stdout_log.log_chunk(b"Echo: hello").unwrap();
+            stdin_log.log_chunk(b"").unwrap();
+
+            assert_eq!(
+                buffer.contents(),
+                "[#0 stdin len=6]\nhello\n\n[#1 stdout len=11]\nEcho: hello\n[#2 stdin len=0]\n\n"
+            );
+        }
+
+        #[test]
+        fn lines_interleave_in_timeline() {
+            let buffer = SharedBuffer::default();
+            let timeline = Arc::new(SharedLog::timeline(
+                Box::new(buffer.clone()),
+                format(LogFormat::Lines),
+            ));
+            let clock = Clock::new();
+            let mut stdout_log = StreamLog::new(
+                "stdout",
+                clock,
+                None,
+                &format(LogFormat::Lines),
+                None,
+                &[timeline.clone()],
+            )
+            .unwrap();
+            let mut stderr_log = StreamLog::new(
+                "stderr",
+                clock,
+                None,
+                &format(LogFormat::Lines),
+                None,
+                &[timeline],
+            )
+            .unwrap();
+
+            stdout_log.log_chunk(b"Echo: he").unwrap();
+            stderr_log.log_chunk(b"Error\n").unwrap();
+            stdout_log.log_chunk(b"llo\n").unwrap();
+            stdout_log.log_chunk(b"bye").unwrap();
+            stdout_log.finish().unwrap();
+
+            assert_eq!(
+                buffer.contents(),
+                "[stderr] Error\n[stdout] Echo: hello\n[stdout] bye\n"
+            );
+        }
+
+        #[test]
+        fn resizes_are_sequenced_with_chunks() {
+            let buffer = SharedBuffer::default();
+            let timeline = Arc::new(SharedLog::timeline(
+                Box::new(buffer.clone()),
+                format(LogFormat::Raw),
+            ));
+            let clock = Clock::new();
+            let mut stdout_log = StreamLog::new(
+                "stdout",
+                clock,
+                None,
+                &format(LogFormat::Raw),
+                None,
+                &[timeline.clone()],
+            )
+            .unwrap();
+
+            stdout_log.log_chunk(b"before").unwrap();
+            let (wall, mono) = clock.now();
+            timeline
+                .log_resize(
+                    wall,
+                    mono,
+                    WindowSize {
+                        cols: 100,
+                        rows: 30,
+                    },
+                )
+                .unwrap();
+            stdout_log.log_chunk(b"after").unwrap();
+
+            assert_eq!(
+                buffer.contents(),
+                "[#0 stdout len=6]\nbefore\n[#1 resize len=6]\n100x30\n[#2 stdout len=5]\nafter\n"
+            );
+        }
+
+        #[test]
+        fn resizes_in_lines_format() {
+            let buffer = SharedBuffer::default();
+            let timeline = SharedLog::timeline(Box::new(buffer.clone()), format(LogFormat::Lines));
+
+            let (wall, mono) = Clock::new().now();
+            timeline
+                .log_resize(
+                    wall,
+                    mono,
+                    WindowSize {
+                        cols: 100,
+                        rows: 30,
+                    },
+                )
+                .unwrap();
+
+            assert_eq!(buffer.contents(), "[resize] 100x30\n");
+        }
+
+        #[test]
+        fn concurrent_streams_get_unique_sequence_numbers() {
+            let buffer = SharedBuffer::default();
+            let timeline = Arc::new(SharedLog::timeline(
+                Box::new(buffer.clone()),
+                format(LogFormat::Raw),
+            ));
+            let clock = Clock::new();
+
+            thread::scope(|scope| {
+                for stream in ["stdin", "stdout", "stderr"] {
+                    let mut log = StreamLog::new(
+                        stream,
+                        clock,
+                        None,
+                        &format(LogFormat::Raw),
+                        None,
+                        &[timeline.clone()],
+                    )
+                    .unwrap();
+                    scope.spawn(move || {
+                        for _ in 0..100 {
+                            log.log_chunk(b"x").unwrap();
+                        }
+                    });
+                }
+            });
+
+            let contents = buffer.contents();
+            for seq in 0..300 {
+                assert!(contents.contains(&format!("[#{seq} ")));
+            }
+            assert!(!contents.contains("[#300 "));
+        }
+    }
+
+    mod stream_log {
+        use super::*;
+
+        #[test]
+        fn no_logs() {
+            assert!(
+                StreamLog::new(
+                    "stdin",
+                    Clock::new(),
+                    None,
+                    &format(LogFormat::Raw),
+                    None,
+                    &[]
+                )
+                .is_none()
+            );
+        }
+
+        #[test]
+        fn file_and_timeline() {
+            let file = SharedBuffer::default();
+            let timeline_buffer = SharedBuffer::default();
+            let timeline = Arc::new(SharedLog::timeline(
+                Box::new(timeline_buffer.clone()),
+                format(LogFormat::Raw),
+            ));
+            let mut log = StreamLog::new(
+                "stderr",
+                Clock::new(),
+                Some(Box::new(file.clone())),
+                &format(LogFormat::Raw),
+                None,
+                &[timeline],
+            )
+            .unwrap();
+
+            log.log_chunk(b"oops").unwrap();
+
+            assert_eq!(file.contents(), "oops");
+            assert_eq!(timeline_buffer.contents(), "[#0 stderr len=4]\noops\n");
+        }
+
+        #[test]
+        fn file_and_timing() {
+            let file = SharedBuffer::default();
+            let timing = SharedBuffer::default();
+            let mut log = StreamLog::new(
+                "stdin",
+                Clock::new(),
+                Some(Box::new(file.clone())),
+                &format(LogFormat::Raw),
+                Some(Box::new(timing.clone())),
+                &[],
+            )
+            .unwrap();
+
+            log.log_chunk(b"hello\n").unwrap();
+            log.log_chunk(b"exit\n").unwrap();
+
+            assert_eq!(file.contents(), "hello\nexit\n");
+            let contents = timing.contents();
+            let lengths: Vec<_> = contents
+                .lines()
+                .map(|line| line.split_once(' ').unwrap().1)
+                .collect();
+            assert_eq!(lengths, vec!["6", "5"]);
+        }
+
+        #[test]
+        fn finish_flushes_held_back_data() {
+            let file = SharedBuffer::default();
+            let timeline_buffer = SharedBuffer::default();
+            let timeline = Arc::new(SharedLog::timeline(
+                Box::new(timeline_buffer.clone()),
+                format(LogFormat::Hex),
+            ));
+            let mut log = StreamLog::new(
+                "stdout",
+                Clock::new(),
+                Some(Box::new(file.clone())),
+                &format(LogFormat::Hex),
+                None,
+                &[timeline],
+            )
+            .unwrap();
+
+            log.log_chunk(b"abc").unwrap();
+            assert_eq!(file.contents(), "");
+            log.finish().unwrap();
+
+            assert_eq!(
+                file.contents(),
+                "00000000: 6162 63                                  abc\n"
+            );
+            // The timeline always marks chunks, so nothing is held back in it.
+            assert_eq!(
+                timeline_buffer.contents(),
+                "[#0 stdout len=3]\n00000000: 6162 63                                  abc\n"
+            );
+        }
+
+        #[test]
+        fn timestamped_file_has_its_own_sequence() {
+            let file = SharedBuffer::default();
+            let mut log = StreamLog::new(
+                "stdin",
+                Clock::new(),
+                Some(Box::new(file.clone())),
+                &format(LogFormat::Timestamped),
+                None,
+                &[],
+            )
+            .unwrap();
+
+            log.log_chunk(b"one").unwrap();
+            log.log_chunk(b"two").unwrap();
+
+            let contents = file.contents();
+            let headers: Vec<_> = contents.lines().filter(|l| l.starts_with("[#")).collect();
+            assert_eq!(headers.len(), 2);
+            assert!(headers[0].starts_with("[#0 stdin wall="));
+            assert!(headers[0].ends_with(" len=3]"));
+            assert!(headers[1].starts_with("[#1 stdin wall="));
+        }
+
+        #[test]
+        fn file_error_still_logs_to_timeline() {
+            let timeline_buffer = SharedBuffer::default();
+            let timeline = Arc::new(SharedLog::timeline(
+                Box::new(timeline_buffer.clone()),
+                format(LogFormat::Raw),
+            ));
+            let mut log = StreamLog::new(
+                "stdout",
+                Clock::new(),
+                Some(Box::new(FailingWriter)),
+                &format(LogFormat::Raw),
+                None,
+                &[timeline],
+            )
+            .unwrap();
+
+            assert!(log.log_chunk(b"data").is_err());
+            assert_eq!(timeline_buffer.contents(), "[#0 stdout len=4]\ndata\n");
+        }
+
+        #[test]
+        fn no_resize_log_in_raw_file() {
+            let log = StreamLog::new(
+                "stdin",
+                Clock::new(),
+                Some(Box::new(SharedBuffer::default())),
+                &format(LogFormat::Raw),
+                Some(Box::new(SharedBuffer::default())),
+                &[],
+            )
+            .unwrap();
+
+            assert!(log.resize_log().is_none());
+        }
+
+        #[test]
+        fn resizes_are_sequenced_with_chunks() {
+            let file = SharedBuffer::default();
+            let clock = Clock::new();
+            let mut log = StreamLog::new(
+                "stdout",
+                clock,
+                Some(Box::new(file.clone())),
+                &format(LogFormat::Jsonl),
+                None,
+                &[],
+            )
+            .unwrap();
+            let resize_log = log.resize_log().unwrap();
+
+            log.log_chunk(b"before").unwrap();
+            let (wall, mono) = clock.now();
+            resize_log
+                .log_resize(
+                    wall,
+                    mono,
+                    WindowSize {
+                        cols: 100,
+                        rows: 30,
+                    },
+                )
+                .unwrap();
+            log.log_chunk(b"after").unwrap();
+
+            let records: Vec<(u64, String, String)> = file
+                .contents()
+                .lines()
+                .map(|line| {
+                    let value: serde_json::Value = serde_json::from_str(line).unwrap();
+                    (
+                        value["seq"].as_u64().unwrap(),
+                        value["stream"].as_str().unwrap().to_string(),
+                        value["payload"].as_str().unwrap().to_string(),
+                    )
+                })
+                .collect();
+            assert_eq!(
+                records,
+                vec![
+                    (0, "stdout".to_string(), "before".to_string()),
+                    (1, "resize".to_string(), "100x30".to_string()),
+                    (2, "stdout".to_string(), "after".to_string()),
+                ]
+            );
+        }
+
+        #[test]
+        fn resize_leaves_partial_line_held_back() {
+            let file = SharedBuffer::default();
+            let clock = Clock::new();
+            let mut log = StreamLog::new(
+                "stdout",
+                clock,
+                Some(Box::new(file.clone())),
+                &format(LogFormat::Lines),
+                None,
+                &[],
+            )
+            .unwrap();
+            let resize_log = log.resize_log().unwrap();
+
+            log.log_chunk(b"hel").unwrap();
+            let (wall, mono) = clock.now();
+            resize_log
+                .log_resize(
+                    wall,
+                    mono,
+                    WindowSize {
+                        cols: 100,
+                        rows: 30,
+                    },
+                )
+                .unwrap();
+            log.log_chunk(b"lo\n").unwrap();
+
+            assert_eq!(file.contents(), "[resize] 100x30\n[stdout] hello\n");
+        }
+    }
+}
@@ -7,31 +7,93 @@
 //! # Features
 //!
 //! - Wraps any command and captures all I/O via stdin, stdout, and stderr.
-//! - Logs each stream to separate files.
+//! - Optionally runs the command in a pseudo-terminal, so that it behaves as it would in an
+//!   interactive terminal.
+//! - Logs each stream to separate files, whose names may have placeholders, e.g., for the PID, so
+//!   that each session gets its own files.
+//! - Optionally keeps the logs of each session in a directory of its own.
+//! - Optionally writes a manifest describing each session, from the target command to how it ended.
+//! - Optionally logs all streams to a single timeline, in the order they were observed.
+//! - Optionally timestamps every intercepted chunk.
+//! - Redacts the input typed while the target command turned terminal echo off, e.g., passwords.
+//! - Counts the traffic of each stream, and optionally summarizes it on exit.
+//! - Only intercepts the streams that are recorded, and passes the others straight through.
+//! - Optionally intercepts and logs additional file descriptors, besides stdin, stdout, and stderr.
+//! - Optionally reads the stdin of the command from a file, a FIFO, or inline text, instead of the
+//!   stdin of fdintercept.
+//! - Optionally records the session in the asciicast v2 format, to be played back with asciinema.
+//! - Replays a recorded stdin log into the target command, to reproduce a session.
+//! - Attaches to a process that is already running, on Linux, and logs its I/O with `ptrace`.
+//! - Relays TCP and Unix domain socket connections to an upstream server, and logs what each side
+//!   of each connection sends.
+//! - Optionally compresses logs with gzip or zstd as they are written.
+//! - Optionally rotates logs once they grow past a maximum size or periodically, and reopens them
+//!   on a signal, for external log rotation.
 //! - Supports configuration via CLI, environment variables, or configuration file.
 //! - Configurable buffer size for I/O operations.
 //! - Preserves original program exit codes.
 //! - Handles process and child process termination gracefully.
+//! - Forwards terminal resizes to the target command, and records them in the logs.
 
+/// Module for attaching to a running process
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+mod attach;
+/// Module for compression of log files
+mod compress;
+/// Module for intercepting additional file descriptors
+mod extra_fd;
 /// Module for file descriptor handling and I/O processing
 mod fd;
+/// Module for sources of the stdin of the target command
+mod input;
+/// Module for log destinations of intercepted streams
+mod log;
 /// Module for child process management
 mod process;
+/// Module for relaying and logging TCP and Unix domain socket connections
+mod proxy;
+/// Module for pseudo-terminals
+mod pty;
+/// Module for redacting input typed while terminal echo is off
+mod redact;
+/// Module for replaying recorded input
+mod replay;
+/// Module for forwarding window size changes
+mod resize;
+/// Module for rotation of log files
+mod rotate;
+/// Module for session identity and per-session log file names
+mod session;
+/// Module for the logs of a session
+mod session_logs;
 /// Module for configuration and settings management
 mod settings;
 /// Module for Unix signal handling
 mod signals;
+/// Module for statistics of intercepted streams
+mod stats;
+/// Module for terminal queries
+mod terminal;
 /// Module for thread management utilities
 mod threads;
 
 use anyhow::{Context, Result};
+use input::Input;
+use log::Clock;
 use nix::unistd::pipe;
-use process::ChildGuard;
-use signal_hook::consts::{SIGCHLD, SIGHUP, SIGINT, SIGTERM};
+use process::ChildStreams;
+use redact::RedactingLog;
+use resize::Resizer;
+use rotate::LogReopener;
+use session::Session;
+use session_logs::{LogTemplates, SessionLogs};
+use settings::{Mode, ReopenSignal};
+use signal_hook::consts::{SIGCHLD, SIGHUP, SIGINT, SIGTERM, SIGWINCH};
 use signal_hook::iterator::Signals;
+use stats::{StreamStats, StreamsSummary};
 use std::io;
 use std::os::unix::process::ExitStatusExt;
-use std::process::{Command, Stdio};
+use std::process::Command;
 use std::sync::mpsc;
 use std::sync::{Arc, Mutex};
 use std::thread;
@@ -39,13 +101,25 @@ use std::thread;
 /// Main entry point for the fdintercept program.
 ///
 /// This function:
-/// 1. Sets up signal handlers for graceful termination.
-/// 2. Loads program settings from various sources.
-/// 3. Creates log files for stdin, stdout, and stderr.
-/// 4. Spawns the target process with piped I/O.
-/// 5. Creates threads to handle I/O processing and signal handling.
-/// 6. Manages thread lifecycle and cleanup.
-/// 7. Preserves the exit code from the child process.
+/// 1. Saves the mode of the terminal, to restore it however the program ends, and loads program
+///    settings from various sources. If asked to attach to a running process, observes it instead
+///    of running the target process, and if asked to proxy connections, relays them instead,
+///    and doesn't go on.
+/// 2. Claims the additional file descriptors to intercept, and sets up signal handlers for graceful
+///    termination.
+/// 3. Parses the names of the log files, which may have placeholders.
+/// 4. Creates the session directory, if there is a log directory.
+/// 5. Spawns the target process with piped I/O for the intercepted streams, or in a
+///    pseudo-terminal.
+/// 6. Writes the manifest, and creates log files for stdin, stdout, and stderr, and the
+///    shared timeline log and asciicast recording, expanding the placeholders in their names.
+/// 7. Puts the terminal into raw mode, if the target process runs in a pseudo-terminal and reads
+///    the stdin of fdintercept, and creates threads to handle I/O processing, feeding stdin from
+///    wherever it comes from, e.g., recorded input, and signal handling.
+/// 8. Manages thread lifecycle and cleanup, finishing the logs once their streams have ended, and
+///    restoring the terminal.
+/// 9. Summarizes the traffic of each stream, if asked to, and records it and how the child process
+///    ended in the manifest, preserving its exit code.
 ///
 /// # Returns
 ///
@@ -64,45 +138,133 @@ use std::thread;
 /// - SIGINT: Interrupt (usually Ctrl+C).
 /// - SIGTERM: Termination request.
 /// - SIGCHLD: Child process status change.
+/// - SIGWINCH: Window size change: Forward the new size to the child process, and record it in the
+///   logs that can hold it.
+/// - The configured reopen signal, if any: Reopen the log files. If it is SIGHUP, SIGHUP no longer
+///   terminates.
 fn main() -> Result<()> {
-    let mut signals = Signals::new([SIGHUP, SIGINT, SIGTERM, SIGCHLD])
-        .context("Failed to register signal handlers")?;
+    // Saved before anything can change it. Dropping the guard restores the terminal when `main`
+    // returns an error.
+    let _terminal_guard = terminal::save_mode();
 
     let settings = settings::get_settings()?;
 
-    let stdin_log = fd::create_log_file(settings.stdin_log.as_ref(), settings.recreate_logs)?;
-    let stdout_log = fd::create_log_file(settings.stdout_log.as_ref(), settings.recreate_logs)?;
-    let stderr_log = fd::create_log_file(settings.stderr_log.as_ref(), settings.recreate_logs)?;
+    // An attached process keeps its own standard streams, so there is nothing to relay.
+    if let Mode::Attach { pid } = settings.mode {
+        #[cfg(all(target_os = "linux", target_env = "gnu"))]
+        return attach::run(&settings, pid);
+        #[cfg(not(all(target_os = "linux", target_env = "gnu")))]
+        anyhow::bail!("Attaching to process {pid} is only supported on Linux");
+    }
 
-    // Don't even start the child process if we were already told to terminate.
-    if let Some(signum) = signals.pending().next() {
-        std::process::exit(128 + signum);
+    // A proxy relays connections instead of the standard streams of a target command.
+    if let Mode::Proxy {
+        listen,
+        upstream,
+        unix,
+        client_log,
+        server_log,
+        connection_info,
+    } = &settings.mode
+    {
+        return proxy::run(
+            &settings,
+            listen,
+            upstream,
+            *unix,
+            client_log,
+            server_log,
+            connection_info,
+        );
+    }
+
+    // Claimed before anything else opens a file descriptor, which might take the number of one
+    // that isn't open.
+    let (child_fds, extra_fd_relays) = extra_fd::open(&settings.extra_fds)?;
+
+    let mut signals = Signals::new([SIGHUP, SIGINT, SIGTERM, SIGCHLD, SIGWINCH])
+        .context("Failed to register signal handlers")?;
+
+    let maybe_reopen_signum = settings.reopen_signal.map(ReopenSignal::signum);
+    if let Some(signum) = maybe_reopen_signum {
+        signals
+            .add_signal(signum)
+            .context("Failed to register reopen signal handler")?;
     }
+    let maybe_reopener = maybe_reopen_signum.map(|_| LogReopener::default());
+
+    let clock = Clock::new();
+    let session = Session::new(clock.started_at());
+
+    // The names of the log files are parsed before the child process is started, so that mistakes
+    // in them are reported before anything runs, but they are only expanded once it started.
+    let log_templates = LogTemplates::parse(&settings)?;
+
+    let input = Input::open(&settings)?;
+
+    // Don't even start the child process if we were already told to terminate.
+    signals::exit_if_terminated(&mut signals, maybe_reopen_signum);
+
+    let maybe_session_dir = session_logs::create_session_dir(&session, &settings)?;
 
     // We're using a pipe here, instead of a mpsc::channel, because pipes have file descriptors
     // that we can wait on with `poll`.
     let (signal_rx, signal_tx) = pipe().context("Error creating pipe")?;
 
-    let mut child_guard = ChildGuard {
-        child: Command::new(settings.target.executable.as_str())
-            .args(&settings.target.args)
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .spawn()
-            .context("Error starting child process")?,
-    };
-    let child = &mut child_guard.child;
-
-    let child_stdin = child.stdin.take().context("Error taking stdin of child")?;
-    let child_stdout = child
-        .stdout
-        .take()
-        .context("Error taking stdout of child")?;
-    let child_stderr = child
-        .stderr
-        .take()
-        .context("Error taking stderr of child")?;
+    // unwrap: Safe because only a proxy has no target command, and it was dispatched above.
+    let target = settings.target.as_ref().unwrap();
+    let mut command = Command::new(target.executable.as_str());
+    command.args(&target.args);
+    let (child_guard, child_streams) =
+        process::spawn(command, settings.pty, settings.stream_modes, child_fds)?;
+    let ChildStreams {
+        stdin: maybe_child_stdin,
+        stdout: maybe_child_stdout,
+        maybe_stderr: maybe_child_stderr,
+        maybe_pty: maybe_child_pty,
+        maybe_input_terminal: maybe_child_input_terminal,
+    } = child_streams;
+
+    // The log files are only created now that the PID of the child process is known, since their
+    // names may include it. Whatever the child process writes in the meantime waits in the pipes,
+    // or in the pseudo-terminal.
+    let child_pid = child_guard.child.id();
+    let SessionLogs {
+        mut maybe_manifest,
+        shared_logs,
+        stdin: stdin_log,
+        stdout: stdout_log,
+        stderr: stderr_log,
+        extra_fds: extra_fd_logs,
+        resize_logs,
+    } = SessionLogs::create(
+        log_templates,
+        &settings,
+        &session,
+        clock,
+        child_pid,
+        maybe_session_dir.as_deref(),
+        maybe_reopener.as_ref(),
+    )?;
+    // What is typed while the target command turned echo off, e.g., a password, is never logged.
+    let stdin_log = stdin_log.map(|log| RedactingLog::new(log, maybe_child_input_terminal));
+
+    let resizer = Resizer::new(child_pid, maybe_child_pty, clock, resize_logs);
+
+    let stdin_stats = &StreamStats::default();
+    let stdout_stats = &StreamStats::default();
+    let stderr_stats = &StreamStats::default();
+    let extra_fd_stats = &extra_fd_relays
+        .iter()
+        .map(|relay| (relay.name, StreamStats::default()))
+        .collect::<Vec<_>>();
+
+    // Keystrokes must reach the pseudo-terminal as they are typed, for it to handle them as the
+    // target command configured it. When stdin is read from elsewhere, e.g., when replaying, there
+    // are no keystrokes to relay.
+    if settings.pty && input.is_stdin() {
+        terminal::enable_raw_mode()?;
+    }
 
     let mutex_child_guard = Arc::new(Mutex::new(child_guard));
     let mutex_child_guard_clone = mutex_child_guard.clone();
@@ -110,60 +272,87 @@ fn main() -> Result<()> {
     thread::scope(move |scope| -> Result<()> {
         let (handle_tx, handle_rx) = mpsc::channel();
 
-        threads::spawn_self_shipping_thread_in_scope(
+        extra_fd::spawn_relays(
             scope,
-            handle_tx.clone(),
-            "process_fd:stdin",
-            move || {
-                fd::process_fd(
-                    io::stdin(),
-                    child_stdin,
-                    settings.buffer_size,
-                    stdin_log,
-                    "stdin",
-                    Some(signal_rx),
-                )
-            },
-        )
-        .context("Failed to create thread to process stdin")?;
-        threads::spawn_self_shipping_thread_in_scope(
-            scope,
-            handle_tx.clone(),
-            "process_fd:stdout",
-            move || {
-                fd::process_fd(
-                    child_stdout,
-                    io::stdout(),
-                    settings.buffer_size,
-                    stdout_log,
-                    "stdout",
-                    None,
-                )
-            },
-        )
-        .context("Failed to create thread to process stdout")?;
+            &handle_tx,
+            extra_fd_relays
+                .into_iter()
+                .zip(extra_fd_logs)
+                .zip(extra_fd_stats)
+                .map(|((relay, maybe_log), (_, stats))| (relay, maybe_log, stats)),
+            settings.buffer_size,
+            &signal_rx,
+        )?;
+        // Only the intercepted streams are relayed. The others don't go through fdintercept.
+        if let Some(child_stdin) = maybe_child_stdin {
+            threads::spawn_self_shipping_thread_in_scope(
+                scope,
+                handle_tx.clone(),
+                "process_fd:stdin",
+                move || {
+                    input.feed(
+                        child_stdin,
+                        settings.buffer_size,
+                        stdin_log,
+                        stdin_stats,
+                        signal_rx,
+                    )
+                },
+            )
+            .context("Failed to create thread to process stdin")?;
+        }
+        if let Some(child_stdout) = maybe_child_stdout {
+            threads::spawn_self_shipping_thread_in_scope(
+                scope,
+                handle_tx.clone(),
+                "process_fd:stdout",
+                move || {
+                    fd::process_fd(
+                        child_stdout,
+                        io::stdout(),
+                        settings.buffer_size,
+                        stdout_log,
+                        stdout_stats,
+                        "stdout",
+                        None,
+                    )
+                },
+            )
+            .context("Failed to create thread to process stdout")?;
+        }
+        if let Some(child_stderr) = maybe_child_stderr {
+            threads::spawn_self_shipping_thread_in_scope(
+                scope,
+                handle_tx.clone(),
+                "process_fd:stderr",
+                move || {
+                    fd::process_fd(
+                        child_stderr,
+                        io::stderr(),
+                        settings.buffer_size,
+                        stderr_log,
+                        stderr_stats,
+                        "stderr",
+                        None,
+                    )
+                },
+            )
+            .context("Failed to create thread to process stderr")?;
+        }
         threads::spawn_self_shipping_thread_in_scope(
             scope,
             handle_tx.clone(),
-            "process_fd:stderr",
+            "process_signals",
             move || {
-                fd::process_fd(
-                    child_stderr,
-                    io::stderr(),
-                    settings.buffer_size,
-                    stderr_log,
-                    "stderr",
-                    None,
+                signals::process_signals(
+                    signals,
+                    mutex_child_guard_clone,
+                    signal_tx,
+                    maybe_reopen_signum.zip(maybe_reopener),
+                    resizer,
                 )
             },
         )
-        .context("Failed to create thread to process stderr")?;
-        threads::spawn_self_shipping_thread_in_scope(
-            scope,
-            handle_tx.clone(),
-            "process_signals",
-            || signals::process_signals(signals, mutex_child_guard_clone, signal_tx),
-        )
         .context("Failed to create thread to process signals")?;
 
         // Close this `handle_tx` so that when all the self-shipping threads are finished and all
@@ -184,24 +373,42 @@ fn main() -> Result<()> {
     })
     .context("Failed to create threads")?;
 
-    std::process::exit(
-        mutex_child_guard
-            .lock()
-            // unwrap: Safe because if we got here, the only other instance of `mutex_child_guard`
-            // is dead, since it lived inside one of the threads that we already joined into.
-            .unwrap()
-            .child
-            .try_wait()
-            .context("Error waiting for child")?
-            .map_or(1, |status| {
-                if let Some(code) = status.code() {
-                    code
-                } else if let Some(signum) = status.signal() {
-                    128 + signum
-                } else {
-                    eprintln!("Error getting child process status");
-                    1
-                }
-            }),
+    let maybe_status = mutex_child_guard
+        .lock()
+        // unwrap: Safe because if we got here, the only other instance of `mutex_child_guard` is
+        // dead, since it lived inside one of the threads that we already joined into.
+        .unwrap()
+        .child
+        .try_wait()
+        .context("Error waiting for child")?;
+
+    // All the streams have ended, either because the child process closed them or because we were
+    // told to terminate, so the shared logs are complete.
+    session_logs::finish(
+        &shared_logs,
+        maybe_manifest.as_mut(),
+        clock,
+        maybe_status,
+        StreamsSummary {
+            stdin: stdin_stats.summary(),
+            stdout: stdout_stats.summary(),
+            stderr: stderr_stats.summary(),
+            extra_fds: extra_fd_stats
+                .iter()
+                .map(|(name, stats)| (*name, stats.summary()))
+                .collect(),
+        },
+        settings.summary,
     );
+
+    std::process::exit(maybe_status.map_or(1, |status| {
+        if let Some(code) = status.code() {
+            code
+        } else if let Some(signum) = status.signal() {
+            128 + signum
+        } else {
+            eprintln!("Error getting child process status");
+            1
+        }
+    }));
 }
@@ -1,13 +1,22 @@
 //! Process management and graceful termination utilities.
 //!
 //! This module provides functionality for managing child processes, including:
+//! - Spawning child processes with their intercepted standard streams relayable, through pipes
+//!   or a pseudo-terminal,
 //! - Automatic cleanup of child processes using RAII, and
 //! - Graceful process termination with configurable timeouts.
 
+use crate::extra_fd::ChildFds;
+use crate::pty::Pty;
+use crate::settings::{StreamMode, StreamModes};
+use crate::terminal;
 use anyhow::{Context, Result};
 use nix::sys::signal::{Signal, kill};
 use nix::unistd::Pid;
-use std::process::{Child, ExitStatus};
+use std::fs::File;
+use std::io::Write;
+use std::os::fd::OwnedFd;
+use std::process::{Child, Command, ExitStatus, Stdio};
 use std::time::Duration;
 use wait_timeout::ChildExt;
 
@@ -23,6 +32,9 @@ pub struct ChildGuard {
 
 impl Drop for ChildGuard {
     fn drop(&mut self) {
+        // The child process is only dropped here while fdintercept is on its way out, so the
+        // terminal is restored first, instead of after the grace period of the child process.
+        terminal::restore_mode();
         if let Err(e) = kill_child_process_with_grace_period(
             &mut self.child,
             Signal::SIGTERM,
@@ -34,6 +46,129 @@ impl Drop for ChildGuard {
     }
 }
 
+/// The standard streams of a child process, as fdintercept relays them.
+pub struct ChildStreams {
+    /// Where the stdin of the child process is written into, if it is intercepted.
+    pub stdin: Option<Box<dyn Write + Send>>,
+    /// Where the stdout of the child process is read from, if it is intercepted.
+    pub stdout: Option<File>,
+    /// Where the stderr of the child process is read from, if it is intercepted, unless it is the
+    /// same stream as stdout.
+    pub maybe_stderr: Option<File>,
+    /// The pseudo-terminal the child process is attached to, if any, to resize it.
+    pub maybe_pty: Option<OwnedFd>,
+    /// The terminal the input of the child process is typed into, if any, to tell whether it hides
+    /// the input. That's its pseudo-terminal, if it has one, and otherwise the terminal of
+    /// fdintercept, which the child process shares, e.g., by opening `/dev/tty`.
+    pub maybe_input_terminal: Option<OwnedFd>,
+}
+
+/// Spawns a child process with its intercepted standard streams ready to be relayed.
+///
+/// Without a pseudo-terminal, each intercepted standard stream of the child process is a pipe of
+/// its own, and the others are inherited from fdintercept or connected to `/dev/null`, as their
+/// modes say. In a pseudo-terminal, all the standard streams are intercepted, and stdout and
+/// stderr are the same stream, so there is no stderr to relay.
+///
+/// # Arguments
+///
+/// * `command` - The command to spawn.
+/// * `pty` - Whether to run the command in a pseudo-terminal, as large as the terminal fdintercept
+///   is attached to, if any.
+/// * `stream_modes` - How each of the standard streams of the command is handled, without a
+///   pseudo-terminal.
+/// * `child_fds` - The additional file descriptors of the command. They are closed once it was
+///   spawned, so that only the command holds them.
+///
+/// # Returns
+///
+/// Returns the guard of the child process, and its standard streams.
+///
+/// # Errors
+///
+/// Returns an error if:
+/// - Failed to open the pseudo-terminal,
+/// - Failed to spawn the command, or
+/// - Failed to duplicate the terminals.
+pub fn spawn(
+    mut command: Command,
+    pty: bool,
+    stream_modes: StreamModes,
+    child_fds: ChildFds,
+) -> Result<(ChildGuard, ChildStreams)> {
+    child_fds.attach(&mut command);
+    if pty {
+        let (child, pty_input, pty_output) =
+            Pty::open(terminal::window_size().unwrap_or_default())?
+                .spawn(command)
+                .context("Error starting child process")?;
+        let duplicate_pty = || -> Result<Option<OwnedFd>> {
+            Ok(Some(
+                pty_output
+                    .try_clone()
+                    .context("Error duplicating pseudo-terminal")?
+                    .into(),
+            ))
+        };
+        return Ok((
+            ChildGuard { child },
+            ChildStreams {
+                maybe_pty: duplicate_pty()?,
+                maybe_input_terminal: duplicate_pty()?,
+                stdin: Some(Box::new(pty_input)),
+                stdout: Some(pty_output),
+                maybe_stderr: None,
+            },
+        ));
+    }
+
+    let mut child_guard = ChildGuard {
+        child: command
+            .stdin(stdio(stream_modes.stdin))
+            .stdout(stdio(stream_modes.stdout))
+            .stderr(stdio(stream_modes.stderr))
+            .spawn()
+            .context("Error starting child process")?,
+    };
+    let child = &mut child_guard.child;
+
+    // Only the intercepted streams are pipes, which fdintercept holds the other end of.
+    let maybe_child_stdin = child.stdin.take();
+    let maybe_child_stdout = child.stdout.take();
+    let maybe_child_stderr = child.stderr.take();
+
+    Ok((
+        child_guard,
+        ChildStreams {
+            stdin: maybe_child_stdin
+                .map(|child_stdin| Box::new(child_stdin) as Box<dyn Write + Send>),
+            stdout: maybe_child_stdout.map(|child_stdout| File::from(OwnedFd::from(child_stdout))),
+            maybe_stderr: maybe_child_stderr
+                .map(|child_stderr| File::from(OwnedFd::from(child_stderr))),
+            maybe_pty: None,
+            maybe_input_terminal: terminal::stdin_terminal().context("Error duplicating stdin")?,
+        },
+    ))
+}
+
+/// Determines what a standard stream of a child process is connected to.
+///
+/// # Arguments
+///
+/// * `stream_mode` - How the stream is handled.
+///
+/// # Returns
+///
+/// Returns a pipe, for an intercepted stream, the stream of fdintercept, for an inherited one, or
+/// `/dev/null`.
+fn stdio(stream_mode: StreamMode) -> Stdio {
+    match stream_mode {
+        StreamMode::Intercept => Stdio::piped(),
+        StreamMode::Inherit => Stdio::inherit(),
+        StreamMode::Null => Stdio::null(),
+    }
+}
+
 /// Attempts to terminate a child process gracefully with configurable timeouts.
 ///
 /// This function follows a multi-step termination process:
@@ -0,0 +1,833 @@
+//! Relaying and logging of TCP and Unix domain socket connections.
+//!
+//! This module provides functionality for listening on a local address, or on a Unix domain socket,
+//! connecting each client that connects to it to an upstream server, and relaying both directions
+//! of each connection, as the standard streams of a target command are relayed, so that network
+//! protocols can be debugged like the I/O of a command. What each client sends, and what the server
+//! sends back, are logged to log files of each connection, next to a file that describes who the
+//! client was.
+
+use crate::fd;
+use crate::log::{Clock, SharedLog, StreamLog};
+use crate::rotate::LogReopener;
+use crate::session::{self, PathTemplate, Session, TemplateScope, TemplateValues};
+use crate::session_logs;
+use crate::settings::{ReopenSignal, ResolvedSettings};
+use crate::stats::StreamStats;
+use anyhow::{Context, Result};
+use nix::errno::Errno;
+use nix::poll::{self, PollFd, PollFlags, PollTimeout};
+use nix::unistd::pipe;
+use serde::Serialize;
+use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
+use signal_hook::iterator::Signals;
+use std::fmt;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+
+/// The socket that connections are accepted on.
+enum Listener {
+    /// Listens for TCP connections.
+    Tcp(TcpListener),
+    /// Listens on a Unix domain socket, at a path that is removed once it is dropped.
+    Unix(UnixListener, PathBuf),
+}
+
+impl Listener {
+    /// Listens on a local address.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The address to listen on, or the path of the socket to create.
+    /// * `unix` - Whether to create a Unix domain socket, instead of listening for TCP
+    ///   connections.
+    ///
+    /// # Returns
+    ///
+    /// Returns the listening socket.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the address couldn't be listened on, e.g., because something already
+    /// exists at the path of the socket.
+    fn bind(address: &str, unix: bool) -> io::Result<Self> {
+        if unix {
+            Ok(Self::Unix(
+                UnixListener::bind(address)?,
+                PathBuf::from(address),
+            ))
+        } else {
+            Ok(Self::Tcp(TcpListener::bind(address)?))
+        }
+    }
+
+    /// Describes the address the socket listens on, which may have been picked by the system.
+    ///
+    /// # Returns
+    ///
+    /// Returns the address, or the path of the socket.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the address of the socket couldn't be retrieved.
+    fn local_address(&self) -> io::Result<String> {
+        match self {
+            Self::Tcp(listener) => Ok(listener.local_addr()?.to_string()),
+            Self::Unix(_, path) => Ok(path.display().to_string()),
+        }
+    }
+
+    /// Accepts a connection.
+    ///
+    /// # Returns
+    ///
+    /// Returns the connection of the client, and who the client is.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no connection could be accepted.
+    fn accept(&self) -> io::Result<(Socket, Client)> {
+        match self {
+            Self::Tcp(listener) => {
+                let (stream, peer) = listener.accept()?;
+                Ok((
+                    Socket::Tcp(stream),
+                    Client::Tcp {
+                        address: peer.to_string(),
+                    },
+                ))
+            }
+            Self::Unix(listener, _) => {
+                let (stream, _) = listener.accept()?;
+                let maybe_credentials = peer_credentials(&stream).ok();
+                Ok((Socket::Unix(stream), Client::Unix(maybe_credentials)))
+            }
+        }
+    }
+}
+
+impl AsFd for Listener {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        match self {
+            Self::Tcp(listener) => listener.as_fd(),
+            Self::Unix(listener, _) => listener.as_fd(),
+        }
+    }
+}
+
+impl Drop for Listener {
+    /// Removes the Unix domain socket, which would otherwise be left behind, and keep the next
+    /// session from creating it again.
+    fn drop(&mut self) {
+        if let Self::Unix(_, path) = self {
+            // We don't care about an error here, because the socket may already have been
+            // removed by someone else, and there is nothing left to clean up then.
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// Who is on the other end of a connection.
+#[derive(Serialize)]
+#[serde(untagged)]
+enum Client {
+    /// A TCP client.
+    Tcp {
+        /// Address of the client.
+        address: String,
+    },
+    /// A Unix domain socket client, with its credentials, if the system tells them.
+    Unix(Option<PeerCredentials>),
+}
+
+impl fmt::Display for Client {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tcp { address } => write!(f, "{address}"),
+            Self::Unix(Some(credentials)) => write!(f, "{credentials}"),
+            Self::Unix(None) => write!(f, "an unknown process"),
+        }
+    }
+}
+
+/// Description of a connection, written next to its log files.
+#[derive(Serialize)]
+struct ConnectionInfo<'a> {
+    /// Number of the connection, counting from 1.
+    connection: u64,
+    /// Wall-clock time at which the client connected.
+    connected_at: String,
+    /// Who the client is.
+    client: &'a Client,
+}
+
+/// The credentials of the process on the other end of a Unix domain socket.
+#[derive(Serialize)]
+struct PeerCredentials {
+    /// PID of the process, if the system tells it.
+    #[serde(rename = "pid")]
+    maybe_pid: Option<i32>,
+    /// Effective user ID of the process.
+    uid: u32,
+    /// Effective group ID of the process.
+    gid: u32,
+}
+
+impl fmt::Display for PeerCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(pid) = self.maybe_pid {
+            write!(f, "pid {pid}, ")?;
+        }
+        write!(f, "uid {}, gid {}", self.uid, self.gid)
+    }
+}
+
+/// Retrieves the credentials of the process on the other end of a Unix domain socket, as they
+/// were when it connected.
+///
+/// # Arguments
+///
+/// * `stream` - The connected socket.
+///
+/// # Returns
+///
+/// Returns the credentials of the process.
+///
+/// # Errors
+///
+/// Returns an error if the credentials couldn't be retrieved, or the system doesn't tell them.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn peer_credentials(stream: &UnixStream) -> io::Result<PeerCredentials> {
+    let credentials =
+        nix::sys::socket::getsockopt(stream, nix::sys::socket::sockopt::PeerCredentials)?;
+    Ok(PeerCredentials {
+        maybe_pid: Some(credentials.pid()),
+        uid: credentials.uid(),
+        gid: credentials.gid(),
+    })
+}
+
+/// Retrieves the credentials of the process on the other end of a Unix domain socket, as they
+/// were when it connected.
+///
+/// # Arguments
+///
+/// * `stream` - The connected socket.
+///
+/// # Returns
+///
+/// Returns the credentials of the process.
+///
+/// # Errors
+///
+/// Returns an error if the credentials couldn't be retrieved, or the system doesn't tell them.
+#[cfg(target_vendor = "apple")]
+fn peer_credentials(stream: &UnixStream) -> io::Result<PeerCredentials> {
+    let (uid, gid) = nix::unistd::getpeereid(stream)?;
+    Ok(PeerCredentials {
+        maybe_pid: nix::sys::socket::getsockopt(stream, nix::sys::socket::sockopt::LocalPeerPid)
+            .ok(),
+        uid: uid.as_raw(),
+        gid: gid.as_raw(),
+    })
+}
+
+/// Retrieves the credentials of the process on the other end of a Unix domain socket, as they
+/// were when it connected.
+///
+/// # Arguments
+///
+/// * `stream` - The connected socket.
+///
+/// # Returns
+///
+/// Returns the credentials of the process.
+///
+/// # Errors
+///
+/// Returns an error if the credentials couldn't be retrieved, or the system doesn't tell them.
+#[cfg(not(any(target_os = "linux", target_os = "android", target_vendor = "apple")))]
+fn peer_credentials(_stream: &UnixStream) -> io::Result<PeerCredentials> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+/// A side of a connection.
+enum Socket {
+    /// A TCP connection.
+    Tcp(TcpStream),
+    /// A Unix domain socket connection.
+    Unix(UnixStream),
+}
+
+impl Socket {
+    /// Connects to a server.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The address of the server, or the path of its socket.
+    /// * `unix` - Whether to connect to a Unix domain socket, instead of over TCP.
+    ///
+    /// # Returns
+    ///
+    /// Returns the connection.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the server couldn't be connected to.
+    fn connect(address: &str, unix: bool) -> io::Result<Self> {
+        if unix {
+            Ok(Self::Unix(UnixStream::connect(address)?))
+        } else {
+            Ok(Self::Tcp(TcpStream::connect(address)?))
+        }
+    }
+
+    /// Duplicates the socket, so that it can be read from and written into at the same time.
+    ///
+    /// # Returns
+    ///
+    /// Returns the duplicate, which refers to the same connection.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the socket couldn't be duplicated.
+    fn try_clone(&self) -> io::Result<Self> {
+        match self {
+            Self::Tcp(stream) => Ok(Self::Tcp(stream.try_clone()?)),
+            Self::Unix(stream) => Ok(Self::Unix(stream.try_clone()?)),
+        }
+    }
+
+    /// Ends either or both directions of the connection.
+    ///
+    /// # Arguments
+    ///
+    /// * `how` - The directions to end.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the connection couldn't be shut down.
+    fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        match self {
+            Self::Tcp(stream) => stream.shutdown(how),
+            Self::Unix(stream) => stream.shutdown(how),
+        }
+    }
+}
+
+impl Read for Socket {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Tcp(stream) => stream.read(buf),
+            Self::Unix(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Socket {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Tcp(stream) => stream.write(buf),
+            Self::Unix(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Tcp(stream) => stream.flush(),
+            Self::Unix(stream) => stream.flush(),
+        }
+    }
+}
+
+impl AsFd for Socket {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        match self {
+            Self::Tcp(stream) => stream.as_fd(),
+            Self::Unix(stream) => stream.as_fd(),
+        }
+    }
+}
+
+impl AsRawFd for Socket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.as_fd().as_raw_fd()
+    }
+}
+
+/// Where a stream of a connection is written into.
+///
+/// Since the same socket is also read from, and is polled, it may be non-blocking, so writing
+/// waits for the socket to be writable whenever it would block.
+struct SocketOutput(Socket);
+
+impl Write for SocketOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        loop {
+            match self.0.write(buf) {
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    poll::poll(
+                        &mut [PollFd::new(self.0.as_fd(), PollFlags::POLLOUT)],
+                        PollTimeout::NONE,
+                    )?;
+                }
+                // The other end went away, which is what a broken pipe means for a pipe.
+                Err(e) if e.kind() == io::ErrorKind::ConnectionReset => {
+                    return Err(io::ErrorKind::BrokenPipe.into());
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl Drop for SocketOutput {
+    /// Ends the stream, so that the other end sees it end, as it would without fdintercept in
+    /// between.
+    fn drop(&mut self) {
+        // We don't care about an error here, because the other end may already have closed the
+        // connection, and then there is no stream to end anymore.
+        let _ = self.0.shutdown(Shutdown::Write);
+    }
+}
+
+/// The parsed names of the log files of each connection, whose placeholders are yet to be
+/// expanded.
+struct ConnectionLogTemplates {
+    /// Name of the log file of what the client sends.
+    client: PathTemplate,
+    /// Name of the log file of what the server sends back.
+    server: PathTemplate,
+    /// Name of the timeline log file, if any.
+    timeline: Option<PathTemplate>,
+    /// Name of the file that describes the connection.
+    info: PathTemplate,
+}
+
+/// The logs of a connection.
+struct ConnectionLogs {
+    /// The log of what the client sends, if it is logged anywhere.
+    client: Option<StreamLog>,
+    /// The log of what the server sends back, if it is logged anywhere.
+    server: Option<StreamLog>,
+    /// The timeline log of the connection, if any.
+    maybe_timeline: Option<Arc<SharedLog>>,
+}
+
+/// What every connection needs to be relayed and logged.
+struct Proxy<'a> {
+    /// The settings of the session.
+    settings: &'a ResolvedSettings,
+    /// Address of the upstream server, or the path of its socket.
+    upstream: &'a str,
+    /// Whether the addresses are paths of Unix domain sockets.
+    unix: bool,
+    /// The session.
+    session: &'a Session,
+    /// Clock that timestamps the records.
+    clock: Clock,
+    /// The parsed names of the log files of each connection.
+    templates: ConnectionLogTemplates,
+    /// The session directory, if any, that relative names are relative to.
+    maybe_session_dir: Option<&'a Path>,
+    /// The reopener of the log files, if they are reopened on a signal.
+    maybe_reopener: Option<&'a LogReopener>,
+}
+
+impl Proxy<'_> {
+    /// Writes the file that describes a connection, expanding the placeholders in its name.
+    ///
+    /// # Arguments
+    ///
+    /// * `connection` - Number of the connection.
+    /// * `client` - Who the client is.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file or its parent directories couldn't be written.
+    fn write_info(&self, connection: u64, client: &Client) -> Result<()> {
+        let path = self
+            .templates
+            .info
+            .expand(self.session, TemplateValues::Connection(connection));
+        let path = session::resolve_path(self.maybe_session_dir, &path);
+        let (connected_at, _) = self.clock.now();
+        let info = ConnectionInfo {
+            connection,
+            connected_at: humantime::format_rfc3339_micros(connected_at).to_string(),
+            client,
+        };
+
+        let mut contents = serde_json::to_vec_pretty(&info)?;
+        contents.push(b'\n');
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).with_context(|| {
+                format!(
+                    "Error creating parent directories of connection info {}",
+                    path.display()
+                )
+            })?;
+        }
+        fs::write(&path, contents)
+            .with_context(|| format!("Error writing connection info {}", path.display()))
+    }
+
+    /// Creates the log files of a connection, expanding the placeholders in their names.
+    ///
+    /// # Arguments
+    ///
+    /// * `connection` - Number of the connection.
+    ///
+    /// # Returns
+    ///
+    /// Returns the logs of the connection.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any of the log files couldn't be created.
+    fn create_logs(&self, connection: u64) -> Result<ConnectionLogs> {
+        let settings = self.settings;
+        let record_format = session_logs::record_format(settings);
+        let maybe_rotation = session_logs::rotation(settings);
+        let create_log_file = |template: &PathTemplate| {
+            fd::create_log_file(
+                Some(&template.expand(self.session, TemplateValues::Connection(connection))),
+                self.maybe_session_dir,
+                settings.recreate_logs,
+                settings.compression,
+                maybe_rotation,
+                self.maybe_reopener,
+            )
+        };
+
+        let mut shared_logs = Vec::new();
+        if let Some(template) = &self.templates.timeline {
+            if let Some(file) = create_log_file(template)? {
+                shared_logs.push(Arc::new(SharedLog::timeline(file, record_format.clone())));
+            }
+        }
+        let client = StreamLog::new(
+            "client",
+            self.clock,
+            create_log_file(&self.templates.client)?,
+            &record_format,
+            None,
+            &shared_logs,
+        );
+        let server = StreamLog::new(
+            "server",
+            self.clock,
+            create_log_file(&self.templates.server)?,
+            &record_format,
+            None,
+            &shared_logs,
+        );
+        Ok(ConnectionLogs {
+            client,
+            server,
+            maybe_timeline: shared_logs.pop(),
+        })
+    }
+
+    /// Describes a client, connects it to the upstream server, and relays both directions of the
+    /// connection until they have both ended, or fdintercept is told to stop. Then summarizes the
+    /// traffic of each direction, if asked to.
+    ///
+    /// # Arguments
+    ///
+    /// * `connection` - Number of the connection.
+    /// * `client` - The connection of the client.
+    /// * `peer` - Who the client is.
+    /// * `signal_rx` - File descriptor that becomes readable once fdintercept should stop.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` once the connection ended.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - Failed to write the file that describes the connection,
+    /// - Failed to connect to the upstream server,
+    /// - Failed to create the log files, or
+    /// - Failed to relay either direction of the connection.
+    fn relay(
+        &self,
+        connection: u64,
+        client: Socket,
+        peer: &Client,
+        signal_rx: OwnedFd,
+    ) -> Result<()> {
+        self.write_info(connection, peer)?;
+        let server = Socket::connect(self.upstream, self.unix)
+            .with_context(|| format!("Error connecting to {}", self.upstream))?;
+        let ConnectionLogs {
+            client: client_log,
+            server: server_log,
+            maybe_timeline,
+        } = self.create_logs(connection)?;
+
+        let buffer_size = self.settings.buffer_size;
+        let client_stats = &StreamStats::default();
+        let server_stats = &StreamStats::default();
+        let to_server_signal_rx = signal_rx
+            .try_clone()
+            .context("Error duplicating signal pipe")?;
+        let (to_server_result, to_client_result) = thread::scope(|scope| {
+            let to_server = scope.spawn(|| {
+                relay_stream(
+                    &client,
+                    &server,
+                    buffer_size,
+                    client_log,
+                    client_stats,
+                    "client",
+                    to_server_signal_rx,
+                )
+            });
+            let to_client_result = relay_stream(
+                &server,
+                &client,
+                buffer_size,
+                server_log,
+                server_stats,
+                "server",
+                signal_rx,
+            );
+            (to_server.join(), to_client_result)
+        });
+
+        if self.settings.summary {
+            eprintln!(
+                "fdintercept summary of connection {connection}:\nclient: {}\nserver: {}",
+                client_stats.summary(),
+                server_stats.summary()
+            );
+        }
+        // Both directions have ended, so the timeline is complete.
+        if let Some(timeline) = maybe_timeline {
+            timeline.finish().context("Error finishing timeline log")?;
+        }
+        to_server_result.map_err(|_| anyhow::anyhow!("Relaying the client panicked"))??;
+        to_client_result
+    }
+}
+
+/// Relays one direction of a connection until it ends, or fdintercept is told to stop, and then
+/// ends it for the other end too.
+///
+/// # Arguments
+///
+/// * `src` - The socket to read from.
+/// * `dst` - The socket to write into.
+/// * `buffer_size` - Size of the buffer in bytes used for data transfer.
+/// * `maybe_log` - Optional log for recording the transferred data.
+/// * `stats` - Counters of the direction.
+/// * `name` - Name of the direction, for error messages.
+/// * `signal_rx` - File descriptor that becomes readable once fdintercept should stop.
+///
+/// # Returns
+///
+/// Returns `Ok(())` once the direction ended.
+///
+/// # Errors
+///
+/// Returns an error if the sockets couldn't be duplicated, or the direction couldn't be relayed.
+fn relay_stream(
+    src: &Socket,
+    dst: &Socket,
+    buffer_size: usize,
+    maybe_log: Option<StreamLog>,
+    stats: &StreamStats,
+    name: &'static str,
+    signal_rx: OwnedFd,
+) -> Result<()> {
+    fd::process_fd(
+        src.try_clone().context("Error duplicating socket")?,
+        SocketOutput(dst.try_clone().context("Error duplicating socket")?),
+        buffer_size,
+        maybe_log,
+        stats,
+        name,
+        Some(signal_rx),
+    )
+}
+
+/// Waits for signals, and tells the connections to stop once fdintercept should.
+///
+/// # Arguments
+///
+/// * `signals` - Signal iterator providing incoming Unix signals.
+/// * `signal_tx` - File descriptor for telling the connections to stop.
+/// * `maybe_reopen` - Optional signal that makes the log files be reopened, and the reopener of
+///   the log files.
+fn process_signals(
+    mut signals: Signals,
+    signal_tx: &OwnedFd,
+    maybe_reopen: Option<(i32, &LogReopener)>,
+) {
+    for signum in signals.forever() {
+        match maybe_reopen {
+            Some((reopen_signum, reopener)) if signum == reopen_signum => {
+                reopener.reopen_all_reporting();
+            }
+            _ => break,
+        }
+    }
+    // The pipe is never read from, so it stays readable for every connection.
+    let _ = nix::unistd::write(signal_tx, &[1]);
+}
+
+/// Listens on a local address, or on a Unix domain socket, and relays each connection to it to an
+/// upstream server, logging what each client sends and what the server sends back, until
+/// fdintercept is told to stop.
+///
+/// # Arguments
+///
+/// * `settings` - The settings of the session.
+/// * `listen` - Local address to listen on, or the path of the socket to create.
+/// * `upstream` - Address of the upstream server, or the path of its socket.
+/// * `unix` - Whether the addresses are paths of Unix domain sockets.
+/// * `client_log` - Name of the log file of what each client sends.
+/// * `server_log` - Name of the log file of what the server sends back to each client.
+/// * `connection_info` - Name of the file that describes each connection.
+///
+/// # Returns
+///
+/// Returns `Ok(())` once all the connections ended, after fdintercept was told to stop.
+///
+/// # Errors
+///
+/// Returns an error if:
+/// - Failed to register the signal handlers,
+/// - Any of the names of the log files isn't a valid template,
+/// - Failed to create the session directory,
+/// - Failed to listen on the local address, or
+/// - Failed to wait for connections.
+///
+/// # Signal Handling
+///
+/// - SIGHUP, SIGINT, and SIGTERM: Stop listening, and end all the connections.
+/// - The configured reopen signal, if any: Reopen the log files. If it is SIGHUP, SIGHUP no longer
+///   stops.
+pub fn run(
+    settings: &ResolvedSettings,
+    listen: &str,
+    upstream: &str,
+    unix: bool,
+    client_log: &Path,
+    server_log: &Path,
+    connection_info: &Path,
+) -> Result<()> {
+    let signals =
+        Signals::new([SIGHUP, SIGINT, SIGTERM]).context("Failed to register signal handlers")?;
+    let maybe_reopen_signum = settings.reopen_signal.map(ReopenSignal::signum);
+    if let Some(signum) = maybe_reopen_signum {
+        signals
+            .add_signal(signum)
+            .context("Failed to register reopen signal handler")?;
+    }
+    let maybe_reopener = maybe_reopen_signum.map(|_| LogReopener::default());
+
+    let clock = Clock::new();
+    let session = Session::new(clock.started_at());
+    let parse = |path: &Path| {
+        PathTemplate::parse(path, TemplateScope::Connection)
+            .with_context(|| format!("Error parsing log file name {}", path.display()))
+    };
+    let templates = ConnectionLogTemplates {
+        client: parse(client_log)?,
+        server: parse(server_log)?,
+        timeline: settings.timeline_log.as_deref().map(parse).transpose()?,
+        info: parse(connection_info)?,
+    };
+    let maybe_session_dir = session_logs::create_session_dir(&session, settings)?;
+
+    let listener =
+        Listener::bind(listen, unix).with_context(|| format!("Error listening on {listen}"))?;
+    // The port may have been picked by the system, so the address is told.
+    eprintln!(
+        "fdintercept: listening on {}",
+        listener
+            .local_address()
+            .context("Error getting listening address")?
+    );
+
+    let proxy = Proxy {
+        settings,
+        upstream,
+        unix,
+        session: &session,
+        clock,
+        templates,
+        maybe_session_dir: maybe_session_dir.as_deref(),
+        maybe_reopener: maybe_reopener.as_ref(),
+    };
+    let (signal_rx, signal_tx) = pipe().context("Error creating pipe")?;
+    let signals_handle = signals.handle();
+
+    thread::scope(|scope| -> Result<()> {
+        let proxy = &proxy;
+        let signal_tx = &signal_tx;
+        scope.spawn(move || {
+            process_signals(
+                signals,
+                signal_tx,
+                maybe_reopen_signum.zip(proxy.maybe_reopener),
+            );
+        });
+
+        let mut connection = 0;
+        let result = loop {
+            let mut fds = [
+                PollFd::new(listener.as_fd(), PollFlags::POLLIN),
+                PollFd::new(signal_rx.as_fd(), PollFlags::POLLIN),
+            ];
+            match poll::poll(&mut fds, PollTimeout::NONE) {
+                Ok(_) | Err(Errno::EINTR) => (),
+                Err(e) => break Err(e).context("Error waiting for connections"),
+            }
+            if fds[1].any().unwrap_or_default() {
+                break Ok(());
+            }
+            if !fds[0].any().unwrap_or_default() {
+                continue;
+            }
+
+            let (client, peer) = match listener.accept() {
+                Ok(accepted) => accepted,
+                // Failing to accept a connection only fails that connection.
+                Err(e) => {
+                    eprintln!("Error accepting connection: {e}");
+                    continue;
+                }
+            };
+            connection += 1;
+            eprintln!("fdintercept: connection {connection} from {peer}");
+            let signal_rx = match signal_rx.try_clone() {
+                Ok(signal_rx) => signal_rx,
+                Err(e) => break Err(e).context("Error duplicating signal pipe"),
+            };
+            scope.spawn(move || {
+                if let Err(e) = proxy.relay(connection, client, &peer, signal_rx) {
+                    eprintln!("Error in connection {connection} from {peer}: {e:#}");
+                }
+            });
+        };
+
+        // Whatever stopped the listening, the connections and the signal processing stop too.
+        let _ = nix::unistd::write(signal_tx, &[1]);
+        signals_handle.close();
+        result
+    })
+}
@@ -0,0 +1,210 @@
+//! Pseudo-terminals for the target command.
+//!
+//! This module provides functionality for running the target command in a pseudo-terminal,
+//! instead of with pipes, so that it finds a terminal on its standard streams and behaves as it
+//! would when run interactively, e.g., with colors, line-buffered output, and interactive prompts.
+
+use crate::terminal::WindowSize;
+use anyhow::{Context, Result};
+use nix::fcntl::{self, FdFlag};
+use nix::poll::{self, PollFd, PollFlags, PollTimeout};
+use nix::pty::{self, OpenptyResult, Winsize};
+use nix::sys::termios::{self, SpecialCharacterIndices, Termios};
+use std::fs::File;
+use std::io::{self, Write};
+use std::os::fd::{AsFd, OwnedFd};
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command};
+
+/// Character that ends the input of a terminal when its settings can't be read: Ctrl+D.
+const DEFAULT_EOF: u8 = 0x04;
+
+nix::ioctl_write_int_bad!(
+    /// Makes the terminal attached to a file descriptor the controlling terminal of the calling
+    /// process.
+    tiocsctty,
+    nix::libc::TIOCSCTTY
+);
+
+/// A pseudo-terminal for the target command to run in.
+pub struct Pty {
+    /// The end of the pseudo-terminal that fdintercept relays the streams through.
+    master: OwnedFd,
+    /// The end of the pseudo-terminal that the target command is attached to.
+    slave: OwnedFd,
+}
+
+impl Pty {
+    /// Opens a pseudo-terminal.
+    ///
+    /// # Arguments
+    ///
+    /// * `window_size` - The initial window size of the pseudo-terminal.
+    ///
+    /// # Returns
+    ///
+    /// Returns the pseudo-terminal.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - Failed to open the pseudo-terminal, or
+    /// - Failed to make its file descriptors close on exec.
+    pub fn open(window_size: WindowSize) -> Result<Self> {
+        let winsize = Winsize::from(window_size);
+        let OpenptyResult { master, slave } =
+            pty::openpty(&winsize, None::<&Termios>).context("Error opening pseudo-terminal")?;
+
+        // The target command must only get the pseudo-terminal as its standard streams. Any other
+        // copy of it that leaked into it would keep the pseudo-terminal open after the target
+        // command ended.
+        for fd in [&master, &slave] {
+            fcntl::fcntl(fd, fcntl::F_SETFD(FdFlag::FD_CLOEXEC))
+                .context("Error setting pseudo-terminal to close on exec")?;
+        }
+
+        Ok(Self { master, slave })
+    }
+
+    /// Spawns a command with the pseudo-terminal as its stdin, stdout, stderr, and controlling
+    /// terminal.
+    ///
+    /// The command is consumed, so that fdintercept holds no copy of the end of the pseudo-terminal
+    /// that the command is attached to, and the pseudo-terminal is closed once the command, and
+    /// anything else it started and attached to it, ended.
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - The command to spawn.
+    ///
+    /// # Returns
+    ///
+    /// Returns the child process, the input of the pseudo-terminal, to write the stdin of the
+    /// child process into, and the output of the pseudo-terminal, to read the stdout and stderr of
+    /// the child process from.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - Failed to duplicate the file descriptors of the pseudo-terminal, or
+    /// - Failed to spawn the command.
+    pub fn spawn(self, mut command: Command) -> Result<(Child, PtyInput, File)> {
+        command
+            .stdin(
+                self.slave
+                    .try_clone()
+                    .context("Error duplicating pseudo-terminal")?,
+            )
+            .stdout(
+                self.slave
+                    .try_clone()
+                    .context("Error duplicating pseudo-terminal")?,
+            )
+            .stderr(self.slave);
+        // SAFETY: The closure runs in the child process, between `fork` and `exec`, where only
+        // async-signal-safe functions may be called, and `setsid` and `ioctl` are.
+        unsafe {
+            command.pre_exec(|| {
+                // A terminal can only become the controlling terminal of a session leader. Being
+                // in a session of its own, the child process also gets the signals of the
+                // pseudo-terminal, such as SIGINT for Ctrl+C, instead of fdintercept.
+                nix::unistd::setsid()?;
+                // By now, stdin is already the pseudo-terminal.
+                tiocsctty(0, 0)?;
+                Ok(())
+            });
+        }
+        let child = command.spawn()?;
+        drop(command);
+
+        let input = PtyInput(File::from(
+            self.master
+                .try_clone()
+                .context("Error duplicating pseudo-terminal")?,
+        ));
+        Ok((child, input, File::from(self.master)))
+    }
+}
+
+/// The input of a pseudo-terminal, through which the stdin of the target command is written.
+///
+/// Since the output of the pseudo-terminal is the same file, and is polled, this may be
+/// non-blocking, so writing waits for the pseudo-terminal to be writable whenever it would block.
+pub struct PtyInput(File);
+
+impl Write for PtyInput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        loop {
+            match self.0.write(buf) {
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    poll::poll(
+                        &mut [PollFd::new(self.0.as_fd(), PollFlags::POLLOUT)],
+                        PollTimeout::NONE,
+                    )?;
+                }
+                // This is how a pseudo-terminal reports that the target command closed it, which
+                // is what a broken pipe means for a pipe.
+                Err(e) if e.raw_os_error() == Some(nix::libc::EIO) => {
+                    return Err(io::ErrorKind::BrokenPipe.into());
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl Drop for PtyInput {
+    /// Ends the input of the target command.
+    ///
+    /// Unlike a pipe, a pseudo-terminal isn't closed when its input is, so the end of the input is
+    /// typed into it instead, like Ctrl+D. As in a terminal, this only ends the input if it comes
+    /// after a newline.
+    fn drop(&mut self) {
+        let eof = termios::tcgetattr(&self.0).map_or(DEFAULT_EOF, |termios| {
+            termios.control_chars[SpecialCharacterIndices::VEOF as usize]
+        });
+        // We don't care about an error here, because the target command may already have ended,
+        // and then there is no input to end anymore.
+        let _ = self.0.write(&[eof]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    mod pty_spawn {
+        use super::*;
+
+        #[test]
+        fn attaches_a_terminal() {
+            let (mut child, mut input, mut output) = Pty::open(WindowSize { cols: 91, rows: 17 })
+                .unwrap()
+                .spawn({
+                    let mut command = Command::new("sh");
+                    command.args(["-c", "[ -t 0 ] && [ -t 1 ] && [ -t 2 ] && stty size; cat"]);
+                    command
+                })
+                .unwrap();
+
+            input.write_all(b"hello\n").unwrap();
+            // Ends `cat`.
+            drop(input);
+            assert!(child.wait().unwrap().success());
+
+            let mut contents = Vec::new();
+            // Reading a pseudo-terminal that was closed fails instead of reaching its end, but
+            // what was read until then is kept.
+            let _ = output.read_to_end(&mut contents);
+            let contents = String::from_utf8(contents).unwrap();
+            assert!(contents.contains("17 91\r\n"));
+            // Once echoed by the terminal, and once by `cat`.
+            assert_eq!(contents.matches("hello\r\n").count(), 2);
+        }
+    }
+}
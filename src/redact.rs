@@ -0,0 +1,169 @@
+//! Redaction of input typed while terminal echo is off.
+//!
+//! Programs that ask for passwords, such as `ssh`, `sudo`, and database CLIs, turn off the echo of
+//! their terminal while the password is typed, so that it isn't seen on screen. This module
+//! provides a log of stdin that honors that: whatever is read from stdin while echo is off is
+//! replaced by a redaction marker, so that credentials are never written to the logs.
+
+use crate::log::ChunkLog;
+use nix::sys::termios::{self, LocalFlags};
+use std::io;
+use std::os::fd::OwnedFd;
+
+/// Marker that replaces the input typed while terminal echo is off.
+pub const REDACTION_MARKER: &[u8] = b"[redacted]";
+
+/// A log of stdin that replaces what is read while terminal echo is off with a redaction marker.
+///
+/// Only a terminal in canonical mode, where input is read a line at a time, with echo off, counts
+/// as hiding its input, as a password prompt does. Shells with line editing and full-screen
+/// programs put their terminal in non-canonical mode with echo off too, but echo the input
+/// themselves, so what is typed into them is still logged.
+///
+/// Each run of chunks read while echo is off is replaced by a single marker, so that not even the
+/// length of what was typed is recorded.
+pub struct RedactingLog<L> {
+    /// The log that the chunks, or the markers replacing them, are written to.
+    inner: L,
+    /// The terminal whose echo is checked, if any. Without one, nothing is redacted.
+    maybe_terminal: Option<OwnedFd>,
+    /// Whether the previous chunk was redacted.
+    redacting: bool,
+}
+
+impl<L> RedactingLog<L> {
+    /// Wraps the log of stdin so that what is read while terminal echo is off is redacted.
+    ///
+    /// # Arguments
+    ///
+    /// * `inner` - The log of stdin.
+    /// * `maybe_terminal` - The terminal the input of the target command is typed into, if any.
+    ///
+    /// # Returns
+    ///
+    /// Returns the wrapped log.
+    pub const fn new(inner: L, maybe_terminal: Option<OwnedFd>) -> Self {
+        Self {
+            inner,
+            maybe_terminal,
+            redacting: false,
+        }
+    }
+
+    /// Checks whether the terminal hides its input.
+    ///
+    /// # Returns
+    ///
+    /// Returns `true` if echo is off in canonical mode. If the mode of the terminal can't be read,
+    /// its input is considered hidden, so that nothing is logged that shouldn't have been.
+    fn input_hidden(&self) -> bool {
+        self.maybe_terminal.as_ref().is_some_and(|terminal| {
+            termios::tcgetattr(terminal).map_or(true, |mode| {
+                mode.local_flags & (LocalFlags::ECHO | LocalFlags::ICANON) == LocalFlags::ICANON
+            })
+        })
+    }
+}
+
+impl<L: ChunkLog> ChunkLog for RedactingLog<L> {
+    fn log_chunk(&mut self, chunk: &[u8]) -> io::Result<()> {
+        if !self.input_hidden() {
+            self.redacting = false;
+            return self.inner.log_chunk(chunk);
+        }
+        if self.redacting {
+            return Ok(());
+        }
+        self.redacting = true;
+        self.inner.log_chunk(REDACTION_MARKER)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.inner.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nix::pty::{OpenptyResult, openpty};
+    use nix::sys::termios::SetArg;
+
+    /// A log that keeps the chunks in memory.
+    #[derive(Default)]
+    struct MemoryLog(Vec<Vec<u8>>);
+
+    impl ChunkLog for &mut MemoryLog {
+        fn log_chunk(&mut self, chunk: &[u8]) -> io::Result<()> {
+            self.0.push(chunk.to_vec());
+            Ok(())
+        }
+    }
+
+    /// Turns the given local flags of a pseudo-terminal on or off.
+    fn set_local_flags(pty: &OpenptyResult, flags: LocalFlags, on: bool) {
+        let mut mode = termios::tcgetattr(&pty.slave).unwrap();
+        mode.local_flags.set(flags, on);
+        termios::tcsetattr(&pty.slave, SetArg::TCSANOW, &mode).unwrap();
+    }
+
+    mod redacting_log {
+        use super::*;
+
+        #[test]
+        fn echo_on() {
+            let pty = openpty(None, None).unwrap();
+            let mut memory = MemoryLog::default();
+            let mut log = RedactingLog::new(&mut memory, Some(pty.master.try_clone().unwrap()));
+
+            log.log_chunk(b"ls\n").unwrap();
+
+            assert_eq!(memory.0, vec![b"ls\n".to_vec()]);
+        }
+
+        #[test]
+        fn echo_off() {
+            let pty = openpty(None, None).unwrap();
+            let mut memory = MemoryLog::default();
+            let mut log = RedactingLog::new(&mut memory, Some(pty.master.try_clone().unwrap()));
+
+            log.log_chunk(b"ssh host\n").unwrap();
+            set_local_flags(&pty, LocalFlags::ECHO, false);
+            log.log_chunk(b"hunter").unwrap();
+            log.log_chunk(b"2\n").unwrap();
+            set_local_flags(&pty, LocalFlags::ECHO, true);
+            log.log_chunk(b"exit\n").unwrap();
+
+            assert_eq!(
+                memory.0,
+                vec![
+                    b"ssh host\n".to_vec(),
+                    REDACTION_MARKER.to_vec(),
+                    b"exit\n".to_vec()
+                ]
+            );
+        }
+
+        #[test]
+        fn echo_off_in_non_canonical_mode() {
+            let pty = openpty(None, None).unwrap();
+            set_local_flags(&pty, LocalFlags::ECHO | LocalFlags::ICANON, false);
+            let mut memory = MemoryLog::default();
+            let mut log = RedactingLog::new(&mut memory, Some(pty.master.try_clone().unwrap()));
+
+            log.log_chunk(b"l").unwrap();
+
+            assert_eq!(memory.0, vec![b"l".to_vec()]);
+        }
+
+        #[test]
+        fn no_terminal() {
+            let mut memory = MemoryLog::default();
+            let mut log = RedactingLog::new(&mut memory, None);
+
+            log.log_chunk(b"hunter2\n").unwrap();
+
+            assert_eq!(memory.0, vec![b"hunter2\n".to_vec()]);
+        }
+    }
+}
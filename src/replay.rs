@@ -0,0 +1,515 @@
+//! Replaying of recorded input.
+//!
+//! This module provides functionality for feeding input that was recorded in a previous session,
+//! such as a raw `stdin.log`, into a fresh run of the target command, so that the session can be
+//! reproduced without whatever originally produced the input.
+//!
+//! If a timing file was recorded alongside the input, the input can be fed with its original
+//! timing, so that targets that behave differently depending on how their input is spread over
+//! time, e.g., because they debounce it, see it just like they did originally.
+
+use crate::compress;
+use crate::fd;
+use crate::log::ChunkLog;
+use crate::stats::{CountingReader, StreamStats};
+use anyhow::{Context, Result};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::fd::{AsRawFd, OwnedFd};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Mio token that represents that a signal has arrived.
+const SIGNAL_TOKEN: usize = 0;
+
+/// Recorded input, ready to be fed into the target command.
+pub struct Recording {
+    /// The recorded input.
+    input: Box<dyn Read + Send>,
+    /// The timing file of the input and the factor by which its timing is sped up, if the input
+    /// should be fed with its original timing.
+    maybe_timing: Option<(BufReader<Box<dyn Read + Send>>, f64)>,
+}
+
+impl Recording {
+    /// Opens recorded input.
+    ///
+    /// The input and the timing file are decompressed as they are read if their names end in
+    /// `.gz` or `.zst`.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - Path to the recorded input.
+    /// * `maybe_timing` - Optional path to the timing file of the input.
+    /// * `speed` - Factor by which the original timing is sped up. Ignored if there's no timing
+    ///   file.
+    ///
+    /// # Returns
+    ///
+    /// Returns the opened recording.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the input or the timing file can't be opened.
+    pub fn open(input: &Path, maybe_timing: Option<&Path>, speed: f64) -> Result<Self> {
+        Ok(Self {
+            input: compress::open_recording(input).context(format!(
+                "Failed to open recorded input: {}",
+                input.display()
+            ))?,
+            maybe_timing: match maybe_timing {
+                Some(timing) => Some((
+                    BufReader::new(
+                        compress::open_recording(timing)
+                            .context(format!("Failed to open timing file: {}", timing.display()))?,
+                    ),
+                    speed,
+                )),
+                None => None,
+            },
+        })
+    }
+
+    /// Wraps text given inline as input, to be fed as it is.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The text to feed.
+    ///
+    /// # Returns
+    ///
+    /// Returns the text as a recording without timing.
+    pub fn text(text: &str) -> Self {
+        Self {
+            input: Box::new(io::Cursor::new(text.as_bytes().to_vec())),
+            maybe_timing: None,
+        }
+    }
+
+    /// Feeds the recording into a destination.
+    ///
+    /// # Arguments
+    ///
+    /// * `dst` - Destination to feed the input into, usually the stdin of the target command.
+    /// * `buffer_size` - Size of the buffer in bytes used for data transfer.
+    /// * `maybe_log` - Optional log for recording the fed data.
+    /// * `stats` - Counters of stdin, updated with every read from the recording.
+    /// * `signal_rx` - File descriptor that becomes readable when fdintercept is told to
+    ///   terminate, which interrupts the wait between timed chunks.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` when all the input was fed, when the destination was closed before that,
+    /// or when a signal arrived.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the input or the timing file can't be read, or if the destination
+    /// can't be written to.
+    pub fn feed(
+        self,
+        dst: impl Write,
+        buffer_size: usize,
+        mut maybe_log: Option<impl ChunkLog>,
+        stats: &StreamStats,
+        signal_rx: OwnedFd,
+    ) -> Result<()> {
+        let input = CountingReader::new(self.input, stats);
+        match self.maybe_timing {
+            Some((timing, speed)) => feed_timed_input(
+                input,
+                timing,
+                speed,
+                dst,
+                buffer_size,
+                &mut maybe_log,
+                &signal_rx,
+            ),
+            // There's no need to listen for signals here: if the child is killed, feeding it
+            // fails with a broken pipe and the replay ends.
+            None => feed_input(input, dst, buffer_size, &mut maybe_log),
+        }?;
+        fd::finish_log(maybe_log, "stdin")
+    }
+}
+
+/// Feeds recorded input into a destination until the input is exhausted.
+///
+/// The input is fed in chunks of at most `buffer_size` bytes, and each chunk is logged before it
+/// is written, just like chunks intercepted from a live stream.
+///
+/// # Arguments
+///
+/// * `input` - The recorded input.
+/// * `dst` - Destination to feed the input into, usually the stdin of the target command.
+/// * `buffer_size` - Size of the buffer in bytes used for data transfer.
+/// * `maybe_log` - Optional log for recording the fed data.
+///
+/// # Returns
+///
+/// Returns `Ok(())` when all the input was fed, or when the destination was closed before that.
+///
+/// # Errors
+///
+/// Returns an error if:
+/// - Failed to read from the input, or
+/// - Failed to write to the destination.
+///
+/// Errors writing to the log are reported and disable logging, but don't stop the replay.
+pub fn feed_input(
+    mut input: impl Read,
+    mut dst: impl Write,
+    buffer_size: usize,
+    maybe_log: &mut Option<impl ChunkLog>,
+) -> Result<()> {
+    let mut buffer = vec![0; buffer_size];
+
+    loop {
+        let bytes_read = match input.read(&mut buffer) {
+            Ok(0) => return Ok(()),
+            Ok(bytes_read) => bytes_read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e).context("Error reading recorded input"),
+        };
+
+        let log_result = maybe_log
+            .as_mut()
+            .map_or(Ok(()), |log| log.log_chunk(&buffer[..bytes_read]));
+
+        match dst.write_all(&buffer[..bytes_read]) {
+            Ok(()) => (),
+            // The target stopped reading its input, so there's no point in feeding it more.
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => return Ok(()),
+            Err(e) => return Err(e).context("Error feeding recorded input"),
+        }
+
+        if let Err(e) = log_result {
+            eprintln!("Error writing to stdin log, disabling logging: {e}");
+            maybe_log.take();
+        }
+    }
+}
+
+/// Feeds recorded input into a destination with its original timing.
+///
+/// Each line of the timing file holds the delay, in seconds, between the previous chunk and the
+/// next one, and the length of the next chunk in bytes. Delays are measured against a schedule
+/// that starts when this function is called, so the time it takes to feed each chunk doesn't add
+/// up over the course of the replay. Any input left once the timing file is exhausted is fed right
+/// away.
+///
+/// # Arguments
+///
+/// * `input` - The recorded input.
+/// * `timing` - The timing file of the input.
+/// * `speed` - Factor by which the original delays are sped up.
+/// * `dst` - Destination to feed the input into.
+/// * `buffer_size` - Size of the buffer in bytes used to feed whatever input is left once the
+///   timing file is exhausted.
+/// * `maybe_log` - Optional log for recording the fed data.
+/// * `signal_rx` - File descriptor that becomes readable when fdintercept is told to terminate.
+///
+/// # Returns
+///
+/// Returns `Ok(())` when all the input was fed, when the destination was closed before that, or
+/// when a signal arrived.
+///
+/// # Errors
+///
+/// Returns an error if:
+/// - Failed to wait for the next chunk to be due,
+/// - The timing file can't be read or has an invalid line,
+/// - A delay is too long once slowed down by the speed factor,
+/// - The input is shorter than the timing file says, or
+/// - Failed to write to the destination.
+fn feed_timed_input(
+    mut input: impl Read,
+    timing: impl BufRead,
+    speed: f64,
+    mut dst: impl Write,
+    buffer_size: usize,
+    maybe_log: &mut Option<impl ChunkLog>,
+    signal_rx: &OwnedFd,
+) -> Result<()> {
+    let mut poll = mio::Poll::new().context("Error creating poll of events")?;
+    poll.registry()
+        .register(
+            &mut mio::unix::SourceFd(&signal_rx.as_raw_fd()),
+            mio::Token(SIGNAL_TOKEN),
+            mio::Interest::READABLE,
+        )
+        .context("Error registering signal pipe in poll of events")?;
+    let mut events = mio::Events::with_capacity(1);
+
+    let mut due = Instant::now();
+    let mut chunk = Vec::new();
+
+    for (line_number, line) in timing.lines().enumerate() {
+        let line = line.context("Error reading timing file")?;
+        let (delay, length) = parse_timing_line(&line)
+            .context(format!("Invalid line {} in timing file", line_number + 1))?;
+
+        // A tiny speed factor can stretch a delay past what a `Duration` or an `Instant` can hold.
+        due = Duration::try_from_secs_f64(delay.as_secs_f64() / speed)
+            .ok()
+            .and_then(|delay| due.checked_add(delay))
+            .context(format!(
+                "Delay in line {} of timing file is too long at speed {speed}",
+                line_number + 1
+            ))?;
+        if wait_until(&mut poll, &mut events, due).context("Error waiting for next chunk")? {
+            return Ok(());
+        }
+
+        chunk.resize(length, 0);
+        input
+            .read_exact(&mut chunk)
+            .context("Recorded input is shorter than its timing file")?;
+
+        let log_result = maybe_log
+            .as_mut()
+            .map_or(Ok(()), |log| log.log_chunk(&chunk));
+
+        match dst.write_all(&chunk) {
+            Ok(()) => (),
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => return Ok(()),
+            Err(e) => return Err(e).context("Error feeding recorded input"),
+        }
+
+        if let Err(e) = log_result {
+            eprintln!("Error writing to stdin log, disabling logging: {e}");
+            maybe_log.take();
+        }
+    }
+
+    feed_input(input, dst, buffer_size, maybe_log)
+}
+
+/// Parses a line of a timing file.
+///
+/// # Arguments
+///
+/// * `line` - The line, in the format `<delay in seconds> <length in bytes>`.
+///
+/// # Returns
+///
+/// Returns the delay and the length.
+///
+/// # Errors
+///
+/// Returns an error if the line doesn't have exactly two fields, if the delay is not a
+/// non-negative number, or if the length is not a non-negative integer.
+fn parse_timing_line(line: &str) -> Result<(Duration, usize)> {
+    let mut fields = line.split_whitespace();
+    let (Some(delay), Some(length), None) = (fields.next(), fields.next(), fields.next()) else {
+        return Err(anyhow::anyhow!("Expected a delay and a length"));
+    };
+    let delay = Duration::try_from_secs_f64(delay.parse().context("Invalid delay")?)
+        .context("Invalid delay")?;
+    let length = length.parse().context("Invalid length")?;
+    Ok((delay, length))
+}
+
+/// Waits until an instant, unless a signal arrives first.
+///
+/// # Arguments
+///
+/// * `poll` - Poll instance with the signal pipe registered into it.
+/// * `events` - Storage for the events returned by the poll.
+/// * `until` - The instant to wait until.
+///
+/// # Returns
+///
+/// Returns `true` if a signal arrived, or `false` if the instant was reached.
+///
+/// # Errors
+///
+/// Returns an error if polling fails.
+fn wait_until(poll: &mut mio::Poll, events: &mut mio::Events, until: Instant) -> io::Result<bool> {
+    loop {
+        let now = Instant::now();
+        if now >= until {
+            return Ok(false);
+        }
+        match poll.poll(events, Some(until - now)) {
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+            Err(e) => return Err(e),
+            Ok(()) if !events.is_empty() => return Ok(true),
+            Ok(()) => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct RecordingLog(Vec<Vec<u8>>);
+
+    impl ChunkLog for &mut RecordingLog {
+        fn log_chunk(&mut self, chunk: &[u8]) -> io::Result<()> {
+            self.0.push(chunk.to_vec());
+            Ok(())
+        }
+    }
+
+    struct ClosedPipe;
+
+    impl Write for ClosedPipe {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::Error::from(io::ErrorKind::BrokenPipe))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    mod feed_input {
+        use super::*;
+
+        #[test]
+        fn feeds_and_logs_in_chunks() {
+            let mut dst = Vec::new();
+            let mut log = RecordingLog(Vec::new());
+
+            feed_input(&b"hello\nexit\n"[..], &mut dst, 4, &mut Some(&mut log)).unwrap();
+
+            assert_eq!(dst, b"hello\nexit\n");
+            assert_eq!(
+                log.0,
+                vec![b"hell".to_vec(), b"o\nex".to_vec(), b"it\n".to_vec()]
+            );
+        }
+
+        #[test]
+        fn stops_when_destination_is_closed() {
+            feed_input(
+                &b"hello\n"[..],
+                ClosedPipe,
+                4,
+                &mut None::<&mut RecordingLog>,
+            )
+            .unwrap();
+        }
+    }
+
+    mod feed_timed_input {
+        use super::*;
+        use nix::unistd::{pipe, write};
+
+        #[test]
+        fn feeds_chunks_with_their_timing() {
+            let (signal_rx, _signal_tx) = pipe().unwrap();
+            let mut dst = Vec::new();
+            let mut log = RecordingLog(Vec::new());
+
+            let start = Instant::now();
+            feed_timed_input(
+                &b"hello\nexit\nleftover"[..],
+                &b"0.000000 6\n0.200000 5\n"[..],
+                2.0,
+                &mut dst,
+                4,
+                &mut Some(&mut log),
+                &signal_rx,
+            )
+            .unwrap();
+
+            assert!(start.elapsed() >= Duration::from_millis(100));
+            assert_eq!(dst, b"hello\nexit\nleftover");
+            assert_eq!(
+                log.0,
+                vec![
+                    b"hello\n".to_vec(),
+                    b"exit\n".to_vec(),
+                    b"left".to_vec(),
+                    b"over".to_vec()
+                ]
+            );
+        }
+
+        #[test]
+        fn stops_on_signal() {
+            let (signal_rx, signal_tx) = pipe().unwrap();
+            write(&signal_tx, &[1]).unwrap();
+            let mut dst = Vec::new();
+
+            let start = Instant::now();
+            feed_timed_input(
+                &b"hello\n"[..],
+                &b"60.000000 6\n"[..],
+                1.0,
+                &mut dst,
+                4,
+                &mut None::<&mut RecordingLog>,
+                &signal_rx,
+            )
+            .unwrap();
+
+            assert!(start.elapsed() < Duration::from_secs(60));
+            assert!(dst.is_empty());
+        }
+
+        #[test]
+        fn input_shorter_than_timing() {
+            let (signal_rx, _signal_tx) = pipe().unwrap();
+
+            let error = feed_timed_input(
+                &b"hi"[..],
+                &b"0 6\n"[..],
+                1.0,
+                Vec::new(),
+                4,
+                &mut None::<&mut RecordingLog>,
+                &signal_rx,
+            )
+            .unwrap_err();
+
+            assert!(error.to_string().contains("shorter than its timing file"));
+        }
+
+        #[test]
+        fn delay_too_long_at_speed() {
+            let (signal_rx, _signal_tx) = pipe().unwrap();
+
+            let error = feed_timed_input(
+                &b"hello\n"[..],
+                &b"0.500000 6\n"[..],
+                1e-300,
+                Vec::new(),
+                4,
+                &mut None::<&mut RecordingLog>,
+                &signal_rx,
+            )
+            .unwrap_err();
+
+            assert!(
+                error
+                    .to_string()
+                    .contains("Delay in line 1 of timing file is too long")
+            );
+        }
+    }
+
+    mod parse_timing_line {
+        use super::*;
+
+        #[test]
+        fn valid() {
+            assert_eq!(
+                parse_timing_line("0.512345 6").unwrap(),
+                (Duration::from_micros(512_345), 6)
+            );
+        }
+
+        #[test]
+        fn invalid() {
+            assert!(parse_timing_line("").is_err());
+            assert!(parse_timing_line("0.5").is_err());
+            assert!(parse_timing_line("0.5 6 7").is_err());
+            assert!(parse_timing_line("-0.5 6").is_err());
+            assert!(parse_timing_line("0.5 -6").is_err());
+            assert!(parse_timing_line("soon 6").is_err());
+        }
+    }
+}
@@ -0,0 +1,106 @@
+//! Forwarding of window size changes to the target command.
+//!
+//! This module provides functionality for applying the changes of the window size of the terminal
+//! fdintercept is attached to, which it learns about with SIGWINCH, to the terminal the target
+//! command is attached to, for letting the target command know about them, and for recording them
+//! in the logs that can hold them, so that a session can be interpreted later at the size it was
+//! seen at.
+
+use crate::log::{self, Clock, ResizeLog};
+use crate::terminal;
+use anyhow::{Context, Result};
+use nix::sys::signal::{Signal, kill};
+use nix::unistd::{Pid, getpgid};
+use std::os::fd::OwnedFd;
+use std::sync::Arc;
+
+/// Forwards the changes of the window size of fdintercept's terminal to the target command.
+pub struct Resizer {
+    /// PID of the target command.
+    child_pid: Pid,
+    /// The pseudo-terminal the target command is attached to, if any. Otherwise, the target
+    /// command shares the terminal of fdintercept, whose size already changed.
+    maybe_pty: Option<OwnedFd>,
+    /// Clock that timestamps the changes.
+    clock: Clock,
+    /// The logs that record the changes.
+    resize_logs: Vec<Arc<dyn ResizeLog>>,
+}
+
+impl Resizer {
+    /// Creates a resizer.
+    ///
+    /// # Arguments
+    ///
+    /// * `child_pid` - PID of the target command.
+    /// * `maybe_pty` - The pseudo-terminal the target command is attached to, if any.
+    /// * `clock` - Clock that timestamps the changes.
+    /// * `resize_logs` - The logs that record the changes.
+    pub fn new(
+        child_pid: u32,
+        maybe_pty: Option<OwnedFd>,
+        clock: Clock,
+        resize_logs: Vec<Arc<dyn ResizeLog>>,
+    ) -> Self {
+        Self {
+            // unwrap: `child_pid` is a PID, so it's guaranteed to be well in the range of `i32`.
+            child_pid: Pid::from_raw(i32::try_from(child_pid).unwrap()),
+            maybe_pty,
+            clock,
+            resize_logs,
+        }
+    }
+
+    /// Forwards the current window size of fdintercept's terminal to the target command.
+    ///
+    /// This function:
+    /// 1. Reads the window size of fdintercept's terminal, if there is one,
+    /// 2. Applies it to the pseudo-terminal of the target command, if there is one, which sends
+    ///    SIGWINCH to the target command,
+    /// 3. Sends SIGWINCH on to the target command, unless a terminal already sent it, and
+    /// 4. Records the new size in the logs.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` if the window size was forwarded and recorded.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - Failed to resize the pseudo-terminal,
+    /// - Failed to send SIGWINCH to the target command, or
+    /// - Failed to record the new size in any of the logs. All the others still record it.
+    pub fn resize(&self) -> Result<()> {
+        let maybe_size = terminal::window_size();
+
+        if let Some((pty, size)) = self.maybe_pty.as_ref().zip(maybe_size) {
+            terminal::set_window_size(pty, size).context("Error resizing pseudo-terminal")?;
+        }
+        if !self.is_signalled_by_terminal() {
+            kill(self.child_pid, Signal::SIGWINCH)
+                .context("Error sending SIGWINCH to child process")?;
+        }
+
+        let Some(size) = maybe_size else {
+            return Ok(());
+        };
+        let (wall, mono) = self.clock.now();
+        log::write_each(&self.resize_logs, |log| log.log_resize(wall, mono, size))
+            .context("Error logging window size")
+    }
+
+    /// Checks whether the target command already gets SIGWINCH from a terminal.
+    ///
+    /// Resizing the pseudo-terminal of the target command sends it SIGWINCH. Without one, the
+    /// terminal of fdintercept sends SIGWINCH to its foreground process group, which includes the
+    /// target command unless it was moved out of it, or fdintercept runs in the background.
+    ///
+    /// # Returns
+    ///
+    /// Returns `true` if the target command already gets SIGWINCH, or `false` if it must be sent.
+    fn is_signalled_by_terminal(&self) -> bool {
+        self.maybe_pty.is_some()
+            || terminal::foreground_process_group()
+                .is_some_and(|group| getpgid(Some(self.child_pid)) == Ok(group))
+    }
+}
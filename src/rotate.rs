@@ -0,0 +1,685 @@
+//! Rotation of log files.
+//!
+//! This module provides log files that roll over once they grow past a maximum size, or once a
+//! period of time, such as an hour or a day, has passed: the current file is renamed to
+//! `<name>.1`, the previous `<name>.1` to `<name>.2`, and so on, and a fresh file is started under
+//! the original name. Only a limited number of rotated files are kept, so that a chatty target
+//! can't fill up the disk.
+//!
+//! Log files can also be rotated by an external tool, such as logrotate, which moves them away and
+//! then tells fdintercept to reopen them. Reopening happens under the same lock that writes go
+//! through, so every record ends up whole in either the old or the new file, and none is lost.
+//!
+//! Rotation only ever happens between records, so a record is never split across two files.
+
+use crate::compress::{Codec, LogFile};
+use crate::log::LogSink;
+use crate::settings::RotationInterval;
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, SystemTime};
+
+/// When log files are rotated, and how many rotated files are kept.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Rotation {
+    /// Size in bytes, before compression, past which a log file is rotated, if any. The contents a
+    /// compressed log file already had when it was opened aren't counted.
+    pub max_size: Option<u64>,
+    /// Period of time after which a log file is rotated, if any.
+    pub interval: Option<RotationInterval>,
+    /// Number of rotated log files that are kept, besides the current one.
+    pub max_files: usize,
+}
+
+/// A log file that is rotated once it grows past a maximum size or once its period has passed,
+/// and that can be reopened.
+pub struct RotatingLogFile {
+    /// Path of the current log file.
+    path: PathBuf,
+    /// Format the log files are compressed in, if any.
+    maybe_codec: Option<Codec>,
+    /// When the log file is rotated.
+    rotation: Rotation,
+    /// The current log file.
+    file: LogFile,
+    /// Number of bytes in the current log file, before compression. See [`existing_size`].
+    size: u64,
+    /// When the period of the current log file ends, if log files are rotated periodically.
+    maybe_period_end: Option<SystemTime>,
+    /// Whether the log file was finished, after which it is never reopened.
+    finished: bool,
+}
+
+impl RotatingLogFile {
+    /// Wraps an open log file into a rotating log file.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the log file.
+    /// * `maybe_codec` - Optional format the log files are compressed in.
+    /// * `rotation` - When the log file is rotated.
+    /// * `file` - The open log file.
+    /// * `size` - Number of bytes already in the log file, before compression, e.g., when appending
+    ///   to it. See [`existing_size`].
+    pub fn new(
+        path: PathBuf,
+        maybe_codec: Option<Codec>,
+        rotation: Rotation,
+        file: LogFile,
+        size: u64,
+    ) -> Self {
+        Self {
+            path,
+            maybe_codec,
+            rotation,
+            file,
+            size,
+            maybe_period_end: rotation
+                .interval
+                .map(|interval| period_end(interval, SystemTime::now())),
+            finished: false,
+        }
+    }
+
+    /// Reopens the log file, e.g., after it was moved away by an external tool. Whatever is at the
+    /// path of the log file by then is appended to, or a new file is created there.
+    ///
+    /// A log file that was already finished is not reopened, since nothing else will be written to
+    /// it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the log file could not be reopened, in which case the current file
+    /// keeps being written to, or if the current file could not be finished after it was replaced.
+    pub fn reopen(&mut self) -> io::Result<()> {
+        if self.finished {
+            return Ok(());
+        }
+        let file = self.open()?;
+        let mut previous = std::mem::replace(&mut self.file, file);
+        previous.finish()
+    }
+
+    /// Checks whether the log file should be rotated before a record is written to it.
+    ///
+    /// # Arguments
+    ///
+    /// * `len` - Length of the record.
+    ///
+    /// # Returns
+    ///
+    /// Returns `true` if the record would take the log file past its maximum size, or if the
+    /// period of the log file has passed. An empty log file is never rotated.
+    fn is_rotation_due(&self, len: u64) -> bool {
+        self.size > 0
+            && (self
+                .rotation
+                .max_size
+                .is_some_and(|max_size| self.size + len > max_size)
+                || self
+                    .maybe_period_end
+                    .is_some_and(|period_end| SystemTime::now() >= period_end))
+    }
+
+    /// Rotates the log file: finishes the current file, shifts the rotated files by one, dropping
+    /// the oldest one if there are too many, and starts a new file under the original name.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the current file could not be finished, if the files could not be
+    /// renamed, or if the new file could not be created.
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.finish()?;
+
+        if self.rotation.max_files == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            for n in (1..self.rotation.max_files).rev() {
+                match fs::rename(rotated_path(&self.path, n), rotated_path(&self.path, n + 1)) {
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+                    result => result?,
+                }
+            }
+            fs::rename(&self.path, rotated_path(&self.path, 1))?;
+        }
+
+        self.file = self.open()?;
+        Ok(())
+    }
+
+    /// Opens the file at the path of the log file, appending to it if it exists, to become the
+    /// current log file, and starts a new period.
+    ///
+    /// # Returns
+    ///
+    /// Returns the opened log file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file could not be opened, or if the compressor could not be set up.
+    fn open(&mut self) -> io::Result<LogFile> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        let size = existing_size(&file, self.maybe_codec)?;
+        let log_file = LogFile::new(file, self.maybe_codec)?;
+        self.size = size;
+        self.maybe_period_end = self
+            .rotation
+            .interval
+            .map(|interval| period_end(interval, SystemTime::now()));
+        Ok(log_file)
+    }
+}
+
+impl Write for RotatingLogFile {
+    /// Writes a whole record, rotating the log file first if it is due.
+    ///
+    /// A record is always written whole, so that it isn't split across two files. For the same
+    /// reason, a record larger than the maximum size is written to a file of its own.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = buf.len() as u64;
+        if self.is_rotation_due(len) {
+            self.rotate()?;
+        }
+        self.file.write_all(buf)?;
+        self.size += len;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl LogSink for RotatingLogFile {
+    fn finish(&mut self) -> io::Result<()> {
+        self.finished = true;
+        self.file.finish()
+    }
+}
+
+/// A rotating log file that is written to by a log, and that can be reopened from another thread
+/// through a [`LogReopener`].
+pub struct SharedRotatingLogFile(Arc<Mutex<RotatingLogFile>>);
+
+impl Write for SharedRotatingLogFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // unwrap: Safe because the lock is only ever held while writing to, finishing, or
+        // reopening the log file, none of which panic.
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        // unwrap: Safe because the lock is only ever held while writing to, finishing, or
+        // reopening the log file, none of which panic.
+        self.0.lock().unwrap().flush()
+    }
+}
+
+impl LogSink for SharedRotatingLogFile {
+    fn finish(&mut self) -> io::Result<()> {
+        // unwrap: Safe because the lock is only ever held while writing to, finishing, or
+        // reopening the log file, none of which panic.
+        self.0.lock().unwrap().finish()
+    }
+}
+
+/// The log files that are reopened on request, e.g., when logrotate signals that it moved them
+/// away.
+#[derive(Default)]
+pub struct LogReopener {
+    /// The log files to reopen. Once a log file is dropped, e.g., because its proxied connection
+    /// closed, it is no longer reopened, nor kept open.
+    log_files: Mutex<Vec<Weak<Mutex<RotatingLogFile>>>>,
+}
+
+impl LogReopener {
+    /// Registers a log file to be reopened on request.
+    ///
+    /// # Arguments
+    ///
+    /// * `log_file` - The log file.
+    ///
+    /// # Returns
+    ///
+    /// Returns the log file, to be written to by a log.
+    pub fn register(&self, log_file: RotatingLogFile) -> SharedRotatingLogFile {
+        let shared = Arc::new(Mutex::new(log_file));
+        // unwrap: Safe because the lock is only ever held while registering or reopening log
+        // files, neither of which panic.
+        {
+            let mut log_files = self.log_files.lock().unwrap();
+            // Log files that were dropped, e.g., those of a connection that ended, are forgotten.
+            log_files.retain(|log_file| log_file.strong_count() > 0);
+            log_files.push(Arc::downgrade(&shared));
+        }
+        SharedRotatingLogFile(shared)
+    }
+
+    /// Reopens all the registered log files.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error that happened while reopening a log file. The other log files are
+    /// reopened regardless.
+    pub fn reopen_all(&self) -> io::Result<()> {
+        let mut result = Ok(());
+        // unwrap: Safe because the lock is only ever held while registering or reopening log
+        // files, neither of which panic.
+        for log_file in self
+            .log_files
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(Weak::upgrade)
+        {
+            // unwrap: Safe because the lock is only ever held while writing to, finishing, or
+            // reopening the log file, none of which panic.
+            result = result.and(log_file.lock().unwrap().reopen());
+        }
+        result
+    }
+
+    /// Reopens all the registered log files, reporting on stderr if any of them failed to reopen.
+    ///
+    /// Failing to reopen a log file must not bring the session down. The log file keeps being
+    /// written to wherever it was before.
+    pub fn reopen_all_reporting(&self) {
+        if let Err(e) = self.reopen_all() {
+            eprintln!("Error reopening log files: {e}");
+        }
+    }
+}
+
+/// Determines the number of bytes, before compression, already in a log file that is appended to.
+///
+/// The size of the contents of a compressed log file isn't known without decompressing all of it,
+/// so those contents aren't counted, and only what is written from then on is. A compressed log
+/// file that is appended to can therefore grow past its maximum size once, before it is rotated.
+///
+/// # Arguments
+///
+/// * `file` - The log file.
+/// * `maybe_codec` - Optional format the log file is compressed in.
+///
+/// # Returns
+///
+/// Returns the number of bytes in the log file, or 0 if it is compressed.
+///
+/// # Errors
+///
+/// Returns an error if the metadata of the log file could not be read.
+pub fn existing_size(file: &File, maybe_codec: Option<Codec>) -> io::Result<u64> {
+    match maybe_codec {
+        Some(_) => Ok(0),
+        None => Ok(file.metadata()?.len()),
+    }
+}
+
+/// Determines when the period of a log file that is rotated periodically ends.
+///
+/// Periods are aligned to the clock, in UTC: hourly periods end at the top of each hour, and daily
+/// periods end at midnight.
+///
+/// # Arguments
+///
+/// * `interval` - Length of the periods.
+/// * `now` - The current time.
+///
+/// # Returns
+///
+/// Returns the end of the period that `now` falls in.
+fn period_end(interval: RotationInterval, now: SystemTime) -> SystemTime {
+    let length = match interval {
+        RotationInterval::Hourly => 60 * 60,
+        RotationInterval::Daily => 24 * 60 * 60,
+    };
+    let since_epoch = now
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |since_epoch| since_epoch.as_secs());
+    SystemTime::UNIX_EPOCH + Duration::from_secs((since_epoch / length + 1) * length)
+}
+
+/// Determines the path of a rotated log file.
+///
+/// The number of the rotated file is appended to the path, e.g., `stdout.log.1`. If the name ends
+/// in the extension of a compression format, the number goes before it instead, e.g.,
+/// `stdout.log.1.gz`, so that the rotated file is still recognized as compressed.
+///
+/// # Arguments
+///
+/// * `path` - Path of the current log file.
+/// * `n` - Number of the rotated file, starting at 1 for the most recent one.
+///
+/// # Returns
+///
+/// Returns the path of the rotated log file.
+fn rotated_path(path: &Path, n: usize) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    if Codec::from_extension(path).is_some() {
+        // unwrap: Safe because `Codec::from_extension` only recognizes paths with an extension.
+        let extension = path.extension().unwrap();
+        name = path.with_extension("").into_os_string();
+        name.push(format!(".{n}."));
+        name.push(extension);
+    } else {
+        name.push(format!(".{n}"));
+    }
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::io::Read;
+    use tempfile::TempDir;
+
+    fn rotating_log_file(path: &Path, max_size: u64, max_files: usize) -> RotatingLogFile {
+        RotatingLogFile::new(
+            path.to_path_buf(),
+            Codec::from_extension(path),
+            Rotation {
+                max_size: Some(max_size),
+                interval: None,
+                max_files,
+            },
+            LogFile::new(File::create(path).unwrap(), Codec::from_extension(path)).unwrap(),
+            0,
+        )
+    }
+
+    mod rotating_log_file {
+        use super::*;
+
+        #[test]
+        fn below_max_size() {
+            let temp_dir = TempDir::new().unwrap();
+            let path = temp_dir.path().join("stdout.log");
+
+            let mut log_file = rotating_log_file(&path, 10, 2);
+            log_file.write_all(b"hello").unwrap();
+            log_file.write_all(b"world").unwrap();
+            log_file.finish().unwrap();
+
+            assert_eq!(fs::read_to_string(&path).unwrap(), "helloworld");
+            assert!(!rotated_path(&path, 1).exists());
+        }
+
+        #[test]
+        fn rotates_past_max_size() {
+            let temp_dir = TempDir::new().unwrap();
+            let path = temp_dir.path().join("stdout.log");
+
+            let mut log_file = rotating_log_file(&path, 10, 2);
+            for record in ["first\n", "second\n", "third\n", "fourth\n"] {
+                log_file.write_all(record.as_bytes()).unwrap();
+            }
+            log_file.finish().unwrap();
+
+            assert_eq!(fs::read_to_string(&path).unwrap(), "fourth\n");
+            assert_eq!(
+                fs::read_to_string(temp_dir.path().join("stdout.log.1")).unwrap(),
+                "third\n"
+            );
+            assert_eq!(
+                fs::read_to_string(temp_dir.path().join("stdout.log.2")).unwrap(),
+                "second\n"
+            );
+            assert!(!temp_dir.path().join("stdout.log.3").exists());
+        }
+
+        #[test]
+        fn record_larger_than_max_size() {
+            let temp_dir = TempDir::new().unwrap();
+            let path = temp_dir.path().join("stdout.log");
+
+            let mut log_file = rotating_log_file(&path, 4, 1);
+            log_file.write_all(b"ab").unwrap();
+            log_file.write_all(b"a long record").unwrap();
+            log_file.finish().unwrap();
+
+            assert_eq!(fs::read_to_string(&path).unwrap(), "a long record");
+            assert_eq!(
+                fs::read_to_string(temp_dir.path().join("stdout.log.1")).unwrap(),
+                "ab"
+            );
+        }
+
+        #[test]
+        fn no_rotated_files_kept() {
+            let temp_dir = TempDir::new().unwrap();
+            let path = temp_dir.path().join("stdout.log");
+
+            let mut log_file = rotating_log_file(&path, 4, 0);
+            log_file.write_all(b"old").unwrap();
+            log_file.write_all(b"new").unwrap();
+            log_file.finish().unwrap();
+
+            assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+            assert!(!temp_dir.path().join("stdout.log.1").exists());
+        }
+
+        #[test]
+        fn period_passed() {
+            let temp_dir = TempDir::new().unwrap();
+            let path = temp_dir.path().join("stdout.log");
+
+            let mut log_file = RotatingLogFile::new(
+                path.clone(),
+                None,
+                Rotation {
+                    max_size: None,
+                    interval: Some(RotationInterval::Hourly),
+                    max_files: 1,
+                },
+                LogFile::new(File::create(&path).unwrap(), None).unwrap(),
+                0,
+            );
+            log_file.write_all(b"old").unwrap();
+            log_file.write_all(b"old").unwrap();
+            log_file.maybe_period_end = Some(SystemTime::now());
+            log_file.write_all(b"new").unwrap();
+            log_file.finish().unwrap();
+
+            assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+            assert_eq!(
+                fs::read_to_string(temp_dir.path().join("stdout.log.1")).unwrap(),
+                "oldold"
+            );
+        }
+
+        #[test]
+        fn compressed() {
+            let temp_dir = TempDir::new().unwrap();
+            let path = temp_dir.path().join("stdout.log.gz");
+
+            let mut log_file = rotating_log_file(&path, 4, 1);
+            log_file.write_all(b"old").unwrap();
+            log_file.write_all(b"new").unwrap();
+            log_file.finish().unwrap();
+
+            let read = |path: &Path| {
+                let mut contents = String::new();
+                flate2::read::GzDecoder::new(File::open(path).unwrap())
+                    .read_to_string(&mut contents)
+                    .unwrap();
+                contents
+            };
+            assert_eq!(read(&path), "new");
+            assert_eq!(read(&temp_dir.path().join("stdout.log.1.gz")), "old");
+        }
+    }
+
+    mod existing_size {
+        use super::*;
+
+        #[test]
+        fn uncompressed() {
+            let temp_dir = TempDir::new().unwrap();
+            let path = temp_dir.path().join("stdout.log");
+            fs::write(&path, "contents").unwrap();
+
+            assert_eq!(existing_size(&File::open(&path).unwrap(), None).unwrap(), 8);
+        }
+
+        #[test]
+        fn compressed() {
+            let temp_dir = TempDir::new().unwrap();
+            let path = temp_dir.path().join("stdout.log.gz");
+            let mut log_file =
+                LogFile::new(File::create(&path).unwrap(), Some(Codec::Gzip)).unwrap();
+            log_file.write_all(b"contents").unwrap();
+            log_file.finish().unwrap();
+
+            assert_eq!(
+                existing_size(&File::open(&path).unwrap(), Some(Codec::Gzip)).unwrap(),
+                0
+            );
+        }
+    }
+
+    mod reopen {
+        use super::*;
+
+        #[test]
+        fn after_move() {
+            let temp_dir = TempDir::new().unwrap();
+            let path = temp_dir.path().join("stdout.log");
+            let moved_path = temp_dir.path().join("stdout.log.moved");
+
+            let mut log_file = rotating_log_file(&path, 1024, 1);
+            log_file.write_all(b"old").unwrap();
+            fs::rename(&path, &moved_path).unwrap();
+            log_file.reopen().unwrap();
+            log_file.write_all(b"new").unwrap();
+            log_file.finish().unwrap();
+
+            assert_eq!(fs::read_to_string(&moved_path).unwrap(), "old");
+            assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        }
+
+        #[test]
+        fn without_move() {
+            let temp_dir = TempDir::new().unwrap();
+            let path = temp_dir.path().join("stdout.log");
+
+            let mut log_file = rotating_log_file(&path, 1024, 1);
+            log_file.write_all(b"old").unwrap();
+            log_file.reopen().unwrap();
+            log_file.write_all(b"new").unwrap();
+            log_file.finish().unwrap();
+
+            assert_eq!(fs::read_to_string(&path).unwrap(), "oldnew");
+        }
+
+        #[test]
+        fn finished() {
+            let temp_dir = TempDir::new().unwrap();
+            let path = temp_dir.path().join("stdout.log");
+            let moved_path = temp_dir.path().join("stdout.log.moved");
+
+            let mut log_file = rotating_log_file(&path, 1024, 1);
+            log_file.write_all(b"old").unwrap();
+            log_file.finish().unwrap();
+            fs::rename(&path, &moved_path).unwrap();
+            log_file.reopen().unwrap();
+
+            assert!(!path.exists());
+        }
+
+        #[test]
+        fn through_reopener() {
+            let temp_dir = TempDir::new().unwrap();
+            let path = temp_dir.path().join("stdout.log.gz");
+            let moved_path = temp_dir.path().join("stdout.log.moved.gz");
+
+            let reopener = LogReopener::default();
+            let mut log_file = reopener.register(rotating_log_file(&path, 1024, 1));
+            log_file.write_all(b"old").unwrap();
+            fs::rename(&path, &moved_path).unwrap();
+            reopener.reopen_all().unwrap();
+            log_file.write_all(b"new").unwrap();
+            log_file.finish().unwrap();
+
+            let read = |path: &Path| {
+                let mut contents = String::new();
+                flate2::read::GzDecoder::new(File::open(path).unwrap())
+                    .read_to_string(&mut contents)
+                    .unwrap();
+                contents
+            };
+            assert_eq!(read(&moved_path), "old");
+            assert_eq!(read(&path), "new");
+        }
+
+        #[test]
+        fn dropped_log_file() {
+            let temp_dir = TempDir::new().unwrap();
+
+            let reopener = LogReopener::default();
+            drop(reopener.register(rotating_log_file(&temp_dir.path().join("1.log"), 1024, 1)));
+            let _log_file =
+                reopener.register(rotating_log_file(&temp_dir.path().join("2.log"), 1024, 1));
+            reopener.reopen_all().unwrap();
+
+            assert_eq!(reopener.log_files.lock().unwrap().len(), 1);
+        }
+    }
+
+    mod period_end {
+        use super::*;
+
+        #[test]
+        fn hourly() {
+            let now = SystemTime::UNIX_EPOCH + Duration::from_secs(3 * 3600 + 1234);
+            assert_eq!(
+                period_end(RotationInterval::Hourly, now),
+                SystemTime::UNIX_EPOCH + Duration::from_secs(4 * 3600)
+            );
+        }
+
+        #[test]
+        fn daily() {
+            let now = SystemTime::UNIX_EPOCH + Duration::from_secs(10 * 86400 + 3600);
+            assert_eq!(
+                period_end(RotationInterval::Daily, now),
+                SystemTime::UNIX_EPOCH + Duration::from_secs(11 * 86400)
+            );
+        }
+
+        #[test]
+        fn at_boundary() {
+            let now = SystemTime::UNIX_EPOCH + Duration::from_secs(3600);
+            assert_eq!(
+                period_end(RotationInterval::Hourly, now),
+                SystemTime::UNIX_EPOCH + Duration::from_secs(2 * 3600)
+            );
+        }
+    }
+
+    mod rotated_path {
+        use super::*;
+
+        #[test]
+        fn uncompressed() {
+            assert_eq!(
+                rotated_path(Path::new("logs/stdout.log"), 2),
+                PathBuf::from("logs/stdout.log.2")
+            );
+        }
+
+        #[test]
+        fn compressed() {
+            assert_eq!(
+                rotated_path(Path::new("logs/stdout.log.zst"), 1),
+                PathBuf::from("logs/stdout.log.1.zst")
+            );
+        }
+    }
+}
@@ -0,0 +1,892 @@
+//! Identity of a session, and paths of log files that are unique to it.
+//!
+//! This module provides:
+//! - The identity of a session, i.e., a single run of fdintercept,
+//! - Session directories, each holding the log files of a single session,
+//! - Manifests, describing a session from the target command it ran to how it ended, and
+//! - Templates of log file paths, whose placeholders are expanded once per session, so that
+//!   concurrent runs of the same target don't clobber or interleave each other's log files.
+
+use crate::settings::Target;
+use crate::stats::StreamsSummary;
+use nix::sys::signal::Signal;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::collections::hash_map::RandomState;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, BufWriter, Write};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::process::ExitStatusExt;
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+use std::time::{Duration, SystemTime};
+
+/// A single run of fdintercept.
+#[derive(Debug)]
+pub struct Session {
+    /// Identifier of the session, unique across sessions.
+    pub id: String,
+    /// PID of fdintercept.
+    pub pid: u32,
+    /// Wall-clock time at which the session started.
+    pub started_at: SystemTime,
+}
+
+impl Session {
+    /// Creates the current session.
+    ///
+    /// # Arguments
+    ///
+    /// * `started_at` - Wall-clock time at which the session started.
+    pub fn new(started_at: SystemTime) -> Self {
+        let pid = std::process::id();
+
+        // `RandomState` is seeded randomly, so hashing the PID and the start time with it gives
+        // an identifier that differs even between sessions started at the same time by processes
+        // that reused a PID.
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u32(pid);
+        hasher.write_u128(
+            started_at
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map_or(0, |since_epoch| since_epoch.as_nanos()),
+        );
+
+        Self {
+            id: format!("{:016x}", hasher.finish()),
+            pid,
+            started_at,
+        }
+    }
+
+    /// Formats the start time of the session to be used in a file name, e.g.,
+    /// `20250601T120102Z`.
+    pub fn timestamp(&self) -> String {
+        humantime::format_rfc3339_seconds(self.started_at)
+            .to_string()
+            .replace(['-', ':'], "")
+    }
+
+    /// Creates the directory of the session, which is unique to it.
+    ///
+    /// The name of the directory starts with the start time of the session, so that the session
+    /// directories sort chronologically, followed by its identifier, e.g.,
+    /// `20250601T120102Z-0123456789abcdef`.
+    ///
+    /// # Arguments
+    ///
+    /// * `log_dir` - Directory to create the session directory in. It is created if missing.
+    ///
+    /// # Returns
+    ///
+    /// Returns the path to the session directory.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directories can't be created, or if the session directory already
+    /// exists.
+    pub fn create_dir(&self, log_dir: &Path) -> io::Result<PathBuf> {
+        fs::create_dir_all(log_dir)?;
+        let dir = log_dir.join(format!("{}-{}", self.timestamp(), self.id));
+        fs::create_dir(&dir)?;
+        Ok(dir)
+    }
+}
+
+/// Resolves the path of a file of a session, relative to the session directory, if there is one.
+///
+/// # Arguments
+///
+/// * `maybe_session_dir` - Optional session directory. If there is none, paths are relative to the
+///   current working directory.
+/// * `path` - Path to resolve. If absolute, it is kept as it is.
+///
+/// # Returns
+///
+/// Returns the resolved path.
+pub fn resolve_path(maybe_session_dir: Option<&Path>, path: &Path) -> PathBuf {
+    // Joining keeps absolute paths as they are.
+    maybe_session_dir.map_or_else(|| path.to_path_buf(), |session_dir| session_dir.join(path))
+}
+
+/// Description of a session, written next to its log files.
+///
+/// The manifest is written once the target command started, and written again, complete, once it
+/// ended. If fdintercept bails out in between, the manifest is left without an end.
+#[derive(Debug, Serialize)]
+pub struct Manifest {
+    /// Path the manifest is written to.
+    #[serde(skip)]
+    path: PathBuf,
+    /// Version of fdintercept.
+    fdintercept_version: &'static str,
+    /// Identifier of the session.
+    session_id: String,
+    /// PID of fdintercept.
+    pid: u32,
+    /// PID of the target command.
+    child_pid: u32,
+    /// The executable of the target command.
+    executable: String,
+    /// Arguments passed to the executable.
+    args: Vec<String>,
+    /// Working directory of the target command, if it could be determined.
+    cwd: Option<PathBuf>,
+    /// The selected environment variables that are set.
+    env: BTreeMap<String, String>,
+    /// Wall-clock time at which the session started.
+    started_at: String,
+    /// Wall-clock time at which the session ended, once it did.
+    ended_at: Option<String>,
+    /// Duration of the session in seconds, once it ended.
+    duration: Option<f64>,
+    /// Exit code of the target command, if it exited.
+    exit_code: Option<i32>,
+    /// Name of the signal that terminated the target command, if one did.
+    signal: Option<String>,
+    /// Statistics of the traffic of each stream, once the session ended.
+    streams: Option<StreamsSummary>,
+}
+
+impl Manifest {
+    /// Creates the manifest of a session whose target command just started.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path the manifest is written to.
+    /// * `session` - The current session.
+    /// * `child_pid` - PID of the target command.
+    /// * `target` - The target command.
+    /// * `env_names` - Names of the environment variables to record, if they are set.
+    ///
+    /// # Returns
+    ///
+    /// Returns the manifest, which isn't written yet.
+    pub fn new(
+        path: PathBuf,
+        session: &Session,
+        child_pid: u32,
+        target: &Target,
+        env_names: &[String],
+    ) -> Self {
+        Self {
+            path,
+            fdintercept_version: env!("CARGO_PKG_VERSION"),
+            session_id: session.id.clone(),
+            pid: session.pid,
+            child_pid,
+            executable: target.executable.to_string(),
+            args: target.args.clone(),
+            cwd: std::env::current_dir().ok(),
+            env: env_names
+                .iter()
+                .filter_map(|name| Some((name.clone(), std::env::var(name).ok()?)))
+                .collect(),
+            started_at: humantime::format_rfc3339_micros(session.started_at).to_string(),
+            ended_at: None,
+            duration: None,
+            exit_code: None,
+            signal: None,
+            streams: None,
+        }
+    }
+
+    /// Records how the session ended.
+    ///
+    /// # Arguments
+    ///
+    /// * `ended_at` - Wall-clock time at which the session ended.
+    /// * `duration` - Duration of the session.
+    /// * `maybe_status` - The exit status of the target command, if it could be determined.
+    /// * `streams` - Statistics of the traffic of each stream.
+    pub fn end(
+        &mut self,
+        ended_at: SystemTime,
+        duration: Duration,
+        maybe_status: Option<ExitStatus>,
+        streams: StreamsSummary,
+    ) {
+        self.ended_at = Some(humantime::format_rfc3339_micros(ended_at).to_string());
+        self.duration = Some(duration.as_secs_f64());
+        self.streams = Some(streams);
+        if let Some(status) = maybe_status {
+            self.exit_code = status.code();
+            self.signal = status.signal().map(|signum| {
+                Signal::try_from(signum)
+                    .map_or_else(|_| signum.to_string(), |signal| signal.as_str().to_string())
+            });
+        }
+    }
+
+    /// Writes the manifest, replacing any previous version of it.
+    ///
+    /// The manifest is written to a temporary file first, which is then renamed over the previous
+    /// version, so that readers never see a partially written manifest.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the manifest or its parent directories can't be written.
+    pub fn write(&self) -> io::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(".tmp");
+
+        let mut file = BufWriter::new(File::create(&tmp_path)?);
+        serde_json::to_writer_pretty(&mut file, self)?;
+        writeln!(file)?;
+        file.flush()?;
+        drop(file);
+
+        fs::rename(tmp_path, &self.path)
+    }
+}
+
+/// Template of a log file path, with placeholders that are expanded once per session, or once per
+/// connection in proxy mode.
+#[derive(Debug, PartialEq, Eq)]
+pub struct PathTemplate(Vec<PathTemplatePart>);
+
+/// What the names of log files are expanded for, which determines the placeholders they may have.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TemplateScope {
+    /// A session with a target command, which has `{child_pid}` and `{executable}`.
+    Session,
+    /// A connection in proxy mode, which has `{connection}`, and must use it, since the files of
+    /// concurrent connections can't be shared.
+    Connection,
+}
+
+/// The values of the placeholders that are specific to the [`TemplateScope`] of a
+/// [`PathTemplate`].
+#[derive(Debug, Clone, Copy)]
+pub enum TemplateValues<'a> {
+    /// The values of a session with a target command.
+    Session {
+        /// PID of the target command.
+        child_pid: u32,
+        /// The executable of the target command, as it was given.
+        executable: &'a str,
+    },
+    /// The number of a connection in proxy mode, counting from 1.
+    Connection(u64),
+}
+
+/// A part of a [`PathTemplate`].
+#[derive(Debug, PartialEq, Eq)]
+enum PathTemplatePart {
+    /// Bytes that are copied as is, including the values of environment variables, which are
+    /// resolved when the template is parsed.
+    Literal(Vec<u8>),
+    /// PID of fdintercept (`{pid}`).
+    Pid,
+    /// PID of the target command (`{child_pid}`).
+    ChildPid,
+    /// Start time of the session (`{timestamp}`).
+    Timestamp,
+    /// File name of the executable of the target command (`{executable}`).
+    Executable,
+    /// Identifier of the session (`{session_id}`).
+    SessionId,
+    /// Number of the connection, in proxy mode (`{connection}`).
+    Connection,
+}
+
+impl PathTemplatePart {
+    /// Checks whether the part has a value in a scope.
+    ///
+    /// # Arguments
+    ///
+    /// * `scope` - The scope the template is expanded for.
+    ///
+    /// # Returns
+    ///
+    /// Returns `true` if the part can be expanded in the scope, or `false` otherwise.
+    const fn is_available_in(&self, scope: TemplateScope) -> bool {
+        match self {
+            Self::ChildPid | Self::Executable => matches!(scope, TemplateScope::Session),
+            Self::Connection => matches!(scope, TemplateScope::Connection),
+            Self::Literal(_) | Self::Pid | Self::Timestamp | Self::SessionId => true,
+        }
+    }
+}
+
+/// Errors that can occur when parsing a log file path template.
+#[derive(Debug, PartialEq, Eq)]
+pub enum PathTemplateParseError {
+    /// A placeholder that isn't known.
+    UnknownPlaceholder(String),
+    /// A `{` without a matching `}`.
+    Unclosed,
+    /// An `{env:VAR}` placeholder whose variable isn't set, or isn't valid Unicode.
+    UnsetEnvVar(String),
+    /// A placeholder that has no value in the scope the template is expanded for.
+    Unavailable(String, TemplateScope),
+    /// A template of the files of each connection without `{connection}`.
+    MissingConnection,
+}
+
+impl std::fmt::Display for PathTemplateParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::UnknownPlaceholder(name) => write!(
+                f,
+                "Unknown placeholder {{{name}}}, expected one of {{pid}}, {{child_pid}}, \
+                 {{timestamp}}, {{executable}}, {{session_id}}, {{connection}}, or {{env:VAR}}"
+            ),
+            Self::Unclosed => write!(f, "Placeholder is missing its closing brace"),
+            Self::UnsetEnvVar(name) => write!(f, "Environment variable {name} is not set"),
+            Self::Unavailable(name, TemplateScope::Session) => {
+                write!(f, "Placeholder {{{name}}} is only available in proxy mode")
+            }
+            Self::Unavailable(name, TemplateScope::Connection) => write!(
+                f,
+                "Placeholder {{{name}}} is not available in proxy mode, which has no target command"
+            ),
+            Self::MissingConnection => write!(
+                f,
+                "The names of the files of each connection must include {{connection}}, so that \
+                 connections don't write to the same files"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PathTemplateParseError {}
+
+impl PathTemplate {
+    /// Parses a log file path template.
+    ///
+    /// Placeholders are enclosed in braces, e.g., `{pid}`. Literal braces are written doubled, as
+    /// `{{` and `}}`. Environment variables in `{env:VAR}` placeholders are resolved right away.
+    ///
+    /// # Arguments
+    ///
+    /// * `template` - The path template.
+    /// * `scope` - What the template is expanded for.
+    ///
+    /// # Returns
+    ///
+    /// Returns the parsed template.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the template has an unknown or unclosed placeholder, a placeholder that
+    /// has no value in `scope`, lacks `{connection}` in [`TemplateScope::Connection`], or if it
+    /// refers to an environment variable that isn't set.
+    pub fn parse(template: &Path, scope: TemplateScope) -> Result<Self, PathTemplateParseError> {
+        let mut parts = Vec::new();
+        let mut literal = Vec::new();
+        let mut bytes = template.as_os_str().as_bytes().iter().copied().peekable();
+        while let Some(b) = bytes.next() {
+            match b {
+                b'{' if bytes.peek() == Some(&b'{') => {
+                    bytes.next();
+                    literal.push(b'{');
+                }
+                b'}' if bytes.peek() == Some(&b'}') => {
+                    bytes.next();
+                    literal.push(b'}');
+                }
+                b'{' => {
+                    let mut name = Vec::new();
+                    loop {
+                        match bytes.next() {
+                            Some(b'}') => break,
+                            Some(b) => name.push(b),
+                            None => return Err(PathTemplateParseError::Unclosed),
+                        }
+                    }
+                    let name = String::from_utf8_lossy(&name);
+                    let part = match name.as_ref() {
+                        "pid" => PathTemplatePart::Pid,
+                        "child_pid" => PathTemplatePart::ChildPid,
+                        "timestamp" => PathTemplatePart::Timestamp,
+                        "executable" => PathTemplatePart::Executable,
+                        "session_id" => PathTemplatePart::SessionId,
+                        "connection" => PathTemplatePart::Connection,
+                        _ => {
+                            if let Some(var) = name.strip_prefix("env:") {
+                                let value = std::env::var(var).map_err(|_| {
+                                    PathTemplateParseError::UnsetEnvVar(var.to_string())
+                                })?;
+                                literal.extend_from_slice(value.as_bytes());
+                                continue;
+                            }
+                            return Err(PathTemplateParseError::UnknownPlaceholder(
+                                name.into_owned(),
+                            ));
+                        }
+                    };
+                    if !part.is_available_in(scope) {
+                        return Err(PathTemplateParseError::Unavailable(
+                            name.into_owned(),
+                            scope,
+                        ));
+                    }
+                    if !literal.is_empty() {
+                        parts.push(PathTemplatePart::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(part);
+                }
+                b => literal.push(b),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(PathTemplatePart::Literal(literal));
+        }
+        if scope == TemplateScope::Connection && !parts.contains(&PathTemplatePart::Connection) {
+            return Err(PathTemplateParseError::MissingConnection);
+        }
+        Ok(Self(parts))
+    }
+
+    /// Expands the placeholders of the template.
+    ///
+    /// # Arguments
+    ///
+    /// * `session` - The current session.
+    /// * `values` - The values of the placeholders of the scope the template was parsed for.
+    ///
+    /// # Returns
+    ///
+    /// Returns the path of the log file for the session.
+    pub fn expand(&self, session: &Session, values: TemplateValues) -> PathBuf {
+        let mut path = Vec::new();
+        for part in &self.0 {
+            match (part, values) {
+                (PathTemplatePart::Literal(bytes), _) => path.extend_from_slice(bytes),
+                (PathTemplatePart::Pid, _) => {
+                    path.extend_from_slice(session.pid.to_string().as_bytes());
+                }
+                (PathTemplatePart::Timestamp, _) => {
+                    path.extend_from_slice(session.timestamp().as_bytes());
+                }
+                (PathTemplatePart::SessionId, _) => path.extend_from_slice(session.id.as_bytes()),
+                (PathTemplatePart::ChildPid, TemplateValues::Session { child_pid, .. }) => {
+                    path.extend_from_slice(child_pid.to_string().as_bytes());
+                }
+                (PathTemplatePart::Executable, TemplateValues::Session { executable, .. }) => {
+                    path.extend_from_slice(
+                        Path::new(executable)
+                            .file_name()
+                            .map_or(executable.as_bytes(), OsStrExt::as_bytes),
+                    );
+                }
+                (PathTemplatePart::Connection, TemplateValues::Connection(connection)) => {
+                    path.extend_from_slice(connection.to_string().as_bytes());
+                }
+                // Placeholders without a value in the scope are rejected when parsing.
+                (
+                    PathTemplatePart::ChildPid
+                    | PathTemplatePart::Executable
+                    | PathTemplatePart::Connection,
+                    _,
+                ) => (),
+            }
+        }
+        PathBuf::from(OsString::from_vec(path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn session() -> Session {
+        Session {
+            id: "0123456789abcdef".to_string(),
+            pid: 42,
+            started_at: SystemTime::UNIX_EPOCH + Duration::from_secs(1_748_779_262),
+        }
+    }
+
+    mod session_new {
+        use super::*;
+
+        #[test]
+        fn unique_ids() {
+            let now = SystemTime::now();
+            let session = Session::new(now);
+
+            assert_eq!(session.pid, std::process::id());
+            assert_eq!(session.id.len(), 16);
+            assert_ne!(session.id, Session::new(now).id);
+        }
+    }
+
+    mod session_create_dir {
+        use super::*;
+
+        #[test]
+        fn create_dir() {
+            let tmp_dir = tempfile::TempDir::new().unwrap();
+            let log_dir = tmp_dir.path().join("logs");
+
+            let dir = session().create_dir(&log_dir).unwrap();
+
+            assert_eq!(dir, log_dir.join("20250601T120102Z-0123456789abcdef"));
+            assert!(dir.is_dir());
+        }
+
+        #[test]
+        fn already_exists() {
+            let tmp_dir = tempfile::TempDir::new().unwrap();
+
+            session().create_dir(tmp_dir.path()).unwrap();
+
+            assert_eq!(
+                session().create_dir(tmp_dir.path()).unwrap_err().kind(),
+                io::ErrorKind::AlreadyExists
+            );
+        }
+    }
+
+    mod resolve_path {
+        use super::*;
+
+        #[test]
+        fn no_session_dir() {
+            assert_eq!(
+                resolve_path(None, Path::new("stdout.log")),
+                PathBuf::from("stdout.log")
+            );
+        }
+
+        #[test]
+        fn relative_path() {
+            assert_eq!(
+                resolve_path(Some(Path::new("/logs/session")), Path::new("stdout.log")),
+                PathBuf::from("/logs/session/stdout.log")
+            );
+        }
+
+        #[test]
+        fn absolute_path() {
+            assert_eq!(
+                resolve_path(
+                    Some(Path::new("/logs/session")),
+                    Path::new("/tmp/stdout.log")
+                ),
+                PathBuf::from("/tmp/stdout.log")
+            );
+        }
+    }
+
+    mod manifest {
+        use super::*;
+        use non_empty_string::NonEmptyString;
+
+        fn manifest(path: PathBuf) -> Manifest {
+            let target = Target {
+                executable: NonEmptyString::new("python".to_string()).unwrap(),
+                args: vec!["script.py".to_string()],
+            };
+            temp_env::with_vars(
+                [
+                    ("FDINTERCEPT_TEST_SET", Some("value")),
+                    ("FDINTERCEPT_TEST_UNSET", None),
+                ],
+                || {
+                    Manifest::new(
+                        path,
+                        &session(),
+                        43,
+                        &target,
+                        &[
+                            "FDINTERCEPT_TEST_SET".to_string(),
+                            "FDINTERCEPT_TEST_UNSET".to_string(),
+                        ],
+                    )
+                },
+            )
+        }
+
+        fn read(path: &Path) -> serde_json::Value {
+            serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap()
+        }
+
+        #[test]
+        fn started() {
+            let tmp_dir = tempfile::TempDir::new().unwrap();
+            let path = tmp_dir.path().join("nested").join("session.json");
+
+            manifest(path.clone()).write().unwrap();
+
+            assert_eq!(
+                read(&path),
+                serde_json::json!({
+                    "fdintercept_version": env!("CARGO_PKG_VERSION"),
+                    "session_id": "0123456789abcdef",
+                    "pid": 42,
+                    "child_pid": 43,
+                    "executable": "python",
+                    "args": ["script.py"],
+                    "cwd": std::env::current_dir().unwrap(),
+                    "env": {"FDINTERCEPT_TEST_SET": "value"},
+                    "started_at": "2025-06-01T12:01:02.000000Z",
+                    "ended_at": null,
+                    "duration": null,
+                    "exit_code": null,
+                    "signal": null,
+                    "streams": null,
+                })
+            );
+        }
+
+        #[test]
+        fn exited() {
+            let tmp_dir = tempfile::TempDir::new().unwrap();
+            let path = tmp_dir.path().join("session.json");
+            let mut manifest = manifest(path.clone());
+            manifest.write().unwrap();
+
+            manifest.end(
+                session().started_at + Duration::from_millis(1500),
+                Duration::from_millis(1500),
+                Some(ExitStatus::from_raw(3 << 8)),
+                StreamsSummary::default(),
+            );
+            manifest.write().unwrap();
+
+            let contents = read(&path);
+            assert_eq!(contents["ended_at"], "2025-06-01T12:01:03.500000Z");
+            assert_eq!(contents["duration"], 1.5);
+            assert_eq!(contents["exit_code"], 3);
+            assert_eq!(contents["signal"], serde_json::Value::Null);
+            assert_eq!(
+                contents["streams"]["stderr"],
+                serde_json::json!({"bytes": 0, "chunks": 0, "read_calls": 0, "max_chunk": 0})
+            );
+            assert_eq!(fs::read_dir(tmp_dir.path()).unwrap().count(), 1);
+        }
+
+        #[test]
+        fn terminated_by_signal() {
+            let tmp_dir = tempfile::TempDir::new().unwrap();
+            let path = tmp_dir.path().join("session.json");
+            let mut manifest = manifest(path.clone());
+
+            manifest.end(
+                session().started_at,
+                Duration::ZERO,
+                Some(ExitStatus::from_raw(Signal::SIGTERM as i32)),
+                StreamsSummary::default(),
+            );
+            manifest.write().unwrap();
+
+            let contents = read(&path);
+            assert_eq!(contents["exit_code"], serde_json::Value::Null);
+            assert_eq!(contents["signal"], "SIGTERM");
+        }
+
+        #[test]
+        fn unknown_status() {
+            let tmp_dir = tempfile::TempDir::new().unwrap();
+            let path = tmp_dir.path().join("session.json");
+            let mut manifest = manifest(path.clone());
+
+            manifest.end(
+                session().started_at,
+                Duration::ZERO,
+                None,
+                StreamsSummary::default(),
+            );
+            manifest.write().unwrap();
+
+            let contents = read(&path);
+            assert_eq!(contents["duration"], 0.0);
+            assert_eq!(contents["exit_code"], serde_json::Value::Null);
+            assert_eq!(contents["signal"], serde_json::Value::Null);
+        }
+    }
+
+    mod session_timestamp {
+        use super::*;
+
+        #[test]
+        fn timestamp() {
+            assert_eq!(session().timestamp(), "20250601T120102Z");
+        }
+    }
+
+    mod path_template_parse {
+        use super::*;
+
+        #[test]
+        fn literal() {
+            assert_eq!(
+                PathTemplate::parse(Path::new("logs/stdout.log"), TemplateScope::Session).unwrap(),
+                PathTemplate(vec![PathTemplatePart::Literal(b"logs/stdout.log".to_vec())])
+            );
+        }
+
+        #[test]
+        fn placeholders() {
+            assert_eq!(
+                PathTemplate::parse(
+                    Path::new("{executable}/{pid}-{child_pid}.log"),
+                    TemplateScope::Session
+                )
+                .unwrap(),
+                PathTemplate(vec![
+                    PathTemplatePart::Executable,
+                    PathTemplatePart::Literal(b"/".to_vec()),
+                    PathTemplatePart::Pid,
+                    PathTemplatePart::Literal(b"-".to_vec()),
+                    PathTemplatePart::ChildPid,
+                    PathTemplatePart::Literal(b".log".to_vec()),
+                ])
+            );
+        }
+
+        #[test]
+        fn escaped_braces() {
+            assert_eq!(
+                PathTemplate::parse(Path::new("{{pid}}.log"), TemplateScope::Session).unwrap(),
+                PathTemplate(vec![PathTemplatePart::Literal(b"{pid}.log".to_vec())])
+            );
+        }
+
+        #[test]
+        fn env_var() {
+            temp_env::with_var("FDINTERCEPT_TEST_LOG_DIR", Some("/tmp/logs"), || {
+                assert_eq!(
+                    PathTemplate::parse(
+                        Path::new("{env:FDINTERCEPT_TEST_LOG_DIR}/stdout.log"),
+                        TemplateScope::Session
+                    )
+                    .unwrap(),
+                    PathTemplate(vec![PathTemplatePart::Literal(
+                        b"/tmp/logs/stdout.log".to_vec()
+                    )])
+                );
+            });
+        }
+
+        #[test]
+        fn unset_env_var() {
+            temp_env::with_var_unset("FDINTERCEPT_TEST_LOG_DIR", || {
+                assert_eq!(
+                    PathTemplate::parse(
+                        Path::new("{env:FDINTERCEPT_TEST_LOG_DIR}/stdout.log"),
+                        TemplateScope::Session
+                    ),
+                    Err(PathTemplateParseError::UnsetEnvVar(
+                        "FDINTERCEPT_TEST_LOG_DIR".to_string()
+                    ))
+                );
+            });
+        }
+
+        #[test]
+        fn unknown_placeholder() {
+            assert_eq!(
+                PathTemplate::parse(Path::new("{user}.log"), TemplateScope::Session),
+                Err(PathTemplateParseError::UnknownPlaceholder(
+                    "user".to_string()
+                ))
+            );
+        }
+
+        #[test]
+        fn connection_in_session() {
+            assert_eq!(
+                PathTemplate::parse(Path::new("client.{connection}.log"), TemplateScope::Session),
+                Err(PathTemplateParseError::Unavailable(
+                    "connection".to_string(),
+                    TemplateScope::Session
+                ))
+            );
+        }
+
+        #[test]
+        fn child_pid_in_connection() {
+            assert_eq!(
+                PathTemplate::parse(
+                    Path::new("client.{child_pid}.log"),
+                    TemplateScope::Connection
+                ),
+                Err(PathTemplateParseError::Unavailable(
+                    "child_pid".to_string(),
+                    TemplateScope::Connection
+                ))
+            );
+        }
+
+        #[test]
+        fn connection_without_connection() {
+            assert_eq!(
+                PathTemplate::parse(Path::new("timeline.log"), TemplateScope::Connection),
+                Err(PathTemplateParseError::MissingConnection)
+            );
+        }
+
+        #[test]
+        fn unclosed() {
+            assert_eq!(
+                PathTemplate::parse(Path::new("{pid.log"), TemplateScope::Session),
+                Err(PathTemplateParseError::Unclosed)
+            );
+        }
+    }
+
+    mod path_template_expand {
+        use super::*;
+
+        #[test]
+        fn expand() {
+            let template = PathTemplate::parse(
+                Path::new("logs/{executable}/{timestamp}-{session_id}/{pid}.{child_pid}.log"),
+                TemplateScope::Session,
+            )
+            .unwrap();
+
+            assert_eq!(
+                template.expand(
+                    &session(),
+                    TemplateValues::Session {
+                        child_pid: 43,
+                        executable: "/usr/bin/python3",
+                    },
+                ),
+                PathBuf::from("logs/python3/20250601T120102Z-0123456789abcdef/42.43.log")
+            );
+        }
+
+        #[test]
+        fn connection() {
+            let template = PathTemplate::parse(
+                Path::new("client.{connection}.log"),
+                TemplateScope::Connection,
+            )
+            .unwrap();
+
+            assert_eq!(
+                template.expand(&session(), TemplateValues::Connection(7)),
+                PathBuf::from("client.7.log")
+            );
+        }
+
+        #[test]
+        fn without_placeholders() {
+            let template =
+                PathTemplate::parse(Path::new("stdout.log"), TemplateScope::Session).unwrap();
+
+            assert_eq!(
+                template.expand(
+                    &session(),
+                    TemplateValues::Session {
+                        child_pid: 43,
+                        executable: "python3",
+                    },
+                ),
+                PathBuf::from("stdout.log")
+            );
+        }
+    }
+}
@@ -0,0 +1,372 @@
+//! The logs of a session.
+//!
+//! This module provides functionality for parsing the names of all the log files of a session
+//! before anything runs, so that mistakes in them are reported early, and for creating the log
+//! files once the PID of the observed process is known, since their names may include it.
+
+use crate::fd;
+use crate::log::{Clock, RecordFormat, ResizeLog, SharedLog, StreamLog};
+use crate::rotate::{LogReopener, Rotation};
+use crate::session::{self, Manifest, PathTemplate, Session, TemplateScope, TemplateValues};
+use crate::settings::ResolvedSettings;
+use crate::stats::StreamsSummary;
+use crate::terminal;
+use anyhow::{Context, Result};
+use std::num::NonZeroU64;
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+use std::sync::Arc;
+
+/// The parsed names of the log files of a session, whose placeholders are yet to be expanded.
+pub struct LogTemplates {
+    /// Name of the stdin log file, if any.
+    stdin: Option<PathTemplate>,
+    /// Name of the stdin timing file, if any.
+    stdin_timing: Option<PathTemplate>,
+    /// Name of the stdout log file, if any.
+    stdout: Option<PathTemplate>,
+    /// Name of the stderr log file, if any.
+    stderr: Option<PathTemplate>,
+    /// Name of the timeline log file, if any.
+    timeline: Option<PathTemplate>,
+    /// Name of the asciicast recording, if any.
+    asciicast: Option<PathTemplate>,
+    /// Name of the manifest, if any.
+    manifest: Option<PathTemplate>,
+    /// Names of the additional file descriptors, e.g., `fd3`, and of their log files, in the same
+    /// order as in the settings.
+    extra_fds: Vec<(&'static str, PathTemplate)>,
+}
+
+impl LogTemplates {
+    /// Parses the names of the log files of a session.
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - The settings with the names of the log files.
+    ///
+    /// # Returns
+    ///
+    /// Returns the parsed names.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any of the names isn't a valid template.
+    pub fn parse(settings: &ResolvedSettings) -> Result<Self> {
+        let parse = |path: &PathBuf| -> Result<PathTemplate> {
+            PathTemplate::parse(path, TemplateScope::Session)
+                .with_context(|| format!("Error parsing log file name {}", path.display()))
+        };
+        let parse_maybe = |maybe_path: Option<&PathBuf>| maybe_path.map(parse).transpose();
+        Ok(Self {
+            stdin: parse_maybe(settings.stdin_log.as_ref())?,
+            stdin_timing: parse_maybe(settings.stdin_timing.as_ref())?,
+            stdout: parse_maybe(settings.stdout_log.as_ref())?,
+            stderr: parse_maybe(settings.stderr_log.as_ref())?,
+            timeline: parse_maybe(settings.timeline_log.as_ref())?,
+            asciicast: parse_maybe(settings.asciicast.as_ref())?,
+            manifest: parse_maybe(settings.manifest.as_ref())?,
+            extra_fds: settings
+                .extra_fds
+                .iter()
+                .map(|extra_fd| Ok((extra_fd.name(), parse(&extra_fd.log)?)))
+                .collect::<Result<_>>()?,
+        })
+    }
+}
+
+/// The logs of a session.
+pub struct SessionLogs {
+    /// The manifest, if any, already written with what is known at the start of the session.
+    pub maybe_manifest: Option<Manifest>,
+    /// The logs shared by all the streams.
+    pub shared_logs: Vec<Arc<SharedLog>>,
+    /// The log of stdin, if it is logged anywhere.
+    pub stdin: Option<StreamLog>,
+    /// The log of stdout, if it is logged anywhere.
+    pub stdout: Option<StreamLog>,
+    /// The log of stderr, if it is logged anywhere.
+    pub stderr: Option<StreamLog>,
+    /// The logs of the additional file descriptors, in the same order as in the settings.
+    pub extra_fds: Vec<Option<StreamLog>>,
+    /// The logs that record the changes of the window size: the shared logs, and the log files of
+    /// the streams whose format can tell the changes apart from the data.
+    pub resize_logs: Vec<Arc<dyn ResizeLog>>,
+}
+
+impl SessionLogs {
+    /// Writes the manifest, and creates the log files of a session, expanding the placeholders in
+    /// their names.
+    ///
+    /// # Arguments
+    ///
+    /// * `templates` - The parsed names of the log files.
+    /// * `settings` - The settings of the session.
+    /// * `session` - The session.
+    /// * `clock` - Clock that timestamps the records.
+    /// * `child_pid` - PID of the observed process.
+    /// * `maybe_session_dir` - The session directory, if any, that relative names are relative to.
+    /// * `maybe_reopener` - The reopener of the log files, if they are reopened on a signal.
+    ///
+    /// # Returns
+    ///
+    /// Returns the logs of the session.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - Failed to write the manifest,
+    /// - Failed to create any of the log files, or
+    /// - Failed to write the header of the asciicast recording.
+    pub fn create(
+        templates: LogTemplates,
+        settings: &ResolvedSettings,
+        session: &Session,
+        clock: Clock,
+        child_pid: u32,
+        maybe_session_dir: Option<&Path>,
+        maybe_reopener: Option<&LogReopener>,
+    ) -> Result<Self> {
+        // unwrap: Safe because the logs of a session are only created when there is a target
+        // command, which is in every mode but the proxy.
+        let target = settings.target.as_ref().unwrap();
+        let expand = |maybe_template: Option<PathTemplate>| {
+            maybe_template.map(|template| {
+                template.expand(
+                    session,
+                    TemplateValues::Session {
+                        child_pid,
+                        executable: target.executable.as_str(),
+                    },
+                )
+            })
+        };
+
+        let maybe_manifest = expand(templates.manifest).map(|path| {
+            Manifest::new(
+                session::resolve_path(maybe_session_dir, &path),
+                session,
+                child_pid,
+                target,
+                &settings.manifest_env,
+            )
+        });
+        if let Some(manifest) = &maybe_manifest {
+            manifest.write().context("Error writing manifest")?;
+        }
+
+        let record_format = record_format(settings);
+        // The timing file and the asciicast recording are only meaningful as a whole, so they are
+        // never rotated nor reopened.
+        let maybe_rotation = rotation(settings);
+
+        let mut shared_logs = Vec::new();
+        if let Some(file) = fd::create_log_file(
+            expand(templates.timeline).as_ref(),
+            maybe_session_dir,
+            settings.recreate_logs,
+            settings.compression,
+            maybe_rotation,
+            maybe_reopener,
+        )? {
+            shared_logs.push(Arc::new(SharedLog::timeline(file, record_format.clone())));
+        }
+        if let Some(file) = fd::create_log_file(
+            expand(templates.asciicast).as_ref(),
+            maybe_session_dir,
+            settings.recreate_logs,
+            settings.compression,
+            None,
+            None,
+        )? {
+            shared_logs.push(Arc::new(
+                SharedLog::asciicast(
+                    file,
+                    clock,
+                    target,
+                    terminal::window_size().unwrap_or_default(),
+                    settings.asciicast_stdin,
+                )
+                .context("Error writing asciicast header")?,
+            ));
+        }
+
+        let create_stream_log =
+            |stream, kind, maybe_template, maybe_timing_template| -> Result<Option<StreamLog>> {
+                Ok(StreamLog::new(
+                    stream,
+                    clock,
+                    fd::create_log_file(
+                        expand(maybe_template).as_ref(),
+                        maybe_session_dir,
+                        settings.recreate_logs,
+                        settings.compression,
+                        maybe_rotation,
+                        maybe_reopener,
+                    )?,
+                    &RecordFormat {
+                        kind,
+                        ..record_format.clone()
+                    },
+                    fd::create_log_file(
+                        expand(maybe_timing_template).as_ref(),
+                        maybe_session_dir,
+                        settings.recreate_logs,
+                        settings.compression,
+                        None,
+                        None,
+                    )?,
+                    &shared_logs,
+                ))
+            };
+
+        let formats = settings.stream_log_formats;
+        let stdin = create_stream_log(
+            "stdin",
+            formats.stdin,
+            templates.stdin,
+            templates.stdin_timing,
+        )?;
+        let stdout = create_stream_log("stdout", formats.stdout, templates.stdout, None)?;
+        let stderr = create_stream_log("stderr", formats.stderr, templates.stderr, None)?;
+        let extra_fds: Vec<_> = templates
+            .extra_fds
+            .into_iter()
+            .map(|(name, template)| {
+                create_stream_log(name, settings.log_format, Some(template), None)
+            })
+            .collect::<Result<_>>()?;
+        let resize_logs = shared_logs
+            .iter()
+            .cloned()
+            .map(|log| log as Arc<dyn ResizeLog>)
+            .chain(
+                [&stdin, &stdout, &stderr]
+                    .into_iter()
+                    .chain(&extra_fds)
+                    .flatten()
+                    .filter_map(StreamLog::resize_log)
+                    .map(|log| Arc::new(log) as Arc<dyn ResizeLog>),
+            )
+            .collect();
+
+        Ok(Self {
+            maybe_manifest,
+            shared_logs,
+            stdin,
+            stdout,
+            stderr,
+            extra_fds,
+            resize_logs,
+        })
+    }
+}
+
+/// Determines the format of the records written to the log files, other than the ones of the
+/// standard streams, whose kind is taken from [`ResolvedSettings::stream_log_formats`] instead.
+///
+/// # Arguments
+///
+/// * `settings` - The settings of the session.
+///
+/// # Returns
+///
+/// Returns the format of the records.
+pub fn record_format(settings: &ResolvedSettings) -> RecordFormat {
+    RecordFormat {
+        kind: settings.log_format,
+        hex_chunk_marks: settings.hex_chunk_marks,
+        line_prefix: settings.line_prefix.clone(),
+    }
+}
+
+/// Determines when the log files are rotated.
+///
+/// # Arguments
+///
+/// * `settings` - The settings of the session.
+///
+/// # Returns
+///
+/// Returns when the log files are rotated, or `None` if they aren't.
+pub fn rotation(settings: &ResolvedSettings) -> Option<Rotation> {
+    (settings.max_log_size.is_some() || settings.rotate_interval.is_some()).then(|| Rotation {
+        max_size: settings.max_log_size.map(NonZeroU64::get),
+        interval: settings.rotate_interval,
+        max_files: settings.max_log_files,
+    })
+}
+
+/// Creates the directory of a session, if there is a log directory.
+///
+/// # Arguments
+///
+/// * `session` - The session.
+/// * `settings` - The settings of the session.
+///
+/// # Returns
+///
+/// Returns the session directory, or `None` if there is no log directory.
+///
+/// # Errors
+///
+/// Returns an error if the session directory couldn't be created.
+pub fn create_session_dir(
+    session: &Session,
+    settings: &ResolvedSettings,
+) -> Result<Option<PathBuf>> {
+    settings
+        .log_dir
+        .as_deref()
+        .map(|log_dir| {
+            session.create_dir(log_dir).with_context(|| {
+                format!("Error creating session directory in {}", log_dir.display())
+            })
+        })
+        .transpose()
+}
+
+/// Completes the shared logs and the manifest once all the streams have ended, and summarizes the
+/// traffic of each stream, if asked to.
+///
+/// This must happen before exiting, since `std::process::exit` doesn't run destructors, and a
+/// compressed log that isn't finished is truncated.
+///
+/// # Arguments
+///
+/// * `shared_logs` - The logs shared by all the streams.
+/// * `maybe_manifest` - The manifest, if any.
+/// * `clock` - Clock that timestamps the end of the session.
+/// * `maybe_status` - How the observed process ended, if it is known.
+/// * `streams_summary` - The summary of the traffic of each stream.
+/// * `print_summary` - Whether to print the summary to stderr.
+pub fn finish(
+    shared_logs: &[Arc<SharedLog>],
+    maybe_manifest: Option<&mut Manifest>,
+    clock: Clock,
+    maybe_status: Option<ExitStatus>,
+    streams_summary: StreamsSummary,
+    print_summary: bool,
+) {
+    for log in shared_logs {
+        if let Err(e) = log.finish() {
+            eprintln!("Error finishing shared log: {e}");
+        }
+    }
+
+    // The terminal is restored before anything else is printed, so that it is printed as usual.
+    // This must happen explicitly, since `std::process::exit` doesn't run destructors.
+    terminal::restore_mode();
+
+    if print_summary {
+        eprintln!("fdintercept summary:\n{streams_summary}");
+    }
+
+    if let Some(manifest) = maybe_manifest {
+        let (ended_at, duration) = clock.now();
+        manifest.end(ended_at, duration, maybe_status, streams_summary);
+        if let Err(e) = manifest.write() {
+            eprintln!("Error writing manifest: {e}");
+        }
+    }
+}
@@ -12,43 +12,189 @@
 //! take precedence over configuration files.
 
 use anyhow::{Context, Result};
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
 use non_empty_string::NonEmptyString;
 use nonempty::NonEmpty;
 use serde::Deserialize;
+use signal_hook::consts::{SIGHUP, SIGUSR1, SIGUSR2};
+use std::collections::BTreeMap;
 use std::env::{self};
+use std::num::NonZeroU64;
+use std::os::fd::RawFd;
 use std::path::PathBuf;
 
 /// Command-line arguments parser.
 #[derive(Parser, Default)]
 #[command(about, version)]
+#[allow(clippy::struct_excessive_bools)]
 struct CliArgs {
+    /// What to do instead of relaying the stdin of fdintercept to the target command.
+    #[command(subcommand)]
+    command: Option<CliCommand>,
+
     /// Path to a configuration file. If relative, this is relative to the current working
     /// directory.
-    #[arg(long)]
+    #[arg(long, global = true)]
     conf: Option<PathBuf>,
 
+    /// Directory under which each session creates a unique subdirectory of its own, with its log
+    /// files and its manifest. Relative log filenames are then relative to that subdirectory,
+    /// instead of to the current working directory. Default: no log directory.
+    #[arg(long, global = true)]
+    log_dir: Option<PathBuf>,
+
     /// Filename of the log file that will record stdin traffic. If relative, this is relative to
-    /// the current working directory. Default: stdin.log.
-    #[arg(long)]
+    /// the current working directory. The placeholders `{pid}`, `{child_pid}`, `{timestamp}`,
+    /// `{executable}`, `{session_id}`, and `{env:VAR}` are replaced once per session, in the names
+    /// of all the log files. Default: stdin.log.
+    #[arg(long, global = true)]
     stdin_log: Option<PathBuf>,
 
     /// Filename of the log file that will record stdout traffic. If relative, this is relative to
     /// the current working directory. Default: stdout.log.
-    #[arg(long)]
+    #[arg(long, global = true)]
     stdout_log: Option<PathBuf>,
 
     /// Filename of the log file that will record stderr traffic. If relative, this is relative to
     /// the current working directory. Default: stderr.log.
-    #[arg(long)]
+    #[arg(long, global = true)]
     stderr_log: Option<PathBuf>,
 
+    /// Filename of the timing file that will record when each stdin chunk was observed, so that
+    /// the stdin log can be replayed with its original timing. If relative, this is relative to the
+    /// current working directory. Default: no timing file.
+    #[arg(long, global = true)]
+    stdin_timing: Option<PathBuf>,
+
+    /// Filename of the log file that will record the traffic of all streams in a single timeline,
+    /// in the order it was observed. If relative, this is relative to the current working
+    /// directory. Default: no timeline log.
+    #[arg(long, global = true)]
+    timeline_log: Option<PathBuf>,
+
+    /// Filename of the asciicast v2 recording of the session, which can be played back with
+    /// asciinema. If relative, this is relative to the current working directory. Default: no
+    /// recording.
+    #[arg(long, global = true)]
+    asciicast: Option<PathBuf>,
+
+    /// Filename of the manifest that will describe the session, from the target command to how it
+    /// ended. If relative, this is relative to the current working directory. Default:
+    /// session.json if there is a log directory, otherwise no manifest.
+    #[arg(long, global = true)]
+    manifest: Option<PathBuf>,
+
+    /// Comma-separated names of the environment variables recorded in the manifest, if they are
+    /// set. Default: PATH,HOME,USER,SHELL,TERM,LANG.
+    #[arg(long, value_delimiter = ',', global = true)]
+    manifest_env: Option<Vec<String>>,
+
+    /// Record stdin in the asciicast recording as input events. Default: false.
+    #[arg(long, global = true)]
+    asciicast_stdin: bool,
+
     /// Re-create log files instead of appending to them. Default: false.
-    #[arg(long)]
+    #[arg(long, global = true)]
     recreate_logs: bool,
 
+    /// Format of the records written to the log files. Default: raw.
+    #[arg(long, value_enum, global = true)]
+    log_format: Option<LogFormat>,
+
+    /// Format of the records written to the stdin log file, instead of the one of --log-format.
+    /// Default: the one of --log-format.
+    #[arg(long, value_enum, global = true)]
+    stdin_log_format: Option<LogFormat>,
+
+    /// Format of the records written to the stdout log file, like --stdin-log-format. Default: the
+    /// one of --log-format.
+    #[arg(long, value_enum, global = true)]
+    stdout_log_format: Option<LogFormat>,
+
+    /// Format of the records written to the stderr log file, like --stdin-log-format. Default: the
+    /// one of --log-format.
+    #[arg(long, value_enum, global = true)]
+    stderr_log_format: Option<LogFormat>,
+
+    /// Mark the boundaries of the chunks, as they were read, in hex dumps. Default: false.
+    #[arg(long, global = true)]
+    hex_chunk_marks: bool,
+
+    /// Template of the prefix of each line in the lines log format. The placeholders {stream},
+    /// {time}, {timestamp}, and {mono} are replaced with the name of the stream and the time at
+    /// which the line started. Default: "[{stream} {time}] ".
+    #[arg(long, global = true)]
+    line_prefix: Option<String>,
+
+    /// Compression of the log files. With auto, log files whose names end in .gz are compressed
+    /// with gzip, and those whose names end in .zst are compressed with zstd. Default: auto.
+    #[arg(long, value_enum, global = true)]
+    compression: Option<Compression>,
+
+    /// Size in bytes, before compression, past which a log file is rotated: it is renamed to
+    /// <name>.1, the previous <name>.1 to <name>.2, and so on, and a fresh log file is started.
+    /// Applies to the per-stream logs and the timeline log. What a compressed log file already had
+    /// when it was appended to isn't counted. Default: no rotation.
+    #[arg(long, global = true)]
+    max_log_size: Option<NonZeroU64>,
+
+    /// Rotate log files periodically, at the top of each hour or at midnight, in UTC, like with
+    /// --max-log-size. Default: no periodic rotation.
+    #[arg(long, value_enum, global = true)]
+    rotate_interval: Option<RotationInterval>,
+
+    /// Number of rotated log files kept for each log, besides the current one. Default: 5.
+    #[arg(long, global = true)]
+    max_log_files: Option<usize>,
+
+    /// Signal that makes fdintercept reopen its log files, e.g., after logrotate moved them away.
+    /// If it is hup, SIGHUP no longer terminates fdintercept. Default: log files are not reopened.
+    #[arg(long, value_enum, global = true)]
+    reopen_signal: Option<ReopenSignal>,
+
+    /// Print a summary of the traffic of each stream to stderr on exit. Default: false.
+    #[arg(long, global = true)]
+    summary: bool,
+
+    /// Run the target command in a pseudo-terminal, instead of with pipes, so that it behaves as
+    /// it would in an interactive terminal. Its stdout and stderr are then the same stream, which
+    /// is logged as stdout. Default: false.
+    #[arg(long, global = true)]
+    pty: bool,
+
+    /// Intercept an additional file descriptor of the target command, such as a status channel,
+    /// as N=in or N=out, optionally followed by :<log file>. The target command gets a pipe as its
+    /// file descriptor N, which fdintercept relays from its own file descriptor N, with in, or to
+    /// it, with out, if it is open. Can be given multiple times. If the log file is relative, this
+    /// is relative to the current working directory. Default log file: fd<N>.log.
+    #[arg(long = "fd", value_name = "N=in|out[:LOG]", global = true)]
+    fds: Vec<FdArg>,
+
+    /// File to read the stdin of the target command from, instead of the stdin of fdintercept,
+    /// such as a regular file, a FIFO, or /dev/null. It is relayed and logged like the stdin of
+    /// fdintercept would be. If relative, this is relative to the current working directory.
+    /// Default: the stdin of fdintercept.
+    #[arg(long, global = true)]
+    stdin_source: Option<PathBuf>,
+
+    /// How the stdin of the target command is handled: intercepted and logged, inherited from
+    /// fdintercept, or connected to /dev/null. Default: intercept if stdin is logged anywhere, or
+    /// counted in the summary or the manifest, otherwise inherit.
+    #[arg(long, value_enum, global = true)]
+    stdin_mode: Option<StreamMode>,
+
+    /// How the stdout of the target command is handled, like --stdin-mode. Default: intercept if
+    /// stdout is logged or counted, otherwise inherit.
+    #[arg(long, value_enum, global = true)]
+    stdout_mode: Option<StreamMode>,
+
+    /// How the stderr of the target command is handled, like --stdin-mode. Default: intercept if
+    /// stderr is logged or counted, otherwise inherit.
+    #[arg(long, value_enum, global = true)]
+    stderr_mode: Option<StreamMode>,
+
     /// Size in bytes of the buffer used for I/O operations. Default: 8 KiB.
-    #[arg(long)]
+    #[arg(long, global = true)]
     buffer_size: Option<usize>,
 
     /// The target command that will be executed.
@@ -56,13 +202,133 @@ struct CliArgs {
     target: Vec<String>,
 }
 
+/// Subcommands of fdintercept.
+#[derive(Subcommand)]
+enum CliCommand {
+    /// Feed a recorded stdin log into the target command, instead of the stdin of fdintercept.
+    /// stdout and stderr are intercepted and logged as usual.
+    Replay {
+        /// Path to the recorded stdin log, in the raw log format. If relative, this is relative to
+        /// the current working directory.
+        input: PathBuf,
+
+        /// Path to the timing file recorded alongside the stdin log. If given, the input is fed
+        /// with its original timing. If relative, this is relative to the current working
+        /// directory. Default: feed the input as fast as the target reads it.
+        #[arg(long)]
+        timing: Option<PathBuf>,
+
+        /// Factor by which the original timing is sped up, e.g., 2 to feed the input twice as
+        /// fast, or 0.5 to feed it at half speed. Default: 1.
+        #[arg(long, default_value_t = 1.0, value_parser = parse_speed)]
+        speed: f64,
+
+        /// The target command that will be executed.
+        #[arg(last = true)]
+        target: Vec<String>,
+    },
+    /// Attach to a process that is already running, instead of running the target command, and
+    /// log what it reads from stdin, and writes to stdout and stderr, and to the additional file
+    /// descriptors given with --fd, until it exits, or fdintercept is interrupted and detaches
+    /// from it. Only supported on Linux.
+    Attach {
+        /// PID of the process to attach to.
+        #[arg(value_parser = clap::value_parser!(i32).range(1..))]
+        pid: i32,
+    },
+    /// Listen for TCP connections, or Unix domain socket connections, instead of running the
+    /// target command, connect each client to an upstream server, and log what the client sends
+    /// and what the server sends back, to log files of each connection, until fdintercept is
+    /// interrupted.
+    Proxy {
+        /// Local address to listen on, e.g., 127.0.0.1:8080, or the path of the socket to create,
+        /// with --unix.
+        listen: String,
+
+        /// Address of the upstream server, e.g., example.com:80, or the path of its socket, with
+        /// --unix.
+        upstream: String,
+
+        /// Listen on, and connect to, Unix domain sockets, instead of TCP. The credentials of each
+        /// client, if available, are recorded in the description of its connection, and printed
+        /// as it connects.
+        #[arg(long)]
+        unix: bool,
+
+        /// Filename of the log file that will record what each client sends to the server. If
+        /// relative, this is relative to the current working directory. The placeholder
+        /// `{connection}`, which it must include, is replaced by the number of the connection,
+        /// counting from 1. Default: client.{connection}.log.
+        #[arg(long)]
+        client_log: Option<PathBuf>,
+
+        /// Filename of the log file that will record what the server sends back to each client.
+        /// If relative, this is relative to the current working directory. It must include
+        /// `{connection}`. Default: server.{connection}.log.
+        #[arg(long)]
+        server_log: Option<PathBuf>,
+
+        /// Filename of the JSON file that will describe each connection: when it was made, and the
+        /// address of the client, or its credentials, with --unix. If relative, this is relative
+        /// to the current working directory. It must include `{connection}`. Default:
+        /// connection.{connection}.json.
+        #[arg(long)]
+        connection_info: Option<PathBuf>,
+    },
+}
+
+/// Parses the speed factor of a replay.
+///
+/// # Arguments
+///
+/// * `value` - The speed factor, as given in the command line.
+///
+/// # Returns
+///
+/// Returns the speed factor if it is a finite number greater than zero, or an error message
+/// otherwise.
+fn parse_speed(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(speed) if speed.is_finite() && speed > 0.0 => Ok(speed),
+        Ok(_) => Err("must be a number greater than zero".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+impl CliArgs {
+    /// Returns the target command given in the command line, either after `--` or after the
+    /// arguments of a subcommand.
+    fn target(&self) -> &[String] {
+        match &self.command {
+            Some(CliCommand::Replay { target, .. }) if !target.is_empty() => target,
+            _ => &self.target,
+        }
+    }
+}
+
 /// Environment variables configuration container.
 #[derive(Default, Debug)]
 struct EnvVars {
     /// Path to configuration file (`FDINTERCEPTRC`).
     conf: Option<PathBuf>,
+    /// Directory of the session directories (`FDINTERCEPT_LOG_DIR`).
+    log_dir: Option<PathBuf>,
+    /// Path to stdin log file (`FDINTERCEPT_STDIN_LOG`).
+    stdin_log: Option<PathBuf>,
+    /// Path to stdout log file (`FDINTERCEPT_STDOUT_LOG`).
+    stdout_log: Option<PathBuf>,
+    /// Path to stderr log file (`FDINTERCEPT_STDERR_LOG`).
+    stderr_log: Option<PathBuf>,
     /// Whether to recreate log files (`FDINTERCEPT_RECREATE_LOGS`).
     recreate_logs: Option<bool>,
+    /// Format of the records written to the log files (`FDINTERCEPT_LOG_FORMAT`).
+    log_format: Option<LogFormat>,
+    /// Format of the records written to the stdin log file (`FDINTERCEPT_STDIN_LOG_FORMAT`).
+    stdin_log_format: Option<LogFormat>,
+    /// Format of the records written to the stdout log file (`FDINTERCEPT_STDOUT_LOG_FORMAT`).
+    stdout_log_format: Option<LogFormat>,
+    /// Format of the records written to the stderr log file (`FDINTERCEPT_STDERR_LOG_FORMAT`).
+    stderr_log_format: Option<LogFormat>,
     /// Buffer size for I/O operations (`FDINTERCEPT_BUFFER_SIZE`).
     buffer_size: Option<usize>,
     /// Target command to execute (`FDINTERCEPT_TARGET`).
@@ -72,20 +338,469 @@ struct EnvVars {
 /// Configuration file structure.
 #[derive(Debug, Default, Deserialize, PartialEq, Eq)]
 struct Config {
+    /// Directory of the session directories.
+    log_dir: Option<PathBuf>,
     /// Path to stdin log file.
     stdin_log: Option<PathBuf>,
     /// Path to stdout log file.
     stdout_log: Option<PathBuf>,
     /// Path to stderr log file.
     stderr_log: Option<PathBuf>,
+    /// Path to stdin timing file.
+    stdin_timing: Option<PathBuf>,
+    /// Path to timeline log file.
+    timeline_log: Option<PathBuf>,
+    /// Path to asciicast recording.
+    asciicast: Option<PathBuf>,
+    /// Path to the manifest.
+    manifest: Option<PathBuf>,
+    /// Names of the environment variables recorded in the manifest.
+    manifest_env: Option<Vec<String>>,
+    /// Whether to record stdin in the asciicast recording.
+    asciicast_stdin: Option<bool>,
     /// Whether to recreate log files.
     recreate_logs: Option<bool>,
+    /// Format of the records written to the log files.
+    log_format: Option<LogFormat>,
+    /// Format of the records written to the stdin log file.
+    stdin_log_format: Option<LogFormat>,
+    /// Format of the records written to the stdout log file.
+    stdout_log_format: Option<LogFormat>,
+    /// Format of the records written to the stderr log file.
+    stderr_log_format: Option<LogFormat>,
+    /// Whether to mark the boundaries of the chunks in hex dumps.
+    hex_chunk_marks: Option<bool>,
+    /// Template of the prefix of each line in the lines log format.
+    line_prefix: Option<String>,
+    /// Compression of the log files.
+    compression: Option<Compression>,
+    /// Size past which a log file is rotated.
+    max_log_size: Option<NonZeroU64>,
+    /// Period after which a log file is rotated.
+    rotate_interval: Option<RotationInterval>,
+    /// Number of rotated log files kept for each log.
+    max_log_files: Option<usize>,
+    /// Signal that makes fdintercept reopen its log files.
+    reopen_signal: Option<ReopenSignal>,
+    /// Whether to print a summary of the traffic of each stream on exit.
+    summary: Option<bool>,
+    /// Whether to run the target command in a pseudo-terminal.
+    pty: Option<bool>,
+    /// Additional file descriptors to intercept, by their numbers.
+    fd: Option<BTreeMap<String, FdConfig>>,
+    /// What the stdin of the target command is read from, instead of the stdin of fdintercept.
+    stdin_source: Option<StdinSource>,
+    /// How the stdin of the target command is handled.
+    stdin_mode: Option<StreamMode>,
+    /// How the stdout of the target command is handled.
+    stdout_mode: Option<StreamMode>,
+    /// How the stderr of the target command is handled.
+    stderr_mode: Option<StreamMode>,
     /// Buffer size for I/O operations.
     buffer_size: Option<usize>,
     /// Target command to execute.
     target: Option<String>,
 }
 
+/// Configuration of an additional file descriptor to intercept, in the configuration file.
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+struct FdConfig {
+    /// Direction in which the file descriptor is relayed.
+    direction: FdDirection,
+    /// Path to the log file of the file descriptor.
+    log: Option<PathBuf>,
+}
+
+/// Format of the records written to the log files.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// The intercepted bytes, exactly as they were intercepted.
+    #[default]
+    Raw,
+    /// Each chunk preceded by a header with its wall-clock and monotonic timestamps and its length
+    /// in bytes.
+    Timestamped,
+    /// Each chunk as a JSON object on its own line, with its stream, sequence number, timestamps,
+    /// length, and payload.
+    Jsonl,
+    /// An `xxd`-style dump with the offset of each line within the stream, the bytes in
+    /// hexadecimal, and the bytes as ASCII.
+    Hex,
+    /// Each complete line preceded by a configurable prefix, such as the name of the stream and
+    /// the time at which the line started.
+    Lines,
+}
+
+/// Compression of the log files.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    /// Compress log files according to the extension of their names: gzip for `.gz`, and zstd for
+    /// `.zst`. Log files with any other extension are not compressed.
+    #[default]
+    Auto,
+    /// Don't compress log files, whatever their names.
+    None,
+    /// Compress all log files with gzip.
+    Gzip,
+    /// Compress all log files with zstd.
+    Zstd,
+}
+
+/// Period after which a log file is rotated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum RotationInterval {
+    /// Rotate log files at the top of each hour.
+    Hourly,
+    /// Rotate log files at midnight, in UTC.
+    Daily,
+}
+
+/// Signal that makes fdintercept reopen its log files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ReopenSignal {
+    /// `SIGHUP`, which then no longer terminates fdintercept.
+    Hup,
+    /// `SIGUSR1`.
+    Usr1,
+    /// `SIGUSR2`.
+    Usr2,
+}
+
+impl ReopenSignal {
+    /// Returns the number of the signal.
+    pub const fn signum(self) -> i32 {
+        match self {
+            Self::Hup => SIGHUP,
+            Self::Usr1 => SIGUSR1,
+            Self::Usr2 => SIGUSR2,
+        }
+    }
+}
+
+/// Direction in which an additional file descriptor is relayed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum FdDirection {
+    /// The target command reads from the file descriptor, which is fed from the file descriptor of
+    /// fdintercept with the same number.
+    In,
+    /// The target command writes into the file descriptor, which is relayed to the file descriptor
+    /// of fdintercept with the same number.
+    Out,
+}
+
+/// How a standard stream of the target command is handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum StreamMode {
+    /// The target command gets a pipe, which fdintercept relays and logs.
+    Intercept,
+    /// The target command gets the stream of fdintercept itself, which isn't relayed nor logged.
+    Inherit,
+    /// The target command gets `/dev/null`.
+    Null,
+}
+
+/// What the stdin of the target command is read from, instead of the stdin of fdintercept.
+///
+/// In the configuration file, this is either the path of a file, as a string, or a table with the
+/// text itself, e.g., `{ text = "yes\n" }`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum StdinSource {
+    /// A file, such as a regular file, a FIFO, or `/dev/null`, which is read until its end.
+    Path(PathBuf),
+    /// Text given inline, which is fed as it is.
+    Text {
+        /// The text to feed.
+        text: String,
+    },
+}
+
+/// Format of the records written to the log file of each of the standard streams.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StreamLogFormats {
+    /// Format of the stdin log file.
+    pub stdin: LogFormat,
+    /// Format of the stdout log file.
+    pub stdout: LogFormat,
+    /// Format of the stderr log file.
+    pub stderr: LogFormat,
+}
+
+/// How each of the standard streams of the target command is handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamModes {
+    /// How stdin is handled.
+    pub stdin: StreamMode,
+    /// How stdout is handled.
+    pub stdout: StreamMode,
+    /// How stderr is handled.
+    pub stderr: StreamMode,
+}
+
+/// An additional file descriptor to intercept, as given in the command line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct FdArg {
+    /// Number of the file descriptor.
+    fd: RawFd,
+    /// Direction in which the file descriptor is relayed.
+    direction: FdDirection,
+    /// Path to the log file of the file descriptor, if given.
+    log: Option<PathBuf>,
+}
+
+/// Errors that can occur when parsing an additional file descriptor to intercept.
+#[derive(Debug, PartialEq, Eq)]
+enum FdParseError {
+    /// The file descriptor isn't followed by `=` and a direction.
+    MissingDirection,
+    /// The number of the file descriptor isn't a number, or is one of the standard streams.
+    InvalidNumber(String),
+    /// The direction is neither `in` nor `out`.
+    InvalidDirection(String),
+}
+
+impl std::fmt::Display for FdParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::MissingDirection => {
+                write!(f, "Expected N=in or N=out, optionally followed by :LOG")
+            }
+            Self::InvalidNumber(number) => write!(
+                f,
+                "Invalid file descriptor {number}, expected a number greater than 2"
+            ),
+            Self::InvalidDirection(direction) => {
+                write!(f, "Invalid direction {direction}, expected in or out")
+            }
+        }
+    }
+}
+
+impl std::error::Error for FdParseError {}
+
+/// Parses the number of an additional file descriptor to intercept.
+///
+/// # Arguments
+///
+/// * `number` - The number of the file descriptor.
+///
+/// # Returns
+///
+/// Returns the number, or an error if it isn't a number, or it is the number of one of the
+/// standard streams, which are intercepted already.
+fn parse_fd_number(number: &str) -> Result<RawFd, FdParseError> {
+    match number.parse() {
+        Ok(fd @ 3..) => Ok(fd),
+        _ => Err(FdParseError::InvalidNumber(number.to_string())),
+    }
+}
+
+impl std::str::FromStr for FdArg {
+    type Err = FdParseError;
+
+    /// Parses an additional file descriptor to intercept, as `N=in` or `N=out`, optionally
+    /// followed by `:` and the path to its log file.
+    fn from_str(arg: &str) -> Result<Self, Self::Err> {
+        let (number, rest) = arg.split_once('=').ok_or(FdParseError::MissingDirection)?;
+        let (direction, log) = match rest.split_once(':') {
+            Some((direction, log)) => (direction, Some(PathBuf::from(log))),
+            None => (rest, None),
+        };
+        Ok(Self {
+            fd: parse_fd_number(number)?,
+            direction: match direction {
+                "in" => FdDirection::In,
+                "out" => FdDirection::Out,
+                _ => return Err(FdParseError::InvalidDirection(direction.to_string())),
+            },
+            log,
+        })
+    }
+}
+
+/// An additional file descriptor to intercept.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtraFd {
+    /// Number of the file descriptor, in both the target command and fdintercept.
+    pub fd: RawFd,
+    /// Direction in which the file descriptor is relayed.
+    pub direction: FdDirection,
+    /// Path to the log file of the file descriptor.
+    pub log: PathBuf,
+    /// Name of the file descriptor, e.g., `fd3`, as it appears in the logs and error messages.
+    name: &'static str,
+}
+
+impl ExtraFd {
+    /// Creates an additional file descriptor to intercept.
+    ///
+    /// # Arguments
+    ///
+    /// * `fd` - Number of the file descriptor, in both the target command and fdintercept.
+    /// * `direction` - Direction in which the file descriptor is relayed.
+    /// * `log` - Path to the log file of the file descriptor.
+    pub fn new(fd: RawFd, direction: FdDirection, log: PathBuf) -> Self {
+        Self {
+            fd,
+            direction,
+            log,
+            // The name is needed for as long as fdintercept runs anyway, and it is only built once
+            // per file descriptor.
+            name: format!("fd{fd}").leak(),
+        }
+    }
+
+    /// Returns the name of the file descriptor, e.g., `fd3`, as it appears in the logs and error
+    /// messages.
+    pub const fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+/// The default template of the prefix of each line in the lines log format.
+const DEFAULT_LINE_PREFIX: &str = "[{stream} {time}] ";
+
+/// The prefix of each line in the lines log format, parsed from a template.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinePrefix(pub Vec<LinePrefixPart>);
+
+/// A part of a [`LinePrefix`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinePrefixPart {
+    /// Text that is copied as is.
+    Literal(String),
+    /// The name of the stream (`{stream}`).
+    Stream,
+    /// The wall-clock time, in UTC, at which the line started, as `HH:MM:SS.mmm` (`{time}`).
+    Time,
+    /// The wall-clock time at which the line started, in RFC 3339 format (`{timestamp}`).
+    Timestamp,
+    /// The monotonic time at which the line started, in seconds since fdintercept started
+    /// (`{mono}`).
+    Mono,
+}
+
+/// Errors that can occur when parsing a line prefix template.
+#[derive(Debug, PartialEq, Eq)]
+pub enum LinePrefixParseError {
+    /// A placeholder that isn't known.
+    UnknownPlaceholder(String),
+    /// A `{` without a matching `}`.
+    Unclosed,
+}
+
+impl std::fmt::Display for LinePrefixParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::UnknownPlaceholder(name) => write!(
+                f,
+                "Unknown placeholder {{{name}}}, expected one of {{stream}}, {{time}}, \
+                 {{timestamp}}, or {{mono}}"
+            ),
+            Self::Unclosed => write!(f, "Placeholder is missing its closing brace"),
+        }
+    }
+}
+
+impl std::error::Error for LinePrefixParseError {}
+
+impl std::str::FromStr for LinePrefix {
+    type Err = LinePrefixParseError;
+
+    /// Parses a line prefix template.
+    ///
+    /// Placeholders are enclosed in braces, e.g., `{stream}`. Literal braces are written doubled,
+    /// as `{{` and `}}`.
+    fn from_str(template: &str) -> Result<Self, Self::Err> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => return Err(LinePrefixParseError::Unclosed),
+                        }
+                    }
+                    let part = match name.as_str() {
+                        "stream" => LinePrefixPart::Stream,
+                        "time" => LinePrefixPart::Time,
+                        "timestamp" => LinePrefixPart::Timestamp,
+                        "mono" => LinePrefixPart::Mono,
+                        _ => return Err(LinePrefixParseError::UnknownPlaceholder(name)),
+                    };
+                    if !literal.is_empty() {
+                        parts.push(LinePrefixPart::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(part);
+                }
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(LinePrefixPart::Literal(literal));
+        }
+        Ok(Self(parts))
+    }
+}
+
+/// What fdintercept feeds into the stdin of the target command, or whether it observes a process
+/// that is already running, or relays connections, instead.
+#[derive(Debug, PartialEq)]
+pub enum Mode {
+    /// Relay the stdin of fdintercept.
+    Run,
+    /// Don't run the target command, but observe a process that is already running.
+    Attach {
+        /// PID of the process to observe.
+        pid: i32,
+    },
+    /// Don't run the target command, but relay TCP connections, or Unix domain socket
+    /// connections, to an upstream server.
+    Proxy {
+        /// Local address to listen on, or the path of the socket to create.
+        listen: String,
+        /// Address of the upstream server, or the path of its socket.
+        upstream: String,
+        /// Whether the addresses are paths of Unix domain sockets.
+        unix: bool,
+        /// Path to the log file of what each client sends.
+        client_log: PathBuf,
+        /// Path to the log file of what the server sends back to each client.
+        server_log: PathBuf,
+        /// Path to the file that describes each connection.
+        connection_info: PathBuf,
+    },
+    /// Feed a recorded stdin log.
+    Replay {
+        /// Path to the recorded stdin log.
+        input: PathBuf,
+        /// Path to the timing file of the recorded stdin log, if the input should be fed with its
+        /// original timing.
+        timing: Option<PathBuf>,
+        /// Factor by which the original timing is sped up.
+        speed: f64,
+    },
+}
+
 /// Target command specification.
 #[derive(Debug)]
 pub struct Target {
@@ -97,19 +812,66 @@ pub struct Target {
 
 /// Resolved settings after merging all configuration sources.
 #[derive(Debug)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct ResolvedSettings {
+    /// What to feed into the stdin of the target command, or which process to observe, or which
+    /// connections to relay, instead.
+    pub mode: Mode,
+    /// Directory under which each session creates its own directory, if any.
+    pub log_dir: Option<PathBuf>,
     /// Path to stdin log file, if enabled.
     pub stdin_log: Option<PathBuf>,
     /// Path to stdout log file, if enabled.
     pub stdout_log: Option<PathBuf>,
     /// Path to stderr log file, if enabled.
     pub stderr_log: Option<PathBuf>,
+    /// Path to stdin timing file, if enabled.
+    pub stdin_timing: Option<PathBuf>,
+    /// Path to timeline log file, if enabled.
+    pub timeline_log: Option<PathBuf>,
+    /// Path to asciicast recording, if enabled.
+    pub asciicast: Option<PathBuf>,
+    /// Path to the manifest, if enabled.
+    pub manifest: Option<PathBuf>,
+    /// Names of the environment variables recorded in the manifest.
+    pub manifest_env: Vec<String>,
+    /// Whether to record stdin in the asciicast recording.
+    pub asciicast_stdin: bool,
     /// Whether to recreate log files.
     pub recreate_logs: bool,
+    /// Format of the records written to the log files, other than the ones of the standard
+    /// streams.
+    pub log_format: LogFormat,
+    /// Format of the records written to the log file of each of the standard streams.
+    pub stream_log_formats: StreamLogFormats,
+    /// Whether to mark the boundaries of the chunks in hex dumps.
+    pub hex_chunk_marks: bool,
+    /// Prefix of each line in the lines log format.
+    pub line_prefix: LinePrefix,
+    /// Compression of the log files.
+    pub compression: Compression,
+    /// Size in bytes past which a log file is rotated, if log files are rotated.
+    pub max_log_size: Option<NonZeroU64>,
+    /// Period after which a log file is rotated, if log files are rotated periodically.
+    pub rotate_interval: Option<RotationInterval>,
+    /// Number of rotated log files kept for each log.
+    pub max_log_files: usize,
+    /// Signal that makes fdintercept reopen its log files, if any.
+    pub reopen_signal: Option<ReopenSignal>,
+    /// Whether to print a summary of the traffic of each stream on exit.
+    pub summary: bool,
+    /// Whether to run the target command in a pseudo-terminal.
+    pub pty: bool,
+    /// Additional file descriptors to intercept, ordered by their numbers.
+    pub extra_fds: Vec<ExtraFd>,
+    /// How each of the standard streams of the target command is handled.
+    pub stream_modes: StreamModes,
+    /// What the stdin of the target command is read from, if not the stdin of fdintercept.
+    pub stdin_source: Option<StdinSource>,
     /// Buffer size for I/O operations.
     pub buffer_size: usize,
-    /// Target command specification.
-    pub target: Target,
+    /// Target command specification, or `None` in proxy mode, which has no target command.
+    pub target: Option<Target>,
 }
 
 /// Gets the resolved settings using command line arguments from the current process.
@@ -151,7 +913,9 @@ pub fn get_settings() -> Result<ResolvedSettings> {
 /// This function will return an error if:
 /// * Command line arguments cannot be parsed,
 /// * Environment variables are invalid or inaccessible,
-/// * Configuration files are malformed or cannot be read, or
+/// * Configuration files are malformed or cannot be read,
+/// * A stdin timing file is requested while log files are rotated,
+/// * A manifest is requested in proxy mode, or
 /// * The target command specification is missing or invalid.
 ///
 /// # Resolution Process
@@ -168,35 +932,213 @@ fn get_settings_with_raw_cli_args(
     let env_vars = get_env_vars().context("Error reading environment variables")?;
     let config = get_config(&cli_args, &env_vars).context("Error reading configuration")?;
 
-    let use_defaults = get_use_defaults(&cli_args, &config);
-
-    Ok(ResolvedSettings {
-        stdin_log: get_log_name(LogFd::Stdin, &cli_args, &config, use_defaults, "stdin.log"),
-        stdout_log: get_log_name(
-            LogFd::Stdout,
-            &cli_args,
-            &config,
-            use_defaults,
-            "stdout.log",
-        ),
-        stderr_log: get_log_name(
+    let use_defaults = get_use_defaults(&cli_args, &env_vars, &config);
+    let mode = get_mode(&cli_args);
+
+    let log_dir = get_log_dir(&cli_args, &env_vars, &config);
+    // An attached process already has whatever standard streams it was started with, and a proxy
+    // has none to relay.
+    let pty = get_pty(&cli_args, &config) && matches!(mode, Mode::Run | Mode::Replay { .. });
+
+    // When replaying, the input is already a record of stdin, and it may even be the stdin log
+    // itself, so stdin is not logged to files of its own.
+    let (stdin_log, stdin_timing) = if matches!(mode, Mode::Replay { .. }) {
+        (None, None)
+    } else {
+        (
+            get_log_name(
+                LogFd::Stdin,
+                &cli_args,
+                &env_vars,
+                &config,
+                use_defaults,
+                "stdin.log",
+            ),
+            get_stdin_timing_name(&cli_args, &config),
+        )
+    };
+    let stdout_log = get_log_name(
+        LogFd::Stdout,
+        &cli_args,
+        &env_vars,
+        &config,
+        use_defaults,
+        "stdout.log",
+    );
+    // In a pseudo-terminal, stderr is the same stream as stdout, so it is logged there.
+    let stderr_log = if pty {
+        None
+    } else {
+        get_log_name(
             LogFd::Stderr,
             &cli_args,
+            &env_vars,
             &config,
             use_defaults,
             "stderr.log",
-        ),
+        )
+    };
+    let timeline_log = get_timeline_log_name(&cli_args, &config);
+    let asciicast = get_asciicast_name(&cli_args, &config);
+    let asciicast_stdin = get_asciicast_stdin(&cli_args, &config);
+    let is_proxy = matches!(mode, Mode::Proxy { .. });
+    // A manifest describes the target command, and a proxy has none.
+    let manifest = get_manifest_name(&cli_args, &config, log_dir.is_some() && !is_proxy);
+    if is_proxy && manifest.is_some() {
+        return Err(anyhow::anyhow!(
+            "A manifest can't be written in proxy mode, which has no target command"
+        ));
+    }
+    let summary = get_summary(&cli_args, &config);
+    // Only a target command that fdintercept runs with its own stdin can read it from elsewhere.
+    let stdin_source = get_stdin_source(&cli_args, &config).filter(|_| mode == Mode::Run);
+    // The traffic of every intercepted stream is counted in the summary and the manifest.
+    let counted = summary || manifest.is_some();
+
+    let stream_modes = get_stream_modes(
+        &cli_args,
+        &config,
+        pty,
+        &mode,
+        stdin_source.is_some(),
+        StreamModes {
+            stdin: default_stream_mode(
+                counted
+                    || stdin_log.is_some()
+                    || stdin_timing.is_some()
+                    || timeline_log.is_some()
+                    || (asciicast.is_some() && asciicast_stdin),
+            ),
+            stdout: default_stream_mode(
+                counted || stdout_log.is_some() || timeline_log.is_some() || asciicast.is_some(),
+            ),
+            stderr: default_stream_mode(
+                counted || stderr_log.is_some() || timeline_log.is_some() || asciicast.is_some(),
+            ),
+        },
+    )
+    .context("Error getting stream modes")?;
+    // An attached process already runs with whatever command line it was started with, and a proxy
+    // has no target command.
+    let target = match &mode {
+        Mode::Attach { pid } => get_target_from_pid(*pid).map(Some),
+        Mode::Proxy { .. } => Ok(None),
+        Mode::Run | Mode::Replay { .. } => get_target(&cli_args, &env_vars, &config).map(Some),
+    }
+    .context("Error getting target")?;
+
+    // Streams that aren't intercepted never reach fdintercept, so there is nothing to log.
+    let intercepted = |stream_mode, maybe_log: Option<PathBuf>| {
+        maybe_log.filter(|_| stream_mode == StreamMode::Intercept)
+    };
+
+    let stdin_timing = intercepted(stream_modes.stdin, stdin_timing);
+    let max_log_size = get_max_log_size(&cli_args, &config);
+    let rotate_interval = get_rotate_interval(&cli_args, &config);
+    // The timing file describes the whole stdin log, so it would no longer match any of the files
+    // the stdin log is rotated into.
+    if stdin_timing.is_some() && (max_log_size.is_some() || rotate_interval.is_some()) {
+        return Err(anyhow::anyhow!(
+            "A stdin timing file can't be written while log files are rotated"
+        ));
+    }
+
+    Ok(ResolvedSettings {
+        stdin_log: intercepted(stream_modes.stdin, stdin_log),
+        stdin_timing,
+        stdout_log: intercepted(stream_modes.stdout, stdout_log),
+        stderr_log: intercepted(stream_modes.stderr, stderr_log),
+        manifest_env: get_manifest_env(&cli_args, &config),
         recreate_logs: get_recreate_logs(&cli_args, &env_vars, &config),
+        log_format: get_log_format(&cli_args, &env_vars, &config),
+        stream_log_formats: get_stream_log_formats(&cli_args, &env_vars, &config),
+        hex_chunk_marks: get_hex_chunk_marks(&cli_args, &config),
+        line_prefix: get_line_prefix(&cli_args, &config).context("Error getting line prefix")?,
+        compression: get_compression(&cli_args, &config),
+        max_log_size,
+        rotate_interval,
+        max_log_files: get_max_log_files(&cli_args, &config),
+        reopen_signal: get_reopen_signal(&cli_args, &config),
+        extra_fds: get_extra_fds(&cli_args, &config)
+            .context("Error getting additional file descriptors")?,
         buffer_size: get_buffer_size(&cli_args, &env_vars, &config),
-        target: get_target(&cli_args, &env_vars, &config).context("Error getting target")?,
+        timeline_log,
+        asciicast,
+        asciicast_stdin,
+        manifest,
+        summary,
+        stream_modes,
+        stdin_source,
+        target,
+        log_dir,
+        pty,
+        mode,
     })
 }
 
+/// Determines what to feed into the stdin of the target command.
+///
+/// This is only configurable via command-line arguments, with the subcommands.
+///
+/// # Arguments
+///
+/// * `cli_args` - Reference to the parsed command-line arguments.
+///
+/// # Returns
+///
+/// Returns [`Mode::Replay`] if the `replay` subcommand was given, [`Mode::Attach`] if the `attach`
+/// subcommand was given, [`Mode::Proxy`] if the `proxy` subcommand was given, or [`Mode::Run`]
+/// otherwise.
+fn get_mode(cli_args: &CliArgs) -> Mode {
+    match &cli_args.command {
+        Some(CliCommand::Replay {
+            input,
+            timing,
+            speed,
+            ..
+        }) => Mode::Replay {
+            input: input.clone(),
+            timing: timing.clone(),
+            speed: *speed,
+        },
+        Some(CliCommand::Attach { pid }) => Mode::Attach { pid: *pid },
+        Some(CliCommand::Proxy {
+            listen,
+            upstream,
+            unix,
+            client_log,
+            server_log,
+            connection_info,
+        }) => Mode::Proxy {
+            listen: listen.clone(),
+            upstream: upstream.clone(),
+            unix: *unix,
+            client_log: client_log
+                .clone()
+                .unwrap_or_else(|| PathBuf::from("client.{connection}.log")),
+            server_log: server_log
+                .clone()
+                .unwrap_or_else(|| PathBuf::from("server.{connection}.log")),
+            connection_info: connection_info
+                .clone()
+                .unwrap_or_else(|| PathBuf::from("connection.{connection}.json")),
+        },
+        None => Mode::Run,
+    }
+}
+
 /// Reads and parses environment variables into a configuration structure.
 ///
 /// This function attempts to read and parse the following environment variables:
 /// - `FDINTERCEPTRC`: Path to a configuration file.
+/// - `FDINTERCEPT_LOG_DIR`: Directory of the session directories.
+/// - `FDINTERCEPT_STDIN_LOG`, `FDINTERCEPT_STDOUT_LOG`, `FDINTERCEPT_STDERR_LOG`: Paths to the log
+///   files.
 /// - `FDINTERCEPT_RECREATE_LOGS`: Boolean flag for recreating log files.
+/// - `FDINTERCEPT_LOG_FORMAT`: Format of the records written to the log files.
+/// - `FDINTERCEPT_STDIN_LOG_FORMAT`, `FDINTERCEPT_STDOUT_LOG_FORMAT`,
+///   `FDINTERCEPT_STDERR_LOG_FORMAT`: Formats of the records written to the log files of each of
+///   the standard streams.
 /// - `FDINTERCEPT_BUFFER_SIZE`: Numeric value for I/O buffer size.
 /// - `FDINTERCEPT_TARGET`: Command string to execute.
 ///
@@ -207,15 +1149,27 @@ fn get_settings_with_raw_cli_args(
 /// # Errors
 ///
 /// This function will return an error if:
-/// - `FDINTERCEPTRC` is defined but empty,
+/// - `FDINTERCEPTRC` or `FDINTERCEPT_LOG_DIR` is defined but empty,
+/// - `FDINTERCEPT_STDIN_LOG`, `FDINTERCEPT_STDOUT_LOG`, or `FDINTERCEPT_STDERR_LOG` is defined but
+///   empty,
 /// - `FDINTERCEPT_RECREATE_LOGS` contains an invalid boolean value,
+/// - `FDINTERCEPT_LOG_FORMAT`, `FDINTERCEPT_STDIN_LOG_FORMAT`, `FDINTERCEPT_STDOUT_LOG_FORMAT`, or
+///   `FDINTERCEPT_STDERR_LOG_FORMAT` contains an unknown log format,
 /// - `FDINTERCEPT_BUFFER_SIZE` contains an invalid numeric value, or
 /// - Any environment variable exists but cannot be read due to invalid Unicode.
 ///
 /// # Environment Variables
 ///
 /// - `FDINTERCEPTRC`: Optional path to configuration file.
+/// - `FDINTERCEPT_LOG_DIR`: Optional directory of the session directories.
+/// - `FDINTERCEPT_STDIN_LOG`: Optional path to stdin log file.
+/// - `FDINTERCEPT_STDOUT_LOG`: Optional path to stdout log file.
+/// - `FDINTERCEPT_STDERR_LOG`: Optional path to stderr log file.
 /// - `FDINTERCEPT_RECREATE_LOGS`: Optional boolean ("true"/"false") for log file handling.
+/// - `FDINTERCEPT_LOG_FORMAT`: Optional log format ("raw"/"timestamped"/"jsonl"/"hex"/"lines").
+/// - `FDINTERCEPT_STDIN_LOG_FORMAT`: Optional log format of the stdin log file.
+/// - `FDINTERCEPT_STDOUT_LOG_FORMAT`: Optional log format of the stdout log file.
+/// - `FDINTERCEPT_STDERR_LOG_FORMAT`: Optional log format of the stderr log file.
 /// - `FDINTERCEPT_BUFFER_SIZE`: Optional positive integer for buffer size.
 /// - `FDINTERCEPT_TARGET`: Optional command string to execute.
 fn get_env_vars() -> Result<EnvVars> {
@@ -237,6 +1191,74 @@ fn get_env_vars() -> Result<EnvVars> {
                 }
             }
         },
+        log_dir: {
+            match env::var("FDINTERCEPT_LOG_DIR") {
+                Ok(env_var) => {
+                    if env_var.is_empty() {
+                        return Err(anyhow::anyhow!("FDINTERCEPT_LOG_DIR is empty"));
+                    }
+                    Some(PathBuf::from(env_var))
+                }
+                Err(std::env::VarError::NotPresent) => None,
+                Err(e) => {
+                    return Err(anyhow::anyhow!(
+                        "Error reading FDINTERCEPT_LOG_DIR environment variable: {}",
+                        e
+                    ));
+                }
+            }
+        },
+        stdin_log: {
+            match env::var("FDINTERCEPT_STDIN_LOG") {
+                Ok(env_var) => {
+                    if env_var.is_empty() {
+                        return Err(anyhow::anyhow!("FDINTERCEPT_STDIN_LOG is empty"));
+                    }
+                    Some(PathBuf::from(env_var))
+                }
+                Err(std::env::VarError::NotPresent) => None,
+                Err(e) => {
+                    return Err(anyhow::anyhow!(
+                        "Error reading FDINTERCEPT_STDIN_LOG environment variable: {}",
+                        e
+                    ));
+                }
+            }
+        },
+        stdout_log: {
+            match env::var("FDINTERCEPT_STDOUT_LOG") {
+                Ok(env_var) => {
+                    if env_var.is_empty() {
+                        return Err(anyhow::anyhow!("FDINTERCEPT_STDOUT_LOG is empty"));
+                    }
+                    Some(PathBuf::from(env_var))
+                }
+                Err(std::env::VarError::NotPresent) => None,
+                Err(e) => {
+                    return Err(anyhow::anyhow!(
+                        "Error reading FDINTERCEPT_STDOUT_LOG environment variable: {}",
+                        e
+                    ));
+                }
+            }
+        },
+        stderr_log: {
+            match env::var("FDINTERCEPT_STDERR_LOG") {
+                Ok(env_var) => {
+                    if env_var.is_empty() {
+                        return Err(anyhow::anyhow!("FDINTERCEPT_STDERR_LOG is empty"));
+                    }
+                    Some(PathBuf::from(env_var))
+                }
+                Err(std::env::VarError::NotPresent) => None,
+                Err(e) => {
+                    return Err(anyhow::anyhow!(
+                        "Error reading FDINTERCEPT_STDERR_LOG environment variable: {}",
+                        e
+                    ));
+                }
+            }
+        },
         recreate_logs: {
             match env::var("FDINTERCEPT_RECREATE_LOGS") {
                 Ok(env_var) => match env_var.parse() {
@@ -257,6 +1279,86 @@ fn get_env_vars() -> Result<EnvVars> {
                 }
             }
         },
+        log_format: {
+            match env::var("FDINTERCEPT_LOG_FORMAT") {
+                Ok(env_var) => match LogFormat::from_str(&env_var, true) {
+                    Ok(log_format) => Some(log_format),
+                    Err(e) => {
+                        return Err(anyhow::anyhow!(
+                            "Error parsing FDINTERCEPT_LOG_FORMAT environment variable: {}",
+                            e
+                        ));
+                    }
+                },
+                Err(std::env::VarError::NotPresent) => None,
+                Err(e) => {
+                    return Err(anyhow::anyhow!(
+                        "Error reading FDINTERCEPT_LOG_FORMAT environment variable: {}",
+                        e
+                    ));
+                }
+            }
+        },
+        stdin_log_format: {
+            match env::var("FDINTERCEPT_STDIN_LOG_FORMAT") {
+                Ok(env_var) => match LogFormat::from_str(&env_var, true) {
+                    Ok(log_format) => Some(log_format),
+                    Err(e) => {
+                        return Err(anyhow::anyhow!(
+                            "Error parsing FDINTERCEPT_STDIN_LOG_FORMAT environment variable: {}",
+                            e
+                        ));
+                    }
+                },
+                Err(std::env::VarError::NotPresent) => None,
+                Err(e) => {
+                    return Err(anyhow::anyhow!(
+                        "Error reading FDINTERCEPT_STDIN_LOG_FORMAT environment variable: {}",
+                        e
+                    ));
+                }
+            }
+        },
+        stdout_log_format: {
+            match env::var("FDINTERCEPT_STDOUT_LOG_FORMAT") {
+                Ok(env_var) => match LogFormat::from_str(&env_var, true) {
+                    Ok(log_format) => Some(log_format),
+                    Err(e) => {
+                        return Err(anyhow::anyhow!(
+                            "Error parsing FDINTERCEPT_STDOUT_LOG_FORMAT environment variable: {}",
+                            e
+                        ));
+                    }
+                },
+                Err(std::env::VarError::NotPresent) => None,
+                Err(e) => {
+                    return Err(anyhow::anyhow!(
+                        "Error reading FDINTERCEPT_STDOUT_LOG_FORMAT environment variable: {}",
+                        e
+                    ));
+                }
+            }
+        },
+        stderr_log_format: {
+            match env::var("FDINTERCEPT_STDERR_LOG_FORMAT") {
+                Ok(env_var) => match LogFormat::from_str(&env_var, true) {
+                    Ok(log_format) => Some(log_format),
+                    Err(e) => {
+                        return Err(anyhow::anyhow!(
+                            "Error parsing FDINTERCEPT_STDERR_LOG_FORMAT environment variable: {}",
+                            e
+                        ));
+                    }
+                },
+                Err(std::env::VarError::NotPresent) => None,
+                Err(e) => {
+                    return Err(anyhow::anyhow!(
+                        "Error reading FDINTERCEPT_STDERR_LOG_FORMAT environment variable: {}",
+                        e
+                    ));
+                }
+            }
+        },
         buffer_size: {
             match env::var("FDINTERCEPT_BUFFER_SIZE") {
                 Ok(env_var) => match env_var.parse() {
@@ -414,30 +1516,62 @@ fn parse_config_contents(contents: &str) -> Result<Config> {
     toml::from_str(contents).context("Error parsing TOML configuration")
 }
 
-/// Determines whether default log files should be used based on CLI arguments and configuration.
+/// Determines the directory of the session directories based on configuration precedence.
 ///
-/// This function checks if any log file paths have been explicitly specified either through
-/// command-line arguments or in the configuration file. If no log files are specified in either
-/// source, it returns `true` indicating that default log files should be used.
+/// This function checks multiple configuration sources in the following order:
+/// 1. Command-line arguments (`--log-dir` option),
+/// 2. Environment variables (`FDINTERCEPT_LOG_DIR`), or
+/// 3. Configuration file (`log_dir` field).
 ///
 /// # Arguments
 ///
 /// * `cli_args` - Reference to the parsed command-line arguments.
-/// * `config` - Reference to the parsed configuration.
+/// * `env_vars` - Reference to the parsed environment variables.
+/// * `config` - Reference to the parsed configuration file settings.
 ///
 /// # Returns
 ///
-/// Returns `true` if no log files are specified in either CLI arguments or configuration,
-/// indicating that default log files should be used. Returns `false` if any log file path is
-/// explicitly specified.
-const fn get_use_defaults(cli_args: &CliArgs, config: &Config) -> bool {
-    cli_args.stdin_log.is_none()
-        && cli_args.stdout_log.is_none()
-        && cli_args.stderr_log.is_none()
-        && config.stdin_log.is_none()
-        && config.stdout_log.is_none()
-        && config.stderr_log.is_none()
-}
+/// Returns an `Option<PathBuf>` which is:
+/// - `Some(PathBuf)` containing the directory under which each session creates its own
+///   directory, or
+/// - `None` if log files are relative to the current working directory.
+fn get_log_dir(cli_args: &CliArgs, env_vars: &EnvVars, config: &Config) -> Option<PathBuf> {
+    cli_args
+        .log_dir
+        .as_ref()
+        .or(env_vars.log_dir.as_ref())
+        .or(config.log_dir.as_ref())
+        .cloned()
+}
+
+/// Determines whether default log files should be used based on CLI arguments and configuration.
+///
+/// This function checks if any log file paths have been explicitly specified either through
+/// command-line arguments, environment variables, or in the configuration file. If no log files
+/// are specified in any source, it returns `true` indicating that default log files should be used.
+///
+/// # Arguments
+///
+/// * `cli_args` - Reference to the parsed command-line arguments.
+/// * `env_vars` - Reference to the parsed environment variables.
+/// * `config` - Reference to the parsed configuration.
+///
+/// # Returns
+///
+/// Returns `true` if no log files are specified in CLI arguments, environment variables, or
+/// configuration, indicating that default log files should be used. Returns `false` if any log
+/// file path is explicitly specified.
+const fn get_use_defaults(cli_args: &CliArgs, env_vars: &EnvVars, config: &Config) -> bool {
+    cli_args.stdin_log.is_none()
+        && cli_args.stdout_log.is_none()
+        && cli_args.stderr_log.is_none()
+        && env_vars.stdin_log.is_none()
+        && env_vars.stdout_log.is_none()
+        && env_vars.stderr_log.is_none()
+        && config.stdin_log.is_none()
+        && config.stdout_log.is_none()
+        && config.stderr_log.is_none()
+}
 
 /// Represents the different types of file descriptors that can be logged.
 enum LogFd {
@@ -454,13 +1588,18 @@ enum LogFd {
 ///
 /// This function resolves the appropriate log filename by checking multiple sources in order:
 /// 1. Command-line arguments,
-/// 2. Configuration file, or
-/// 3. Default filename (if enabled).
+/// 2. Environment variables,
+/// 3. Configuration file, or
+/// 4. Default filename (if enabled).
+///
+/// The path may be a template with placeholders, which are only expanded once the target command
+/// was started. See [`crate::session::PathTemplate`].
 ///
 /// # Arguments
 ///
 /// * `log_fd` - The file descriptor type ([`LogFd`]) to get the log name for.
 /// * `cli_args` - Reference to the parsed command-line arguments.
+/// * `env_vars` - Reference to the parsed environment variables.
 /// * `config` - Reference to the parsed configuration.
 /// * `use_default` - Whether to use default filenames when no explicit path is specified.
 /// * `default_name` - The default filename to use when no explicit path is specified and defaults
@@ -474,6 +1613,7 @@ enum LogFd {
 fn get_log_name(
     log_fd: LogFd,
     cli_args: &CliArgs,
+    env_vars: &EnvVars,
     config: &Config,
     use_default: bool,
     default_name: &str,
@@ -483,18 +1623,208 @@ fn get_log_name(
         LogFd::Stdout => &cli_args.stdout_log,
         LogFd::Stderr => &cli_args.stderr_log,
     };
+    let env_name = match log_fd {
+        LogFd::Stdin => &env_vars.stdin_log,
+        LogFd::Stdout => &env_vars.stdout_log,
+        LogFd::Stderr => &env_vars.stderr_log,
+    };
     let config_name = match log_fd {
         LogFd::Stdin => &config.stdin_log,
         LogFd::Stdout => &config.stdout_log,
         LogFd::Stderr => &config.stderr_log,
     };
-    match (cli_name, config_name) {
-        (Some(p), _) | (None, Some(p)) => Some(p.clone()),
-        (None, None) if use_default => Some(PathBuf::from(default_name)),
+    match (cli_name, env_name, config_name) {
+        (Some(p), _, _) | (None, Some(p), _) | (None, None, Some(p)) => Some(p.clone()),
+        (None, None, None) if use_default => Some(PathBuf::from(default_name)),
         _ => None,
     }
 }
 
+/// Determines the stdin timing filename based on configuration precedence.
+///
+/// This function checks multiple configuration sources in the following order:
+/// 1. Command-line arguments (`--stdin-timing` option), or
+/// 2. Configuration file (`stdin_timing` field).
+///
+/// The timing file has no default, and requesting it doesn't affect the defaults of the log files.
+///
+/// # Arguments
+///
+/// * `cli_args` - Reference to the parsed command-line arguments.
+/// * `config` - Reference to the parsed configuration file settings.
+///
+/// # Returns
+///
+/// Returns an `Option<PathBuf>` which is:
+/// - `Some(PathBuf)` containing the timing file path if one was requested, or
+/// - `None` if there should be no timing file.
+fn get_stdin_timing_name(cli_args: &CliArgs, config: &Config) -> Option<PathBuf> {
+    cli_args
+        .stdin_timing
+        .as_ref()
+        .or(config.stdin_timing.as_ref())
+        .cloned()
+}
+
+/// Determines what the stdin of the target command is read from based on configuration
+/// precedence.
+///
+/// This function checks multiple configuration sources in the following order:
+/// 1. Command-line arguments (`--stdin-source` option), or
+/// 2. Configuration file (`stdin_source` field).
+///
+/// Only the configuration file can give the text to feed inline.
+///
+/// # Arguments
+///
+/// * `cli_args` - Reference to the parsed command-line arguments.
+/// * `config` - Reference to the parsed configuration file settings.
+///
+/// # Returns
+///
+/// Returns an `Option<StdinSource>` which is:
+/// - `Some(StdinSource)` containing what stdin is read from, if it was configured, or
+/// - `None` if stdin is the stdin of fdintercept.
+fn get_stdin_source(cli_args: &CliArgs, config: &Config) -> Option<StdinSource> {
+    cli_args
+        .stdin_source
+        .clone()
+        .map(StdinSource::Path)
+        .or_else(|| config.stdin_source.clone())
+}
+
+/// Determines the timeline log filename based on configuration precedence.
+///
+/// This function checks multiple configuration sources in the following order:
+/// 1. Command-line arguments (`--timeline-log` option), or
+/// 2. Configuration file (`timeline_log` field).
+///
+/// Unlike the per-stream logs, the timeline log has no default: it is only created if explicitly
+/// requested, and requesting it doesn't affect the defaults of the per-stream logs.
+///
+/// # Arguments
+///
+/// * `cli_args` - Reference to the parsed command-line arguments.
+/// * `config` - Reference to the parsed configuration file settings.
+///
+/// # Returns
+///
+/// Returns an `Option<PathBuf>` which is:
+/// - `Some(PathBuf)` containing the timeline log file path if one was requested, or
+/// - `None` if there should be no timeline log.
+fn get_timeline_log_name(cli_args: &CliArgs, config: &Config) -> Option<PathBuf> {
+    cli_args
+        .timeline_log
+        .as_ref()
+        .or(config.timeline_log.as_ref())
+        .cloned()
+}
+
+/// Determines the asciicast recording filename based on configuration precedence.
+///
+/// This function checks multiple configuration sources in the following order:
+/// 1. Command-line arguments (`--asciicast` option), or
+/// 2. Configuration file (`asciicast` field).
+///
+/// Like the timeline log, the recording has no default and doesn't affect the defaults of the
+/// per-stream logs.
+///
+/// # Arguments
+///
+/// * `cli_args` - Reference to the parsed command-line arguments.
+/// * `config` - Reference to the parsed configuration file settings.
+///
+/// # Returns
+///
+/// Returns an `Option<PathBuf>` which is:
+/// - `Some(PathBuf)` containing the recording file path if one was requested, or
+/// - `None` if the session should not be recorded.
+fn get_asciicast_name(cli_args: &CliArgs, config: &Config) -> Option<PathBuf> {
+    cli_args
+        .asciicast
+        .as_ref()
+        .or(config.asciicast.as_ref())
+        .cloned()
+}
+
+/// Determines whether to record stdin in the asciicast recording based on configuration
+/// precedence.
+///
+/// This function checks multiple configuration sources in the following order:
+/// 1. Command-line arguments (`--asciicast-stdin` flag), or
+/// 2. Configuration file (`asciicast_stdin` field).
+///
+/// If none of these sources specify the setting, it defaults to `false`.
+///
+/// # Arguments
+///
+/// * `cli_args` - Reference to the parsed command-line arguments.
+/// * `config` - Reference to the parsed configuration file settings.
+///
+/// # Returns
+///
+/// Returns a boolean indicating whether stdin should be recorded as input events.
+fn get_asciicast_stdin(cli_args: &CliArgs, config: &Config) -> bool {
+    cli_args.asciicast_stdin || config.asciicast_stdin.unwrap_or(false)
+}
+
+/// Determines the manifest filename based on configuration precedence.
+///
+/// This function checks multiple configuration sources in the following order:
+/// 1. Command-line arguments (`--manifest` option),
+/// 2. Configuration file (`manifest` field), or
+/// 3. `session.json`, if there is a log directory.
+///
+/// # Arguments
+///
+/// * `cli_args` - Reference to the parsed command-line arguments.
+/// * `config` - Reference to the parsed configuration file settings.
+/// * `has_log_dir` - Whether there is a log directory, in whose session directory the manifest is
+///   written by default.
+///
+/// # Returns
+///
+/// Returns an `Option<PathBuf>` which is:
+/// - `Some(PathBuf)` containing the manifest path if one should be written, or
+/// - `None` if there should be no manifest.
+fn get_manifest_name(cli_args: &CliArgs, config: &Config, has_log_dir: bool) -> Option<PathBuf> {
+    cli_args
+        .manifest
+        .as_ref()
+        .or(config.manifest.as_ref())
+        .cloned()
+        .or_else(|| has_log_dir.then(|| PathBuf::from("session.json")))
+}
+
+/// Determines the names of the environment variables recorded in the manifest based on
+/// configuration precedence.
+///
+/// This function checks multiple configuration sources in the following order:
+/// 1. Command-line arguments (`--manifest-env` option),
+/// 2. Configuration file (`manifest_env` field), or
+/// 3. Default value (`PATH`, `HOME`, `USER`, `SHELL`, `TERM`, and `LANG`).
+///
+/// # Arguments
+///
+/// * `cli_args` - Reference to the parsed command-line arguments.
+/// * `config` - Reference to the parsed configuration file settings.
+///
+/// # Returns
+///
+/// Returns the names of the environment variables to record.
+fn get_manifest_env(cli_args: &CliArgs, config: &Config) -> Vec<String> {
+    cli_args
+        .manifest_env
+        .as_ref()
+        .or(config.manifest_env.as_ref())
+        .cloned()
+        .unwrap_or_else(|| {
+            ["PATH", "HOME", "USER", "SHELL", "TERM", "LANG"]
+                .map(String::from)
+                .to_vec()
+        })
+}
+
 /// Determines whether to recreate log files based on configuration precedence.
 ///
 /// This function checks multiple configuration sources in the following order:
@@ -521,14 +1851,14 @@ fn get_recreate_logs(cli_args: &CliArgs, env_vars: &EnvVars, config: &Config) ->
             .unwrap_or(false)
 }
 
-/// Determines the I/O buffer size based on configuration precedence.
+/// Determines the format of the log records based on configuration precedence.
 ///
 /// This function checks multiple configuration sources in the following order:
-/// 1. Command-line arguments (`--buffer-size` option),
-/// 2. Environment variables (`FDINTERCEPT_BUFFER_SIZE`), or
-/// 3. Configuration file (`buffer_size` field).
+/// 1. Command-line arguments (`--log-format` option),
+/// 2. Environment variables (`FDINTERCEPT_LOG_FORMAT`), or
+/// 3. Configuration file (`log_format` field).
 ///
-/// If none of these sources specify the setting, it defaults to 8192 bytes (8 KiB).
+/// If none of these sources specify the setting, it defaults to [`LogFormat::Raw`].
 ///
 /// # Arguments
 ///
@@ -538,24 +1868,28 @@ fn get_recreate_logs(cli_args: &CliArgs, env_vars: &EnvVars, config: &Config) ->
 ///
 /// # Returns
 ///
-/// Returns a `usize` representing the buffer size in bytes to use for I/O operations. The
-/// precedence order is CLI args > environment vars > config file > default value (8,192).
-fn get_buffer_size(cli_args: &CliArgs, env_vars: &EnvVars, config: &Config) -> usize {
+/// Returns the [`LogFormat`] to use for the log files.
+fn get_log_format(cli_args: &CliArgs, env_vars: &EnvVars, config: &Config) -> LogFormat {
     cli_args
-        .buffer_size
-        .or(env_vars.buffer_size)
-        .or(config.buffer_size)
-        .unwrap_or(8192)
+        .log_format
+        .or(env_vars.log_format)
+        .or(config.log_format)
+        .unwrap_or_default()
 }
 
-/// Retrieves the target command to execute based on configuration precedence.
+/// Determines the format of the log records of each of the standard streams based on
+/// configuration precedence.
 ///
-/// This function checks multiple configuration sources in the following order:
-/// 1. Command-line arguments (after `--`),
-/// 2. Environment variables (`FDINTERCEPT_TARGET`), or
-/// 3. Configuration file (`target` field).
+/// This function checks multiple configuration sources in the following order, and in each of
+/// them, the format of the stream comes before the format of all the log files:
+/// 1. Command-line arguments (`--stdin-log-format`, `--stdout-log-format`, and
+///    `--stderr-log-format` options, then `--log-format`),
+/// 2. Environment variables (`FDINTERCEPT_STDIN_LOG_FORMAT`, `FDINTERCEPT_STDOUT_LOG_FORMAT`, and
+///    `FDINTERCEPT_STDERR_LOG_FORMAT`, then `FDINTERCEPT_LOG_FORMAT`), or
+/// 3. Configuration file (`stdin_log_format`, `stdout_log_format`, and `stderr_log_format`
+///    fields, then `log_format`).
 ///
-/// The target command consists of an executable name and optional arguments.
+/// If none of these sources specify the setting, it defaults to [`LogFormat::Raw`].
 ///
 /// # Arguments
 ///
@@ -565,966 +1899,3424 @@ fn get_buffer_size(cli_args: &CliArgs, env_vars: &EnvVars, config: &Config) -> u
 ///
 /// # Returns
 ///
-/// Returns a `Result<Target>` which is:
-/// - `Ok(Target)` containing the parsed target command if successful, or
-/// - `Err` if no valid target is found or if parsing fails.
-///
-/// # Errors
-///
-/// This function will return an error if:
-/// - No target is defined in any configuration source,
-/// - The target executable name is empty,
-/// - The target string cannot be properly tokenized (for environment variables and config file),
-///   or
-/// - Any parsing error occurs while processing the target.
-fn get_target(cli_args: &CliArgs, env_vars: &EnvVars, config: &Config) -> Result<Target> {
-    match get_target_from_cli_arg(&cli_args.target) {
-        Ok(target) => return Ok(target),
-        Err(CliArgsTargetParseError::NotDefined) => (),
-        Err(e) => return Err(e).context("Error getting target from CLI arguments"),
-    }
-
-    if let Some(ref target) = env_vars.target {
-        match get_target_from_string(target) {
-            Ok(target) => return Ok(target),
-            Err(e) => {
-                return Err(e)
-                    .context("Error getting target from FDINTERCEPT_TARGET environment variable");
-            }
-        }
-    }
-
-    if let Some(ref target) = config.target {
-        match get_target_from_string(target) {
-            Ok(target) => return Ok(target),
-            Err(e) => return Err(e).context("Error getting target from configuration file"),
-        }
+/// Returns the [`LogFormat`] to use for the log file of each of the standard streams.
+fn get_stream_log_formats(
+    cli_args: &CliArgs,
+    env_vars: &EnvVars,
+    config: &Config,
+) -> StreamLogFormats {
+    let get = |cli_arg: Option<LogFormat>, env_var: Option<LogFormat>, config_field| {
+        cli_arg
+            .or(cli_args.log_format)
+            .or(env_var)
+            .or(env_vars.log_format)
+            .or(config_field)
+            .or(config.log_format)
+            .unwrap_or_default()
+    };
+    StreamLogFormats {
+        stdin: get(
+            cli_args.stdin_log_format,
+            env_vars.stdin_log_format,
+            config.stdin_log_format,
+        ),
+        stdout: get(
+            cli_args.stdout_log_format,
+            env_vars.stdout_log_format,
+            config.stdout_log_format,
+        ),
+        stderr: get(
+            cli_args.stderr_log_format,
+            env_vars.stderr_log_format,
+            config.stderr_log_format,
+        ),
     }
-
-    Err(anyhow::anyhow!(
-        "Target not defined in CLI arguments, FDINTERCEPT_TARGET environment variable, or \
-         configuration file"
-    ))
-}
-
-/// Errors that can occur when parsing target from CLI arguments.
-#[derive(Debug)]
-enum CliArgsTargetParseError {
-    /// No target was provided
-    NotDefined,
-    /// The executable name was empty
-    EmptyExecutable,
 }
 
-impl std::fmt::Display for CliArgsTargetParseError {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        match self {
-            Self::NotDefined => write!(f, "Target is not defined"),
-            Self::EmptyExecutable => write!(f, "Target executable cannot be empty"),
-        }
-    }
+/// Determines whether to mark the boundaries of the chunks in hex dumps based on configuration
+/// precedence.
+///
+/// This function checks multiple configuration sources in the following order:
+/// 1. Command-line arguments (`--hex-chunk-marks` flag), or
+/// 2. Configuration file (`hex_chunk_marks` field).
+///
+/// If none of these sources specify the setting, it defaults to `false`. It only has an effect
+/// with the [`LogFormat::Hex`] format.
+///
+/// # Arguments
+///
+/// * `cli_args` - Reference to the parsed command-line arguments.
+/// * `config` - Reference to the parsed configuration file settings.
+///
+/// # Returns
+///
+/// Returns a boolean indicating whether chunk boundaries should be marked.
+fn get_hex_chunk_marks(cli_args: &CliArgs, config: &Config) -> bool {
+    cli_args.hex_chunk_marks || config.hex_chunk_marks.unwrap_or(false)
 }
 
-impl std::error::Error for CliArgsTargetParseError {}
-
-/// Parses a target command from CLI arguments.
+/// Determines the prefix of each line in the lines log format based on configuration precedence.
 ///
-/// Takes a slice of strings representing command-line arguments and attempts to parse them into a
-/// target command structure. The first argument becomes the executable name, and any remaining
-/// arguments are stored as the command arguments.
+/// This function checks multiple configuration sources in the following order:
+/// 1. Command-line arguments (`--line-prefix` option), or
+/// 2. Configuration file (`line_prefix` field).
+///
+/// If none of these sources specify the setting, it defaults to `[{stream} {time}] `.
 ///
 /// # Arguments
 ///
-/// * `cli_arg` - A slice of strings containing the command and its arguments. Must not be empty
-///   and the first argument (executable) must not be empty.
+/// * `cli_args` - Reference to the parsed command-line arguments.
+/// * `config` - Reference to the parsed configuration file settings.
 ///
 /// # Returns
 ///
-/// Returns a `Result<Target, CliArgsTargetParseError>` which is:
-/// - `Ok(Target)` containing the parsed executable name and arguments if successful, or
-/// - `Err(CliArgsTargetParseError)` if parsing fails.
+/// Returns the parsed [`LinePrefix`].
 ///
 /// # Errors
 ///
-/// Returns `CliArgsTargetParseError`:
-/// - `NotDefined` if the input slice is empty, or
-/// - `EmptyExecutable` if the first argument (executable name) is empty.
-fn get_target_from_cli_arg(cli_arg: &[String]) -> Result<Target, CliArgsTargetParseError> {
-    let target_vec = NonEmpty::from_slice(cli_arg).ok_or(CliArgsTargetParseError::NotDefined)?;
-    Ok(Target {
-        executable: NonEmptyString::new(target_vec.head)
-            .map_err(|_| CliArgsTargetParseError::EmptyExecutable)?,
-        args: target_vec.tail,
-    })
-}
-
-/// Errors that can occur when parsing target from a string.
-#[derive(Debug)]
-enum StringTargetParseError {
-    /// The target string was empty.
-    Empty,
-    /// Failed to tokenize the target string.
-    FailedToTokenize,
-    /// The executable name was empty.
-    EmptyExecutable,
+/// Returns an error if the template has an unknown or unclosed placeholder.
+fn get_line_prefix(cli_args: &CliArgs, config: &Config) -> Result<LinePrefix> {
+    let template = cli_args
+        .line_prefix
+        .as_deref()
+        .or(config.line_prefix.as_deref())
+        .unwrap_or(DEFAULT_LINE_PREFIX);
+    template
+        .parse()
+        .context(format!("Invalid line prefix template: {template}"))
 }
 
-impl std::fmt::Display for StringTargetParseError {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        match self {
+/// Determines the compression of the log files based on configuration precedence.
+///
+/// This function checks multiple configuration sources in the following order:
+/// 1. Command-line arguments (`--compression` option), or
+/// 2. Configuration file (`compression` field).
+///
+/// If none of these sources specify the setting, it defaults to [`Compression::Auto`].
+///
+/// # Arguments
+///
+/// * `cli_args` - Reference to the parsed command-line arguments.
+/// * `config` - Reference to the parsed configuration file settings.
+///
+/// # Returns
+///
+/// Returns the [`Compression`] to use for the log files.
+fn get_compression(cli_args: &CliArgs, config: &Config) -> Compression {
+    cli_args
+        .compression
+        .or(config.compression)
+        .unwrap_or_default()
+}
+
+/// Determines the size past which a log file is rotated based on configuration precedence.
+///
+/// This function checks multiple configuration sources in the following order:
+/// 1. Command-line arguments (`--max-log-size` option), or
+/// 2. Configuration file (`max_log_size` field).
+///
+/// If none of these sources specify the setting, log files are not rotated.
+///
+/// # Arguments
+///
+/// * `cli_args` - Reference to the parsed command-line arguments.
+/// * `config` - Reference to the parsed configuration file settings.
+///
+/// # Returns
+///
+/// Returns the size in bytes past which a log file is rotated, or `None` if log files should not
+/// be rotated.
+fn get_max_log_size(cli_args: &CliArgs, config: &Config) -> Option<NonZeroU64> {
+    cli_args.max_log_size.or(config.max_log_size)
+}
+
+/// Determines the period after which a log file is rotated based on configuration precedence.
+///
+/// This function checks multiple configuration sources in the following order:
+/// 1. Command-line arguments (`--rotate-interval` option), or
+/// 2. Configuration file (`rotate_interval` field).
+///
+/// If none of these sources specify the setting, log files are not rotated periodically.
+///
+/// # Arguments
+///
+/// * `cli_args` - Reference to the parsed command-line arguments.
+/// * `config` - Reference to the parsed configuration file settings.
+///
+/// # Returns
+///
+/// Returns the period after which a log file is rotated, or `None` if log files should not be
+/// rotated periodically.
+fn get_rotate_interval(cli_args: &CliArgs, config: &Config) -> Option<RotationInterval> {
+    cli_args.rotate_interval.or(config.rotate_interval)
+}
+
+/// Determines the signal that makes fdintercept reopen its log files based on configuration
+/// precedence.
+///
+/// This function checks multiple configuration sources in the following order:
+/// 1. Command-line arguments (`--reopen-signal` option), or
+/// 2. Configuration file (`reopen_signal` field).
+///
+/// If none of these sources specify the setting, log files are never reopened.
+///
+/// # Arguments
+///
+/// * `cli_args` - Reference to the parsed command-line arguments.
+/// * `config` - Reference to the parsed configuration file settings.
+///
+/// # Returns
+///
+/// Returns the signal that makes fdintercept reopen its log files, or `None` if log files should
+/// not be reopened.
+fn get_reopen_signal(cli_args: &CliArgs, config: &Config) -> Option<ReopenSignal> {
+    cli_args.reopen_signal.or(config.reopen_signal)
+}
+
+/// Determines the number of rotated log files kept for each log based on configuration
+/// precedence.
+///
+/// This function checks multiple configuration sources in the following order:
+/// 1. Command-line arguments (`--max-log-files` option), or
+/// 2. Configuration file (`max_log_files` field).
+///
+/// If none of these sources specify the setting, it defaults to 5. It only has an effect if log
+/// files are rotated.
+///
+/// # Arguments
+///
+/// * `cli_args` - Reference to the parsed command-line arguments.
+/// * `config` - Reference to the parsed configuration file settings.
+///
+/// # Returns
+///
+/// Returns the number of rotated log files to keep.
+fn get_max_log_files(cli_args: &CliArgs, config: &Config) -> usize {
+    cli_args.max_log_files.or(config.max_log_files).unwrap_or(5)
+}
+
+/// Determines whether to print a summary of the traffic of each stream on exit based on
+/// configuration precedence.
+///
+/// This function checks multiple configuration sources in the following order:
+/// 1. Command-line arguments (`--summary` flag), or
+/// 2. Configuration file (`summary` field).
+///
+/// If none of these sources specify the setting, it defaults to `false`.
+///
+/// # Arguments
+///
+/// * `cli_args` - Reference to the parsed command-line arguments.
+/// * `config` - Reference to the parsed configuration file settings.
+///
+/// # Returns
+///
+/// Returns a boolean indicating whether the summary should be printed.
+fn get_summary(cli_args: &CliArgs, config: &Config) -> bool {
+    cli_args.summary || config.summary.unwrap_or(false)
+}
+
+/// Determines whether to run the target command in a pseudo-terminal based on configuration
+/// precedence.
+///
+/// This function checks multiple configuration sources in the following order:
+/// 1. Command-line arguments (`--pty` flag), or
+/// 2. Configuration file (`pty` field).
+///
+/// If none of these sources specify the setting, it defaults to `false`.
+///
+/// # Arguments
+///
+/// * `cli_args` - Reference to the parsed command-line arguments.
+/// * `config` - Reference to the parsed configuration file settings.
+///
+/// # Returns
+///
+/// Returns a boolean indicating whether the target command should run in a pseudo-terminal.
+fn get_pty(cli_args: &CliArgs, config: &Config) -> bool {
+    cli_args.pty || config.pty.unwrap_or(false)
+}
+
+/// Determines the additional file descriptors to intercept based on configuration precedence.
+///
+/// This function merges multiple configuration sources, in the following order:
+/// 1. Command-line arguments (`--fd` options), and
+/// 2. Configuration file (`fd` table).
+///
+/// A file descriptor given in the command line replaces the one with the same number in the
+/// configuration file. The log file of a file descriptor defaults to `fd<N>.log`.
+///
+/// # Arguments
+///
+/// * `cli_args` - Reference to the parsed command-line arguments.
+/// * `config` - Reference to the parsed configuration file settings.
+///
+/// # Returns
+///
+/// Returns the additional file descriptors to intercept, ordered by their numbers.
+///
+/// # Errors
+///
+/// Returns an error if a key of the `fd` table isn't a valid file descriptor number.
+fn get_extra_fds(cli_args: &CliArgs, config: &Config) -> Result<Vec<ExtraFd>> {
+    let mut extra_fds = BTreeMap::new();
+    for (number, fd_config) in config.fd.iter().flatten() {
+        let fd = parse_fd_number(number).context("Invalid fd table in configuration")?;
+        extra_fds.insert(fd, (fd_config.direction, fd_config.log.clone()));
+    }
+    for fd_arg in &cli_args.fds {
+        extra_fds.insert(fd_arg.fd, (fd_arg.direction, fd_arg.log.clone()));
+    }
+    Ok(extra_fds
+        .into_iter()
+        .map(|(fd, (direction, maybe_log))| {
+            ExtraFd::new(
+                fd,
+                direction,
+                maybe_log.unwrap_or_else(|| PathBuf::from(format!("fd{fd}.log"))),
+            )
+        })
+        .collect())
+}
+
+/// Determines how a standard stream of the target command is handled if it isn't configured.
+///
+/// # Arguments
+///
+/// * `recorded` - Whether the stream is recorded anywhere: in a log of its own, a timing file, a
+///   shared log, the summary, or the manifest.
+///
+/// # Returns
+///
+/// Returns [`StreamMode::Intercept`] if the stream is recorded, or [`StreamMode::Inherit`]
+/// otherwise, so that the target command keeps, e.g., its terminal.
+const fn default_stream_mode(recorded: bool) -> StreamMode {
+    if recorded {
+        StreamMode::Intercept
+    } else {
+        StreamMode::Inherit
+    }
+}
+
+/// Determines how each of the standard streams of the target command is handled based on
+/// configuration precedence.
+///
+/// This function checks multiple configuration sources in the following order:
+/// 1. Command-line arguments (`--stdin-mode`, `--stdout-mode`, and `--stderr-mode` options), or
+/// 2. Configuration file (`stdin_mode`, `stdout_mode`, and `stderr_mode` fields).
+///
+/// In a pseudo-terminal, all the standard streams are attached to it, so they are all
+/// intercepted. When replaying, stdin is always intercepted, to feed the recorded input into it,
+/// and so it is when it is read from a stdin source.
+///
+/// # Arguments
+///
+/// * `cli_args` - Reference to the parsed command-line arguments.
+/// * `config` - Reference to the parsed configuration file settings.
+/// * `pty` - Whether the target command runs in a pseudo-terminal.
+/// * `mode` - What to feed into the stdin of the target command.
+/// * `has_stdin_source` - Whether stdin is read from a stdin source.
+/// * `defaults` - How each stream is handled if neither source specifies it.
+///
+/// # Returns
+///
+/// Returns how each of the standard streams is handled.
+///
+/// # Errors
+///
+/// Returns an error if:
+/// - A stream is configured not to be intercepted in a pseudo-terminal, or
+/// - Stdin is configured not to be intercepted when replaying, or when it has a stdin source.
+fn get_stream_modes(
+    cli_args: &CliArgs,
+    config: &Config,
+    pty: bool,
+    mode: &Mode,
+    has_stdin_source: bool,
+    defaults: StreamModes,
+) -> Result<StreamModes> {
+    let replay = matches!(mode, Mode::Replay { .. });
+    let configured = [
+        cli_args.stdin_mode.or(config.stdin_mode),
+        cli_args.stdout_mode.or(config.stdout_mode),
+        cli_args.stderr_mode.or(config.stderr_mode),
+    ];
+    let not_intercepted = |maybe_stream_mode: &Option<StreamMode>| {
+        maybe_stream_mode.is_some_and(|stream_mode| stream_mode != StreamMode::Intercept)
+    };
+
+    if pty && configured.iter().any(not_intercepted) {
+        anyhow::bail!("All the standard streams are intercepted in a pseudo-terminal");
+    }
+    if replay && not_intercepted(&configured[0]) {
+        anyhow::bail!("Stdin is always intercepted when replaying");
+    }
+    if has_stdin_source && not_intercepted(&configured[0]) {
+        anyhow::bail!("Stdin is always intercepted when it is read from a stdin source");
+    }
+
+    let [stdin, stdout, stderr] = configured;
+    Ok(if pty {
+        StreamModes {
+            stdin: StreamMode::Intercept,
+            stdout: StreamMode::Intercept,
+            stderr: StreamMode::Intercept,
+        }
+    } else {
+        StreamModes {
+            stdin: stdin.unwrap_or(if replay || has_stdin_source {
+                StreamMode::Intercept
+            } else {
+                defaults.stdin
+            }),
+            stdout: stdout.unwrap_or(defaults.stdout),
+            stderr: stderr.unwrap_or(defaults.stderr),
+        }
+    })
+}
+
+/// Determines the I/O buffer size based on configuration precedence.
+///
+/// This function checks multiple configuration sources in the following order:
+/// 1. Command-line arguments (`--buffer-size` option),
+/// 2. Environment variables (`FDINTERCEPT_BUFFER_SIZE`), or
+/// 3. Configuration file (`buffer_size` field).
+///
+/// If none of these sources specify the setting, it defaults to 8192 bytes (8 KiB).
+///
+/// # Arguments
+///
+/// * `cli_args` - Reference to the parsed command-line arguments.
+/// * `env_vars` - Reference to the parsed environment variables.
+/// * `config` - Reference to the parsed configuration file settings.
+///
+/// # Returns
+///
+/// Returns a `usize` representing the buffer size in bytes to use for I/O operations. The
+/// precedence order is CLI args > environment vars > config file > default value (8,192).
+fn get_buffer_size(cli_args: &CliArgs, env_vars: &EnvVars, config: &Config) -> usize {
+    cli_args
+        .buffer_size
+        .or(env_vars.buffer_size)
+        .or(config.buffer_size)
+        .unwrap_or(8192)
+}
+
+/// Retrieves the target command to execute based on configuration precedence.
+///
+/// This function checks multiple configuration sources in the following order:
+/// 1. Command-line arguments (after `--`, also when given to a subcommand),
+/// 2. Environment variables (`FDINTERCEPT_TARGET`), or
+/// 3. Configuration file (`target` field).
+///
+/// The target command consists of an executable name and optional arguments.
+///
+/// # Arguments
+///
+/// * `cli_args` - Reference to the parsed command-line arguments.
+/// * `env_vars` - Reference to the parsed environment variables.
+/// * `config` - Reference to the parsed configuration file settings.
+///
+/// # Returns
+///
+/// Returns a `Result<Target>` which is:
+/// - `Ok(Target)` containing the parsed target command if successful, or
+/// - `Err` if no valid target is found or if parsing fails.
+///
+/// # Errors
+///
+/// This function will return an error if:
+/// - No target is defined in any configuration source,
+/// - The target executable name is empty,
+/// - The target string cannot be properly tokenized (for environment variables and config file),
+///   or
+/// - Any parsing error occurs while processing the target.
+fn get_target(cli_args: &CliArgs, env_vars: &EnvVars, config: &Config) -> Result<Target> {
+    match get_target_from_cli_arg(cli_args.target()) {
+        Ok(target) => return Ok(target),
+        Err(CliArgsTargetParseError::NotDefined) => (),
+        Err(e) => return Err(e).context("Error getting target from CLI arguments"),
+    }
+
+    if let Some(ref target) = env_vars.target {
+        match get_target_from_string(target) {
+            Ok(target) => return Ok(target),
+            Err(e) => {
+                return Err(e)
+                    .context("Error getting target from FDINTERCEPT_TARGET environment variable");
+            }
+        }
+    }
+
+    if let Some(ref target) = config.target {
+        match get_target_from_string(target) {
+            Ok(target) => return Ok(target),
+            Err(e) => return Err(e).context("Error getting target from configuration file"),
+        }
+    }
+
+    Err(anyhow::anyhow!(
+        "Target not defined in CLI arguments, FDINTERCEPT_TARGET environment variable, or \
+         configuration file"
+    ))
+}
+
+/// Determines the target command of a process that is already running, from its command line.
+///
+/// # Arguments
+///
+/// * `pid` - PID of the process.
+///
+/// # Returns
+///
+/// Returns the command line the process was started with.
+///
+/// # Errors
+///
+/// This function will return an error if:
+/// - The command line of the process can't be read, e.g., because it doesn't exist, or
+/// - The process has no command line, e.g., because it is a kernel thread.
+fn get_target_from_pid(pid: i32) -> Result<Target> {
+    let cmdline = std::fs::read(format!("/proc/{pid}/cmdline"))
+        .with_context(|| format!("Error reading command line of process {pid}"))?;
+    // Each argument is terminated by a NUL byte.
+    let args: Vec<_> = cmdline
+        .split(|&byte| byte == 0)
+        .map(|arg| String::from_utf8_lossy(arg).into_owned())
+        .collect();
+    let args = args.split_last().map_or(&[][..], |(_, args)| args);
+    get_target_from_cli_arg(args)
+        .with_context(|| format!("Error getting target from command line of process {pid}"))
+}
+
+/// Errors that can occur when parsing target from CLI arguments.
+#[derive(Debug)]
+enum CliArgsTargetParseError {
+    /// No target was provided
+    NotDefined,
+    /// The executable name was empty
+    EmptyExecutable,
+}
+
+impl std::fmt::Display for CliArgsTargetParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::NotDefined => write!(f, "Target is not defined"),
+            Self::EmptyExecutable => write!(f, "Target executable cannot be empty"),
+        }
+    }
+}
+
+impl std::error::Error for CliArgsTargetParseError {}
+
+/// Parses a target command from CLI arguments.
+///
+/// Takes a slice of strings representing command-line arguments and attempts to parse them into a
+/// target command structure. The first argument becomes the executable name, and any remaining
+/// arguments are stored as the command arguments.
+///
+/// # Arguments
+///
+/// * `cli_arg` - A slice of strings containing the command and its arguments. Must not be empty
+///   and the first argument (executable) must not be empty.
+///
+/// # Returns
+///
+/// Returns a `Result<Target, CliArgsTargetParseError>` which is:
+/// - `Ok(Target)` containing the parsed executable name and arguments if successful, or
+/// - `Err(CliArgsTargetParseError)` if parsing fails.
+///
+/// # Errors
+///
+/// Returns `CliArgsTargetParseError`:
+/// - `NotDefined` if the input slice is empty, or
+/// - `EmptyExecutable` if the first argument (executable name) is empty.
+fn get_target_from_cli_arg(cli_arg: &[String]) -> Result<Target, CliArgsTargetParseError> {
+    let target_vec = NonEmpty::from_slice(cli_arg).ok_or(CliArgsTargetParseError::NotDefined)?;
+    Ok(Target {
+        executable: NonEmptyString::new(target_vec.head)
+            .map_err(|_| CliArgsTargetParseError::EmptyExecutable)?,
+        args: target_vec.tail,
+    })
+}
+
+/// Errors that can occur when parsing target from a string.
+#[derive(Debug)]
+enum StringTargetParseError {
+    /// The target string was empty.
+    Empty,
+    /// Failed to tokenize the target string.
+    FailedToTokenize,
+    /// The executable name was empty.
+    EmptyExecutable,
+}
+
+impl std::fmt::Display for StringTargetParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
             Self::FailedToTokenize => write!(f, "Failed to tokenize target"),
             Self::Empty => write!(f, "Target cannot be empty"),
             Self::EmptyExecutable => write!(f, "Target executable cannot be empty"),
         }
-    }
-}
+    }
+}
+
+impl std::error::Error for StringTargetParseError {}
+
+/// Parses a target command from a string.
+///
+/// Takes a string containing a shell-style command and parses it into a target command structure.
+/// The string is tokenized using shell-like rules (handling quotes and escapes), with the first
+/// token becoming the executable name and the remaining tokens becoming the command arguments.
+///
+/// # Arguments
+///
+/// * `target` - A string containing the command to parse. Must not be empty and must contain a
+///   valid executable name as its first token.
+///
+/// # Returns
+///
+/// Returns a `Result<Target, StringTargetParseError>` which is:
+/// - `Ok(Target)` containing the parsed executable name and arguments if successful, or
+/// - `Err(StringTargetParseError)` if parsing fails.
+///
+/// # Errors
+///
+/// Returns `StringTargetParseError`:
+/// - `Empty` if the input string is empty,
+/// - `FailedToTokenize` if the string cannot be properly tokenized (e.g., unmatched quotes), or
+/// - `EmptyExecutable` if the first token (executable name) is empty.
+fn get_target_from_string(target: &str) -> Result<Target, StringTargetParseError> {
+    if target.is_empty() {
+        return Err(StringTargetParseError::Empty);
+    }
+    let tokenized_target = shlex::split(target).ok_or(StringTargetParseError::FailedToTokenize)?;
+    // unwrap: Safe because we already ensure that target is not empty.
+    let target_vec = NonEmpty::from_vec(tokenized_target).unwrap();
+    Ok(Target {
+        executable: NonEmptyString::new(target_vec.head)
+            .map_err(|_| StringTargetParseError::EmptyExecutable)?,
+        args: target_vec.tail,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod get_settings_with_raw_cli_args {
+        use super::*;
+
+        #[test]
+        fn from_cli_args() {
+            let settings = get_settings_with_raw_cli_args(vec![
+                "fdintercept".to_string(),
+                "--log-dir".to_string(),
+                "custom_logs".to_string(),
+                "--stdin-log".to_string(),
+                "custom_stdin.log".to_string(),
+                "--stdout-log".to_string(),
+                "custom_stdout.log".to_string(),
+                "--stderr-log".to_string(),
+                "custom_stderr.log".to_string(),
+                "--timeline-log".to_string(),
+                "custom_timeline.log".to_string(),
+                "--asciicast".to_string(),
+                "custom.cast".to_string(),
+                "--asciicast-stdin".to_string(),
+                "--manifest".to_string(),
+                "custom_manifest.json".to_string(),
+                "--manifest-env".to_string(),
+                "PATH,RUST_LOG".to_string(),
+                "--recreate-logs".to_string(),
+                "--log-format".to_string(),
+                "timestamped".to_string(),
+                "--hex-chunk-marks".to_string(),
+                "--compression".to_string(),
+                "zstd".to_string(),
+                "--max-log-size".to_string(),
+                "1048576".to_string(),
+                "--max-log-files".to_string(),
+                "3".to_string(),
+                "--rotate-interval".to_string(),
+                "hourly".to_string(),
+                "--reopen-signal".to_string(),
+                "usr1".to_string(),
+                "--summary".to_string(),
+                "--buffer-size".to_string(),
+                "4096".to_string(),
+                "--".to_string(),
+                "executable".to_string(),
+                "arg1".to_string(),
+                "arg2".to_string(),
+            ])
+            .unwrap();
+
+            assert_eq!(settings.log_dir, Some(PathBuf::from("custom_logs")));
+            assert_eq!(settings.stdin_log, Some(PathBuf::from("custom_stdin.log")));
+            assert_eq!(
+                settings.stdout_log,
+                Some(PathBuf::from("custom_stdout.log"))
+            );
+            assert_eq!(
+                settings.stderr_log,
+                Some(PathBuf::from("custom_stderr.log"))
+            );
+            assert_eq!(
+                settings.timeline_log,
+                Some(PathBuf::from("custom_timeline.log"))
+            );
+            assert_eq!(settings.asciicast, Some(PathBuf::from("custom.cast")));
+            assert!(settings.asciicast_stdin);
+            assert_eq!(
+                settings.manifest,
+                Some(PathBuf::from("custom_manifest.json"))
+            );
+            assert_eq!(settings.manifest_env, ["PATH", "RUST_LOG"]);
+            assert!(settings.recreate_logs);
+            assert_eq!(settings.log_format, LogFormat::Timestamped);
+            assert!(settings.hex_chunk_marks);
+            assert_eq!(settings.compression, Compression::Zstd);
+            assert_eq!(settings.max_log_size, NonZeroU64::new(1_048_576));
+            assert_eq!(settings.max_log_files, 3);
+            assert_eq!(settings.rotate_interval, Some(RotationInterval::Hourly));
+            assert_eq!(settings.reopen_signal, Some(ReopenSignal::Usr1));
+            assert!(settings.summary);
+            assert_eq!(settings.buffer_size, 4096);
+            assert_eq!(
+                settings.target.as_ref().unwrap().executable.as_str(),
+                "executable"
+            );
+            assert_eq!(settings.target.as_ref().unwrap().args, vec!["arg1", "arg2"]);
+        }
+
+        #[test]
+        fn from_env_vars() {
+            temp_env::with_vars(
+                vec![
+                    ("FDINTERCEPT_LOG_DIR", Some("env_logs")),
+                    ("FDINTERCEPT_RECREATE_LOGS", Some("true")),
+                    ("FDINTERCEPT_LOG_FORMAT", Some("timestamped")),
+                    ("FDINTERCEPT_BUFFER_SIZE", Some("2048")),
+                    ("FDINTERCEPT_TARGET", Some("executable arg1 arg2")),
+                ],
+                || {
+                    let settings =
+                        get_settings_with_raw_cli_args(vec!["intercept".to_string()]).unwrap();
+
+                    assert_eq!(settings.log_dir, Some(PathBuf::from("env_logs")));
+                    assert_eq!(settings.manifest, Some(PathBuf::from("session.json")));
+                    assert_eq!(settings.stdin_log, Some(PathBuf::from("stdin.log")));
+                    assert_eq!(settings.stdout_log, Some(PathBuf::from("stdout.log")));
+                    assert_eq!(settings.stderr_log, Some(PathBuf::from("stderr.log")));
+                    assert!(settings.recreate_logs);
+                    assert_eq!(settings.log_format, LogFormat::Timestamped);
+                    assert_eq!(settings.buffer_size, 2048);
+                    assert_eq!(
+                        settings.target.as_ref().unwrap().executable.as_str(),
+                        "executable"
+                    );
+                    assert_eq!(settings.target.as_ref().unwrap().args, vec!["arg1", "arg2"]);
+                },
+            );
+        }
+
+        #[test]
+        fn from_config() {
+            let tmp_dir = tempfile::TempDir::new().unwrap();
+            let config_path = tmp_dir.path().join("config.toml");
+            std::fs::write(
+                &config_path,
+                r#"
+                    log_dir = "config_logs"
+                    stdin_log = "config_stdin.log"
+                    stdout_log = "config_stdout.log"
+                    stderr_log = "config_stderr.log"
+                    timeline_log = "config_timeline.log"
+                    asciicast = "config.cast"
+                    asciicast_stdin = true
+                    manifest = "config_manifest.json"
+                    manifest_env = ["RUST_LOG"]
+                    recreate_logs = true
+                    log_format = "hex"
+                    stdin_log_format = "raw"
+                    hex_chunk_marks = true
+                    compression = "gzip"
+                    max_log_size = 65536
+                    max_log_files = 2
+                    rotate_interval = "daily"
+                    reopen_signal = "hup"
+                    summary = true
+                    buffer_size = 1024
+                    target = "executable arg1 arg2"
+                "#,
+            )
+            .unwrap();
+
+            let settings = get_settings_with_raw_cli_args(vec![
+                "fdintercept".to_string(),
+                "--conf".to_string(),
+                config_path.to_str().unwrap().to_string(),
+            ])
+            .unwrap();
+
+            assert_eq!(settings.log_dir, Some(PathBuf::from("config_logs")));
+            assert_eq!(settings.stdin_log, Some(PathBuf::from("config_stdin.log")));
+            assert_eq!(
+                settings.stdout_log,
+                Some(PathBuf::from("config_stdout.log"))
+            );
+            assert_eq!(
+                settings.stderr_log,
+                Some(PathBuf::from("config_stderr.log"))
+            );
+            assert_eq!(
+                settings.timeline_log,
+                Some(PathBuf::from("config_timeline.log"))
+            );
+            assert_eq!(settings.asciicast, Some(PathBuf::from("config.cast")));
+            assert!(settings.asciicast_stdin);
+            assert_eq!(
+                settings.manifest,
+                Some(PathBuf::from("config_manifest.json"))
+            );
+            assert_eq!(settings.manifest_env, ["RUST_LOG"]);
+            assert!(settings.recreate_logs);
+            assert_eq!(settings.log_format, LogFormat::Hex);
+            assert_eq!(
+                settings.stream_log_formats,
+                StreamLogFormats {
+                    stdin: LogFormat::Raw,
+                    stdout: LogFormat::Hex,
+                    stderr: LogFormat::Hex,
+                }
+            );
+            assert!(settings.hex_chunk_marks);
+            assert_eq!(settings.compression, Compression::Gzip);
+            assert_eq!(settings.max_log_size, NonZeroU64::new(65536));
+            assert_eq!(settings.max_log_files, 2);
+            assert_eq!(settings.rotate_interval, Some(RotationInterval::Daily));
+            assert_eq!(settings.reopen_signal, Some(ReopenSignal::Hup));
+            assert!(settings.summary);
+            assert_eq!(settings.buffer_size, 1024);
+            assert_eq!(
+                settings.target.as_ref().unwrap().executable.as_str(),
+                "executable"
+            );
+            assert_eq!(settings.target.as_ref().unwrap().args, vec!["arg1", "arg2"]);
+        }
+
+        #[test]
+        fn replay() {
+            let settings = get_settings_with_raw_cli_args(vec![
+                "fdintercept".to_string(),
+                "--stdout-log".to_string(),
+                "custom_stdout.log".to_string(),
+                "replay".to_string(),
+                "recorded_stdin.log".to_string(),
+                "--stderr-log".to_string(),
+                "custom_stderr.log".to_string(),
+                "--".to_string(),
+                "executable".to_string(),
+                "arg1".to_string(),
+            ])
+            .unwrap();
+
+            assert_eq!(
+                settings.mode,
+                Mode::Replay {
+                    input: PathBuf::from("recorded_stdin.log"),
+                    timing: None,
+                    speed: 1.0,
+                }
+            );
+            assert_eq!(settings.stdin_log, None);
+            assert_eq!(
+                settings.stdout_log,
+                Some(PathBuf::from("custom_stdout.log"))
+            );
+            assert_eq!(
+                settings.stderr_log,
+                Some(PathBuf::from("custom_stderr.log"))
+            );
+            assert_eq!(
+                settings.target.as_ref().unwrap().executable.as_str(),
+                "executable"
+            );
+            assert_eq!(settings.target.as_ref().unwrap().args, vec!["arg1"]);
+        }
+
+        #[test]
+        fn attach() {
+            let pid = i32::try_from(std::process::id()).unwrap();
+            let settings = get_settings_with_raw_cli_args(vec![
+                "fdintercept".to_string(),
+                "--pty".to_string(),
+                "attach".to_string(),
+                pid.to_string(),
+            ])
+            .unwrap();
+
+            assert_eq!(settings.mode, Mode::Attach { pid });
+            assert!(!settings.pty);
+            assert_eq!(settings.stdin_log, Some(PathBuf::from("stdin.log")));
+            // The target is the command line of the process, i.e., this test binary.
+            assert_eq!(
+                settings.target.as_ref().unwrap().executable.as_str(),
+                std::env::args().next().unwrap()
+            );
+        }
+
+        #[test]
+        fn proxy() {
+            let settings = get_settings_with_raw_cli_args(vec![
+                "fdintercept".to_string(),
+                "--pty".to_string(),
+                "proxy".to_string(),
+                "127.0.0.1:8080".to_string(),
+                "example.com:80".to_string(),
+                "--server-log".to_string(),
+                "responses.log".to_string(),
+            ])
+            .unwrap();
+
+            assert_eq!(
+                settings.mode,
+                Mode::Proxy {
+                    listen: "127.0.0.1:8080".to_string(),
+                    upstream: "example.com:80".to_string(),
+                    unix: false,
+                    client_log: PathBuf::from("client.{connection}.log"),
+                    server_log: PathBuf::from("responses.log"),
+                    connection_info: PathBuf::from("connection.{connection}.json"),
+                }
+            );
+            assert!(!settings.pty);
+            assert!(settings.target.is_none());
+        }
+
+        #[test]
+        fn unix_proxy() {
+            let settings = get_settings_with_raw_cli_args(vec![
+                "fdintercept".to_string(),
+                "proxy".to_string(),
+                "--unix".to_string(),
+                "/tmp/proxy.sock".to_string(),
+                "/run/daemon.sock".to_string(),
+            ])
+            .unwrap();
+
+            assert_eq!(
+                settings.mode,
+                Mode::Proxy {
+                    listen: "/tmp/proxy.sock".to_string(),
+                    upstream: "/run/daemon.sock".to_string(),
+                    unix: true,
+                    client_log: PathBuf::from("client.{connection}.log"),
+                    server_log: PathBuf::from("server.{connection}.log"),
+                    connection_info: PathBuf::from("connection.{connection}.json"),
+                }
+            );
+        }
+
+        #[test]
+        fn proxy_with_log_dir() {
+            let settings = get_settings_with_raw_cli_args(vec![
+                "fdintercept".to_string(),
+                "--log-dir".to_string(),
+                "logs".to_string(),
+                "proxy".to_string(),
+                "127.0.0.1:8080".to_string(),
+                "example.com:80".to_string(),
+            ])
+            .unwrap();
+
+            assert_eq!(settings.manifest, None);
+        }
+
+        #[test]
+        fn proxy_with_manifest() {
+            let error = get_settings_with_raw_cli_args(vec![
+                "fdintercept".to_string(),
+                "--manifest".to_string(),
+                "session.json".to_string(),
+                "proxy".to_string(),
+                "127.0.0.1:8080".to_string(),
+                "example.com:80".to_string(),
+            ])
+            .unwrap_err();
+
+            assert_eq!(
+                error.to_string(),
+                "A manifest can't be written in proxy mode, which has no target command"
+            );
+        }
+
+        #[test]
+        fn replay_with_target_from_env_var() {
+            temp_env::with_var("FDINTERCEPT_TARGET", Some("executable arg1"), || {
+                let settings = get_settings_with_raw_cli_args(vec![
+                    "fdintercept".to_string(),
+                    "replay".to_string(),
+                    "stdin.log".to_string(),
+                ])
+                .unwrap();
+
+                assert_eq!(
+                    settings.mode,
+                    Mode::Replay {
+                        input: PathBuf::from("stdin.log"),
+                        timing: None,
+                        speed: 1.0,
+                    }
+                );
+                assert_eq!(settings.stdin_log, None);
+                assert_eq!(settings.stdout_log, Some(PathBuf::from("stdout.log")));
+                assert_eq!(
+                    settings.target.as_ref().unwrap().executable.as_str(),
+                    "executable"
+                );
+            });
+        }
+
+        #[test]
+        fn stdin_timing() {
+            let settings = get_settings_with_raw_cli_args(vec![
+                "fdintercept".to_string(),
+                "--stdin-timing".to_string(),
+                "custom_stdin.timing".to_string(),
+                "--".to_string(),
+                "executable".to_string(),
+            ])
+            .unwrap();
+
+            assert_eq!(
+                settings.stdin_timing,
+                Some(PathBuf::from("custom_stdin.timing"))
+            );
+        }
+
+        #[test]
+        fn stdin_timing_with_rotation() {
+            for rotation in [["--max-log-size", "1024"], ["--rotate-interval", "daily"]] {
+                let error = get_settings_with_raw_cli_args(
+                    ["fdintercept", "--stdin-timing", "stdin.timing"]
+                        .into_iter()
+                        .chain(rotation)
+                        .chain(["--", "executable"])
+                        .map(str::to_string),
+                )
+                .unwrap_err();
+
+                assert_eq!(
+                    error.to_string(),
+                    "A stdin timing file can't be written while log files are rotated"
+                );
+            }
+        }
+
+        #[test]
+        fn replay_with_timing() {
+            let settings = get_settings_with_raw_cli_args(vec![
+                "fdintercept".to_string(),
+                "--stdin-timing".to_string(),
+                "stdin.timing".to_string(),
+                "replay".to_string(),
+                "stdin.log".to_string(),
+                "--timing".to_string(),
+                "stdin.timing".to_string(),
+                "--speed".to_string(),
+                "2.5".to_string(),
+                "--".to_string(),
+                "executable".to_string(),
+            ])
+            .unwrap();
+
+            assert_eq!(
+                settings.mode,
+                Mode::Replay {
+                    input: PathBuf::from("stdin.log"),
+                    timing: Some(PathBuf::from("stdin.timing")),
+                    speed: 2.5,
+                }
+            );
+            // The timing file being replayed is not overwritten.
+            assert_eq!(settings.stdin_timing, None);
+        }
+
+        #[test]
+        fn pty() {
+            let settings = get_settings_with_raw_cli_args(vec![
+                "fdintercept".to_string(),
+                "--pty".to_string(),
+                "--stdout-log".to_string(),
+                "custom_stdout.log".to_string(),
+                "--stderr-log".to_string(),
+                "custom_stderr.log".to_string(),
+                "--".to_string(),
+                "executable".to_string(),
+            ])
+            .unwrap();
+
+            assert!(settings.pty);
+            assert_eq!(
+                settings.stdout_log,
+                Some(PathBuf::from("custom_stdout.log"))
+            );
+            // Stderr is logged along with stdout.
+            assert_eq!(settings.stderr_log, None);
+        }
+
+        #[test]
+        fn stream_modes() {
+            let settings = get_settings_with_raw_cli_args(vec![
+                "fdintercept".to_string(),
+                "--stdout-log".to_string(),
+                "custom_stdout.log".to_string(),
+                "--stderr-log".to_string(),
+                "custom_stderr.log".to_string(),
+                "--stderr-mode".to_string(),
+                "null".to_string(),
+                "--".to_string(),
+                "executable".to_string(),
+            ])
+            .unwrap();
+
+            assert_eq!(
+                settings.stream_modes,
+                StreamModes {
+                    stdin: StreamMode::Inherit,
+                    stdout: StreamMode::Intercept,
+                    stderr: StreamMode::Null,
+                }
+            );
+            assert_eq!(settings.stdin_log, None);
+            assert_eq!(
+                settings.stdout_log,
+                Some(PathBuf::from("custom_stdout.log"))
+            );
+            // Stderr never reaches fdintercept.
+            assert_eq!(settings.stderr_log, None);
+        }
+
+        #[test]
+        fn with_no_log_paths() {
+            let settings = get_settings_with_raw_cli_args(vec![
+                "fdintercept".to_string(),
+                "--".to_string(),
+                "executable".to_string(),
+                "arg1".to_string(),
+                "arg2".to_string(),
+            ])
+            .unwrap();
+
+            assert_eq!(settings.stdin_log, Some(PathBuf::from("stdin.log")));
+            assert_eq!(settings.stdout_log, Some(PathBuf::from("stdout.log")));
+            assert_eq!(settings.stderr_log, Some(PathBuf::from("stderr.log")));
+            assert_eq!(settings.timeline_log, None);
+            assert_eq!(settings.mode, Mode::Run);
+            assert_eq!(settings.stdin_timing, None);
+            assert_eq!(settings.asciicast, None);
+            assert!(!settings.asciicast_stdin);
+            assert_eq!(settings.manifest, None);
+            assert_eq!(
+                settings.manifest_env,
+                ["PATH", "HOME", "USER", "SHELL", "TERM", "LANG"]
+            );
+            assert!(!settings.recreate_logs);
+            assert_eq!(settings.log_format, LogFormat::Raw);
+            assert!(!settings.hex_chunk_marks);
+            assert_eq!(settings.compression, Compression::Auto);
+            assert_eq!(settings.max_log_size, None);
+            assert_eq!(settings.max_log_files, 5);
+            assert_eq!(settings.rotate_interval, None);
+            assert_eq!(settings.reopen_signal, None);
+            assert!(!settings.summary);
+            assert!(!settings.pty);
+            assert_eq!(settings.buffer_size, 8192);
+            assert_eq!(
+                settings.target.as_ref().unwrap().executable.as_str(),
+                "executable"
+            );
+            assert_eq!(settings.target.as_ref().unwrap().args, vec!["arg1", "arg2"]);
+        }
+
+        #[test]
+        fn with_invalid_env_var() {
+            temp_env::with_vars(
+                vec![("FDINTERCEPT_BUFFER_SIZE", Some("not_a_number"))],
+                || {
+                    assert!(
+                        get_settings_with_raw_cli_args(vec![
+                            "fdintercept".to_string(),
+                            "--".to_string(),
+                            "executable".to_string(),
+                            "arg1".to_string(),
+                            "arg2".to_string(),
+                        ])
+                        .unwrap_err()
+                        .to_string()
+                        .contains("Error reading environment variables")
+                    );
+                },
+            );
+        }
+
+        #[test]
+        fn with_invalid_config() {
+            let tmp_dir = tempfile::TempDir::new().unwrap();
+            let config_path = tmp_dir.path().join("config.toml");
+            std::fs::write(&config_path, "invalid toml").unwrap();
+
+            let args = vec![
+                "fdintercept".to_string(),
+                "--conf".to_string(),
+                config_path.to_str().unwrap().to_string(),
+            ];
+
+            assert!(
+                get_settings_with_raw_cli_args(args)
+                    .unwrap_err()
+                    .to_string()
+                    .contains("Error reading configuration")
+            );
+        }
+
+        #[test]
+        fn test_settings_with_missing_target() {
+            assert!(
+                get_settings_with_raw_cli_args(vec!["fdintercept".to_string()])
+                    .unwrap_err()
+                    .to_string()
+                    .contains("Error getting target")
+            );
+        }
+    }
+
+    mod get_env_vars {
+        use super::*;
+
+        #[test]
+        fn empty_environment() {
+            temp_env::with_vars(
+                vec![
+                    ("FDINTERCEPTRC", None::<&str>),
+                    ("FDINTERCEPT_LOG_DIR", None::<&str>),
+                    ("FDINTERCEPT_STDIN_LOG", None::<&str>),
+                    ("FDINTERCEPT_STDOUT_LOG", None::<&str>),
+                    ("FDINTERCEPT_STDERR_LOG", None::<&str>),
+                    ("FDINTERCEPT_RECREATE_LOGS", None::<&str>),
+                    ("FDINTERCEPT_LOG_FORMAT", None::<&str>),
+                    ("FDINTERCEPT_STDIN_LOG_FORMAT", None::<&str>),
+                    ("FDINTERCEPT_STDOUT_LOG_FORMAT", None::<&str>),
+                    ("FDINTERCEPT_STDERR_LOG_FORMAT", None::<&str>),
+                    ("FDINTERCEPT_BUFFER_SIZE", None::<&str>),
+                    ("FDINTERCEPT_TARGET", None::<&str>),
+                ],
+                || {
+                    let env_vars = get_env_vars().unwrap();
+                    assert_eq!(env_vars.conf, None);
+                    assert_eq!(env_vars.log_dir, None);
+                    assert_eq!(env_vars.stdin_log, None);
+                    assert_eq!(env_vars.stdout_log, None);
+                    assert_eq!(env_vars.stderr_log, None);
+                    assert_eq!(env_vars.recreate_logs, None);
+                    assert_eq!(env_vars.log_format, None);
+                    assert_eq!(env_vars.stdin_log_format, None);
+                    assert_eq!(env_vars.stdout_log_format, None);
+                    assert_eq!(env_vars.stderr_log_format, None);
+                    assert_eq!(env_vars.buffer_size, None);
+                    assert_eq!(env_vars.target, None);
+                },
+            );
+        }
+
+        #[test]
+        fn valid_conf() {
+            temp_env::with_vars(vec![("FDINTERCEPTRC", Some("/path/to/config"))], || {
+                assert_eq!(
+                    get_env_vars().unwrap().conf,
+                    Some(PathBuf::from("/path/to/config"))
+                );
+            });
+        }
+
+        #[test]
+        fn empty_conf() {
+            temp_env::with_vars(vec![("FDINTERCEPTRC", Some(""))], || {
+                assert_eq!(
+                    get_env_vars().unwrap_err().to_string(),
+                    "FDINTERCEPTRC is empty"
+                );
+            });
+        }
+
+        #[test]
+        fn valid_log_dir() {
+            temp_env::with_vars(
+                vec![("FDINTERCEPT_LOG_DIR", Some("/var/log/fdintercept"))],
+                || {
+                    assert_eq!(
+                        get_env_vars().unwrap().log_dir,
+                        Some(PathBuf::from("/var/log/fdintercept"))
+                    );
+                },
+            );
+        }
+
+        #[test]
+        fn empty_log_dir() {
+            temp_env::with_vars(vec![("FDINTERCEPT_LOG_DIR", Some(""))], || {
+                assert_eq!(
+                    get_env_vars().unwrap_err().to_string(),
+                    "FDINTERCEPT_LOG_DIR is empty"
+                );
+            });
+        }
+
+        #[test]
+        fn valid_log_names() {
+            temp_env::with_vars(
+                vec![
+                    ("FDINTERCEPT_STDIN_LOG", Some("{pid}/stdin.log")),
+                    ("FDINTERCEPT_STDOUT_LOG", Some("{pid}/stdout.log")),
+                    ("FDINTERCEPT_STDERR_LOG", Some("{pid}/stderr.log")),
+                ],
+                || {
+                    let env_vars = get_env_vars().unwrap();
+                    assert_eq!(env_vars.stdin_log, Some(PathBuf::from("{pid}/stdin.log")));
+                    assert_eq!(env_vars.stdout_log, Some(PathBuf::from("{pid}/stdout.log")));
+                    assert_eq!(env_vars.stderr_log, Some(PathBuf::from("{pid}/stderr.log")));
+                },
+            );
+        }
+
+        #[test]
+        fn empty_log_name() {
+            temp_env::with_vars(vec![("FDINTERCEPT_STDOUT_LOG", Some(""))], || {
+                assert_eq!(
+                    get_env_vars().unwrap_err().to_string(),
+                    "FDINTERCEPT_STDOUT_LOG is empty"
+                );
+            });
+        }
+
+        #[test]
+        fn valid_recreate_logs() {
+            temp_env::with_vars(vec![("FDINTERCEPT_RECREATE_LOGS", Some("true"))], || {
+                assert_eq!(get_env_vars().unwrap().recreate_logs, Some(true));
+            });
+        }
+
+        #[test]
+        fn invalid_recreate_logs() {
+            temp_env::with_vars(
+                vec![("FDINTERCEPT_RECREATE_LOGS", Some("not_a_bool"))],
+                || {
+                    assert!(
+                        get_env_vars().unwrap_err().to_string().contains(
+                            "Error parsing FDINTERCEPT_RECREATE_LOGS environment variable"
+                        )
+                    );
+                },
+            );
+        }
+
+        #[test]
+        fn valid_log_format() {
+            temp_env::with_vars(
+                vec![("FDINTERCEPT_LOG_FORMAT", Some("timestamped"))],
+                || {
+                    assert_eq!(
+                        get_env_vars().unwrap().log_format,
+                        Some(LogFormat::Timestamped)
+                    );
+                },
+            );
+        }
+
+        #[test]
+        fn valid_jsonl_log_format() {
+            temp_env::with_vars(vec![("FDINTERCEPT_LOG_FORMAT", Some("jsonl"))], || {
+                assert_eq!(get_env_vars().unwrap().log_format, Some(LogFormat::Jsonl));
+            });
+        }
+
+        #[test]
+        fn invalid_log_format() {
+            temp_env::with_vars(vec![("FDINTERCEPT_LOG_FORMAT", Some("fancy"))], || {
+                assert!(
+                    get_env_vars()
+                        .unwrap_err()
+                        .to_string()
+                        .contains("Error parsing FDINTERCEPT_LOG_FORMAT environment variable")
+                );
+            });
+        }
+
+        #[test]
+        fn valid_stream_log_formats() {
+            temp_env::with_vars(
+                vec![
+                    ("FDINTERCEPT_STDIN_LOG_FORMAT", Some("raw")),
+                    ("FDINTERCEPT_STDOUT_LOG_FORMAT", Some("hex")),
+                    ("FDINTERCEPT_STDERR_LOG_FORMAT", Some("lines")),
+                ],
+                || {
+                    let env_vars = get_env_vars().unwrap();
+                    assert_eq!(env_vars.stdin_log_format, Some(LogFormat::Raw));
+                    assert_eq!(env_vars.stdout_log_format, Some(LogFormat::Hex));
+                    assert_eq!(env_vars.stderr_log_format, Some(LogFormat::Lines));
+                },
+            );
+        }
+
+        #[test]
+        fn invalid_stream_log_format() {
+            temp_env::with_vars(
+                vec![("FDINTERCEPT_STDOUT_LOG_FORMAT", Some("fancy"))],
+                || {
+                    assert!(get_env_vars().unwrap_err().to_string().contains(
+                        "Error parsing FDINTERCEPT_STDOUT_LOG_FORMAT environment variable"
+                    ));
+                },
+            );
+        }
+
+        #[test]
+        fn valid_buffer_size() {
+            temp_env::with_vars(vec![("FDINTERCEPT_BUFFER_SIZE", Some("1024"))], || {
+                assert_eq!(get_env_vars().unwrap().buffer_size, Some(1024));
+            });
+        }
+
+        #[test]
+        fn invalid_buffer_size() {
+            temp_env::with_vars(
+                vec![("FDINTERCEPT_BUFFER_SIZE", Some("not_a_number"))],
+                || {
+                    assert!(
+                        get_env_vars()
+                            .unwrap_err()
+                            .to_string()
+                            .contains("Error parsing FDINTERCEPT_BUFFER_SIZE environment variable")
+                    );
+                },
+            );
+        }
+
+        #[test]
+        fn valid_target() {
+            temp_env::with_vars(vec![("FDINTERCEPT_TARGET", Some("echo hello"))], || {
+                assert_eq!(
+                    get_env_vars().unwrap().target,
+                    Some("echo hello".to_string())
+                );
+            });
+        }
+
+        #[test]
+        fn all_valid_vars() {
+            temp_env::with_vars(
+                vec![
+                    ("FDINTERCEPTRC", Some("/path/to/config")),
+                    ("FDINTERCEPT_RECREATE_LOGS", Some("true")),
+                    ("FDINTERCEPT_BUFFER_SIZE", Some("1024")),
+                    ("FDINTERCEPT_TARGET", Some("echo hello")),
+                ],
+                || {
+                    let env_vars = get_env_vars().unwrap();
+                    assert_eq!(env_vars.conf, Some(PathBuf::from("/path/to/config")));
+                    assert_eq!(env_vars.recreate_logs, Some(true));
+                    assert_eq!(env_vars.buffer_size, Some(1024));
+                    assert_eq!(env_vars.target, Some("echo hello".to_string()));
+                },
+            );
+        }
+    }
+
+    mod get_config {
+        use super::*;
+        use std::fs;
+        use tempfile::TempDir;
+
+        #[test]
+        fn from_cli_args() {
+            let tmp_dir = TempDir::new().unwrap();
+            let config_path = tmp_dir.path().join("config.toml");
+            fs::write(&config_path, "buffer_size = 1024").unwrap();
+
+            let cli_args = CliArgs {
+                conf: Some(config_path),
+                ..Default::default()
+            };
+            let env_vars = EnvVars::default();
+
+            assert_eq!(
+                get_config(&cli_args, &env_vars).unwrap().buffer_size,
+                Some(1024)
+            );
+        }
+
+        #[test]
+        fn from_cli_args_nonexistent_file() {
+            let cli_args = CliArgs {
+                conf: Some(PathBuf::from("/nonexistent/config.toml")),
+                ..Default::default()
+            };
+            let env_vars = EnvVars::default();
+
+            assert!(
+                get_config(&cli_args, &env_vars)
+                    .unwrap_err()
+                    .to_string()
+                    .contains("Error reading configuration file")
+            );
+        }
+
+        #[test]
+        fn from_cli_args_invalid_toml() {
+            let tmp_dir = TempDir::new().unwrap();
+            let config_path = tmp_dir.path().join("config.toml");
+            fs::write(&config_path, "invalid toml").unwrap();
+
+            let cli_args = CliArgs {
+                conf: Some(config_path),
+                ..Default::default()
+            };
+            let env_vars = EnvVars::default();
+
+            assert!(
+                get_config(&cli_args, &env_vars)
+                    .unwrap_err()
+                    .to_string()
+                    .contains("Error parsing TOML configuration")
+            );
+        }
+
+        #[test]
+        fn from_env_vars() {
+            let tmp_dir = TempDir::new().unwrap();
+            let config_path = tmp_dir.path().join("config.toml");
+            fs::write(&config_path, "buffer_size = 2048").unwrap();
+
+            let cli_args = CliArgs::default();
+            let env_vars = EnvVars {
+                conf: Some(config_path),
+                ..Default::default()
+            };
+
+            assert_eq!(
+                get_config(&cli_args, &env_vars).unwrap().buffer_size,
+                Some(2048)
+            );
+        }
+
+        #[test]
+        fn from_env_vars_nonexistent_file() {
+            let cli_args = CliArgs::default();
+            let env_vars = EnvVars {
+                conf: Some(PathBuf::from("/nonexistent/config.toml")),
+                ..Default::default()
+            };
+
+            assert!(
+                get_config(&cli_args, &env_vars)
+                    .unwrap_err()
+                    .to_string()
+                    .contains("Error reading configuration file")
+            );
+        }
+
+        #[test]
+        fn from_env_vars_invalid_toml() {
+            let tmp_dir = TempDir::new().unwrap();
+            let config_path = tmp_dir.path().join("config.toml");
+            fs::write(&config_path, "invalid toml").unwrap();
+
+            let cli_args = CliArgs::default();
+            let env_vars = EnvVars {
+                conf: Some(config_path),
+                ..Default::default()
+            };
+
+            assert!(
+                get_config(&cli_args, &env_vars)
+                    .unwrap_err()
+                    .to_string()
+                    .contains("Error parsing TOML configuration")
+            );
+        }
+
+        #[test]
+        fn from_home_dir() {
+            let tmp_dir = TempDir::new().unwrap();
+            let config_path = tmp_dir.path().join(".fdinterceptrc.toml");
+            fs::write(&config_path, "buffer_size = 4096").unwrap();
+
+            let cli_args = CliArgs::default();
+            let env_vars = EnvVars::default();
+
+            temp_env::with_vars(
+                vec![("HOME", Some(tmp_dir.path().to_str().unwrap()))],
+                || {
+                    assert_eq!(
+                        get_config(&cli_args, &env_vars).unwrap().buffer_size,
+                        Some(4096)
+                    );
+                },
+            );
+        }
+
+        #[test]
+        fn from_home_dir_invalid_toml() {
+            let tmp_dir = TempDir::new().unwrap();
+            let config_path = tmp_dir.path().join(".fdinterceptrc.toml");
+            fs::write(&config_path, "invalid toml").unwrap();
+
+            let cli_args = CliArgs::default();
+            let env_vars = EnvVars::default();
+
+            temp_env::with_vars(
+                vec![("HOME", Some(tmp_dir.path().to_str().unwrap()))],
+                || {
+                    assert!(
+                        get_config(&cli_args, &env_vars)
+                            .unwrap_err()
+                            .to_string()
+                            .contains("Error parsing TOML configuration")
+                    );
+                },
+            );
+        }
+
+        #[test]
+        fn if_home_dir_not_found_move_on() {
+            let tmp_dir = TempDir::new().unwrap();
+
+            let cli_args = CliArgs::default();
+            let env_vars = EnvVars::default();
+
+            temp_env::with_vars(
+                vec![("HOME", Some(tmp_dir.path().to_str().unwrap()))],
+                || {
+                    assert_eq!(get_config(&cli_args, &env_vars).unwrap(), Config::default());
+                },
+            );
+        }
+
+        #[test]
+        fn from_xdg_config_home() {
+            let tmp_dir = TempDir::new().unwrap();
+            fs::create_dir_all(tmp_dir.path().join("fdintercept")).unwrap();
+            let config_path = tmp_dir.path().join("fdintercept/rc.toml");
+            fs::write(&config_path, "buffer_size = 8192").unwrap();
+
+            let cli_args = CliArgs::default();
+            let env_vars = EnvVars::default();
+
+            temp_env::with_vars(
+                vec![
+                    ("HOME", None),
+                    ("XDG_CONFIG_HOME", Some(tmp_dir.path().to_str().unwrap())),
+                ],
+                || {
+                    assert_eq!(
+                        get_config(&cli_args, &env_vars).unwrap().buffer_size,
+                        Some(8192)
+                    );
+                },
+            );
+        }
+
+        #[test]
+        fn from_xdg_config_home_invalid_toml() {
+            let tmp_dir = TempDir::new().unwrap();
+            fs::create_dir_all(tmp_dir.path().join("fdintercept")).unwrap();
+            let config_path = tmp_dir.path().join("fdintercept/rc.toml");
+            fs::write(&config_path, "invalid toml").unwrap();
+
+            let cli_args = CliArgs::default();
+            let env_vars = EnvVars::default();
+
+            temp_env::with_vars(
+                vec![
+                    ("HOME", None),
+                    ("XDG_CONFIG_HOME", Some(tmp_dir.path().to_str().unwrap())),
+                ],
+                || {
+                    assert!(
+                        get_config(&cli_args, &env_vars)
+                            .unwrap_err()
+                            .to_string()
+                            .contains("Error parsing TOML configuration")
+                    );
+                },
+            );
+        }
+
+        #[test]
+        fn if_xdg_config_home_dir_not_found_move_on() {
+            let tmp_dir = TempDir::new().unwrap();
+
+            let cli_args = CliArgs::default();
+            let env_vars = EnvVars::default();
+
+            temp_env::with_vars(
+                vec![
+                    ("HOME", None),
+                    ("XDG_CONFIG_HOME", Some(tmp_dir.path().to_str().unwrap())),
+                ],
+                || {
+                    assert_eq!(get_config(&cli_args, &env_vars).unwrap(), Config::default());
+                },
+            );
+        }
+
+        #[test]
+        fn no_config_found() {
+            let cli_args = CliArgs::default();
+            let env_vars = EnvVars::default();
+
+            temp_env::with_vars(
+                vec![("HOME", None::<&str>), ("XDG_CONFIG_HOME", None::<&str>)],
+                || {
+                    assert_eq!(get_config(&cli_args, &env_vars).unwrap(), Config::default());
+                },
+            );
+        }
+    }
+
+    mod get_log_dir {
+        use super::*;
+
+        #[test]
+        fn cli_args() {
+            let cli_args = CliArgs {
+                log_dir: Some(PathBuf::from("cli_logs")),
+                ..Default::default()
+            };
+            let env_vars = EnvVars::default();
+            let config = Config::default();
+
+            assert_eq!(
+                get_log_dir(&cli_args, &env_vars, &config),
+                Some(PathBuf::from("cli_logs"))
+            );
+        }
+
+        #[test]
+        fn from_env_vars() {
+            let cli_args = CliArgs::default();
+            let env_vars = EnvVars {
+                log_dir: Some(PathBuf::from("env_logs")),
+                ..Default::default()
+            };
+            let config = Config::default();
+
+            assert_eq!(
+                get_log_dir(&cli_args, &env_vars, &config),
+                Some(PathBuf::from("env_logs"))
+            );
+        }
+
+        #[test]
+        fn from_config() {
+            let cli_args = CliArgs::default();
+            let env_vars = EnvVars::default();
+            let config = Config {
+                log_dir: Some(PathBuf::from("config_logs")),
+                ..Default::default()
+            };
+
+            assert_eq!(
+                get_log_dir(&cli_args, &env_vars, &config),
+                Some(PathBuf::from("config_logs"))
+            );
+        }
+
+        #[test]
+        fn default() {
+            let cli_args = CliArgs::default();
+            let env_vars = EnvVars::default();
+            let config = Config::default();
+
+            assert_eq!(get_log_dir(&cli_args, &env_vars, &config), None);
+        }
+
+        #[test]
+        fn precedence_cli_args_over_env_vars() {
+            let cli_args = CliArgs {
+                log_dir: Some(PathBuf::from("cli_logs")),
+                ..Default::default()
+            };
+            let env_vars = EnvVars {
+                log_dir: Some(PathBuf::from("env_logs")),
+                ..Default::default()
+            };
+            let config = Config::default();
+
+            assert_eq!(
+                get_log_dir(&cli_args, &env_vars, &config),
+                Some(PathBuf::from("cli_logs"))
+            );
+        }
+
+        #[test]
+        fn precedence_env_vars_over_config() {
+            let cli_args = CliArgs::default();
+            let env_vars = EnvVars {
+                log_dir: Some(PathBuf::from("env_logs")),
+                ..Default::default()
+            };
+            let config = Config {
+                log_dir: Some(PathBuf::from("config_logs")),
+                ..Default::default()
+            };
+
+            assert_eq!(
+                get_log_dir(&cli_args, &env_vars, &config),
+                Some(PathBuf::from("env_logs"))
+            );
+        }
+    }
+
+    mod get_use_defaults {
+        use super::*;
+
+        #[test]
+        fn no_logs() {
+            let cli_args = CliArgs::default();
+            let env_vars = EnvVars::default();
+            let config = Config::default();
+
+            assert!(get_use_defaults(&cli_args, &env_vars, &config));
+        }
+
+        #[test]
+        fn cli_stdin_log() {
+            let cli_args = CliArgs {
+                stdin_log: Some(PathBuf::from("stdin.log")),
+                ..Default::default()
+            };
+            let env_vars = EnvVars::default();
+            let config = Config::default();
+
+            assert!(!get_use_defaults(&cli_args, &env_vars, &config));
+        }
+
+        #[test]
+        fn cli_stdout_log() {
+            let cli_args = CliArgs {
+                stdout_log: Some(PathBuf::from("stdout.log")),
+                ..Default::default()
+            };
+            let env_vars = EnvVars::default();
+            let config = Config::default();
+
+            assert!(!get_use_defaults(&cli_args, &env_vars, &config));
+        }
+
+        #[test]
+        fn cli_stderr_log() {
+            let cli_args = CliArgs {
+                stderr_log: Some(PathBuf::from("stderr.log")),
+                ..Default::default()
+            };
+            let env_vars = EnvVars::default();
+            let config = Config::default();
+
+            assert!(!get_use_defaults(&cli_args, &env_vars, &config));
+        }
+
+        #[test]
+        fn env_stdout_log() {
+            let cli_args = CliArgs::default();
+            let env_vars = EnvVars {
+                stdout_log: Some(PathBuf::from("stdout.log")),
+                ..Default::default()
+            };
+            let config = Config::default();
+
+            assert!(!get_use_defaults(&cli_args, &env_vars, &config));
+        }
+
+        #[test]
+        fn config_stdin_log() {
+            let cli_args = CliArgs::default();
+            let env_vars = EnvVars::default();
+            let config = Config {
+                stdin_log: Some(PathBuf::from("stdin.log")),
+                ..Default::default()
+            };
+
+            assert!(!get_use_defaults(&cli_args, &env_vars, &config));
+        }
+
+        #[test]
+        fn config_stdout_log() {
+            let cli_args = CliArgs::default();
+            let env_vars = EnvVars::default();
+            let config = Config {
+                stdout_log: Some(PathBuf::from("stdout.log")),
+                ..Default::default()
+            };
+
+            assert!(!get_use_defaults(&cli_args, &env_vars, &config));
+        }
+
+        #[test]
+        fn config_stderr_log() {
+            let cli_args = CliArgs::default();
+            let env_vars = EnvVars::default();
+            let config = Config {
+                stderr_log: Some(PathBuf::from("stderr.log")),
+                ..Default::default()
+            };
+
+            assert!(!get_use_defaults(&cli_args, &env_vars, &config));
+        }
+    }
+
+    mod get_log_name {
+        use super::*;
+
+        #[test]
+        fn from_cli_args() {
+            let cli_args = CliArgs {
+                stdin_log: Some(PathBuf::from("cli.log")),
+                ..Default::default()
+            };
+            let env_vars = EnvVars::default();
+            let config = Config::default();
+
+            assert_eq!(
+                get_log_name(
+                    LogFd::Stdin,
+                    &cli_args,
+                    &env_vars,
+                    &config,
+                    true,
+                    "default.log"
+                ),
+                Some(PathBuf::from("cli.log"))
+            );
+        }
+
+        #[test]
+        fn from_env_vars() {
+            let cli_args = CliArgs::default();
+            let env_vars = EnvVars {
+                stdin_log: Some(PathBuf::from("env.log")),
+                ..Default::default()
+            };
+            let config = Config {
+                stdin_log: Some(PathBuf::from("config.log")),
+                ..Default::default()
+            };
+
+            assert_eq!(
+                get_log_name(
+                    LogFd::Stdin,
+                    &cli_args,
+                    &env_vars,
+                    &config,
+                    true,
+                    "default.log"
+                ),
+                Some(PathBuf::from("env.log"))
+            );
+        }
+
+        #[test]
+        fn from_config() {
+            let cli_args = CliArgs::default();
+            let env_vars = EnvVars::default();
+            let config = Config {
+                stdin_log: Some(PathBuf::from("config.log")),
+                ..Default::default()
+            };
+
+            assert_eq!(
+                get_log_name(
+                    LogFd::Stdin,
+                    &cli_args,
+                    &env_vars,
+                    &config,
+                    true,
+                    "default.log"
+                ),
+                Some(PathBuf::from("config.log"))
+            );
+        }
+
+        #[test]
+        fn from_default() {
+            let cli_args = CliArgs::default();
+            let env_vars = EnvVars::default();
+            let config = Config::default();
+
+            assert_eq!(
+                get_log_name(
+                    LogFd::Stdin,
+                    &cli_args,
+                    &env_vars,
+                    &config,
+                    true,
+                    "default.log"
+                ),
+                Some(PathBuf::from("default.log"))
+            );
+        }
+
+        #[test]
+        fn no_default_returns_none() {
+            let cli_args = CliArgs::default();
+            let env_vars = EnvVars::default();
+            let config = Config::default();
+
+            assert_eq!(
+                get_log_name(
+                    LogFd::Stdin,
+                    &cli_args,
+                    &env_vars,
+                    &config,
+                    false,
+                    "default.log"
+                ),
+                None
+            );
+        }
+
+        #[test]
+        fn cli_args_take_precedence_over_config() {
+            let cli_args = CliArgs {
+                stdin_log: Some(PathBuf::from("cli.log")),
+                ..Default::default()
+            };
+            let env_vars = EnvVars::default();
+            let config = Config {
+                stdout_log: Some(PathBuf::from("config.log")),
+                ..Default::default()
+            };
+
+            assert_eq!(
+                get_log_name(
+                    LogFd::Stdin,
+                    &cli_args,
+                    &env_vars,
+                    &config,
+                    true,
+                    "default.log"
+                ),
+                Some(PathBuf::from("cli.log"))
+            );
+        }
+
+        #[test]
+        fn test_all_log_fd_variants() {
+            let cli_args = CliArgs {
+                stdin_log: Some(PathBuf::from("stdin.log")),
+                stdout_log: Some(PathBuf::from("stdout.log")),
+                stderr_log: Some(PathBuf::from("stderr.log")),
+                ..Default::default()
+            };
+            let env_vars = EnvVars::default();
+            let config = Config::default();
+
+            assert_eq!(
+                get_log_name(
+                    LogFd::Stdin,
+                    &cli_args,
+                    &env_vars,
+                    &config,
+                    true,
+                    "default.log"
+                ),
+                Some(PathBuf::from("stdin.log"))
+            );
+            assert_eq!(
+                get_log_name(
+                    LogFd::Stdout,
+                    &cli_args,
+                    &env_vars,
+                    &config,
+                    true,
+                    "default.log"
+                ),
+                Some(PathBuf::from("stdout.log"))
+            );
+            assert_eq!(
+                get_log_name(
+                    LogFd::Stderr,
+                    &cli_args,
+                    &env_vars,
+                    &config,
+                    true,
+                    "default.log"
+                ),
+                Some(PathBuf::from("stderr.log"))
+            );
+        }
+    }
+
+    mod line_prefix {
+        use super::*;
+
+        #[test]
+        fn default() {
+            assert_eq!(
+                DEFAULT_LINE_PREFIX.parse::<LinePrefix>().unwrap(),
+                LinePrefix(vec![
+                    LinePrefixPart::Literal("[".to_string()),
+                    LinePrefixPart::Stream,
+                    LinePrefixPart::Literal(" ".to_string()),
+                    LinePrefixPart::Time,
+                    LinePrefixPart::Literal("] ".to_string()),
+                ])
+            );
+        }
+
+        #[test]
+        fn all_placeholders_and_escapes() {
+            assert_eq!(
+                "{{{stream}}}{timestamp}{mono}"
+                    .parse::<LinePrefix>()
+                    .unwrap(),
+                LinePrefix(vec![
+                    LinePrefixPart::Literal("{".to_string()),
+                    LinePrefixPart::Stream,
+                    LinePrefixPart::Literal("}".to_string()),
+                    LinePrefixPart::Timestamp,
+                    LinePrefixPart::Mono,
+                ])
+            );
+        }
+
+        #[test]
+        fn empty() {
+            assert_eq!("".parse::<LinePrefix>().unwrap(), LinePrefix(vec![]));
+        }
+
+        #[test]
+        fn unknown_placeholder() {
+            assert_eq!(
+                "{pid}".parse::<LinePrefix>().unwrap_err(),
+                LinePrefixParseError::UnknownPlaceholder("pid".to_string())
+            );
+        }
+
+        #[test]
+        fn unclosed_placeholder() {
+            assert_eq!(
+                "[{stream".parse::<LinePrefix>().unwrap_err(),
+                LinePrefixParseError::Unclosed
+            );
+        }
+    }
+
+    mod get_line_prefix {
+        use super::*;
+
+        #[test]
+        fn cli_args_take_precedence_over_config() {
+            let cli_args = CliArgs {
+                line_prefix: Some("{stream}: ".to_string()),
+                ..Default::default()
+            };
+            let config = Config {
+                line_prefix: Some("{mono} ".to_string()),
+                ..Default::default()
+            };
+
+            assert_eq!(
+                get_line_prefix(&cli_args, &config).unwrap(),
+                LinePrefix(vec![
+                    LinePrefixPart::Stream,
+                    LinePrefixPart::Literal(": ".to_string())
+                ])
+            );
+        }
+
+        #[test]
+        fn invalid() {
+            let config = Config {
+                line_prefix: Some("{oops}".to_string()),
+                ..Default::default()
+            };
+
+            assert!(
+                get_line_prefix(&CliArgs::default(), &config)
+                    .unwrap_err()
+                    .to_string()
+                    .contains("Invalid line prefix template: {oops}")
+            );
+        }
+    }
+
+    mod parse_speed {
+        use super::*;
+
+        #[test]
+        fn valid() {
+            assert_eq!(parse_speed("0.5"), Ok(0.5));
+            assert_eq!(parse_speed("3"), Ok(3.0));
+        }
+
+        #[test]
+        fn invalid() {
+            assert!(parse_speed("0").is_err());
+            assert!(parse_speed("-1").is_err());
+            assert!(parse_speed("inf").is_err());
+            assert!(parse_speed("fast").is_err());
+        }
+    }
+
+    mod get_asciicast_stdin {
+        use super::*;
+
+        #[test]
+        fn default() {
+            assert!(!get_asciicast_stdin(
+                &CliArgs::default(),
+                &Config::default()
+            ));
+        }
+
+        #[test]
+        fn from_config() {
+            let config = Config {
+                asciicast_stdin: Some(true),
+                ..Default::default()
+            };
+
+            assert!(get_asciicast_stdin(&CliArgs::default(), &config));
+        }
+
+        #[test]
+        fn cli_flag_overrides_config() {
+            let cli_args = CliArgs {
+                asciicast_stdin: true,
+                ..Default::default()
+            };
+            let config = Config {
+                asciicast_stdin: Some(false),
+                ..Default::default()
+            };
+
+            assert!(get_asciicast_stdin(&cli_args, &config));
+        }
+    }
+
+    mod get_manifest_name {
+        use super::*;
+
+        #[test]
+        fn from_cli_args() {
+            let cli_args = CliArgs {
+                manifest: Some(PathBuf::from("cli_manifest.json")),
+                ..Default::default()
+            };
+            let config = Config::default();
+
+            assert_eq!(
+                get_manifest_name(&cli_args, &config, false),
+                Some(PathBuf::from("cli_manifest.json"))
+            );
+        }
+
+        #[test]
+        fn from_config() {
+            let cli_args = CliArgs::default();
+            let config = Config {
+                manifest: Some(PathBuf::from("config_manifest.json")),
+                ..Default::default()
+            };
+
+            assert_eq!(
+                get_manifest_name(&cli_args, &config, true),
+                Some(PathBuf::from("config_manifest.json"))
+            );
+        }
+
+        #[test]
+        fn default_with_log_dir() {
+            assert_eq!(
+                get_manifest_name(&CliArgs::default(), &Config::default(), true),
+                Some(PathBuf::from("session.json"))
+            );
+        }
+
+        #[test]
+        fn no_default_without_log_dir() {
+            assert_eq!(
+                get_manifest_name(&CliArgs::default(), &Config::default(), false),
+                None
+            );
+        }
+
+        #[test]
+        fn cli_args_take_precedence_over_config() {
+            let cli_args = CliArgs {
+                manifest: Some(PathBuf::from("cli_manifest.json")),
+                ..Default::default()
+            };
+            let config = Config {
+                manifest: Some(PathBuf::from("config_manifest.json")),
+                ..Default::default()
+            };
+
+            assert_eq!(
+                get_manifest_name(&cli_args, &config, false),
+                Some(PathBuf::from("cli_manifest.json"))
+            );
+        }
+    }
+
+    mod get_manifest_env {
+        use super::*;
+
+        #[test]
+        fn cli_args() {
+            let cli_args = CliArgs::parse_from(["fdintercept", "--manifest-env", "PATH,RUST_LOG"]);
+            let config = Config::default();
+
+            assert_eq!(get_manifest_env(&cli_args, &config), ["PATH", "RUST_LOG"]);
+        }
 
-impl std::error::Error for StringTargetParseError {}
+        #[test]
+        fn from_config() {
+            let cli_args = CliArgs::default();
+            let config = Config {
+                manifest_env: Some(vec!["RUST_LOG".to_string()]),
+                ..Default::default()
+            };
 
-/// Parses a target command from a string.
-///
-/// Takes a string containing a shell-style command and parses it into a target command structure.
-/// The string is tokenized using shell-like rules (handling quotes and escapes), with the first
-/// token becoming the executable name and the remaining tokens becoming the command arguments.
-///
-/// # Arguments
-///
-/// * `target` - A string containing the command to parse. Must not be empty and must contain a
-///   valid executable name as its first token.
-///
-/// # Returns
-///
-/// Returns a `Result<Target, StringTargetParseError>` which is:
-/// - `Ok(Target)` containing the parsed executable name and arguments if successful, or
-/// - `Err(StringTargetParseError)` if parsing fails.
-///
-/// # Errors
-///
-/// Returns `StringTargetParseError`:
-/// - `Empty` if the input string is empty,
-/// - `FailedToTokenize` if the string cannot be properly tokenized (e.g., unmatched quotes), or
-/// - `EmptyExecutable` if the first token (executable name) is empty.
-fn get_target_from_string(target: &str) -> Result<Target, StringTargetParseError> {
-    if target.is_empty() {
-        return Err(StringTargetParseError::Empty);
-    }
-    let tokenized_target = shlex::split(target).ok_or(StringTargetParseError::FailedToTokenize)?;
-    // unwrap: Safe because we already ensure that target is not empty.
-    let target_vec = NonEmpty::from_vec(tokenized_target).unwrap();
-    Ok(Target {
-        executable: NonEmptyString::new(target_vec.head)
-            .map_err(|_| StringTargetParseError::EmptyExecutable)?,
-        args: target_vec.tail,
-    })
-}
+            assert_eq!(get_manifest_env(&cli_args, &config), ["RUST_LOG"]);
+        }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        #[test]
+        fn default() {
+            assert_eq!(
+                get_manifest_env(&CliArgs::default(), &Config::default()),
+                ["PATH", "HOME", "USER", "SHELL", "TERM", "LANG"]
+            );
+        }
 
-    mod get_settings_with_raw_cli_args {
+        #[test]
+        fn precedence_cli_args_over_config() {
+            let cli_args = CliArgs {
+                manifest_env: Some(vec![]),
+                ..Default::default()
+            };
+            let config = Config {
+                manifest_env: Some(vec!["RUST_LOG".to_string()]),
+                ..Default::default()
+            };
+
+            assert!(get_manifest_env(&cli_args, &config).is_empty());
+        }
+    }
+
+    mod get_stdin_source {
         use super::*;
 
         #[test]
         fn from_cli_args() {
-            let settings = get_settings_with_raw_cli_args(vec![
-                "fdintercept".to_string(),
-                "--stdin-log".to_string(),
-                "custom_stdin.log".to_string(),
-                "--stdout-log".to_string(),
-                "custom_stdout.log".to_string(),
-                "--stderr-log".to_string(),
-                "custom_stderr.log".to_string(),
-                "--recreate-logs".to_string(),
-                "--buffer-size".to_string(),
-                "4096".to_string(),
-                "--".to_string(),
-                "executable".to_string(),
-                "arg1".to_string(),
-                "arg2".to_string(),
-            ])
-            .unwrap();
+            let cli_args = CliArgs {
+                stdin_source: Some(PathBuf::from("/dev/null")),
+                ..Default::default()
+            };
 
-            assert_eq!(settings.stdin_log, Some(PathBuf::from("custom_stdin.log")));
             assert_eq!(
-                settings.stdout_log,
-                Some(PathBuf::from("custom_stdout.log"))
+                get_stdin_source(&cli_args, &Config::default()),
+                Some(StdinSource::Path(PathBuf::from("/dev/null")))
             );
+        }
+
+        #[test]
+        fn path_from_config() {
+            let config = parse_config_contents("stdin_source = \"answers.txt\"").unwrap();
+
             assert_eq!(
-                settings.stderr_log,
-                Some(PathBuf::from("custom_stderr.log"))
+                get_stdin_source(&CliArgs::default(), &config),
+                Some(StdinSource::Path(PathBuf::from("answers.txt")))
             );
-            assert!(settings.recreate_logs);
-            assert_eq!(settings.buffer_size, 4096);
-            assert_eq!(settings.target.executable.as_str(), "executable");
-            assert_eq!(settings.target.args, vec!["arg1", "arg2"]);
         }
 
         #[test]
-        fn from_env_vars() {
-            temp_env::with_vars(
-                vec![
-                    ("FDINTERCEPT_RECREATE_LOGS", Some("true")),
-                    ("FDINTERCEPT_BUFFER_SIZE", Some("2048")),
-                    ("FDINTERCEPT_TARGET", Some("executable arg1 arg2")),
-                ],
-                || {
-                    let settings =
-                        get_settings_with_raw_cli_args(vec!["intercept".to_string()]).unwrap();
+        fn text_from_config() {
+            let config =
+                parse_config_contents("stdin_source = { text = \"yes\\nno\\n\" }").unwrap();
 
-                    assert_eq!(settings.stdin_log, Some(PathBuf::from("stdin.log")));
-                    assert_eq!(settings.stdout_log, Some(PathBuf::from("stdout.log")));
-                    assert_eq!(settings.stderr_log, Some(PathBuf::from("stderr.log")));
-                    assert!(settings.recreate_logs);
-                    assert_eq!(settings.buffer_size, 2048);
-                    assert_eq!(settings.target.executable.as_str(), "executable");
-                    assert_eq!(settings.target.args, vec!["arg1", "arg2"]);
-                },
+            assert_eq!(
+                get_stdin_source(&CliArgs::default(), &config),
+                Some(StdinSource::Text {
+                    text: "yes\nno\n".to_string()
+                })
+            );
+        }
+
+        #[test]
+        fn cli_args_take_precedence_over_config() {
+            let cli_args = CliArgs {
+                stdin_source: Some(PathBuf::from("input.fifo")),
+                ..Default::default()
+            };
+            let config = Config {
+                stdin_source: Some(StdinSource::Text {
+                    text: "yes\n".to_string(),
+                }),
+                ..Default::default()
+            };
+
+            assert_eq!(
+                get_stdin_source(&cli_args, &config),
+                Some(StdinSource::Path(PathBuf::from("input.fifo")))
+            );
+        }
+
+        #[test]
+        fn no_default() {
+            assert_eq!(
+                get_stdin_source(&CliArgs::default(), &Config::default()),
+                None
+            );
+        }
+    }
+
+    mod get_timeline_log_name {
+        use super::*;
+
+        #[test]
+        fn from_cli_args() {
+            let cli_args = CliArgs {
+                timeline_log: Some(PathBuf::from("cli_timeline.log")),
+                ..Default::default()
+            };
+            let config = Config::default();
+
+            assert_eq!(
+                get_timeline_log_name(&cli_args, &config),
+                Some(PathBuf::from("cli_timeline.log"))
             );
         }
 
         #[test]
         fn from_config() {
-            let tmp_dir = tempfile::TempDir::new().unwrap();
-            let config_path = tmp_dir.path().join("config.toml");
-            std::fs::write(
-                &config_path,
-                r#"
-                    stdin_log = "config_stdin.log"
-                    stdout_log = "config_stdout.log"
-                    stderr_log = "config_stderr.log"
-                    recreate_logs = true
-                    buffer_size = 1024
-                    target = "executable arg1 arg2"
-                "#,
-            )
-            .unwrap();
+            let cli_args = CliArgs::default();
+            let config = Config {
+                timeline_log: Some(PathBuf::from("config_timeline.log")),
+                ..Default::default()
+            };
 
-            let settings = get_settings_with_raw_cli_args(vec![
-                "fdintercept".to_string(),
-                "--conf".to_string(),
-                config_path.to_str().unwrap().to_string(),
-            ])
-            .unwrap();
+            assert_eq!(
+                get_timeline_log_name(&cli_args, &config),
+                Some(PathBuf::from("config_timeline.log"))
+            );
+        }
 
-            assert_eq!(settings.stdin_log, Some(PathBuf::from("config_stdin.log")));
+        #[test]
+        fn no_default() {
             assert_eq!(
-                settings.stdout_log,
-                Some(PathBuf::from("config_stdout.log"))
+                get_timeline_log_name(&CliArgs::default(), &Config::default()),
+                None
             );
+        }
+
+        #[test]
+        fn cli_args_take_precedence_over_config() {
+            let cli_args = CliArgs {
+                timeline_log: Some(PathBuf::from("cli_timeline.log")),
+                ..Default::default()
+            };
+            let config = Config {
+                timeline_log: Some(PathBuf::from("config_timeline.log")),
+                ..Default::default()
+            };
+
             assert_eq!(
-                settings.stderr_log,
-                Some(PathBuf::from("config_stderr.log"))
+                get_timeline_log_name(&cli_args, &config),
+                Some(PathBuf::from("cli_timeline.log"))
             );
-            assert!(settings.recreate_logs);
-            assert_eq!(settings.buffer_size, 1024);
-            assert_eq!(settings.target.executable.as_str(), "executable");
-            assert_eq!(settings.target.args, vec!["arg1", "arg2"]);
         }
 
         #[test]
-        fn with_no_log_paths() {
-            let settings = get_settings_with_raw_cli_args(vec![
-                "fdintercept".to_string(),
-                "--".to_string(),
-                "executable".to_string(),
-                "arg1".to_string(),
-                "arg2".to_string(),
-            ])
-            .unwrap();
+        fn does_not_disable_default_stream_logs() {
+            let cli_args = CliArgs {
+                timeline_log: Some(PathBuf::from("cli_timeline.log")),
+                ..Default::default()
+            };
+            let env_vars = EnvVars::default();
+            let config = Config {
+                timeline_log: Some(PathBuf::from("config_timeline.log")),
+                ..Default::default()
+            };
 
-            assert_eq!(settings.stdin_log, Some(PathBuf::from("stdin.log")));
-            assert_eq!(settings.stdout_log, Some(PathBuf::from("stdout.log")));
-            assert_eq!(settings.stderr_log, Some(PathBuf::from("stderr.log")));
-            assert!(!settings.recreate_logs);
-            assert_eq!(settings.buffer_size, 8192);
-            assert_eq!(settings.target.executable.as_str(), "executable");
-            assert_eq!(settings.target.args, vec!["arg1", "arg2"]);
+            assert!(get_use_defaults(&cli_args, &env_vars, &config));
+        }
+    }
+
+    mod get_recreate_logs {
+        use super::*;
+
+        #[test]
+        fn cli_args_true() {
+            let cli_args = CliArgs {
+                recreate_logs: true,
+                ..Default::default()
+            };
+            let env_vars = EnvVars::default();
+            let config = Config::default();
+
+            assert!(get_recreate_logs(&cli_args, &env_vars, &config));
+        }
+
+        #[test]
+        fn from_env_vars_true() {
+            let cli_args = CliArgs::default();
+            let env_vars = EnvVars {
+                recreate_logs: Some(true),
+                ..Default::default()
+            };
+            let config = Config::default();
+
+            assert!(get_recreate_logs(&cli_args, &env_vars, &config));
+        }
+
+        #[test]
+        fn from_config_true() {
+            let cli_args = CliArgs::default();
+            let env_vars = EnvVars::default();
+            let config = Config {
+                recreate_logs: Some(true),
+                ..Default::default()
+            };
+
+            assert!(get_recreate_logs(&cli_args, &env_vars, &config));
         }
 
         #[test]
-        fn with_invalid_env_var() {
-            temp_env::with_vars(
-                vec![("FDINTERCEPT_BUFFER_SIZE", Some("not_a_number"))],
-                || {
-                    assert!(
-                        get_settings_with_raw_cli_args(vec![
-                            "fdintercept".to_string(),
-                            "--".to_string(),
-                            "executable".to_string(),
-                            "arg1".to_string(),
-                            "arg2".to_string(),
-                        ])
-                        .unwrap_err()
-                        .to_string()
-                        .contains("Error reading environment variables")
-                    );
-                },
-            );
+        fn default_false() {
+            let cli_args = CliArgs::default();
+            let env_vars = EnvVars::default();
+            let config = Config::default();
+
+            assert!(!get_recreate_logs(&cli_args, &env_vars, &config));
         }
 
         #[test]
-        fn with_invalid_config() {
-            let tmp_dir = tempfile::TempDir::new().unwrap();
-            let config_path = tmp_dir.path().join("config.toml");
-            std::fs::write(&config_path, "invalid toml").unwrap();
+        fn precedence_cli_args_over_env_vars() {
+            let cli_args = CliArgs {
+                recreate_logs: true,
+                ..Default::default()
+            };
+            let env_vars = EnvVars {
+                recreate_logs: Some(false),
+                ..Default::default()
+            };
+            let config = Config::default();
 
-            let args = vec![
-                "fdintercept".to_string(),
-                "--conf".to_string(),
-                config_path.to_str().unwrap().to_string(),
-            ];
+            assert!(get_recreate_logs(&cli_args, &env_vars, &config));
+        }
 
-            assert!(
-                get_settings_with_raw_cli_args(args)
-                    .unwrap_err()
-                    .to_string()
-                    .contains("Error reading configuration")
-            );
+        #[test]
+        fn precedence_env_vars_over_config() {
+            let cli_args = CliArgs::default();
+            let env_vars = EnvVars {
+                recreate_logs: Some(true),
+                ..Default::default()
+            };
+            let config = Config {
+                recreate_logs: Some(false),
+                ..Default::default()
+            };
+
+            assert!(get_recreate_logs(&cli_args, &env_vars, &config));
         }
 
         #[test]
-        fn test_settings_with_missing_target() {
-            assert!(
-                get_settings_with_raw_cli_args(vec!["fdintercept".to_string()])
-                    .unwrap_err()
-                    .to_string()
-                    .contains("Error getting target")
-            );
+        fn precedence_cli_args_over_config() {
+            let cli_args = CliArgs {
+                recreate_logs: true,
+                ..Default::default()
+            };
+            let env_vars = EnvVars::default();
+            let config = Config {
+                recreate_logs: Some(false),
+                ..Default::default()
+            };
+
+            assert!(get_recreate_logs(&cli_args, &env_vars, &config));
         }
     }
 
-    mod get_env_vars {
+    mod get_log_format {
         use super::*;
 
         #[test]
-        fn empty_environment() {
-            temp_env::with_vars(
-                vec![
-                    ("FDINTERCEPTRC", None::<&str>),
-                    ("FDINTERCEPT_RECREATE_LOGS", None::<&str>),
-                    ("FDINTERCEPT_BUFFER_SIZE", None::<&str>),
-                    ("FDINTERCEPT_TARGET", None::<&str>),
-                ],
-                || {
-                    let env_vars = get_env_vars().unwrap();
-                    assert_eq!(env_vars.conf, None);
-                    assert_eq!(env_vars.recreate_logs, None);
-                    assert_eq!(env_vars.buffer_size, None);
-                    assert_eq!(env_vars.target, None);
-                },
+        fn cli_args() {
+            let cli_args = CliArgs {
+                log_format: Some(LogFormat::Timestamped),
+                ..Default::default()
+            };
+            let env_vars = EnvVars::default();
+            let config = Config::default();
+
+            assert_eq!(
+                get_log_format(&cli_args, &env_vars, &config),
+                LogFormat::Timestamped
             );
         }
 
         #[test]
-        fn valid_conf() {
-            temp_env::with_vars(vec![("FDINTERCEPTRC", Some("/path/to/config"))], || {
-                assert_eq!(
-                    get_env_vars().unwrap().conf,
-                    Some(PathBuf::from("/path/to/config"))
-                );
-            });
-        }
+        fn from_env_vars() {
+            let cli_args = CliArgs::default();
+            let env_vars = EnvVars {
+                log_format: Some(LogFormat::Timestamped),
+                ..Default::default()
+            };
+            let config = Config::default();
 
-        #[test]
-        fn empty_conf() {
-            temp_env::with_vars(vec![("FDINTERCEPTRC", Some(""))], || {
-                assert_eq!(
-                    get_env_vars().unwrap_err().to_string(),
-                    "FDINTERCEPTRC is empty"
-                );
-            });
+            assert_eq!(
+                get_log_format(&cli_args, &env_vars, &config),
+                LogFormat::Timestamped
+            );
         }
 
         #[test]
-        fn valid_recreate_logs() {
-            temp_env::with_vars(vec![("FDINTERCEPT_RECREATE_LOGS", Some("true"))], || {
-                assert_eq!(get_env_vars().unwrap().recreate_logs, Some(true));
-            });
-        }
+        fn from_config() {
+            let cli_args = CliArgs::default();
+            let env_vars = EnvVars::default();
+            let config = Config {
+                log_format: Some(LogFormat::Timestamped),
+                ..Default::default()
+            };
 
-        #[test]
-        fn invalid_recreate_logs() {
-            temp_env::with_vars(
-                vec![("FDINTERCEPT_RECREATE_LOGS", Some("not_a_bool"))],
-                || {
-                    assert!(
-                        get_env_vars().unwrap_err().to_string().contains(
-                            "Error parsing FDINTERCEPT_RECREATE_LOGS environment variable"
-                        )
-                    );
-                },
+            assert_eq!(
+                get_log_format(&cli_args, &env_vars, &config),
+                LogFormat::Timestamped
             );
         }
 
         #[test]
-        fn valid_buffer_size() {
-            temp_env::with_vars(vec![("FDINTERCEPT_BUFFER_SIZE", Some("1024"))], || {
-                assert_eq!(get_env_vars().unwrap().buffer_size, Some(1024));
-            });
-        }
+        fn default() {
+            let cli_args = CliArgs::default();
+            let env_vars = EnvVars::default();
+            let config = Config::default();
 
-        #[test]
-        fn invalid_buffer_size() {
-            temp_env::with_vars(
-                vec![("FDINTERCEPT_BUFFER_SIZE", Some("not_a_number"))],
-                || {
-                    assert!(
-                        get_env_vars()
-                            .unwrap_err()
-                            .to_string()
-                            .contains("Error parsing FDINTERCEPT_BUFFER_SIZE environment variable")
-                    );
-                },
+            assert_eq!(
+                get_log_format(&cli_args, &env_vars, &config),
+                LogFormat::Raw
             );
         }
 
         #[test]
-        fn valid_target() {
-            temp_env::with_vars(vec![("FDINTERCEPT_TARGET", Some("echo hello"))], || {
-                assert_eq!(
-                    get_env_vars().unwrap().target,
-                    Some("echo hello".to_string())
-                );
-            });
+        fn precedence_cli_args_over_env_vars() {
+            let cli_args = CliArgs {
+                log_format: Some(LogFormat::Raw),
+                ..Default::default()
+            };
+            let env_vars = EnvVars {
+                log_format: Some(LogFormat::Timestamped),
+                ..Default::default()
+            };
+            let config = Config::default();
+
+            assert_eq!(
+                get_log_format(&cli_args, &env_vars, &config),
+                LogFormat::Raw
+            );
         }
 
         #[test]
-        fn all_valid_vars() {
-            temp_env::with_vars(
-                vec![
-                    ("FDINTERCEPTRC", Some("/path/to/config")),
-                    ("FDINTERCEPT_RECREATE_LOGS", Some("true")),
-                    ("FDINTERCEPT_BUFFER_SIZE", Some("1024")),
-                    ("FDINTERCEPT_TARGET", Some("echo hello")),
-                ],
-                || {
-                    let env_vars = get_env_vars().unwrap();
-                    assert_eq!(env_vars.conf, Some(PathBuf::from("/path/to/config")));
-                    assert_eq!(env_vars.recreate_logs, Some(true));
-                    assert_eq!(env_vars.buffer_size, Some(1024));
-                    assert_eq!(env_vars.target, Some("echo hello".to_string()));
-                },
+        fn precedence_env_vars_over_config() {
+            let cli_args = CliArgs::default();
+            let env_vars = EnvVars {
+                log_format: Some(LogFormat::Raw),
+                ..Default::default()
+            };
+            let config = Config {
+                log_format: Some(LogFormat::Timestamped),
+                ..Default::default()
+            };
+
+            assert_eq!(
+                get_log_format(&cli_args, &env_vars, &config),
+                LogFormat::Raw
             );
         }
     }
 
-    mod get_config {
+    mod get_stream_log_formats {
         use super::*;
-        use std::fs;
-        use tempfile::TempDir;
 
         #[test]
-        fn from_cli_args() {
-            let tmp_dir = TempDir::new().unwrap();
-            let config_path = tmp_dir.path().join("config.toml");
-            fs::write(&config_path, "buffer_size = 1024").unwrap();
-
+        fn cli_args() {
             let cli_args = CliArgs {
-                conf: Some(config_path),
+                stdout_log_format: Some(LogFormat::Hex),
+                ..Default::default()
+            };
+            let env_vars = EnvVars::default();
+            let config = Config::default();
+
+            assert_eq!(
+                get_stream_log_formats(&cli_args, &env_vars, &config),
+                StreamLogFormats {
+                    stdin: LogFormat::Raw,
+                    stdout: LogFormat::Hex,
+                    stderr: LogFormat::Raw,
+                }
+            );
+        }
+
+        #[test]
+        fn from_env_vars() {
+            let cli_args = CliArgs::default();
+            let env_vars = EnvVars {
+                stderr_log_format: Some(LogFormat::Lines),
+                ..Default::default()
+            };
+            let config = Config::default();
+
+            assert_eq!(
+                get_stream_log_formats(&cli_args, &env_vars, &config),
+                StreamLogFormats {
+                    stdin: LogFormat::Raw,
+                    stdout: LogFormat::Raw,
+                    stderr: LogFormat::Lines,
+                }
+            );
+        }
+
+        #[test]
+        fn from_config() {
+            let cli_args = CliArgs::default();
+            let env_vars = EnvVars::default();
+            let config = Config {
+                stdin_log_format: Some(LogFormat::Jsonl),
                 ..Default::default()
             };
+
+            assert_eq!(
+                get_stream_log_formats(&cli_args, &env_vars, &config),
+                StreamLogFormats {
+                    stdin: LogFormat::Jsonl,
+                    stdout: LogFormat::Raw,
+                    stderr: LogFormat::Raw,
+                }
+            );
+        }
+
+        #[test]
+        fn default() {
+            let cli_args = CliArgs::default();
             let env_vars = EnvVars::default();
+            let config = Config::default();
 
             assert_eq!(
-                get_config(&cli_args, &env_vars).unwrap().buffer_size,
-                Some(1024)
+                get_stream_log_formats(&cli_args, &env_vars, &config),
+                StreamLogFormats::default()
             );
         }
 
         #[test]
-        fn from_cli_args_nonexistent_file() {
+        fn falls_back_to_log_format() {
             let cli_args = CliArgs {
-                conf: Some(PathBuf::from("/nonexistent/config.toml")),
+                log_format: Some(LogFormat::Hex),
+                stdin_log_format: Some(LogFormat::Raw),
                 ..Default::default()
             };
             let env_vars = EnvVars::default();
+            let config = Config::default();
 
-            assert!(
-                get_config(&cli_args, &env_vars)
-                    .unwrap_err()
-                    .to_string()
-                    .contains("Error reading configuration file")
+            assert_eq!(
+                get_stream_log_formats(&cli_args, &env_vars, &config),
+                StreamLogFormats {
+                    stdin: LogFormat::Raw,
+                    stdout: LogFormat::Hex,
+                    stderr: LogFormat::Hex,
+                }
             );
         }
 
         #[test]
-        fn from_cli_args_invalid_toml() {
-            let tmp_dir = TempDir::new().unwrap();
-            let config_path = tmp_dir.path().join("config.toml");
-            fs::write(&config_path, "invalid toml").unwrap();
-
+        fn precedence_cli_log_format_over_env_vars() {
             let cli_args = CliArgs {
-                conf: Some(config_path),
+                log_format: Some(LogFormat::Hex),
                 ..Default::default()
             };
-            let env_vars = EnvVars::default();
+            let env_vars = EnvVars {
+                stdout_log_format: Some(LogFormat::Jsonl),
+                ..Default::default()
+            };
+            let config = Config::default();
 
-            assert!(
-                get_config(&cli_args, &env_vars)
-                    .unwrap_err()
-                    .to_string()
-                    .contains("Error parsing TOML configuration")
+            assert_eq!(
+                get_stream_log_formats(&cli_args, &env_vars, &config).stdout,
+                LogFormat::Hex
             );
         }
 
         #[test]
-        fn from_env_vars() {
-            let tmp_dir = TempDir::new().unwrap();
-            let config_path = tmp_dir.path().join("config.toml");
-            fs::write(&config_path, "buffer_size = 2048").unwrap();
-
+        fn precedence_env_log_format_over_config() {
             let cli_args = CliArgs::default();
             let env_vars = EnvVars {
-                conf: Some(config_path),
+                log_format: Some(LogFormat::Timestamped),
+                ..Default::default()
+            };
+            let config = Config {
+                stdout_log_format: Some(LogFormat::Jsonl),
                 ..Default::default()
             };
 
             assert_eq!(
-                get_config(&cli_args, &env_vars).unwrap().buffer_size,
-                Some(2048)
+                get_stream_log_formats(&cli_args, &env_vars, &config).stdout,
+                LogFormat::Timestamped
             );
         }
+    }
+
+    mod get_compression {
+        use super::*;
 
         #[test]
-        fn from_env_vars_nonexistent_file() {
-            let cli_args = CliArgs::default();
-            let env_vars = EnvVars {
-                conf: Some(PathBuf::from("/nonexistent/config.toml")),
+        fn cli_args() {
+            let cli_args = CliArgs {
+                compression: Some(Compression::Zstd),
                 ..Default::default()
             };
+            let config = Config::default();
 
-            assert!(
-                get_config(&cli_args, &env_vars)
-                    .unwrap_err()
-                    .to_string()
-                    .contains("Error reading configuration file")
-            );
+            assert_eq!(get_compression(&cli_args, &config), Compression::Zstd);
         }
 
         #[test]
-        fn from_env_vars_invalid_toml() {
-            let tmp_dir = TempDir::new().unwrap();
-            let config_path = tmp_dir.path().join("config.toml");
-            fs::write(&config_path, "invalid toml").unwrap();
+        fn from_config() {
+            let cli_args = CliArgs::default();
+            let config = Config {
+                compression: Some(Compression::Gzip),
+                ..Default::default()
+            };
+
+            assert_eq!(get_compression(&cli_args, &config), Compression::Gzip);
+        }
 
+        #[test]
+        fn default() {
             let cli_args = CliArgs::default();
-            let env_vars = EnvVars {
-                conf: Some(config_path),
+            let config = Config::default();
+
+            assert_eq!(get_compression(&cli_args, &config), Compression::Auto);
+        }
+
+        #[test]
+        fn precedence_cli_args_over_config() {
+            let cli_args = CliArgs {
+                compression: Some(Compression::None),
+                ..Default::default()
+            };
+            let config = Config {
+                compression: Some(Compression::Gzip),
                 ..Default::default()
             };
 
-            assert!(
-                get_config(&cli_args, &env_vars)
-                    .unwrap_err()
-                    .to_string()
-                    .contains("Error parsing TOML configuration")
-            );
+            assert_eq!(get_compression(&cli_args, &config), Compression::None);
         }
+    }
+
+    mod get_max_log_size {
+        use super::*;
 
         #[test]
-        fn from_home_dir() {
-            let tmp_dir = TempDir::new().unwrap();
-            let config_path = tmp_dir.path().join(".fdinterceptrc.toml");
-            fs::write(&config_path, "buffer_size = 4096").unwrap();
+        fn cli_args() {
+            let cli_args = CliArgs {
+                max_log_size: NonZeroU64::new(1024),
+                ..Default::default()
+            };
+            let config = Config::default();
 
+            assert_eq!(get_max_log_size(&cli_args, &config), NonZeroU64::new(1024));
+        }
+
+        #[test]
+        fn from_config() {
             let cli_args = CliArgs::default();
-            let env_vars = EnvVars::default();
+            let config = Config {
+                max_log_size: NonZeroU64::new(2048),
+                ..Default::default()
+            };
 
-            temp_env::with_vars(
-                vec![("HOME", Some(tmp_dir.path().to_str().unwrap()))],
-                || {
-                    assert_eq!(
-                        get_config(&cli_args, &env_vars).unwrap().buffer_size,
-                        Some(4096)
-                    );
-                },
-            );
+            assert_eq!(get_max_log_size(&cli_args, &config), NonZeroU64::new(2048));
         }
 
         #[test]
-        fn from_home_dir_invalid_toml() {
-            let tmp_dir = TempDir::new().unwrap();
-            let config_path = tmp_dir.path().join(".fdinterceptrc.toml");
-            fs::write(&config_path, "invalid toml").unwrap();
-
+        fn default() {
             let cli_args = CliArgs::default();
-            let env_vars = EnvVars::default();
+            let config = Config::default();
 
-            temp_env::with_vars(
-                vec![("HOME", Some(tmp_dir.path().to_str().unwrap()))],
-                || {
-                    assert!(
-                        get_config(&cli_args, &env_vars)
-                            .unwrap_err()
-                            .to_string()
-                            .contains("Error parsing TOML configuration")
-                    );
-                },
-            );
+            assert_eq!(get_max_log_size(&cli_args, &config), None);
         }
 
         #[test]
-        fn if_home_dir_not_found_move_on() {
-            let tmp_dir = TempDir::new().unwrap();
+        fn precedence_cli_args_over_config() {
+            let cli_args = CliArgs {
+                max_log_size: NonZeroU64::new(1024),
+                ..Default::default()
+            };
+            let config = Config {
+                max_log_size: NonZeroU64::new(2048),
+                ..Default::default()
+            };
 
-            let cli_args = CliArgs::default();
-            let env_vars = EnvVars::default();
+            assert_eq!(get_max_log_size(&cli_args, &config), NonZeroU64::new(1024));
+        }
+    }
 
-            temp_env::with_vars(
-                vec![("HOME", Some(tmp_dir.path().to_str().unwrap()))],
-                || {
-                    assert_eq!(get_config(&cli_args, &env_vars).unwrap(), Config::default());
-                },
+    mod get_rotate_interval {
+        use super::*;
+
+        #[test]
+        fn cli_args() {
+            let cli_args = CliArgs {
+                rotate_interval: Some(RotationInterval::Hourly),
+                ..Default::default()
+            };
+            let config = Config::default();
+
+            assert_eq!(
+                get_rotate_interval(&cli_args, &config),
+                Some(RotationInterval::Hourly)
             );
         }
 
         #[test]
-        fn from_xdg_config_home() {
-            let tmp_dir = TempDir::new().unwrap();
-            fs::create_dir_all(tmp_dir.path().join("fdintercept")).unwrap();
-            let config_path = tmp_dir.path().join("fdintercept/rc.toml");
-            fs::write(&config_path, "buffer_size = 8192").unwrap();
-
+        fn from_config() {
             let cli_args = CliArgs::default();
-            let env_vars = EnvVars::default();
+            let config = Config {
+                rotate_interval: Some(RotationInterval::Daily),
+                ..Default::default()
+            };
 
-            temp_env::with_vars(
-                vec![
-                    ("HOME", None),
-                    ("XDG_CONFIG_HOME", Some(tmp_dir.path().to_str().unwrap())),
-                ],
-                || {
-                    assert_eq!(
-                        get_config(&cli_args, &env_vars).unwrap().buffer_size,
-                        Some(8192)
-                    );
-                },
+            assert_eq!(
+                get_rotate_interval(&cli_args, &config),
+                Some(RotationInterval::Daily)
             );
         }
 
         #[test]
-        fn from_xdg_config_home_invalid_toml() {
-            let tmp_dir = TempDir::new().unwrap();
-            fs::create_dir_all(tmp_dir.path().join("fdintercept")).unwrap();
-            let config_path = tmp_dir.path().join("fdintercept/rc.toml");
-            fs::write(&config_path, "invalid toml").unwrap();
-
+        fn default() {
             let cli_args = CliArgs::default();
-            let env_vars = EnvVars::default();
+            let config = Config::default();
 
-            temp_env::with_vars(
-                vec![
-                    ("HOME", None),
-                    ("XDG_CONFIG_HOME", Some(tmp_dir.path().to_str().unwrap())),
-                ],
-                || {
-                    assert!(
-                        get_config(&cli_args, &env_vars)
-                            .unwrap_err()
-                            .to_string()
-                            .contains("Error parsing TOML configuration")
-                    );
-                },
-            );
+            assert_eq!(get_rotate_interval(&cli_args, &config), None);
         }
 
         #[test]
-        fn if_xdg_config_home_dir_not_found_move_on() {
-            let tmp_dir = TempDir::new().unwrap();
+        fn precedence_cli_args_over_config() {
+            let cli_args = CliArgs {
+                rotate_interval: Some(RotationInterval::Hourly),
+                ..Default::default()
+            };
+            let config = Config {
+                rotate_interval: Some(RotationInterval::Daily),
+                ..Default::default()
+            };
 
-            let cli_args = CliArgs::default();
-            let env_vars = EnvVars::default();
+            assert_eq!(
+                get_rotate_interval(&cli_args, &config),
+                Some(RotationInterval::Hourly)
+            );
+        }
+    }
 
-            temp_env::with_vars(
-                vec![
-                    ("HOME", None),
-                    ("XDG_CONFIG_HOME", Some(tmp_dir.path().to_str().unwrap())),
-                ],
-                || {
-                    assert_eq!(get_config(&cli_args, &env_vars).unwrap(), Config::default());
-                },
+    mod get_reopen_signal {
+        use super::*;
+
+        #[test]
+        fn cli_args() {
+            let cli_args = CliArgs {
+                reopen_signal: Some(ReopenSignal::Hup),
+                ..Default::default()
+            };
+            let config = Config::default();
+
+            assert_eq!(
+                get_reopen_signal(&cli_args, &config),
+                Some(ReopenSignal::Hup)
             );
         }
 
         #[test]
-        fn no_config_found() {
+        fn from_config() {
             let cli_args = CliArgs::default();
-            let env_vars = EnvVars::default();
+            let config = Config {
+                reopen_signal: Some(ReopenSignal::Usr1),
+                ..Default::default()
+            };
 
-            temp_env::with_vars(
-                vec![("HOME", None::<&str>), ("XDG_CONFIG_HOME", None::<&str>)],
-                || {
-                    assert_eq!(get_config(&cli_args, &env_vars).unwrap(), Config::default());
-                },
+            assert_eq!(
+                get_reopen_signal(&cli_args, &config),
+                Some(ReopenSignal::Usr1)
             );
         }
-    }
-
-    mod get_use_defaults {
-        use super::*;
 
         #[test]
-        fn no_logs() {
+        fn default() {
             let cli_args = CliArgs::default();
             let config = Config::default();
 
-            assert!(get_use_defaults(&cli_args, &config));
+            assert_eq!(get_reopen_signal(&cli_args, &config), None);
         }
 
         #[test]
-        fn cli_stdin_log() {
+        fn precedence_cli_args_over_config() {
             let cli_args = CliArgs {
-                stdin_log: Some(PathBuf::from("stdin.log")),
+                reopen_signal: Some(ReopenSignal::Usr2),
+                ..Default::default()
+            };
+            let config = Config {
+                reopen_signal: Some(ReopenSignal::Usr1),
                 ..Default::default()
             };
-            let config = Config::default();
 
-            assert!(!get_use_defaults(&cli_args, &config));
+            assert_eq!(
+                get_reopen_signal(&cli_args, &config),
+                Some(ReopenSignal::Usr2)
+            );
         }
+    }
+
+    mod get_max_log_files {
+        use super::*;
 
         #[test]
-        fn cli_stdout_log() {
+        fn cli_args() {
             let cli_args = CliArgs {
-                stdout_log: Some(PathBuf::from("stdout.log")),
+                max_log_files: Some(2),
                 ..Default::default()
             };
             let config = Config::default();
 
-            assert!(!get_use_defaults(&cli_args, &config));
+            assert_eq!(get_max_log_files(&cli_args, &config), 2);
         }
 
         #[test]
-        fn cli_stderr_log() {
-            let cli_args = CliArgs {
-                stderr_log: Some(PathBuf::from("stderr.log")),
+        fn from_config() {
+            let cli_args = CliArgs::default();
+            let config = Config {
+                max_log_files: Some(0),
                 ..Default::default()
             };
-            let config = Config::default();
 
-            assert!(!get_use_defaults(&cli_args, &config));
+            assert_eq!(get_max_log_files(&cli_args, &config), 0);
         }
 
         #[test]
-        fn config_stdin_log() {
+        fn default() {
             let cli_args = CliArgs::default();
+            let config = Config::default();
+
+            assert_eq!(get_max_log_files(&cli_args, &config), 5);
+        }
+
+        #[test]
+        fn precedence_cli_args_over_config() {
+            let cli_args = CliArgs {
+                max_log_files: Some(2),
+                ..Default::default()
+            };
             let config = Config {
-                stdin_log: Some(PathBuf::from("stdin.log")),
+                max_log_files: Some(3),
                 ..Default::default()
             };
 
-            assert!(!get_use_defaults(&cli_args, &config));
+            assert_eq!(get_max_log_files(&cli_args, &config), 2);
         }
+    }
+
+    mod get_summary {
+        use super::*;
 
         #[test]
-        fn config_stdout_log() {
-            let cli_args = CliArgs::default();
+        fn default() {
+            assert!(!get_summary(&CliArgs::default(), &Config::default()));
+        }
+
+        #[test]
+        fn from_config() {
             let config = Config {
-                stdout_log: Some(PathBuf::from("stdout.log")),
+                summary: Some(true),
                 ..Default::default()
             };
 
-            assert!(!get_use_defaults(&cli_args, &config));
+            assert!(get_summary(&CliArgs::default(), &config));
         }
 
         #[test]
-        fn config_stderr_log() {
-            let cli_args = CliArgs::default();
+        fn cli_flag_overrides_config() {
+            let cli_args = CliArgs {
+                summary: true,
+                ..Default::default()
+            };
             let config = Config {
-                stderr_log: Some(PathBuf::from("stderr.log")),
+                summary: Some(false),
                 ..Default::default()
             };
 
-            assert!(!get_use_defaults(&cli_args, &config));
+            assert!(get_summary(&cli_args, &config));
         }
     }
 
-    mod get_log_name {
+    mod get_pty {
         use super::*;
 
         #[test]
-        fn from_cli_args() {
-            let cli_args = CliArgs {
-                stdin_log: Some(PathBuf::from("cli.log")),
+        fn default() {
+            assert!(!get_pty(&CliArgs::default(), &Config::default()));
+        }
+
+        #[test]
+        fn from_config() {
+            let config = Config {
+                pty: Some(true),
                 ..Default::default()
             };
-            let config = Config::default();
 
-            assert_eq!(
-                get_log_name(LogFd::Stdin, &cli_args, &config, true, "default.log"),
-                Some(PathBuf::from("cli.log"))
-            );
+            assert!(get_pty(&CliArgs::default(), &config));
         }
 
         #[test]
-        fn from_config() {
-            let cli_args = CliArgs::default();
+        fn cli_flag_overrides_config() {
+            let cli_args = CliArgs {
+                pty: true,
+                ..Default::default()
+            };
             let config = Config {
-                stdin_log: Some(PathBuf::from("config.log")),
+                pty: Some(false),
                 ..Default::default()
             };
 
+            assert!(get_pty(&cli_args, &config));
+        }
+    }
+
+    mod fd_arg {
+        use super::*;
+
+        #[test]
+        fn without_log() {
             assert_eq!(
-                get_log_name(LogFd::Stdin, &cli_args, &config, true, "default.log"),
-                Some(PathBuf::from("config.log"))
+                "3=out".parse(),
+                Ok(FdArg {
+                    fd: 3,
+                    direction: FdDirection::Out,
+                    log: None,
+                })
             );
         }
 
         #[test]
-        fn from_default() {
-            let cli_args = CliArgs::default();
-            let config = Config::default();
-
+        fn with_log() {
             assert_eq!(
-                get_log_name(LogFd::Stdin, &cli_args, &config, true, "default.log"),
-                Some(PathBuf::from("default.log"))
+                "4=in:logs/fd4:input.log".parse(),
+                Ok(FdArg {
+                    fd: 4,
+                    direction: FdDirection::In,
+                    log: Some(PathBuf::from("logs/fd4:input.log")),
+                })
             );
         }
 
         #[test]
-        fn no_default_returns_none() {
-            let cli_args = CliArgs::default();
-            let config = Config::default();
+        fn missing_direction() {
+            assert_eq!("3".parse::<FdArg>(), Err(FdParseError::MissingDirection));
+        }
 
+        #[test]
+        fn standard_stream() {
             assert_eq!(
-                get_log_name(LogFd::Stdin, &cli_args, &config, false, "default.log"),
-                None
+                "1=out".parse::<FdArg>(),
+                Err(FdParseError::InvalidNumber("1".to_string()))
             );
         }
 
         #[test]
-        fn cli_args_take_precedence_over_config() {
-            let cli_args = CliArgs {
-                stdin_log: Some(PathBuf::from("cli.log")),
-                ..Default::default()
-            };
-            let config = Config {
-                stdout_log: Some(PathBuf::from("config.log")),
-                ..Default::default()
-            };
-
+        fn invalid_number() {
             assert_eq!(
-                get_log_name(LogFd::Stdin, &cli_args, &config, true, "default.log"),
-                Some(PathBuf::from("cli.log"))
+                "three=out".parse::<FdArg>(),
+                Err(FdParseError::InvalidNumber("three".to_string()))
             );
         }
 
         #[test]
-        fn test_all_log_fd_variants() {
-            let cli_args = CliArgs {
-                stdin_log: Some(PathBuf::from("stdin.log")),
-                stdout_log: Some(PathBuf::from("stdout.log")),
-                stderr_log: Some(PathBuf::from("stderr.log")),
-                ..Default::default()
-            };
-            let config = Config::default();
+        fn invalid_direction() {
+            assert_eq!(
+                "3=both".parse::<FdArg>(),
+                Err(FdParseError::InvalidDirection("both".to_string()))
+            );
+        }
+    }
+
+    mod get_extra_fds {
+        use super::*;
 
+        #[test]
+        fn default() {
             assert_eq!(
-                get_log_name(LogFd::Stdin, &cli_args, &config, true, "default.log"),
-                Some(PathBuf::from("stdin.log"))
+                get_extra_fds(&CliArgs::default(), &Config::default()).unwrap(),
+                vec![]
             );
+        }
+
+        #[test]
+        fn from_config() {
+            let config = parse_config_contents(
+                r#"
+                [fd.4]
+                direction = "in"
+
+                [fd.3]
+                direction = "out"
+                log = "status.log"
+                "#,
+            )
+            .unwrap();
+
             assert_eq!(
-                get_log_name(LogFd::Stdout, &cli_args, &config, true, "default.log"),
-                Some(PathBuf::from("stdout.log"))
+                get_extra_fds(&CliArgs::default(), &config).unwrap(),
+                vec![
+                    ExtraFd::new(3, FdDirection::Out, PathBuf::from("status.log")),
+                    ExtraFd::new(4, FdDirection::In, PathBuf::from("fd4.log")),
+                ]
             );
+        }
+
+        #[test]
+        fn cli_args_override_config() {
+            let cli_args =
+                CliArgs::parse_from(["fdintercept", "--fd", "3=in:cli.log", "--fd", "5=out"]);
+            let config = parse_config_contents(
+                r#"
+                [fd.3]
+                direction = "out"
+                log = "config.log"
+
+                [fd.4]
+                direction = "out"
+                "#,
+            )
+            .unwrap();
+
             assert_eq!(
-                get_log_name(LogFd::Stderr, &cli_args, &config, true, "default.log"),
-                Some(PathBuf::from("stderr.log"))
+                get_extra_fds(&cli_args, &config).unwrap(),
+                vec![
+                    ExtraFd::new(3, FdDirection::In, PathBuf::from("cli.log")),
+                    ExtraFd::new(4, FdDirection::Out, PathBuf::from("fd4.log")),
+                    ExtraFd::new(5, FdDirection::Out, PathBuf::from("fd5.log")),
+                ]
             );
         }
+
+        #[test]
+        fn invalid_config_key() {
+            let config = parse_config_contents(
+                r#"
+                [fd.stdout]
+                direction = "out"
+                "#,
+            )
+            .unwrap();
+
+            assert!(get_extra_fds(&CliArgs::default(), &config).is_err());
+        }
     }
 
-    mod get_recreate_logs {
+    mod get_stream_modes {
         use super::*;
 
-        #[test]
-        fn cli_args_true() {
-            let cli_args = CliArgs {
-                recreate_logs: true,
-                ..Default::default()
-            };
-            let env_vars = EnvVars::default();
-            let config = Config::default();
+        const INTERCEPT_STDOUT: StreamModes = StreamModes {
+            stdin: StreamMode::Inherit,
+            stdout: StreamMode::Intercept,
+            stderr: StreamMode::Inherit,
+        };
 
-            assert!(get_recreate_logs(&cli_args, &env_vars, &config));
+        #[test]
+        fn default() {
+            assert_eq!(
+                get_stream_modes(
+                    &CliArgs::default(),
+                    &Config::default(),
+                    false,
+                    &Mode::Run,
+                    false,
+                    INTERCEPT_STDOUT
+                )
+                .unwrap(),
+                INTERCEPT_STDOUT
+            );
         }
 
         #[test]
-        fn from_env_vars_true() {
-            let cli_args = CliArgs::default();
-            let env_vars = EnvVars {
-                recreate_logs: Some(true),
+        fn from_config() {
+            let config = Config {
+                stdin_mode: Some(StreamMode::Null),
+                stdout_mode: Some(StreamMode::Inherit),
                 ..Default::default()
             };
-            let config = Config::default();
 
-            assert!(get_recreate_logs(&cli_args, &env_vars, &config));
+            assert_eq!(
+                get_stream_modes(
+                    &CliArgs::default(),
+                    &config,
+                    false,
+                    &Mode::Run,
+                    false,
+                    INTERCEPT_STDOUT
+                )
+                .unwrap(),
+                StreamModes {
+                    stdin: StreamMode::Null,
+                    stdout: StreamMode::Inherit,
+                    stderr: StreamMode::Inherit,
+                }
+            );
         }
 
         #[test]
-        fn from_config_true() {
-            let cli_args = CliArgs::default();
-            let env_vars = EnvVars::default();
+        fn cli_args_override_config() {
+            let cli_args = CliArgs {
+                stderr_mode: Some(StreamMode::Intercept),
+                ..Default::default()
+            };
             let config = Config {
-                recreate_logs: Some(true),
+                stderr_mode: Some(StreamMode::Null),
                 ..Default::default()
             };
 
-            assert!(get_recreate_logs(&cli_args, &env_vars, &config));
+            assert_eq!(
+                get_stream_modes(
+                    &cli_args,
+                    &config,
+                    false,
+                    &Mode::Run,
+                    false,
+                    INTERCEPT_STDOUT
+                )
+                .unwrap()
+                .stderr,
+                StreamMode::Intercept
+            );
         }
 
         #[test]
-        fn default_false() {
-            let cli_args = CliArgs::default();
-            let env_vars = EnvVars::default();
-            let config = Config::default();
-
-            assert!(!get_recreate_logs(&cli_args, &env_vars, &config));
+        fn pty() {
+            assert_eq!(
+                get_stream_modes(
+                    &CliArgs::default(),
+                    &Config::default(),
+                    true,
+                    &Mode::Run,
+                    false,
+                    INTERCEPT_STDOUT
+                )
+                .unwrap(),
+                StreamModes {
+                    stdin: StreamMode::Intercept,
+                    stdout: StreamMode::Intercept,
+                    stderr: StreamMode::Intercept,
+                }
+            );
         }
 
         #[test]
-        fn precedence_cli_args_over_env_vars() {
+        fn pty_with_stream_not_intercepted() {
             let cli_args = CliArgs {
-                recreate_logs: true,
-                ..Default::default()
-            };
-            let env_vars = EnvVars {
-                recreate_logs: Some(false),
+                stdout_mode: Some(StreamMode::Inherit),
                 ..Default::default()
             };
-            let config = Config::default();
 
-            assert!(get_recreate_logs(&cli_args, &env_vars, &config));
+            assert!(
+                get_stream_modes(
+                    &cli_args,
+                    &Config::default(),
+                    true,
+                    &Mode::Run,
+                    false,
+                    INTERCEPT_STDOUT
+                )
+                .is_err()
+            );
         }
 
         #[test]
-        fn precedence_env_vars_over_config() {
-            let cli_args = CliArgs::default();
-            let env_vars = EnvVars {
-                recreate_logs: Some(true),
-                ..Default::default()
+        fn replay() {
+            let mode = Mode::Replay {
+                input: PathBuf::from("stdin.log"),
+                timing: None,
+                speed: 1.0,
             };
-            let config = Config {
-                recreate_logs: Some(false),
+
+            assert_eq!(
+                get_stream_modes(
+                    &CliArgs::default(),
+                    &Config::default(),
+                    false,
+                    &mode,
+                    false,
+                    INTERCEPT_STDOUT
+                )
+                .unwrap()
+                .stdin,
+                StreamMode::Intercept
+            );
+            let cli_args = CliArgs {
+                stdin_mode: Some(StreamMode::Null),
                 ..Default::default()
             };
-
-            assert!(get_recreate_logs(&cli_args, &env_vars, &config));
+            assert!(
+                get_stream_modes(
+                    &cli_args,
+                    &Config::default(),
+                    false,
+                    &mode,
+                    false,
+                    INTERCEPT_STDOUT
+                )
+                .is_err()
+            );
         }
 
         #[test]
-        fn precedence_cli_args_over_config() {
-            let cli_args = CliArgs {
-                recreate_logs: true,
-                ..Default::default()
-            };
-            let env_vars = EnvVars::default();
+        fn stdin_source() {
+            assert_eq!(
+                get_stream_modes(
+                    &CliArgs::default(),
+                    &Config::default(),
+                    false,
+                    &Mode::Run,
+                    true,
+                    INTERCEPT_STDOUT
+                )
+                .unwrap()
+                .stdin,
+                StreamMode::Intercept
+            );
             let config = Config {
-                recreate_logs: Some(false),
+                stdin_mode: Some(StreamMode::Inherit),
                 ..Default::default()
             };
-
-            assert!(get_recreate_logs(&cli_args, &env_vars, &config));
+            assert!(
+                get_stream_modes(
+                    &CliArgs::default(),
+                    &config,
+                    false,
+                    &Mode::Run,
+                    true,
+                    INTERCEPT_STDOUT
+                )
+                .is_err()
+            );
         }
     }
 
@@ -1,28 +1,58 @@
 //! Signal handling functionality for managing child process termination.
 //!
 //! This module provides functionality for handling Unix signals (`SIGHUP`, `SIGINT`, `SIGTERM`) and
-//! gracefully terminating child processes when these signals are received.
+//! gracefully terminating child processes when these signals are received, for forwarding window
+//! size changes when `SIGWINCH` is received, and for reopening the log files when the configured
+//! reopen signal is received.
 
 use crate::process::{self, ChildGuard};
+use crate::resize::Resizer;
+use crate::rotate::LogReopener;
+use crate::terminal;
 use anyhow::Result;
 use nix::sys::signal::Signal;
-use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
+use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM, SIGWINCH};
 use signal_hook::iterator::SignalsInfo;
 use std::os::fd::OwnedFd;
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
+/// Exits right away if a signal that terminates fdintercept is already pending.
+///
+/// # Arguments
+///
+/// * `signals` - Signal iterator providing incoming Unix signals.
+/// * `maybe_reopen_signum` - Optional signal that makes the log files be reopened, which doesn't
+///   terminate fdintercept.
+pub fn exit_if_terminated(signals: &mut SignalsInfo, maybe_reopen_signum: Option<i32>) {
+    if let Some(signum) = signals
+        .pending()
+        .find(|&signum| signum != SIGWINCH && Some(signum) != maybe_reopen_signum)
+    {
+        std::process::exit(128 + signum);
+    }
+}
+
 /// Processes incoming Unix signals and handles child process termination.
 ///
 /// This function waits for signals (`SIGHUP`, `SIGINT`, or `SIGTERM`) and attempts to gracefully
 /// terminate the child process when one is received. After signal processing, it notifies the main
 /// thread through a file descriptor.
 ///
+/// If a reopen signal is given, the log files are reopened every time it is received, and waiting
+/// for the other signals goes on. The reopen signal takes precedence, so if it is `SIGHUP`, then
+/// `SIGHUP` no longer terminates the child process.
+///
+/// Likewise, the window size is forwarded to the child process every time `SIGWINCH` is received.
+///
 /// # Arguments
 ///
 /// * `signals` - Signal iterator providing incoming Unix signals.
 /// * `mutex_child_guard` - Thread-safe reference to the child process guard.
 /// * `signal_tx` - File descriptor for notifying the main thread of signal processing completion.
+/// * `maybe_reopen` - Optional signal that makes the log files be reopened, and the reopener of
+///   the log files.
+/// * `resizer` - Forwards the window size to the child process.
 ///
 /// # Returns
 ///
@@ -43,11 +73,34 @@ pub fn process_signals(
     mut signals: SignalsInfo,
     mutex_child_guard: Arc<Mutex<ChildGuard>>,
     signal_tx: OwnedFd,
+    maybe_reopen: Option<(i32, LogReopener)>,
+    resizer: Resizer,
 ) -> Result<()> {
+    let signum = loop {
+        // unwrap: Safe because `signals.forever()` is never empty.
+        let signum = signals.forever().next().unwrap();
+        match &maybe_reopen {
+            Some((reopen_signum, reopener)) if signum == *reopen_signum => {
+                reopener.reopen_all_reporting();
+            }
+            // Failing to forward the window size must not bring the session down. It only leaves
+            // the child process at the previous one.
+            _ if signum == SIGWINCH => {
+                if let Err(e) = resizer.resize() {
+                    eprintln!("Error forwarding window size: {e}");
+                }
+            }
+            _ => break signum,
+        }
+    };
+
+    // The user gets their terminal back right away, instead of after the grace period of the
+    // child process.
+    terminal::restore_mode();
+
     // If we got a SIGCHLD, there's no need to run `process::kill_child_process_with_grace_period`
     // since the child process is already dead.
-    // unwrap: Safe because `signals.forever()` is never empty.
-    if let signum @ (SIGHUP | SIGINT | SIGTERM) = signals.forever().next().unwrap() {
+    if let SIGHUP | SIGINT | SIGTERM = signum {
         process::kill_child_process_with_grace_period(
             // unwrap: Safe because if this thread is running, the main thread is waiting for it to
             // finish, so it can't be holding this lock.
@@ -72,12 +125,24 @@ mod tests {
 
     mod process_signals {
         use super::*;
+        use crate::log::Clock;
         use nix::unistd::pipe;
+        use signal_hook::consts::SIGUSR1;
         use signal_hook::iterator::Signals;
+        use std::io::Write;
         use std::os::fd::AsFd;
         use std::os::unix::process::ExitStatusExt;
         use std::process::Command;
 
+        fn resizer(child_guard: &Arc<Mutex<ChildGuard>>) -> Resizer {
+            Resizer::new(
+                child_guard.lock().unwrap().child.id(),
+                None,
+                Clock::new(),
+                Vec::new(),
+            )
+        }
+
         #[test]
         fn process_signal() {
             let (signal_rx, signal_tx) = pipe().unwrap();
@@ -93,7 +158,14 @@ mod tests {
             )
             .unwrap();
 
-            process_signals(signals, child_guard.clone(), signal_tx).unwrap();
+            process_signals(
+                signals,
+                child_guard.clone(),
+                signal_tx,
+                None,
+                resizer(&child_guard),
+            )
+            .unwrap();
 
             let status = child_guard.lock().unwrap().child.wait().unwrap();
             assert!(!status.success());
@@ -120,11 +192,105 @@ mod tests {
 
             drop(signal_rx);
 
-            process_signals(signals, child_guard.clone(), signal_tx).unwrap();
+            process_signals(
+                signals,
+                child_guard.clone(),
+                signal_tx,
+                None,
+                resizer(&child_guard),
+            )
+            .unwrap();
 
             let status = child_guard.lock().unwrap().child.wait().unwrap();
             assert!(!status.success());
             assert_eq!(status.signal().unwrap(), Signal::SIGTERM as i32);
         }
+
+        #[test]
+        fn process_resize_signal() {
+            let (_signal_rx, signal_tx) = pipe().unwrap();
+
+            let child_guard = Arc::new(Mutex::new(ChildGuard {
+                child: Command::new("sleep").arg("30").spawn().unwrap(),
+            }));
+
+            let signals = Signals::new([SIGTERM, SIGWINCH]).unwrap();
+            let pid = nix::unistd::Pid::from_raw(i32::try_from(std::process::id()).unwrap());
+            // Send the signals from another thread, one at a time, so that they are received in
+            // order.
+            let sender = std::thread::spawn(move || {
+                nix::sys::signal::kill(pid, Signal::SIGWINCH).unwrap();
+                std::thread::sleep(Duration::from_millis(100));
+                nix::sys::signal::kill(pid, Signal::SIGTERM).unwrap();
+            });
+
+            process_signals(
+                signals,
+                child_guard.clone(),
+                signal_tx,
+                None,
+                resizer(&child_guard),
+            )
+            .unwrap();
+            sender.join().unwrap();
+
+            // The child process ignores SIGWINCH, so it was SIGTERM that ended it.
+            let status = child_guard.lock().unwrap().child.wait().unwrap();
+            assert_eq!(status.signal().unwrap(), Signal::SIGTERM as i32);
+        }
+
+        #[test]
+        fn process_reopen_signal() {
+            let tmp_dir = tempfile::TempDir::new().unwrap();
+            let log_path = tmp_dir.path().join("stdout.log");
+            let moved_log_path = tmp_dir.path().join("stdout.log.1");
+            let (_signal_rx, signal_tx) = pipe().unwrap();
+
+            let reopener = LogReopener::default();
+            let mut log_file = crate::fd::create_log_file(
+                Some(&log_path),
+                None,
+                false,
+                crate::settings::Compression::Auto,
+                None,
+                Some(&reopener),
+            )
+            .unwrap()
+            .unwrap();
+            log_file.write_all(b"old").unwrap();
+            std::fs::rename(&log_path, &moved_log_path).unwrap();
+
+            let child_guard = Arc::new(Mutex::new(ChildGuard {
+                child: Command::new("sleep").arg("30").spawn().unwrap(),
+            }));
+
+            let signals = Signals::new([SIGTERM, SIGUSR1]).unwrap();
+            let pid = nix::unistd::Pid::from_raw(i32::try_from(std::process::id()).unwrap());
+            // Send the signals from another thread, one at a time, so that they are received in
+            // order.
+            let sender = std::thread::spawn(move || {
+                nix::sys::signal::kill(pid, Signal::SIGUSR1).unwrap();
+                std::thread::sleep(Duration::from_millis(100));
+                nix::sys::signal::kill(pid, Signal::SIGTERM).unwrap();
+            });
+
+            process_signals(
+                signals,
+                child_guard.clone(),
+                signal_tx,
+                Some((SIGUSR1, reopener)),
+                resizer(&child_guard),
+            )
+            .unwrap();
+            sender.join().unwrap();
+
+            log_file.write_all(b"new").unwrap();
+            drop(log_file);
+            assert_eq!(std::fs::read_to_string(&moved_log_path).unwrap(), "old");
+            assert_eq!(std::fs::read_to_string(&log_path).unwrap(), "new");
+
+            let status = child_guard.lock().unwrap().child.wait().unwrap();
+            assert_eq!(status.signal().unwrap(), Signal::SIGTERM as i32);
+        }
     }
 }
@@ -0,0 +1,253 @@
+//! Statistics of intercepted streams.
+//!
+//! This module provides counters of how much data flowed through each stream, and in how many
+//! chunks and read calls, so that questions such as "did the target write anything to stderr?"
+//! can be answered without opening the logs. The counters are updated by the threads processing
+//! the streams, and summarized once they have all ended.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, Read};
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, RawFd};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Counters of the data read from a stream.
+#[derive(Debug, Default)]
+pub struct StreamStats {
+    /// Number of bytes read.
+    bytes: AtomicU64,
+    /// Number of read calls that returned data.
+    chunks: AtomicU64,
+    /// Number of read calls, including those that returned no data or failed.
+    read_calls: AtomicU64,
+    /// Size in bytes of the largest chunk read.
+    max_chunk: AtomicU64,
+}
+
+impl StreamStats {
+    /// Records the outcome of a read call.
+    ///
+    /// # Arguments
+    ///
+    /// * `result` - The result of the read call.
+    pub fn record_read(&self, result: &io::Result<usize>) {
+        // The counters are only read once the threads updating them were joined, which already
+        // synchronizes them, so no ordering is needed here.
+        self.read_calls.fetch_add(1, Ordering::Relaxed);
+        if let Ok(bytes_read @ 1..) = *result {
+            let bytes_read = bytes_read as u64;
+            self.bytes.fetch_add(bytes_read, Ordering::Relaxed);
+            self.chunks.fetch_add(1, Ordering::Relaxed);
+            self.max_chunk.fetch_max(bytes_read, Ordering::Relaxed);
+        }
+    }
+
+    /// Summarizes the counters.
+    ///
+    /// # Returns
+    ///
+    /// Returns the values of the counters.
+    pub fn summary(&self) -> StreamSummary {
+        StreamSummary {
+            bytes: self.bytes.load(Ordering::Relaxed),
+            chunks: self.chunks.load(Ordering::Relaxed),
+            read_calls: self.read_calls.load(Ordering::Relaxed),
+            max_chunk: self.max_chunk.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Values of the counters of a stream.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct StreamSummary {
+    /// Number of bytes read.
+    pub bytes: u64,
+    /// Number of read calls that returned data.
+    pub chunks: u64,
+    /// Number of read calls, including those that returned no data or failed.
+    pub read_calls: u64,
+    /// Size in bytes of the largest chunk read.
+    pub max_chunk: u64,
+}
+
+impl fmt::Display for StreamSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} bytes in {} chunks, {} read calls, largest chunk {} bytes",
+            self.bytes, self.chunks, self.read_calls, self.max_chunk
+        )
+    }
+}
+
+/// Summaries of all the intercepted streams.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct StreamsSummary {
+    /// Summary of stdin.
+    pub stdin: StreamSummary,
+    /// Summary of stdout.
+    pub stdout: StreamSummary,
+    /// Summary of stderr.
+    pub stderr: StreamSummary,
+    /// Summary of each additional file descriptor, by its name, e.g., `fd3`.
+    #[serde(flatten)]
+    pub extra_fds: BTreeMap<&'static str, StreamSummary>,
+}
+
+impl fmt::Display for StreamsSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "stdin: {}", self.stdin)?;
+        writeln!(f, "stdout: {}", self.stdout)?;
+        write!(f, "stderr: {}", self.stderr)?;
+        for (name, summary) in &self.extra_fds {
+            write!(f, "\n{name}: {summary}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A reader that records every read call into the counters of its stream.
+pub struct CountingReader<'a, R> {
+    /// The reader being counted.
+    inner: R,
+    /// The counters of the stream.
+    stats: &'a StreamStats,
+}
+
+impl<'a, R> CountingReader<'a, R> {
+    /// Wraps a reader so that its read calls are counted.
+    ///
+    /// # Arguments
+    ///
+    /// * `inner` - The reader to count.
+    /// * `stats` - The counters of the stream.
+    pub const fn new(inner: R, stats: &'a StreamStats) -> Self {
+        Self { inner, stats }
+    }
+}
+
+impl<R: Read> Read for CountingReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let result = self.inner.read(buf);
+        self.stats.record_read(&result);
+        result
+    }
+}
+
+impl<R: AsFd> AsFd for CountingReader<'_, R> {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.inner.as_fd()
+    }
+}
+
+impl<R: AsRawFd> AsRawFd for CountingReader<'_, R> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A reader that returns a fixed sequence of results.
+    struct ScriptedReader(Vec<io::Result<usize>>);
+
+    impl Read for ScriptedReader {
+        fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+            self.0.remove(0)
+        }
+    }
+
+    mod counting_reader {
+        use super::*;
+
+        #[test]
+        fn counts_reads() {
+            let stats = StreamStats::default();
+            let mut reader = CountingReader::new(
+                ScriptedReader(vec![
+                    Ok(4),
+                    Err(io::ErrorKind::WouldBlock.into()),
+                    Ok(10),
+                    Ok(2),
+                    Ok(0),
+                ]),
+                &stats,
+            );
+
+            let mut buffer = [0; 16];
+            for _ in 0..5 {
+                let _ = reader.read(&mut buffer);
+            }
+
+            assert_eq!(
+                stats.summary(),
+                StreamSummary {
+                    bytes: 16,
+                    chunks: 3,
+                    read_calls: 5,
+                    max_chunk: 10,
+                }
+            );
+        }
+
+        #[test]
+        fn no_reads() {
+            assert_eq!(StreamStats::default().summary(), StreamSummary::default());
+        }
+    }
+
+    mod streams_summary {
+        use super::*;
+
+        #[test]
+        fn display() {
+            let summary = StreamsSummary {
+                stdin: StreamSummary {
+                    bytes: 11,
+                    chunks: 2,
+                    read_calls: 4,
+                    max_chunk: 6,
+                },
+                ..Default::default()
+            };
+
+            assert_eq!(
+                summary.to_string(),
+                "stdin: 11 bytes in 2 chunks, 4 read calls, largest chunk 6 bytes\n\
+                 stdout: 0 bytes in 0 chunks, 0 read calls, largest chunk 0 bytes\n\
+                 stderr: 0 bytes in 0 chunks, 0 read calls, largest chunk 0 bytes"
+            );
+        }
+
+        #[test]
+        fn display_extra_fds() {
+            let summary = StreamsSummary {
+                extra_fds: BTreeMap::from([
+                    (
+                        "fd3",
+                        StreamSummary {
+                            bytes: 5,
+                            chunks: 1,
+                            read_calls: 2,
+                            max_chunk: 5,
+                        },
+                    ),
+                    ("fd4", StreamSummary::default()),
+                ]),
+                ..Default::default()
+            };
+
+            assert_eq!(
+                summary.to_string(),
+                "stdin: 0 bytes in 0 chunks, 0 read calls, largest chunk 0 bytes\n\
+                 stdout: 0 bytes in 0 chunks, 0 read calls, largest chunk 0 bytes\n\
+                 stderr: 0 bytes in 0 chunks, 0 read calls, largest chunk 0 bytes\n\
+                 fd3: 5 bytes in 1 chunks, 2 read calls, largest chunk 5 bytes\n\
+                 fd4: 0 bytes in 0 chunks, 0 read calls, largest chunk 0 bytes"
+            );
+        }
+    }
+}
@@ -0,0 +1,295 @@
+//! Terminal queries and modes.
+//!
+//! This module provides functionality for inspecting the terminal that fdintercept is attached to,
+//! for resizing terminals, for putting the terminal into raw mode, and for restoring the terminal
+//! to the state it was in when fdintercept started, however fdintercept ends.
+
+use anyhow::{Context, Result};
+use nix::sys::termios::{self, SetArg, Termios};
+use nix::unistd::Pid;
+use std::fs::File;
+use std::io;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd, RawFd};
+use std::panic;
+use std::sync::OnceLock;
+
+nix::ioctl_read_bad!(
+    /// Reads the window size of the terminal attached to a file descriptor.
+    tiocgwinsz,
+    nix::libc::TIOCGWINSZ,
+    nix::libc::winsize
+);
+
+nix::ioctl_write_ptr_bad!(
+    /// Sets the window size of the terminal attached to a file descriptor.
+    tiocswinsz,
+    nix::libc::TIOCSWINSZ,
+    nix::libc::winsize
+);
+
+/// Size of a terminal window, in character cells.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WindowSize {
+    /// Number of columns.
+    pub cols: u16,
+    /// Number of rows.
+    pub rows: u16,
+}
+
+impl Default for WindowSize {
+    /// The size of a classic terminal, used when there is no terminal to ask.
+    fn default() -> Self {
+        Self { cols: 80, rows: 24 }
+    }
+}
+
+impl From<WindowSize> for nix::libc::winsize {
+    fn from(size: WindowSize) -> Self {
+        Self {
+            ws_row: size.rows,
+            ws_col: size.cols,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        }
+    }
+}
+
+/// Reads the window size of the terminal fdintercept is attached to.
+///
+/// Stdout is asked first, since that's where the output of the target command is usually seen,
+/// then stderr, then stdin, and then the controlling terminal itself, in case all of them are
+/// redirected.
+///
+/// # Returns
+///
+/// Returns the window size of the first standard stream that is attached to a terminal, or of the
+/// controlling terminal, or `None` if there is no terminal to ask.
+pub fn window_size() -> Option<WindowSize> {
+    query_terminal(window_size_of)
+}
+
+/// Reads the foreground process group of the terminal fdintercept is attached to, which the
+/// terminal sends SIGWINCH to whenever it is resized.
+///
+/// The terminal is looked for like in [`window_size`].
+///
+/// # Returns
+///
+/// Returns the foreground process group, or `None` if there is no terminal to ask.
+pub fn foreground_process_group() -> Option<Pid> {
+    query_terminal(|fd| {
+        // SAFETY: `fd` is open for as long as this closure runs.
+        nix::unistd::tcgetpgrp(unsafe { BorrowedFd::borrow_raw(fd) }).ok()
+    })
+}
+
+/// Asks the terminal fdintercept is attached to something, through the first of stdout, stderr,
+/// stdin, and the controlling terminal that answers.
+///
+/// # Arguments
+///
+/// * `query` - Asks a file descriptor something, returning `None` if it isn't attached to a
+///   terminal.
+///
+/// # Returns
+///
+/// Returns the answer of the first file descriptor that gave one, or `None` if none did.
+fn query_terminal<T>(query: impl Fn(RawFd) -> Option<T>) -> Option<T> {
+    [
+        io::stdout().as_raw_fd(),
+        io::stderr().as_raw_fd(),
+        io::stdin().as_raw_fd(),
+    ]
+    .into_iter()
+    .find_map(&query)
+    .or_else(|| {
+        let tty = File::open("/dev/tty").ok()?;
+        query(tty.as_raw_fd())
+    })
+}
+
+/// Duplicates stdin, if it is attached to a terminal.
+///
+/// # Returns
+///
+/// Returns the duplicate of stdin, or `None` if stdin isn't attached to a terminal.
+///
+/// # Errors
+///
+/// Returns an error if stdin couldn't be duplicated.
+pub fn stdin_terminal() -> io::Result<Option<OwnedFd>> {
+    let stdin = io::stdin();
+    if termios::tcgetattr(&stdin).is_err() {
+        return Ok(None);
+    }
+    stdin.as_fd().try_clone_to_owned().map(Some)
+}
+
+/// Sets the window size of the terminal attached to a file descriptor.
+///
+/// If the size changed, the terminal sends SIGWINCH to its foreground process group.
+///
+/// # Arguments
+///
+/// * `fd` - The file descriptor attached to the terminal, e.g., the master end of a
+///   pseudo-terminal.
+/// * `size` - The new window size.
+///
+/// # Errors
+///
+/// Returns an error if the file descriptor isn't attached to a terminal.
+pub fn set_window_size(fd: &impl AsRawFd, size: WindowSize) -> io::Result<()> {
+    let winsize = nix::libc::winsize::from(size);
+    // SAFETY: `TIOCSWINSZ` only reads the `winsize` struct we pass, which lives until the end of
+    // this function.
+    unsafe { tiocswinsz(fd.as_raw_fd(), &raw const winsize) }?;
+    Ok(())
+}
+
+/// Reads the window size of the terminal attached to a file descriptor.
+///
+/// # Arguments
+///
+/// * `fd` - The file descriptor to ask.
+///
+/// # Returns
+///
+/// Returns the window size, or `None` if the file descriptor isn't attached to a terminal or the
+/// terminal doesn't know its size.
+fn window_size_of(fd: RawFd) -> Option<WindowSize> {
+    let mut winsize = nix::libc::winsize {
+        ws_row: 0,
+        ws_col: 0,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    // SAFETY: `TIOCGWINSZ` only writes into the `winsize` struct we pass, which lives until the
+    // end of this function.
+    unsafe { tiocgwinsz(fd, &raw mut winsize) }.ok()?;
+    if winsize.ws_col == 0 || winsize.ws_row == 0 {
+        return None;
+    }
+    Some(WindowSize {
+        cols: winsize.ws_col,
+        rows: winsize.ws_row,
+    })
+}
+
+/// The mode of the terminal attached to stdin when fdintercept started, if stdin is attached to
+/// one. It's kept as the raw attributes, because [`Termios`] can't be shared between threads.
+static SAVED_MODE: OnceLock<nix::libc::termios> = OnceLock::new();
+
+/// A guard that restores the mode of the terminal when dropped, which covers `main` returning an
+/// error.
+pub struct TerminalGuard(());
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        restore_mode();
+    }
+}
+
+/// Saves the mode of the terminal attached to stdin, so that it can be restored however
+/// fdintercept ends.
+///
+/// Besides returning a guard that restores the mode when dropped, this makes panics restore it
+/// before they are reported, since a panic in another thread doesn't drop anything in the main
+/// thread.
+///
+/// # Returns
+///
+/// Returns the guard. If stdin isn't attached to a terminal, there is nothing to restore, and the
+/// guard does nothing.
+pub fn save_mode() -> TerminalGuard {
+    if let Ok(mode) = termios::tcgetattr(io::stdin()) {
+        if SAVED_MODE.set(mode.into()).is_ok() {
+            let previous_hook = panic::take_hook();
+            panic::set_hook(Box::new(move |info| {
+                restore_mode();
+                previous_hook(info);
+            }));
+        }
+    }
+    TerminalGuard(())
+}
+
+/// Restores the mode of the terminal attached to stdin to the one saved with [`save_mode`].
+///
+/// This can be called any number of times, from any thread, and does nothing if no mode was
+/// saved. Errors are only reported, since this is called on the way out of fdintercept, where
+/// there is nothing else to do about them.
+pub fn restore_mode() {
+    if let Some(mode) = SAVED_MODE.get() {
+        if let Err(e) = termios::tcsetattr(io::stdin(), SetArg::TCSADRAIN, &Termios::from(*mode)) {
+            eprintln!("Error restoring terminal mode: {e}");
+        }
+    }
+}
+
+/// Puts the terminal attached to stdin into raw mode, until the mode saved with [`save_mode`] is
+/// restored.
+///
+/// In raw mode, every keystroke is passed on as soon as it is typed, and as it is, instead of
+/// being echoed, edited into lines, or turned into signals, such as Ctrl+C into SIGINT. This lets
+/// a pseudo-terminal further along do all of that, as the target command configured it.
+///
+/// Does nothing if stdin isn't attached to a terminal.
+///
+/// # Returns
+///
+/// Returns `Ok(())` if the terminal was put into raw mode, or there is no terminal.
+///
+/// # Errors
+///
+/// Returns an error if the terminal couldn't be put into raw mode.
+pub fn enable_raw_mode() -> Result<()> {
+    let Some(mode) = SAVED_MODE.get() else {
+        return Ok(());
+    };
+
+    let mut raw = Termios::from(*mode);
+    termios::cfmakeraw(&mut raw);
+    termios::tcsetattr(io::stdin(), SetArg::TCSADRAIN, &raw)
+        .context("Error putting terminal into raw mode")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod window_size_of {
+        use super::*;
+        use nix::unistd::pipe;
+
+        #[test]
+        fn not_a_terminal() {
+            let (rx, _tx) = pipe().unwrap();
+            assert_eq!(window_size_of(rx.as_raw_fd()), None);
+        }
+    }
+
+    mod set_window_size {
+        use super::*;
+        use nix::pty::openpty;
+        use nix::unistd::pipe;
+
+        #[test]
+        fn pseudo_terminal() {
+            let pty = openpty(None, None).unwrap();
+            let size = WindowSize {
+                cols: 132,
+                rows: 43,
+            };
+
+            set_window_size(&pty.master, size).unwrap();
+
+            assert_eq!(window_size_of(pty.slave.as_raw_fd()), Some(size));
+        }
+
+        #[test]
+        fn not_a_terminal() {
+            let (rx, _tx) = pipe().unwrap();
+            assert!(set_window_size(&rx, WindowSize::default()).is_err());
+        }
+    }
+}
@@ -1,11 +1,16 @@
+use nix::pty::openpty;
 use nix::sys::signal::{self, Signal};
+use nix::sys::termios::{self, LocalFlags};
 use nix::unistd::Pid;
 use std::fs::{self, OpenOptions};
 use std::io::Write;
-use std::io::{ErrorKind, Read};
+use std::io::{BufRead, BufReader, ErrorKind, Read};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::os::unix::process::CommandExt;
 use std::path::{Path, PathBuf};
 use std::process::{Child, Command, Stdio};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 #[test]
 fn test_normal_termination() {
@@ -260,6 +265,1449 @@ fn test_append() {
     );
 }
 
+#[test]
+fn test_timeline_log() {
+    let child_binary_dir = get_child_binary_dir();
+    let timeline_log =
+        child_binary_dir.join(format!("timeline.{:?}.log", std::thread::current().id()));
+    let stdout_log = child_binary_dir.join(format!("stdout.{:?}.log", std::thread::current().id()));
+
+    let mut fdintercept = Command::new("target/debug/fdintercept")
+        .args([
+            "--stdout-log",
+            stdout_log.to_str().unwrap(),
+            "--timeline-log",
+            timeline_log.to_str().unwrap(),
+            "--recreate-logs",
+            "--",
+            child_binary_dir.join(CHILD_BINARY_NAME).to_str().unwrap(),
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let mut stdin = fdintercept.stdin.take().unwrap();
+    stdin.write_all(b"hello\nexit\n").unwrap();
+    assert!(fdintercept.wait().unwrap().success());
+
+    // The per-stream log keeps working alongside the timeline.
+    assert_eq!(
+        fs::read_to_string(&stdout_log).unwrap(),
+        "Starting...\nEcho: hello\n"
+    );
+
+    let timeline = fs::read_to_string(&timeline_log).unwrap();
+    assert!(timeline.contains(" stderr len=14]\nError message\n\n"));
+    // The child can only echo what it read, so its reply must come after the input in the
+    // timeline.
+    let stdin_position = timeline.find(" stdin len=").unwrap();
+    let echo_position = timeline.find("Echo: hello\n").unwrap();
+    assert!(stdin_position < echo_position);
+}
+
+#[test]
+fn test_asciicast() {
+    let child_binary_dir = get_child_binary_dir();
+    let cast = child_binary_dir.join(format!("session.{:?}.cast", std::thread::current().id()));
+
+    let mut fdintercept = Command::new("target/debug/fdintercept")
+        .args([
+            "--asciicast",
+            cast.to_str().unwrap(),
+            "--asciicast-stdin",
+            "--recreate-logs",
+            "--",
+            child_binary_dir.join(CHILD_BINARY_NAME).to_str().unwrap(),
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let mut stdin = fdintercept.stdin.take().unwrap();
+    stdin.write_all(b"hello\nexit\n").unwrap();
+    assert!(fdintercept.wait().unwrap().success());
+
+    let recording = fs::read_to_string(&cast).unwrap();
+    let mut lines = recording.lines();
+    // There's no terminal attached in the tests, so the default size is used.
+    let header = lines.next().unwrap();
+    assert!(header.starts_with("{\"version\":2,\"width\":80,\"height\":24,"));
+    assert!(header.contains(CHILD_BINARY_NAME));
+    let events: Vec<_> = lines.collect();
+    assert!(events.iter().any(|e| e.contains(",\"i\",\"hello\\n")));
+    assert!(
+        events
+            .iter()
+            .any(|e| e.contains(",\"o\",\"Error message\\n\"]"))
+    );
+    assert!(events.iter().any(|e| e.contains("Echo: hello\\n")));
+}
+
+#[test]
+fn test_replay() {
+    let child_binary_dir = get_child_binary_dir();
+    let recorded_stdin =
+        child_binary_dir.join(format!("recorded.{:?}.log", std::thread::current().id()));
+    let stdout_log = child_binary_dir.join(format!("stdout.{:?}.log", std::thread::current().id()));
+    fs::write(&recorded_stdin, "hello\nexit\n").unwrap();
+
+    let fdintercept = Command::new("target/debug/fdintercept")
+        .args([
+            "replay",
+            recorded_stdin.to_str().unwrap(),
+            "--stdout-log",
+            stdout_log.to_str().unwrap(),
+            "--recreate-logs",
+            "--",
+            child_binary_dir.join(CHILD_BINARY_NAME).to_str().unwrap(),
+        ])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let output = fdintercept.wait_with_output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Starting...\nEcho: hello\n"
+    );
+    assert_eq!(
+        fs::read_to_string(&stdout_log).unwrap(),
+        "Starting...\nEcho: hello\n"
+    );
+    // The recorded input is left untouched.
+    assert_eq!(
+        fs::read_to_string(&recorded_stdin).unwrap(),
+        "hello\nexit\n"
+    );
+}
+
+#[test]
+fn test_stdin_source() {
+    let tmp_dir = tempfile::TempDir::new().unwrap();
+    let child_binary_dir = get_child_binary_dir();
+    let input = tmp_dir.path().join("input");
+    fs::write(&input, "hello\nexit\n").unwrap();
+
+    // The stdin of fdintercept is a terminal, which is left alone.
+    let pty = openpty(None, None).unwrap();
+    let output = Command::new("target/debug/fdintercept")
+        .args([
+            "--stdin-source",
+            input.to_str().unwrap(),
+            "--stdin-log",
+            tmp_dir.path().join("stdin.log").to_str().unwrap(),
+            "--stdout-log",
+            tmp_dir.path().join("stdout.log").to_str().unwrap(),
+            "--",
+            child_binary_dir.join(CHILD_BINARY_NAME).to_str().unwrap(),
+        ])
+        .stdin(Stdio::from(pty.slave))
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Starting...\nEcho: hello\n"
+    );
+    assert_eq!(
+        fs::read_to_string(tmp_dir.path().join("stdin.log")).unwrap(),
+        "hello\nexit\n"
+    );
+}
+
+#[test]
+fn test_stdin_source_text() {
+    let tmp_dir = tempfile::TempDir::new().unwrap();
+    let child_binary_dir = get_child_binary_dir();
+    let config = tmp_dir.path().join("config.toml");
+    fs::write(&config, "stdin_source = { text = \"hello\\nexit\\n\" }\n").unwrap();
+
+    let output = Command::new("target/debug/fdintercept")
+        .args([
+            "--conf",
+            config.to_str().unwrap(),
+            "--stdin-log",
+            tmp_dir.path().join("stdin.log").to_str().unwrap(),
+            "--",
+            child_binary_dir.join(CHILD_BINARY_NAME).to_str().unwrap(),
+        ])
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Starting...\nEcho: hello\n"
+    );
+    assert_eq!(
+        fs::read_to_string(tmp_dir.path().join("stdin.log")).unwrap(),
+        "hello\nexit\n"
+    );
+}
+
+#[test]
+fn test_timed_replay() {
+    let child_binary_dir = get_child_binary_dir();
+    let stdin_log = child_binary_dir.join(format!("stdin.{:?}.log", std::thread::current().id()));
+    let stdin_timing =
+        child_binary_dir.join(format!("stdin.{:?}.timing", std::thread::current().id()));
+
+    let mut fdintercept = Command::new("target/debug/fdintercept")
+        .args([
+            "--stdin-log",
+            stdin_log.to_str().unwrap(),
+            "--stdin-timing",
+            stdin_timing.to_str().unwrap(),
+            "--recreate-logs",
+            "--",
+            child_binary_dir.join(CHILD_BINARY_NAME).to_str().unwrap(),
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let mut stdin = fdintercept.stdin.take().unwrap();
+    stdin.write_all(b"hello\n").unwrap();
+    std::thread::sleep(Duration::from_millis(400));
+    stdin.write_all(b"exit\n").unwrap();
+    assert!(fdintercept.wait().unwrap().success());
+
+    let timing = fs::read_to_string(&stdin_timing).unwrap();
+    let timing: Vec<(f64, usize)> = timing
+        .lines()
+        .map(|line| {
+            let (delay, length) = line.split_once(' ').unwrap();
+            (delay.parse().unwrap(), length.parse().unwrap())
+        })
+        .collect();
+    assert_eq!(timing.len(), 2);
+    assert_eq!(timing[0].1, 6);
+    assert_eq!(timing[1].1, 5);
+    assert!(timing[1].0 >= 0.3);
+
+    let start = Instant::now();
+    let output = Command::new("target/debug/fdintercept")
+        .args([
+            "--stdout-log",
+            "/dev/null",
+            "--stderr-log",
+            "/dev/null",
+            "replay",
+            stdin_log.to_str().unwrap(),
+            "--timing",
+            stdin_timing.to_str().unwrap(),
+            "--speed",
+            "2",
+            "--",
+            child_binary_dir.join(CHILD_BINARY_NAME).to_str().unwrap(),
+        ])
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Starting...\nEcho: hello\n"
+    );
+    assert!(start.elapsed() >= Duration::from_secs_f64(timing[1].0 / 2.0));
+}
+
+#[test]
+fn test_hex_log_format() {
+    let child_binary_dir = get_child_binary_dir();
+    let stdout_log = child_binary_dir.join(format!("stdout.{:?}.log", std::thread::current().id()));
+
+    let mut fdintercept = Command::new("target/debug/fdintercept")
+        .args([
+            "--stdout-log",
+            stdout_log.to_str().unwrap(),
+            "--log-format",
+            "hex",
+            "--recreate-logs",
+            "--",
+            child_binary_dir.join(CHILD_BINARY_NAME).to_str().unwrap(),
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let mut stdin = fdintercept.stdin.take().unwrap();
+    stdin.write_all(b"hello\nexit\n").unwrap();
+    assert!(fdintercept.wait().unwrap().success());
+
+    // The dump is continuous regardless of how the output was chunked, and the last, partial line
+    // is written when stdout is closed.
+    assert_eq!(
+        fs::read_to_string(&stdout_log).unwrap(),
+        "00000000: 5374 6172 7469 6e67 2e2e 2e0a 4563 686f  Starting....Echo\n\
+         00000010: 3a20 6865 6c6c 6f0a                      : hello.\n"
+    );
+}
+
+#[test]
+fn test_stream_log_formats() {
+    let child_binary_dir = get_child_binary_dir();
+    let stdin_log = child_binary_dir.join(format!("stdin.{:?}.log", std::thread::current().id()));
+    let stdout_log = child_binary_dir.join(format!("stdout.{:?}.log", std::thread::current().id()));
+
+    let mut fdintercept = Command::new("target/debug/fdintercept")
+        .args([
+            "--stdin-log",
+            stdin_log.to_str().unwrap(),
+            "--stdout-log",
+            stdout_log.to_str().unwrap(),
+            "--log-format",
+            "hex",
+            "--stdin-log-format",
+            "raw",
+            "--recreate-logs",
+            "--",
+            child_binary_dir.join(CHILD_BINARY_NAME).to_str().unwrap(),
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let mut stdin = fdintercept.stdin.take().unwrap();
+    stdin.write_all(b"hello\nexit\n").unwrap();
+    assert!(fdintercept.wait().unwrap().success());
+
+    assert_eq!(fs::read_to_string(&stdin_log).unwrap(), "hello\nexit\n");
+    assert_eq!(
+        fs::read_to_string(&stdout_log).unwrap(),
+        "00000000: 5374 6172 7469 6e67 2e2e 2e0a 4563 686f  Starting....Echo\n\
+         00000010: 3a20 6865 6c6c 6f0a                      : hello.\n"
+    );
+
+    // The raw stdin log can still be replayed.
+    let fdintercept = Command::new("target/debug/fdintercept")
+        .args([
+            "replay",
+            stdin_log.to_str().unwrap(),
+            "--",
+            child_binary_dir.join(CHILD_BINARY_NAME).to_str().unwrap(),
+        ])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let output = fdintercept.wait_with_output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Starting...\nEcho: hello\n"
+    );
+}
+
+#[test]
+fn test_lines_log_format() {
+    let child_binary_dir = get_child_binary_dir();
+    let stdout_log = child_binary_dir.join(format!("stdout.{:?}.log", std::thread::current().id()));
+    let timeline_log =
+        child_binary_dir.join(format!("timeline.{:?}.log", std::thread::current().id()));
+
+    let mut fdintercept = Command::new("target/debug/fdintercept")
+        .args([
+            "--stdout-log",
+            stdout_log.to_str().unwrap(),
+            "--timeline-log",
+            timeline_log.to_str().unwrap(),
+            "--log-format",
+            "lines",
+            "--line-prefix",
+            "{stream}| ",
+            "--recreate-logs",
+            "--",
+            child_binary_dir.join(CHILD_BINARY_NAME).to_str().unwrap(),
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let mut stdin = fdintercept.stdin.take().unwrap();
+    stdin.write_all(b"hello\nexit\n").unwrap();
+    assert!(fdintercept.wait().unwrap().success());
+
+    assert_eq!(
+        fs::read_to_string(&stdout_log).unwrap(),
+        "stdout| Starting...\nstdout| Echo: hello\n"
+    );
+    let timeline = fs::read_to_string(&timeline_log).unwrap();
+    for line in [
+        "stdout| Starting...\n",
+        "stdin| hello\n",
+        "stdin| exit\n",
+        "stdout| Echo: hello\n",
+        "stderr| Error message\n",
+    ] {
+        assert!(timeline.contains(line));
+    }
+}
+
+#[test]
+fn test_compressed_logs() {
+    let child_binary_dir = get_child_binary_dir();
+    let stdin_log =
+        child_binary_dir.join(format!("stdin.{:?}.log.gz", std::thread::current().id()));
+    let stdout_log =
+        child_binary_dir.join(format!("stdout.{:?}.log.zst", std::thread::current().id()));
+    let timeline_log =
+        child_binary_dir.join(format!("timeline.{:?}.log", std::thread::current().id()));
+
+    let mut fdintercept = Command::new("target/debug/fdintercept")
+        .args([
+            "--stdin-log",
+            stdin_log.to_str().unwrap(),
+            "--stdout-log",
+            stdout_log.to_str().unwrap(),
+            "--timeline-log",
+            timeline_log.to_str().unwrap(),
+            "--compression",
+            "gzip",
+            "--recreate-logs",
+            "--",
+            child_binary_dir.join(CHILD_BINARY_NAME).to_str().unwrap(),
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let mut stdin = fdintercept.stdin.take().unwrap();
+    stdin.write_all(b"hello\nexit\n").unwrap();
+    assert!(fdintercept.wait().unwrap().success());
+
+    // With explicit compression, every log is compressed with it, whatever its extension.
+    assert_eq!(read_gzip(&stdin_log), "hello\nexit\n");
+    assert_eq!(read_gzip(&stdout_log), "Starting...\nEcho: hello\n");
+    assert!(read_gzip(&timeline_log).contains("Echo: hello\n"));
+}
+
+#[test]
+fn test_compressed_logs_termination_by_signal() {
+    let child_binary_dir = get_child_binary_dir();
+    let stdout_log =
+        child_binary_dir.join(format!("stdout.{:?}.log.zst", std::thread::current().id()));
+    let stderr_log =
+        child_binary_dir.join(format!("stderr.{:?}.log.gz", std::thread::current().id()));
+
+    let mut fdintercept = Command::new("target/debug/fdintercept")
+        .args([
+            "--stdout-log",
+            stdout_log.to_str().unwrap(),
+            "--stderr-log",
+            stderr_log.to_str().unwrap(),
+            "--recreate-logs",
+            "--",
+            child_binary_dir.join(CHILD_BINARY_NAME).to_str().unwrap(),
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    // Wait until both streams were logged, so that there is something to finish in both logs.
+    let mut stdout = fdintercept.stdout.take().unwrap();
+    stdout.read_exact(&mut [0; 1]).unwrap();
+    let mut stderr = fdintercept.stderr.take().unwrap();
+    stderr.read_exact(&mut [0; 1]).unwrap();
+    signal::kill(
+        Pid::from_raw(i32::try_from(fdintercept.id()).unwrap()),
+        Signal::SIGTERM,
+    )
+    .unwrap();
+    assert_eq!(fdintercept.wait().unwrap().code().unwrap(), 143); // 128 + SIGTERM (15)
+
+    let mut contents = String::new();
+    zstd::Decoder::new(fs::File::open(&stdout_log).unwrap())
+        .unwrap()
+        .read_to_string(&mut contents)
+        .unwrap();
+    assert_eq!(contents, "Starting...\n");
+    assert_eq!(read_gzip(&stderr_log), "Error message\n");
+}
+
+#[test]
+fn test_log_rotation() {
+    let child_binary_dir = get_child_binary_dir();
+    let stdin_log = child_binary_dir.join(format!("stdin.{:?}.log", std::thread::current().id()));
+    let rotated_stdin_log = |n: usize| {
+        child_binary_dir.join(format!("stdin.{:?}.log.{n}", std::thread::current().id()))
+    };
+    for n in 1..=2 {
+        let _ = fs::remove_file(rotated_stdin_log(n));
+    }
+
+    let mut fdintercept = Command::new("target/debug/fdintercept")
+        .args([
+            "--stdin-log",
+            stdin_log.to_str().unwrap(),
+            "--max-log-size",
+            "8",
+            "--max-log-files",
+            "1",
+            "--recreate-logs",
+            "--",
+            child_binary_dir.join(CHILD_BINARY_NAME).to_str().unwrap(),
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    // Write each line separately, so that each is intercepted as a chunk of its own.
+    let mut stdin = fdintercept.stdin.take().unwrap();
+    for line in [b"hello\n".as_slice(), b"world\n", b"exit\n"] {
+        stdin.write_all(line).unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    assert!(fdintercept.wait().unwrap().success());
+
+    assert_eq!(fs::read_to_string(&stdin_log).unwrap(), "exit\n");
+    assert_eq!(fs::read_to_string(rotated_stdin_log(1)).unwrap(), "world\n");
+    assert!(!rotated_stdin_log(2).exists());
+}
+
+#[test]
+fn test_reopen_logs_on_signal() {
+    let child_binary_dir = get_child_binary_dir();
+    let stdin_log = child_binary_dir.join(format!("stdin.{:?}.log", std::thread::current().id()));
+    let moved_stdin_log =
+        child_binary_dir.join(format!("stdin.{:?}.log.moved", std::thread::current().id()));
+
+    let mut fdintercept = Command::new("target/debug/fdintercept")
+        .args([
+            "--stdin-log",
+            stdin_log.to_str().unwrap(),
+            "--reopen-signal",
+            "hup",
+            "--recreate-logs",
+            "--",
+            child_binary_dir.join(CHILD_BINARY_NAME).to_str().unwrap(),
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let mut stdin = fdintercept.stdin.take().unwrap();
+    stdin.write_all(b"hello\n").unwrap();
+    std::thread::sleep(Duration::from_millis(100));
+
+    // Move the log away and ask for it to be reopened, like logrotate does. SIGHUP must not
+    // terminate fdintercept, since it's the reopen signal.
+    fs::rename(&stdin_log, &moved_stdin_log).unwrap();
+    signal::kill(
+        Pid::from_raw(i32::try_from(fdintercept.id()).unwrap()),
+        Signal::SIGHUP,
+    )
+    .unwrap();
+    std::thread::sleep(Duration::from_millis(100));
+
+    stdin.write_all(b"world\nexit\n").unwrap();
+    assert!(fdintercept.wait().unwrap().success());
+
+    assert_eq!(fs::read_to_string(&moved_stdin_log).unwrap(), "hello\n");
+    assert_eq!(fs::read_to_string(&stdin_log).unwrap(), "world\nexit\n");
+}
+
+#[test]
+fn test_log_name_placeholders() {
+    let child_binary_dir = get_child_binary_dir();
+    let tmp_dir = tempfile::TempDir::new().unwrap();
+
+    let mut fdintercept = Command::new("target/debug/fdintercept")
+        .args([
+            "--stdout-log",
+            tmp_dir
+                .path()
+                .join("{executable}.{pid}.{child_pid}.stdout.log")
+                .to_str()
+                .unwrap(),
+            "--",
+            child_binary_dir.join(CHILD_BINARY_NAME).to_str().unwrap(),
+        ])
+        .env(
+            "FDINTERCEPT_STDERR_LOG",
+            "{env:FDINTERCEPT_TEST_LOG_DIR}/{session_id}.stderr.log",
+        )
+        .env("FDINTERCEPT_TEST_LOG_DIR", tmp_dir.path())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let fdintercept_pid = fdintercept.id();
+    let mut stdin = fdintercept.stdin.take().unwrap();
+    stdin.write_all(b"hello\nexit\n").unwrap();
+    assert!(fdintercept.wait().unwrap().success());
+
+    let mut file_names: Vec<String> = fs::read_dir(tmp_dir.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    file_names.sort_by_key(|file_name| file_name.ends_with(".stderr.log"));
+    assert_eq!(file_names.len(), 2);
+
+    let stdout_log_name = &file_names[0];
+    let target_pid = stdout_log_name
+        .strip_prefix(&format!("{CHILD_BINARY_NAME}.{fdintercept_pid}."))
+        .unwrap()
+        .strip_suffix(".stdout.log")
+        .unwrap();
+    assert_ne!(target_pid.parse::<u32>().unwrap(), fdintercept_pid);
+    assert_eq!(
+        fs::read_to_string(tmp_dir.path().join(stdout_log_name)).unwrap(),
+        "Starting...\nEcho: hello\n"
+    );
+
+    let stderr_log_name = &file_names[1];
+    let id = stderr_log_name.strip_suffix(".stderr.log").unwrap();
+    assert_eq!(id.len(), 16);
+    assert!(id.chars().all(|c| c.is_ascii_hexdigit()));
+    assert_eq!(
+        fs::read_to_string(tmp_dir.path().join(stderr_log_name)).unwrap(),
+        "Error message\n"
+    );
+}
+
+#[test]
+fn test_log_dir() {
+    let child_binary_dir = get_child_binary_dir();
+    let tmp_dir = tempfile::TempDir::new().unwrap();
+
+    for input in ["first", "second"] {
+        let mut fdintercept = Command::new("target/debug/fdintercept")
+            .args([
+                "--log-dir",
+                tmp_dir.path().to_str().unwrap(),
+                "--",
+                child_binary_dir.join(CHILD_BINARY_NAME).to_str().unwrap(),
+            ])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut stdin = fdintercept.stdin.take().unwrap();
+        stdin
+            .write_all(format!("{input}\nexit\n").as_bytes())
+            .unwrap();
+        assert!(fdintercept.wait().unwrap().success());
+    }
+
+    let session_dirs: Vec<PathBuf> = fs::read_dir(tmp_dir.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    assert_eq!(session_dirs.len(), 2);
+
+    let mut stdin_logs = Vec::new();
+    for session_dir in &session_dirs {
+        let mut file_names: Vec<String> = fs::read_dir(session_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        file_names.sort();
+        assert_eq!(
+            file_names,
+            ["session.json", "stderr.log", "stdin.log", "stdout.log"]
+        );
+        stdin_logs.push(fs::read_to_string(session_dir.join("stdin.log")).unwrap());
+
+        let manifest: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(session_dir.join("session.json")).unwrap())
+                .unwrap();
+        assert!(
+            session_dir
+                .file_name()
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .ends_with(manifest["session_id"].as_str().unwrap())
+        );
+        assert!(
+            manifest["executable"]
+                .as_str()
+                .unwrap()
+                .ends_with(CHILD_BINARY_NAME)
+        );
+        assert_eq!(manifest["exit_code"], 0);
+    }
+    // Both sessions may have started within the same second, so their order is unknown.
+    stdin_logs.sort();
+    assert_eq!(stdin_logs, ["first\nexit\n", "second\nexit\n"]);
+}
+
+#[test]
+fn test_manifest() {
+    let child_binary_dir = get_child_binary_dir();
+    let tmp_dir = tempfile::TempDir::new().unwrap();
+    let manifest_path = tmp_dir.path().join("session.json");
+
+    let mut fdintercept = Command::new("target/debug/fdintercept")
+        .args([
+            "--stdout-log",
+            tmp_dir.path().join("stdout.log").to_str().unwrap(),
+            "--manifest",
+            manifest_path.to_str().unwrap(),
+            "--manifest-env",
+            "FDINTERCEPT_TEST_VAR,FDINTERCEPT_TEST_UNSET_VAR",
+            "--",
+            child_binary_dir.join(CHILD_BINARY_NAME).to_str().unwrap(),
+            "arg1",
+        ])
+        .env("FDINTERCEPT_TEST_VAR", "value")
+        .env_remove("FDINTERCEPT_TEST_UNSET_VAR")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let fdintercept_pid = fdintercept.id();
+    let mut stdin = fdintercept.stdin.take().unwrap();
+    stdin.write_all(b"error\n").unwrap();
+    assert_eq!(fdintercept.wait().unwrap().code(), Some(42));
+
+    let manifest: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&manifest_path).unwrap()).unwrap();
+    assert_eq!(manifest["fdintercept_version"], env!("CARGO_PKG_VERSION"));
+    assert_eq!(manifest["pid"], fdintercept_pid);
+    assert_ne!(manifest["child_pid"], fdintercept_pid);
+    assert_eq!(
+        manifest["executable"],
+        child_binary_dir.join(CHILD_BINARY_NAME).to_str().unwrap()
+    );
+    assert_eq!(manifest["args"], serde_json::json!(["arg1"]));
+    assert_eq!(
+        manifest["cwd"],
+        std::env::current_dir().unwrap().to_str().unwrap()
+    );
+    assert_eq!(
+        manifest["env"],
+        serde_json::json!({"FDINTERCEPT_TEST_VAR": "value"})
+    );
+    let started_at = humantime::parse_rfc3339(manifest["started_at"].as_str().unwrap()).unwrap();
+    let ended_at = humantime::parse_rfc3339(manifest["ended_at"].as_str().unwrap()).unwrap();
+    assert!(ended_at >= started_at);
+    assert!(manifest["duration"].as_f64().unwrap() >= 0.0);
+    assert_eq!(manifest["exit_code"], 42);
+    assert_eq!(manifest["signal"], serde_json::Value::Null);
+    assert_eq!(manifest["streams"]["stdin"]["bytes"], 6);
+    assert!(manifest["streams"]["stdin"]["chunks"].as_u64().unwrap() >= 1);
+    assert!(
+        manifest["streams"]["stdin"]["read_calls"].as_u64().unwrap()
+            > manifest["streams"]["stdin"]["chunks"].as_u64().unwrap()
+    );
+}
+
+#[test]
+fn test_manifest_termination_by_signal() {
+    let child_binary_dir = get_child_binary_dir();
+    let tmp_dir = tempfile::TempDir::new().unwrap();
+    let manifest_path = tmp_dir.path().join("session.json");
+
+    let mut fdintercept = Command::new("target/debug/fdintercept")
+        .args([
+            "--stdout-log",
+            tmp_dir.path().join("stdout.log").to_str().unwrap(),
+            "--manifest",
+            manifest_path.to_str().unwrap(),
+            "--",
+            child_binary_dir.join(CHILD_BINARY_NAME).to_str().unwrap(),
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdout = fdintercept.stdout.take().unwrap();
+    stdout.read_exact(&mut [0; 1]).unwrap();
+
+    // The manifest is written as soon as the child process started, before it ended.
+    let manifest: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&manifest_path).unwrap()).unwrap();
+    assert_eq!(manifest["ended_at"], serde_json::Value::Null);
+
+    signal::kill(
+        Pid::from_raw(i32::try_from(fdintercept.id()).unwrap()),
+        Signal::SIGTERM,
+    )
+    .unwrap();
+    assert_eq!(fdintercept.wait().unwrap().code(), Some(143));
+
+    let manifest: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&manifest_path).unwrap()).unwrap();
+    assert_eq!(manifest["exit_code"], serde_json::Value::Null);
+    assert_eq!(manifest["signal"], "SIGTERM");
+}
+
+#[test]
+fn test_summary() {
+    let child_binary_dir = get_child_binary_dir();
+    let tmp_dir = tempfile::TempDir::new().unwrap();
+
+    let mut fdintercept = Command::new("target/debug/fdintercept")
+        .args([
+            "--summary",
+            "--stdout-log",
+            tmp_dir.path().join("stdout.log").to_str().unwrap(),
+            "--",
+            child_binary_dir.join(CHILD_BINARY_NAME).to_str().unwrap(),
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = fdintercept.stdin.take().unwrap();
+    stdin.write_all(b"hello\nworld\nexit\n").unwrap();
+    drop(stdin);
+    let output = fdintercept.wait_with_output().unwrap();
+
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let summary = stderr.split_once("fdintercept summary:\n").unwrap().1;
+    let lines: Vec<&str> = summary.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert!(lines[0].starts_with("stdin: 17 bytes in "));
+    assert!(lines[1].starts_with("stdout: 36 bytes in "));
+    assert!(lines[2].starts_with("stderr: 14 bytes in "));
+}
+
+#[test]
+fn test_pty() {
+    let tmp_dir = tempfile::TempDir::new().unwrap();
+
+    let status = Command::new("target/debug/fdintercept")
+        .args([
+            "--pty",
+            "--stdout-log",
+            tmp_dir.path().join("stdout.log").to_str().unwrap(),
+            "--stderr-log",
+            tmp_dir.path().join("stderr.log").to_str().unwrap(),
+            "--",
+            "sh",
+            "-c",
+            "[ -t 0 ] && [ -t 1 ] && [ -t 2 ] && echo terminal >&2; exit 3",
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .status()
+        .unwrap();
+
+    assert_eq!(status.code(), Some(3));
+    // The terminal turns newlines into carriage returns and newlines, and stderr is logged along
+    // with stdout.
+    assert_eq!(
+        fs::read_to_string(tmp_dir.path().join("stdout.log")).unwrap(),
+        "terminal\r\n"
+    );
+    assert!(!tmp_dir.path().join("stderr.log").exists());
+}
+
+#[test]
+fn test_pty_input() {
+    let child_binary_dir = get_child_binary_dir();
+    let tmp_dir = tempfile::TempDir::new().unwrap();
+
+    let mut fdintercept = Command::new("target/debug/fdintercept")
+        .args([
+            "--pty",
+            "--stdin-log",
+            tmp_dir.path().join("stdin.log").to_str().unwrap(),
+            "--stdout-log",
+            tmp_dir.path().join("stdout.log").to_str().unwrap(),
+            "--",
+            child_binary_dir.join(CHILD_BINARY_NAME).to_str().unwrap(),
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = fdintercept.stdin.take().unwrap();
+    stdin.write_all(b"hello\nerror\n").unwrap();
+    assert_eq!(fdintercept.wait().unwrap().code(), Some(42));
+
+    assert_eq!(
+        fs::read_to_string(tmp_dir.path().join("stdin.log")).unwrap(),
+        "hello\nerror\n"
+    );
+    let stdout_log = fs::read_to_string(tmp_dir.path().join("stdout.log")).unwrap();
+    assert!(stdout_log.contains("Starting...\r\n"));
+    assert!(stdout_log.contains("Error message\r\n"));
+    assert!(stdout_log.contains("Echo: hello\r\n"));
+    assert!(stdout_log.contains("Exiting with error...\r\n"));
+}
+
+#[test]
+fn test_window_size_change() {
+    let tmp_dir = tempfile::TempDir::new().unwrap();
+
+    let mut fdintercept = Command::new("target/debug/fdintercept")
+        .args([
+            "--stdout-log",
+            tmp_dir.path().join("stdout.log").to_str().unwrap(),
+            "--",
+            "sh",
+            "-c",
+            "trap 'echo resized; exit 0' WINCH; echo ready; while :; do sleep 0.01; done",
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        // A process group of its own, that SIGWINCH is sent to like a terminal would.
+        .process_group(0)
+        .spawn()
+        .unwrap();
+    let mut stdout = fdintercept.stdout.take().unwrap();
+    stdout.read_exact(&mut [0; 6]).unwrap();
+
+    signal::killpg(
+        Pid::from_raw(i32::try_from(fdintercept.id()).unwrap()),
+        Signal::SIGWINCH,
+    )
+    .unwrap();
+
+    // fdintercept isn't terminated by SIGWINCH, and the target command gets it too.
+    assert!(fdintercept.wait().unwrap().success());
+    assert_eq!(
+        fs::read_to_string(tmp_dir.path().join("stdout.log")).unwrap(),
+        "ready\nresized\n"
+    );
+}
+
+#[test]
+fn test_window_size_change_in_stream_log() {
+    let tmp_dir = tempfile::TempDir::new().unwrap();
+    let pty = openpty(
+        Some(&nix::pty::Winsize {
+            ws_row: 30,
+            ws_col: 100,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        }),
+        None,
+    )
+    .unwrap();
+
+    let mut fdintercept = Command::new("target/debug/fdintercept")
+        .args([
+            "--log-format",
+            "timestamped",
+            "--stdout-log",
+            tmp_dir.path().join("stdout.log").to_str().unwrap(),
+            "--",
+            "sh",
+            "-c",
+            "trap 'echo resized; exit 0' WINCH; echo ready; while :; do sleep 0.01; done",
+        ])
+        // The size is read from stdin, the only standard stream attached to a terminal.
+        .stdin(pty.slave.try_clone().unwrap())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        // A process group of its own, that SIGWINCH is sent to like a terminal would.
+        .process_group(0)
+        .spawn()
+        .unwrap();
+    let mut stdout = fdintercept.stdout.take().unwrap();
+    stdout.read_exact(&mut [0; 6]).unwrap();
+
+    signal::killpg(
+        Pid::from_raw(i32::try_from(fdintercept.id()).unwrap()),
+        Signal::SIGWINCH,
+    )
+    .unwrap();
+
+    assert!(fdintercept.wait().unwrap().success());
+    let stdout_log = fs::read_to_string(tmp_dir.path().join("stdout.log")).unwrap();
+    let lines: Vec<_> = stdout_log.lines().collect();
+    assert_eq!(lines.len(), 8, "{stdout_log}");
+    assert!(lines[0].starts_with("[#0 stdout wall="));
+    assert_eq!(lines[1..3], ["ready", ""]);
+    assert!(lines[3].starts_with("[#1 resize wall="));
+    assert!(lines[3].ends_with(" len=6]"));
+    assert_eq!(lines[4], "100x30");
+    assert!(lines[5].starts_with("[#2 stdout wall="));
+    assert_eq!(lines[6..], ["resized", ""]);
+}
+
+#[test]
+fn test_terminal_restored_on_signal() {
+    let tmp_dir = tempfile::TempDir::new().unwrap();
+    let pty = openpty(None, None).unwrap();
+
+    let mut fdintercept = Command::new("target/debug/fdintercept")
+        .args([
+            "--pty",
+            "--stdout-log",
+            tmp_dir.path().join("stdout.log").to_str().unwrap(),
+            "--",
+            "sleep",
+            "30",
+        ])
+        .stdin(pty.slave.try_clone().unwrap())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    // Wait until fdintercept put its terminal into raw mode.
+    let start = Instant::now();
+    while termios::tcgetattr(&pty.slave)
+        .unwrap()
+        .local_flags
+        .contains(LocalFlags::ECHO)
+    {
+        assert!(start.elapsed() < Duration::from_secs(5));
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    signal::kill(
+        Pid::from_raw(i32::try_from(fdintercept.id()).unwrap()),
+        Signal::SIGTERM,
+    )
+    .unwrap();
+    assert_eq!(fdintercept.wait().unwrap().code(), Some(128 + 15));
+
+    let local_flags = termios::tcgetattr(&pty.slave).unwrap().local_flags;
+    assert!(local_flags.contains(LocalFlags::ECHO | LocalFlags::ICANON | LocalFlags::ISIG));
+}
+
+#[test]
+fn test_input_redacted_while_echo_is_off() {
+    let tmp_dir = tempfile::TempDir::new().unwrap();
+
+    let mut fdintercept = Command::new("target/debug/fdintercept")
+        .args([
+            "--pty",
+            "--stdin-log",
+            tmp_dir.path().join("stdin.log").to_str().unwrap(),
+            "--stdout-log",
+            tmp_dir.path().join("stdout.log").to_str().unwrap(),
+            "--",
+            "sh",
+            "-c",
+            "stty -echo; printf 'Password: '; read secret; stty echo; \
+             printf '\\nName: '; read name; echo \"$name\"",
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = fdintercept.stdin.take().unwrap();
+    let mut stdout = fdintercept.stdout.take().unwrap();
+    let mut wait_for = |prompt: &str| {
+        let mut output = Vec::new();
+        while !output.ends_with(prompt.as_bytes()) {
+            let mut byte = [0];
+            stdout.read_exact(&mut byte).unwrap();
+            output.push(byte[0]);
+        }
+    };
+
+    wait_for("Password: ");
+    stdin.write_all(b"hunter2\n").unwrap();
+    wait_for("Name: ");
+    stdin.write_all(b"alice\n").unwrap();
+    drop(stdin);
+    assert!(fdintercept.wait().unwrap().success());
+
+    assert_eq!(
+        fs::read_to_string(tmp_dir.path().join("stdin.log")).unwrap(),
+        "[redacted]alice\n"
+    );
+    assert!(
+        !fs::read_to_string(tmp_dir.path().join("stdout.log"))
+            .unwrap()
+            .contains("hunter2")
+    );
+}
+
+#[test]
+fn test_extra_fds() {
+    let tmp_dir = tempfile::TempDir::new().unwrap();
+    fs::write(tmp_dir.path().join("input"), "status: ok\n").unwrap();
+
+    // The shell gives fdintercept its file descriptors 3 and 4.
+    let output = Command::new("sh")
+        .args([
+            "-c",
+            "target/debug/fdintercept --fd 3=out:\"$1/fd3.log\" --fd 4=in:\"$1/fd4.log\" \
+             --stdout-log \"$1/stdout.log\" --summary -- sh -c 'cat <&4 >&3' \
+             3>\"$1/output\" 4<\"$1/input\"",
+            "sh",
+            tmp_dir.path().to_str().unwrap(),
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    for file in ["output", "fd3.log", "fd4.log"] {
+        assert_eq!(
+            fs::read_to_string(tmp_dir.path().join(file)).unwrap(),
+            "status: ok\n"
+        );
+    }
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let summary = stderr.split_once("fdintercept summary:\n").unwrap().1;
+    let lines: Vec<&str> = summary.lines().collect();
+    assert!(lines[3].starts_with("fd3: 11 bytes in 1 chunks, "));
+    assert!(lines[4].starts_with("fd4: 11 bytes in 1 chunks, "));
+}
+
+#[test]
+fn test_stream_modes() {
+    let tmp_dir = tempfile::TempDir::new().unwrap();
+    let pty = openpty(None, None).unwrap();
+
+    // Stdin isn't logged, so it is inherited, along with its terminal.
+    let output = Command::new("target/debug/fdintercept")
+        .args([
+            "--stdout-log",
+            tmp_dir.path().join("stdout.log").to_str().unwrap(),
+            "--stderr-mode",
+            "null",
+            "--",
+            "sh",
+            "-c",
+            "[ -t 0 ] && echo tty; echo err >&2",
+        ])
+        .stdin(Stdio::from(pty.slave))
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"tty\n");
+    assert_eq!(output.stderr, b"");
+    assert_eq!(
+        fs::read_to_string(tmp_dir.path().join("stdout.log")).unwrap(),
+        "tty\n"
+    );
+}
+
+#[test]
+fn test_attach() {
+    let tmp_dir = tempfile::TempDir::new().unwrap();
+
+    // The target is a child of fdintercept, which may be required to trace it. Without job
+    // control, the shell gives a background command /dev/null as stdin, unless told otherwise.
+    let mut fdintercept = Command::new("sh")
+        .args([
+            "-c",
+            "exec 3<&0; cat <&3 >\"$1/output\" 3<&- & exec 3<&-; \
+             exec target/debug/fdintercept --stdin-log \"$1/stdin.log\" \
+             --stdout-log \"$1/stdout.log\" attach $!",
+            "sh",
+            tmp_dir.path().to_str().unwrap(),
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    std::thread::sleep(Duration::from_millis(500));
+
+    let mut stdin = fdintercept.stdin.take().unwrap();
+    stdin.write_all(b"hello\n").unwrap();
+    std::thread::sleep(Duration::from_millis(100));
+
+    // fdintercept detaches, and the target goes on.
+    signal::kill(
+        Pid::from_raw(i32::try_from(fdintercept.id()).unwrap()),
+        Signal::SIGINT,
+    )
+    .unwrap();
+    assert!(fdintercept.wait().unwrap().success());
+    stdin.write_all(b"world\n").unwrap();
+    drop(stdin);
+
+    let output = tmp_dir.path().join("output");
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while fs::read_to_string(&output).unwrap() != "hello\nworld\n" {
+        assert!(Instant::now() < deadline);
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(
+        fs::read_to_string(tmp_dir.path().join("stdin.log")).unwrap(),
+        "hello\n"
+    );
+    assert_eq!(
+        fs::read_to_string(tmp_dir.path().join("stdout.log")).unwrap(),
+        "hello\n"
+    );
+}
+
+#[test]
+fn test_proxy() {
+    let tmp_dir = tempfile::TempDir::new().unwrap();
+
+    // An echo server, as the upstream server.
+    let upstream = TcpListener::bind("127.0.0.1:0").unwrap();
+    let upstream_addr = upstream.local_addr().unwrap();
+    std::thread::spawn(move || {
+        let (mut connection, _) = upstream.accept().unwrap();
+        std::io::copy(&mut connection.try_clone().unwrap(), &mut connection).unwrap();
+    });
+
+    let mut fdintercept = Command::new("target/debug/fdintercept")
+        .args([
+            "--summary",
+            "proxy",
+            "127.0.0.1:0",
+            &upstream_addr.to_string(),
+            "--client-log",
+            tmp_dir
+                .path()
+                .join("client.{connection}.log")
+                .to_str()
+                .unwrap(),
+            "--server-log",
+            tmp_dir
+                .path()
+                .join("server.{connection}.log")
+                .to_str()
+                .unwrap(),
+            "--connection-info",
+            tmp_dir
+                .path()
+                .join("connection.{connection}.json")
+                .to_str()
+                .unwrap(),
+        ])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    // The port is picked by the system, and told on stderr.
+    let mut stderr = BufReader::new(fdintercept.stderr.take().unwrap());
+    let mut line = String::new();
+    stderr.read_line(&mut line).unwrap();
+    let listen_addr = line
+        .trim_end()
+        .strip_prefix("fdintercept: listening on ")
+        .unwrap();
+
+    let mut client = TcpStream::connect(listen_addr).unwrap();
+    client.write_all(b"hello\n").unwrap();
+    client.shutdown(Shutdown::Write).unwrap();
+    let mut echoed = String::new();
+    client.read_to_string(&mut echoed).unwrap();
+    assert_eq!(echoed, "hello\n");
+
+    signal::kill(
+        Pid::from_raw(i32::try_from(fdintercept.id()).unwrap()),
+        Signal::SIGINT,
+    )
+    .unwrap();
+    assert!(fdintercept.wait().unwrap().success());
+
+    let mut rest = String::new();
+    stderr.read_to_string(&mut rest).unwrap();
+    let summary = rest
+        .split_once("fdintercept summary of connection 1:\n")
+        .unwrap()
+        .1;
+    let lines: Vec<&str> = summary.lines().collect();
+    assert!(lines[0].starts_with("client: 6 bytes in 1 chunks, "));
+    assert!(lines[1].starts_with("server: 6 bytes in 1 chunks, "));
+
+    assert_eq!(
+        fs::read_to_string(tmp_dir.path().join("client.1.log")).unwrap(),
+        "hello\n"
+    );
+    assert_eq!(
+        fs::read_to_string(tmp_dir.path().join("server.1.log")).unwrap(),
+        "hello\n"
+    );
+
+    let info: serde_json::Value = serde_json::from_str(
+        &fs::read_to_string(tmp_dir.path().join("connection.1.json")).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        info["client"]["address"],
+        client.local_addr().unwrap().to_string()
+    );
+}
+
+#[test]
+fn test_proxy_timeline_log_without_connection() {
+    let tmp_dir = tempfile::TempDir::new().unwrap();
+
+    let output = Command::new("target/debug/fdintercept")
+        .args([
+            "--timeline-log",
+            tmp_dir.path().join("timeline.log").to_str().unwrap(),
+            "proxy",
+            "127.0.0.1:0",
+            "127.0.0.1:1",
+        ])
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8(output.stderr)
+            .unwrap()
+            .contains("must include {connection}")
+    );
+    assert!(!tmp_dir.path().join("timeline.log").exists());
+}
+
+#[test]
+fn test_unix_proxy() {
+    let tmp_dir = tempfile::TempDir::new().unwrap();
+
+    // An echo server, as the upstream server.
+    let upstream_path = tmp_dir.path().join("upstream.sock");
+    let upstream = UnixListener::bind(&upstream_path).unwrap();
+    std::thread::spawn(move || {
+        let (mut connection, _) = upstream.accept().unwrap();
+        std::io::copy(&mut connection.try_clone().unwrap(), &mut connection).unwrap();
+    });
+
+    let listen_path = tmp_dir.path().join("proxy.sock");
+    let mut fdintercept = Command::new("target/debug/fdintercept")
+        .args([
+            "proxy",
+            "--unix",
+            listen_path.to_str().unwrap(),
+            upstream_path.to_str().unwrap(),
+            "--client-log",
+            tmp_dir
+                .path()
+                .join("client.{connection}.log")
+                .to_str()
+                .unwrap(),
+            "--server-log",
+            tmp_dir
+                .path()
+                .join("server.{connection}.log")
+                .to_str()
+                .unwrap(),
+            "--connection-info",
+            tmp_dir
+                .path()
+                .join("connection.{connection}.json")
+                .to_str()
+                .unwrap(),
+        ])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let mut stderr = BufReader::new(fdintercept.stderr.take().unwrap());
+    let mut line = String::new();
+    stderr.read_line(&mut line).unwrap();
+    assert_eq!(
+        line,
+        format!("fdintercept: listening on {}\n", listen_path.display())
+    );
+
+    let mut client = UnixStream::connect(&listen_path).unwrap();
+    client.write_all(b"hello\n").unwrap();
+    client.shutdown(Shutdown::Write).unwrap();
+    let mut echoed = String::new();
+    client.read_to_string(&mut echoed).unwrap();
+    assert_eq!(echoed, "hello\n");
+
+    signal::kill(
+        Pid::from_raw(i32::try_from(fdintercept.id()).unwrap()),
+        Signal::SIGINT,
+    )
+    .unwrap();
+    assert!(fdintercept.wait().unwrap().success());
+
+    // The socket is removed once fdintercept stops listening.
+    assert!(!listen_path.exists());
+    assert_eq!(
+        fs::read_to_string(tmp_dir.path().join("client.1.log")).unwrap(),
+        "hello\n"
+    );
+    assert_eq!(
+        fs::read_to_string(tmp_dir.path().join("server.1.log")).unwrap(),
+        "hello\n"
+    );
+
+    // The credentials of the client, i.e., this test, are recorded next to the logs.
+    let info: serde_json::Value = serde_json::from_str(
+        &fs::read_to_string(tmp_dir.path().join("connection.1.json")).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(info["connection"], 1);
+    assert_eq!(info["client"]["pid"], std::process::id());
+    assert_eq!(info["client"]["uid"], nix::unistd::geteuid().as_raw());
+    assert_eq!(info["client"]["gid"], nix::unistd::getegid().as_raw());
+}
+
+#[test]
+fn test_timestamped_log_format() {
+    let child_binary_dir = get_child_binary_dir();
+    let stdin_log = child_binary_dir.join(format!("stdin.{:?}.log", std::thread::current().id()));
+
+    let mut fdintercept = Command::new("target/debug/fdintercept")
+        .args([
+            "--stdin-log",
+            stdin_log.to_str().unwrap(),
+            "--log-format",
+            "timestamped",
+            "--recreate-logs",
+            "--",
+            child_binary_dir.join(CHILD_BINARY_NAME).to_str().unwrap(),
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let mut stdin = fdintercept.stdin.take().unwrap();
+    stdin.write_all(b"exit\n").unwrap();
+    assert!(fdintercept.wait().unwrap().success());
+
+    let log = fs::read_to_string(&stdin_log).unwrap();
+    assert!(log.starts_with("[#0 stdin wall="));
+    assert!(log.ends_with(" len=5]\nexit\n\n"));
+    assert!(log.contains(" mono="));
+}
+
 const CHILD_BINARY_NAME: &str = "child_process";
 
 fn get_child_binary_dir() -> PathBuf {
@@ -327,3 +1775,11 @@ fn run_main_process(child_binary_dir: &Path) -> Child {
         .spawn()
         .unwrap()
 }
+
+fn read_gzip(path: &Path) -> String {
+    let mut contents = String::new();
+    flate2::read::GzDecoder::new(fs::File::open(path).unwrap())
+        .read_to_string(&mut contents)
+        .unwrap();
+    contents
+}